@@ -0,0 +1,364 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Cursor, Write},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use typed_path::Utf8NativePath;
+
+use crate::{
+    obj::{ObjInfo, ObjReloc, ObjRelocKind, ObjSymbol, ObjSymbols},
+    util::{
+        config::{apply_splits, apply_symbols, write_all_symbols, write_splits, SectionAddressRef},
+        file::buf_writer,
+        signatures::min_confidence,
+    },
+};
+
+/// Analysis results for an unchanged input. Symbols and splits are stored in the
+/// `symbols.txt` and `splits.txt` formats, so that the cache can be inspected by hand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisCache {
+    /// Toolkit version that created the cache.
+    pub version: String,
+    /// Hash of the input, the configuration and the object before analysis.
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sda2_base: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sda_base: Option<u32>,
+    pub splits: String,
+    pub symbols: String,
+    /// Units and splits created by analysis, which `splits.txt` doesn't record.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub autogenerated_units: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub autogenerated_splits: Vec<SectionAddressRef>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relocations: Vec<CachedRelocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedRelocation {
+    pub source: SectionAddressRef,
+    #[serde(rename = "type")]
+    pub kind: ObjRelocKind,
+    /// Target symbol reference. Format: `name!section:address`
+    pub target: String,
+    #[serde(default)]
+    pub addend: i64,
+}
+
+/// Computes the cache key for an object about to be analyzed. `inputs` should contain
+/// everything that affects analysis but isn't part of the object, e.g. the input file
+/// and the project configuration.
+pub fn cache_key(inputs: &[&[u8]], obj: &ObjInfo) -> Result<String> {
+    let mut hasher = Sha1::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    for input in inputs {
+        hasher.update((input.len() as u64).to_be_bytes());
+        hasher.update(input);
+    }
+    hasher.update(min_confidence().to_bits().to_be_bytes());
+    // Relocation targets depend on the resolver
+    hasher.update(format!("{:?}", obj.symbols.reloc_resolver()).as_bytes());
+    let mut state = Vec::new();
+    write_splits(&mut state, obj, true)?;
+    write_all_symbols(&mut state, obj)?;
+    hasher.update(&state);
+    let hash = hasher.finalize();
+    let mut hash_buf = [0u8; 40];
+    let hash_str = base16ct::lower::encode_str(&hash, &mut hash_buf)
+        .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
+    Ok(hash_str.to_string())
+}
+
+impl AnalysisCache {
+    /// Captures the analysis results of `obj`.
+    pub fn new(key: String, obj: &ObjInfo) -> Result<Self> {
+        let mut splits = Vec::new();
+        write_splits(&mut splits, obj, true)?;
+        let mut symbols = Vec::new();
+        write_all_symbols(&mut symbols, obj)?;
+        let autogenerated_units = obj
+            .link_order
+            .iter()
+            .filter(|unit| unit.autogenerated)
+            .map(|unit| unit.name.clone())
+            .collect();
+        let mut autogenerated_splits = Vec::new();
+        let mut relocations = Vec::new();
+        for (_, section) in obj.sections.iter() {
+            for (address, split) in section.splits.iter() {
+                if split.autogenerated {
+                    autogenerated_splits
+                        .push(SectionAddressRef::new(Some(section.name.clone()), address));
+                }
+            }
+            for (address, reloc) in section.relocations.iter() {
+                if reloc.module.is_some() {
+                    continue;
+                }
+                let target = &obj.symbols[reloc.target_symbol];
+                let target_ref = match target.section {
+                    Some(section_index) => format!(
+                        "{}!{}:{:#010X}",
+                        target.name, obj.sections[section_index].name, target.address
+                    ),
+                    None => format!("{}!{:#010X}", target.name, target.address),
+                };
+                relocations.push(CachedRelocation {
+                    source: SectionAddressRef::new(Some(section.name.clone()), address),
+                    kind: reloc.kind,
+                    target: target_ref,
+                    addend: reloc.addend,
+                });
+            }
+        }
+        Ok(Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            key,
+            sda2_base: obj.sda2_base,
+            sda_base: obj.sda_base,
+            splits: String::from_utf8(splits)?,
+            symbols: String::from_utf8(symbols)?,
+            autogenerated_units,
+            autogenerated_splits,
+            relocations,
+        })
+    }
+
+    /// Replaces the symbols, splits and relocations of `obj` with the cached results.
+    pub fn apply(&self, obj: &mut ObjInfo) -> Result<()> {
+        // The cache is written in address order, so keep the order of the symbols file
+        let sort_indices = obj
+            .symbols
+            .iter()
+            .filter_map(|(_, s)| Some(((s.name.clone(), s.address), s.sort_index?)))
+            .collect::<HashMap<_, _>>();
+        let size_conflict_align = obj.symbols.size_conflict_align();
+        let reloc_resolver = obj.symbols.reloc_resolver();
+        obj.symbols = ObjSymbols::new(obj.kind, vec![]);
+        obj.symbols.set_size_conflict_align(size_conflict_align);
+        obj.symbols.set_reloc_resolver(reloc_resolver);
+        obj.link_order.clear();
+        for (_, section) in obj.sections.iter_mut() {
+            section.splits = Default::default();
+            section.relocations = Default::default();
+        }
+        apply_splits(&mut Cursor::new(self.splits.as_bytes()), obj)?;
+        apply_symbols(&mut Cursor::new(self.symbols.as_bytes()), obj)?;
+        let updates = obj
+            .symbols
+            .iter()
+            .filter_map(|(symbol_idx, symbol)| {
+                let sort_index = sort_indices.get(&(symbol.name.clone(), symbol.address)).copied();
+                (symbol.sort_index != sort_index)
+                    .then(|| (symbol_idx, ObjSymbol { sort_index, ..symbol.clone() }))
+            })
+            .collect::<Vec<_>>();
+        for (symbol_idx, symbol) in updates {
+            obj.symbols.replace(symbol_idx, symbol)?;
+        }
+        for unit in &mut obj.link_order {
+            unit.autogenerated = self.autogenerated_units.contains(&unit.name);
+        }
+        for split_ref in &self.autogenerated_splits {
+            let addr = split_ref.resolve(obj)?;
+            let split = obj.sections[addr.section]
+                .splits
+                .iter_mut()
+                .find(|(address, _)| *address == addr.address)
+                .map(|(_, split)| split)
+                .ok_or_else(|| anyhow!("Split not found at {:#010X}", addr.address))?;
+            split.autogenerated = true;
+        }
+        for reloc in &self.relocations {
+            let addr = reloc.source.resolve(obj)?;
+            let (target_symbol, _) = obj
+                .symbols
+                .by_ref(&obj.sections, &reloc.target)?
+                .ok_or_else(|| anyhow!("Relocation target {} not found", reloc.target))?;
+            obj.sections[addr.section].relocations.insert(addr.address, ObjReloc {
+                kind: reloc.kind,
+                target_symbol,
+                addend: reloc.addend,
+                module: None,
+            })?;
+        }
+        if self.sda2_base.is_some() {
+            obj.sda2_base = self.sda2_base;
+        }
+        if self.sda_base.is_some() {
+            obj.sda_base = self.sda_base;
+        }
+        Ok(())
+    }
+}
+
+fn read_cache(path: &Utf8NativePath, key: &str) -> Option<AnalysisCache> {
+    let data = fs::read(path).ok()?;
+    let cache: AnalysisCache = match serde_json::from_slice(&data) {
+        Ok(cache) => cache,
+        Err(e) => {
+            log::warn!("Ignoring invalid analysis cache '{}': {}", path, e);
+            return None;
+        }
+    };
+    if cache.version != env!("CARGO_PKG_VERSION") {
+        log::info!("Analysis cache '{}' is from version {}, reanalyzing", path, cache.version);
+        return None;
+    }
+    if cache.key != key {
+        log::debug!("Analysis cache '{}' is out of date", path);
+        return None;
+    }
+    Some(cache)
+}
+
+fn write_cache(path: &Utf8NativePath, cache: &AnalysisCache) -> Result<()> {
+    let mut w = buf_writer(path)?;
+    serde_json::to_writer_pretty(&mut w, cache)?;
+    w.flush().with_context(|| format!("Failed to write '{}'", path))?;
+    Ok(())
+}
+
+/// Runs `analyze` on `obj`, unless the cache at `path` was created with the same key,
+/// in which case the cached results are applied instead. Returns whether the cache was used.
+pub fn analyze_cached<F>(
+    cache: Option<(&Utf8NativePath, &str)>,
+    obj: &mut ObjInfo,
+    analyze: F,
+) -> Result<bool>
+where
+    F: FnOnce(&mut ObjInfo) -> Result<()>,
+{
+    if let Some((path, key)) = cache {
+        if let Some(cache) = read_cache(path, key) {
+            let mut cached_obj = obj.clone();
+            match cache.apply(&mut cached_obj) {
+                Ok(()) => {
+                    log::info!("Loaded analysis from cache '{}'", path);
+                    *obj = cached_obj;
+                    return Ok(true);
+                }
+                Err(e) => log::warn!("Failed to apply analysis cache '{}': {:#}", path, e),
+            }
+        }
+    }
+    analyze(obj)?;
+    if let Some((path, key)) = cache {
+        if let Err(e) = AnalysisCache::new(key.to_string(), obj).and_then(|c| write_cache(path, &c))
+        {
+            log::warn!("Failed to write analysis cache '{}': {:#}", path, e);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use typed_path::Utf8NativePathBuf;
+
+    use super::*;
+    use crate::obj::{
+        test_section_data, test_symbol, ObjArchitecture, ObjKind, ObjSection, ObjSectionKind,
+        ObjSplit, ObjSymbolKind, ObjUnit,
+    };
+
+    fn test_obj() -> ObjInfo {
+        // blr * 8
+        let data = [0x4E, 0x80, 0x00, 0x20].repeat(8);
+        let section = ObjSection {
+            virtual_address: Some(0x80003100),
+            file_offset: 0x100,
+            ..test_section_data(".text", ObjSectionKind::Code, 0x80003100, data)
+        };
+        let symbols = vec![test_symbol("main", Some(0), 0x80003100, 4, ObjSymbolKind::Function)];
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            symbols,
+            vec![section],
+        )
+    }
+
+    fn analyze(obj: &mut ObjInfo) -> Result<()> {
+        let target = obj.add_symbol(
+            test_symbol("fn_80003110", Some(0), 0x80003110, 4, ObjSymbolKind::Function),
+            false,
+        )?;
+        obj.link_order.push(ObjUnit {
+            name: "auto_80003110.c".to_string(),
+            autogenerated: true,
+            comment_version: None,
+            order: None,
+        });
+        obj.add_split(0, 0x80003110, ObjSplit {
+            unit: "auto_80003110.c".to_string(),
+            end: 0x80003114,
+            align: None,
+            common: false,
+            autogenerated: true,
+            skip: false,
+            rename: None,
+        })?;
+        obj.sections[0].relocations.insert(0x80003104, ObjReloc {
+            kind: ObjRelocKind::PpcRel24,
+            target_symbol: target,
+            addend: 0,
+            module: None,
+        })?;
+        obj.sda2_base = Some(0x80010000);
+        Ok(())
+    }
+
+    fn serialize(obj: &ObjInfo) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_splits(&mut out, obj, false).unwrap();
+        write_all_symbols(&mut out, obj).unwrap();
+        for (address, reloc) in obj.sections[0].relocations.iter() {
+            let target = &obj.symbols[reloc.target_symbol];
+            out.extend(format!("{:#X} {:?} {}\n", address, reloc.kind, target.name).bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_analyze_cached() {
+        let path = Utf8NativePathBuf::from(
+            std::env::temp_dir()
+                .join(format!("dtk-analysis-cache-{}.json", std::process::id()))
+                .to_string_lossy()
+                .to_string(),
+        );
+        let _ = fs::remove_file(&path);
+
+        let mut first = test_obj();
+        let key = cache_key(&[&b"input"[..], &b"config"[..]], &first).unwrap();
+        assert!(!analyze_cached(Some((&path, &key)), &mut first, analyze).unwrap());
+
+        let mut second = test_obj();
+        assert_eq!(cache_key(&[&b"input"[..], &b"config"[..]], &second).unwrap(), key);
+        let used = analyze_cached(Some((&path, &key)), &mut second, |_| {
+            panic!("analysis should have been loaded from cache")
+        })
+        .unwrap();
+        assert!(used);
+        assert_eq!(serialize(&first), serialize(&second));
+        assert_eq!(second.sda2_base, Some(0x80010000));
+        assert!(second.link_order[0].autogenerated);
+
+        // A different key reanalyzes
+        let mut third = test_obj();
+        let other_key = cache_key(&[&b"input"[..], &b"other config"[..]], &third).unwrap();
+        assert_ne!(other_key, key);
+        assert!(!analyze_cached(Some((&path, &other_key)), &mut third, analyze).unwrap());
+
+        let _ = fs::remove_file(&path);
+    }
+}
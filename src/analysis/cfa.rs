@@ -11,15 +11,17 @@ use itertools::Itertools;
 use crate::{
     analysis::{
         executor::{ExecCbData, ExecCbResult, Executor},
+        progress::{report_progress, ProgressPhase},
         skip_alignment,
         slices::{FunctionSlices, TailCallResult},
         vm::{BranchTarget, GprValue, StepResult, VM},
         RelocationTarget,
     },
     obj::{
-        ObjInfo, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
-        SectionIndex,
+        ObjDataKind, ObjInfo, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags,
+        ObjSymbolKind, SectionIndex,
     },
+    util::config::auto_symbol_naming,
 };
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -117,17 +119,37 @@ impl FunctionInfo {
     }
 }
 
-#[derive(Debug, Default)]
+/// Default limit for the size of functions discovered by control flow analysis.
+pub const DEFAULT_MAX_FUNCTION_SIZE: u32 = 0x10000;
+
+#[derive(Debug)]
 pub struct AnalyzerState {
     pub sda_bases: Option<(u32, u32)>,
     pub functions: BTreeMap<SectionAddress, FunctionInfo>,
     pub jump_tables: BTreeMap<SectionAddress, u32>,
     pub known_symbols: BTreeMap<SectionAddress, Vec<ObjSymbol>>,
     pub known_sections: BTreeMap<SectionIndex, String>,
+    /// Functions without a known end are capped at this size, in case control flow
+    /// runs into data.
+    pub max_function_size: u32,
+}
+
+impl Default for AnalyzerState {
+    fn default() -> Self {
+        Self {
+            sda_bases: None,
+            functions: Default::default(),
+            jump_tables: Default::default(),
+            known_symbols: Default::default(),
+            known_sections: Default::default(),
+            max_function_size: DEFAULT_MAX_FUNCTION_SIZE,
+        }
+    }
 }
 
 impl AnalyzerState {
     pub fn apply(&self, obj: &mut ObjInfo) -> Result<()> {
+        let naming = auto_symbol_naming();
         for (&section_index, section_name) in &self.known_sections {
             obj.sections[section_index].rename(section_name.clone())?;
         }
@@ -143,11 +165,7 @@ impl AnalyzerState {
                 section.address,
                 section.address + section.size
             );
-            let name = if obj.module_id == 0 {
-                format!("fn_{:08X}", start.address)
-            } else {
-                format!("fn_{}_{:X}", obj.module_id, start.address)
-            };
+            let name = naming.function_name(obj.module_id, start.address);
             obj.add_symbol(
                 ObjSymbol {
                     name,
@@ -169,6 +187,17 @@ impl AnalyzerState {
                     size = min(size, next_addr.address - addr.address);
                 }
             }
+            // Truncate at the next function or sized symbol, which the last entry
+            // of a guessed table can run into
+            if let Some((_, next)) = obj
+                .symbols
+                .for_section_range(addr.section, addr.address + 1..addr.address + size)
+                .find(|(_, s)| s.is_code() || s.size_known)
+            {
+                size = next.address as u32 - addr.address;
+            }
+            // Round down to whole entries
+            size &= !3;
             let section = &obj.sections[addr.section];
             ensure!(
                 section.contains_range(addr.address..addr.address + size),
@@ -179,25 +208,17 @@ impl AnalyzerState {
                 section.address,
                 section.address + section.size
             );
-            let address_str = if obj.module_id == 0 {
-                format!("{:08X}", addr.address)
-            } else {
-                format!(
-                    "{}_{}_{:X}",
-                    obj.module_id,
-                    section.name.trim_start_matches('.'),
-                    addr.address
-                )
-            };
+            let name = naming.jump_table_name(obj.module_id, &section.name, addr.address);
             obj.add_symbol(
                 ObjSymbol {
-                    name: format!("jumptable_{}", address_str),
+                    name,
                     address: addr.address as u64,
                     section: Some(addr.section),
                     size: size as u64,
                     size_known: true,
                     flags: ObjSymbolFlagSet(ObjSymbolFlags::Local.into()),
                     kind: ObjSymbolKind::Object,
+                    data_kind: ObjDataKind::JumpTable,
                     ..Default::default()
                 },
                 false,
@@ -241,8 +262,13 @@ impl AnalyzerState {
     }
 
     pub fn detect_functions(&mut self, obj: &ObjInfo) -> Result<()> {
+        // Verbatim sections are opaque, so nothing inside of them is analyzed
+        let is_verbatim = |addr: SectionAddress| obj.sections[addr.section].verbatim;
         // Apply known functions from extab
         for (&addr, &size) in &obj.known_functions {
+            if is_verbatim(addr) {
+                continue;
+            }
             self.functions.insert(addr, FunctionInfo {
                 analyzed: false,
                 end: size.map(|size| addr + size),
@@ -253,6 +279,9 @@ impl AnalyzerState {
         for (_, symbol) in obj.symbols.by_kind(ObjSymbolKind::Function) {
             let Some(section_index) = symbol.section else { continue };
             let addr_ref = SectionAddress::new(section_index, symbol.address as u32);
+            if is_verbatim(addr_ref) {
+                continue;
+            }
             self.functions.insert(addr_ref, FunctionInfo {
                 analyzed: false,
                 end: if symbol.size_known { Some(addr_ref + symbol.size as u32) } else { None },
@@ -260,14 +289,18 @@ impl AnalyzerState {
             });
         }
         // Also check the beginning of every code section
-        for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        for (section_index, section) in
+            obj.sections.by_kind(ObjSectionKind::Code).filter(|(_, s)| !s.verbatim)
+        {
             self.functions
                 .entry(SectionAddress::new(section_index, section.address as u32))
                 .or_default();
         }
 
         // Process known functions first
-        for addr in self.functions.keys().cloned().collect_vec() {
+        let known_functions = self.functions.keys().cloned().collect_vec();
+        for (i, addr) in known_functions.into_iter().enumerate() {
+            report_progress(ProgressPhase::FunctionDiscovery, i, self.functions.len());
             self.process_function_at(obj, addr)?;
         }
         if let Some(entry) = obj.entry.map(|n| n as u32) {
@@ -276,7 +309,10 @@ impl AnalyzerState {
                 .sections
                 .at_address(entry)
                 .context(format!("Entry point {:#010X} outside of any section", entry))?;
-            self.process_function_at(obj, SectionAddress::new(section_index, entry))?;
+            let entry_addr = SectionAddress::new(section_index, entry);
+            if !is_verbatim(entry_addr) {
+                self.process_function_at(obj, entry_addr)?;
+            }
         }
         // Locate bounds for referenced functions until none are left
         self.process_functions(obj)?;
@@ -398,11 +434,15 @@ impl AnalyzerState {
     }
 
     fn process_functions(&mut self, obj: &ObjInfo) -> Result<()> {
+        let mut processed = 0;
         loop {
             match self.first_unbounded_function() {
                 Some(addr) => {
                     log::trace!("Processing {:#010X}", addr);
+                    let total = self.functions.len();
+                    report_progress(ProgressPhase::FunctionDiscovery, processed, total);
                     self.process_function_at(obj, addr)?;
+                    processed += 1;
                 }
                 None => {
                     if !self.finalize_functions(obj, false)? && !self.detect_new_functions(obj)? {
@@ -419,7 +459,7 @@ impl AnalyzerState {
             for address in slices.function_references.iter().cloned() {
                 // Only create functions for code sections
                 // Some games use branches to data sections to prevent dead stripping (Mario Party)
-                if matches!(obj.sections.get(address.section), Some(section) if section.kind == ObjSectionKind::Code)
+                if matches!(obj.sections.get(address.section), Some(section) if section.kind == ObjSectionKind::Code && !section.verbatim)
                 {
                     self.functions.entry(address).or_default();
                 }
@@ -452,8 +492,11 @@ impl AnalyzerState {
         obj: &ObjInfo,
         start: SectionAddress,
     ) -> Result<Option<FunctionSlices>> {
-        let mut slices = FunctionSlices::default();
         let function_end = self.functions.get(&start).and_then(|info| info.end);
+        let mut slices = FunctionSlices {
+            max_size: function_end.is_none().then_some(self.max_function_size),
+            ..Default::default()
+        };
         Ok(match slices.analyze(obj, start, start, function_end, &self.functions, None)? {
             true => Some(slices),
             false => None,
@@ -632,3 +675,67 @@ pub fn locate_bss_memsets(obj: &mut ObjInfo) -> Result<Vec<(u32, u32)>> {
     )?;
     Ok(bss_sections)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section_data, test_symbol, ObjArchitecture, ObjKind};
+
+    #[test]
+    fn test_apply_jump_table() {
+        // A 5-entry switch table, followed by another object
+        let mut rodata = vec![];
+        for i in 0..5u32 {
+            rodata.extend_from_slice(&(0x80003110 + i * 8).to_be_bytes());
+        }
+        rodata.extend_from_slice(&[0; 12]);
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![test_symbol("sTable", Some(1), 0x80004014, 8, ObjSymbolKind::Object)],
+            vec![
+                test_section_data(".text", ObjSectionKind::Code, 0x80003100, vec![0; 0x40]),
+                test_section_data(".rodata", ObjSectionKind::ReadOnlyData, 0x80004000, rodata),
+            ],
+        );
+        // Guessed size runs one entry into the next object
+        let mut state = AnalyzerState::default();
+        state.jump_tables.insert(SectionAddress::new(1, 0x80004000), 24);
+        state.apply(&mut obj).unwrap();
+
+        let (_, symbol) = obj.symbols.by_name("jumptable_80004000").unwrap().unwrap();
+        assert_eq!(symbol.data_kind, ObjDataKind::JumpTable);
+        assert_eq!(symbol.size, 20);
+        assert!(symbol.size_known);
+        assert_eq!(symbol.jump_table_entries(), Some(5));
+    }
+
+    #[test]
+    fn test_max_function_size() {
+        // Straight-line code without a return until the end of the section, as when
+        // analysis runs into data
+        let mut data = 0x38630001u32.to_be_bytes().repeat(0xFF); // addi r3, r3, 1
+        data.extend_from_slice(&0x4E800020u32.to_be_bytes()); // blr
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![],
+            vec![test_section_data(".text", ObjSectionKind::Code, 0x80003000, data)],
+        );
+        let mut state = AnalyzerState { max_function_size: 0x100, ..Default::default() };
+        state.detect_functions(&obj).unwrap();
+        let functions = state
+            .functions
+            .iter()
+            .map(|(addr, info)| (addr.address, info.end.map(|end| end.address)))
+            .collect_vec();
+        assert_eq!(functions, [
+            (0x80003000, Some(0x80003100)),
+            (0x80003100, Some(0x80003200)),
+            (0x80003200, Some(0x80003300)),
+            (0x80003300, Some(0x80003400)),
+        ]);
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::{
+    analysis::{cfa::SectionAddress, read_u32, relocation_target_for, RelocationTarget},
+    obj::{
+        ObjInfo, ObjKind, ObjRelocKind, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet,
+        ObjSymbolFlags, ObjSymbolKind, SectionIndex,
+    },
+};
+
+/// Size of an extabindex entry: function address, function size and extab address.
+const EXTABINDEX_ENTRY_SIZE: u32 = 12;
+
+#[derive(Debug, Clone)]
+struct ExtabIndexEntry {
+    address: u32,
+    function: SectionAddress,
+    function_size: u32,
+    extab: SectionAddress,
+}
+
+/// Creates symbols for the entries of the extabindex section and the extab records they
+/// reference, named after their addresses (`@eti_XXXXXXXX` and `@etb_XXXXXXXX`).
+/// The functions are added to the known function bounds. Returns the number of entries.
+pub fn create_extab_symbols(obj: &mut ObjInfo) -> Result<usize> {
+    let (Some((extabindex_index, _)), Some((extab_index, _))) =
+        (obj.sections.by_name("extabindex")?, obj.sections.by_name("extab")?)
+    else {
+        return Ok(0);
+    };
+    let entries = read_extabindex(obj, extabindex_index, extab_index)?;
+
+    // Records are variable length, so each one extends to the next (or the end of the section)
+    let extab_section = &obj.sections[extab_index];
+    let extab_end = (extab_section.address + extab_section.size) as u32;
+    let mut records = BTreeMap::<u32, &ExtabIndexEntry>::new();
+    for entry in &entries {
+        records.entry(entry.extab.address).or_insert(entry);
+    }
+    let mut record_sizes = BTreeMap::<u32, u32>::new();
+    let mut iter = records.keys().peekable();
+    while let Some(&address) = iter.next() {
+        let end = iter.peek().map_or(extab_end, |&&next| next);
+        record_sizes.insert(address, end - address);
+    }
+
+    let mut new_symbols = vec![];
+    for entry in &entries {
+        new_symbols.push(ObjSymbol {
+            name: format!("@eti_{:08X}", entry.address),
+            address: entry.address as u64,
+            section: Some(extabindex_index),
+            size: EXTABINDEX_ENTRY_SIZE as u64,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Local | ObjSymbolFlags::Hidden),
+            kind: ObjSymbolKind::Object,
+            ..Default::default()
+        });
+        if records.get(&entry.extab.address).is_some_and(|e| e.address == entry.address) {
+            new_symbols.push(ObjSymbol {
+                name: format!("@etb_{:08X}", entry.extab.address),
+                address: entry.extab.address as u64,
+                section: Some(extab_index),
+                size: record_sizes[&entry.extab.address] as u64,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Local | ObjSymbolFlags::Hidden),
+                kind: ObjSymbolKind::Object,
+                ..Default::default()
+            });
+        }
+
+        if let Some(Some(old_size)) =
+            obj.known_functions.insert(entry.function, Some(entry.function_size))
+        {
+            if old_size != entry.function_size {
+                log::warn!(
+                    "Conflicting sizes for {:#010X}: {:#X} != {:#X}",
+                    entry.function,
+                    entry.function_size,
+                    old_size
+                );
+            }
+        }
+    }
+    for symbol in new_symbols {
+        obj.add_symbol(symbol, false)?;
+    }
+    Ok(entries.len())
+}
+
+/// Reads extabindex entries up to a null terminator, `_eti_init_info` or the end of the
+/// section. Trailing bytes shorter than an entry are alignment padding.
+fn read_extabindex(
+    obj: &ObjInfo,
+    extabindex_index: SectionIndex,
+    extab_index: SectionIndex,
+) -> Result<Vec<ExtabIndexEntry>> {
+    let section = &obj.sections[extabindex_index];
+    let mut end = (section.address + section.size) as u32;
+    if let Some((_, eti_init_info)) = obj.symbols.by_name("_eti_init_info")? {
+        if eti_init_info.section == Some(extabindex_index) {
+            end = end.min(eti_init_info.address as u32);
+        }
+    }
+
+    let mut entries = vec![];
+    let mut address = section.address as u32;
+    while address + EXTABINDEX_ENTRY_SIZE <= end {
+        let Some(function) = read_entry_address(obj, extabindex_index, address)? else {
+            break;
+        };
+        let function_size = read_u32(section, address + 4).ok_or_else(|| {
+            anyhow!("Failed to read extabindex entry function size @ {:#010X}", address + 4)
+        })?;
+        let extab = read_entry_address(obj, extabindex_index, address + 8)?
+            .ok_or_else(|| anyhow!("extabindex entry @ {:#010X} has no extab address", address))?;
+        ensure!(
+            obj.sections[function.section].kind == ObjSectionKind::Code,
+            "extabindex entry @ {:#010X} references non-code address {:#010X}",
+            address,
+            function
+        );
+        ensure!(
+            extab.section == extab_index && extab.address & 3 == 0,
+            "extabindex entry @ {:#010X} has invalid extab address {:#010X}",
+            address,
+            extab
+        );
+        entries.push(ExtabIndexEntry { address, function, function_size, extab });
+        address += EXTABINDEX_ENTRY_SIZE;
+    }
+    Ok(entries)
+}
+
+/// Reads an address from a relocation or, in executables, the section data.
+/// Returns `None` for a null (terminator) address.
+fn read_entry_address(
+    obj: &ObjInfo,
+    section_index: SectionIndex,
+    address: u32,
+) -> Result<Option<SectionAddress>> {
+    let addr = SectionAddress::new(section_index, address);
+    match relocation_target_for(obj, addr, Some(ObjRelocKind::Absolute))? {
+        Some(RelocationTarget::Address(target)) => return Ok(Some(target)),
+        Some(RelocationTarget::External) => {
+            bail!("extabindex entry @ {:#010X} references another module", address)
+        }
+        None => {}
+    }
+    let value = read_u32(&obj.sections[section_index], address)
+        .ok_or_else(|| anyhow!("Failed to read extabindex entry @ {:#010X}", address))?;
+    if value == 0 {
+        return Ok(None);
+    }
+    ensure!(
+        obj.kind == ObjKind::Executable,
+        "Missing relocation for extabindex entry @ {:#010X}",
+        address
+    );
+    let (target_section, _) = obj.sections.at_address(value)?;
+    Ok(Some(SectionAddress::new(target_section, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section_data, test_symbol, ObjArchitecture};
+
+    #[test]
+    fn test_create_extab_symbols() {
+        let words = |words: &[u32]| words.iter().flat_map(|w| w.to_be_bytes()).collect();
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![test_symbol("__dt__4GameFv", Some(0), 0x80003000, 0x40, ObjSymbolKind::Function)],
+            vec![
+                test_section_data(".text", ObjSectionKind::Code, 0x80003000, vec![0; 0x80]),
+                // Two records, the second followed by alignment padding
+                test_section_data("extab", ObjSectionKind::ReadOnlyData, 0x80005000, vec![0; 0x20]),
+                test_section_data(
+                    "extabindex",
+                    ObjSectionKind::ReadOnlyData,
+                    0x80006000,
+                    // Two functions, the terminator and alignment padding
+                    words(&[
+                        0x80003000, 0x40, 0x80005000, 0x80003040, 0x40, 0x80005008, 0, 0, 0, 0,
+                    ]),
+                ),
+            ],
+        );
+
+        assert_eq!(create_extab_symbols(&mut obj).unwrap(), 2);
+
+        let symbol = |name: &str| {
+            let (_, symbol) = obj.symbols.by_name(name).unwrap().unwrap();
+            (symbol.address, symbol.size, symbol.section)
+        };
+        assert_eq!(symbol("@eti_80006000"), (0x80006000, 12, Some(2)));
+        assert_eq!(symbol("@etb_80005000"), (0x80005000, 8, Some(1)));
+        assert_eq!(symbol("@eti_8000600C"), (0x8000600C, 12, Some(2)));
+        assert_eq!(symbol("@etb_80005008"), (0x80005008, 0x18, Some(1)));
+        assert_eq!(obj.known_functions.get(&SectionAddress::new(0, 0x80003040)), Some(&Some(0x40)));
+    }
+}
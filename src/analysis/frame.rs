@@ -0,0 +1,116 @@
+use anyhow::Result;
+
+use crate::{
+    analysis::{cfa::SectionAddress, read_u32},
+    obj::{ObjInfo, ObjSection, ObjSectionKind},
+};
+
+/// Number of instructions searched for the stack frame setup.
+const PROLOGUE_SEARCH_LIMIT: u32 = 8;
+
+/// Determines the stack frame size of each function with a known prologue and records it in
+/// [`ObjInfo::frame_sizes`]. Returns the number of functions with a known frame size.
+pub fn detect_frame_sizes(obj: &mut ObjInfo) -> Result<usize> {
+    let mut frame_sizes = vec![];
+    for (_, symbol) in obj.symbols.iter() {
+        if !symbol.is_code() {
+            continue;
+        }
+        let Some(section_index) = symbol.section else {
+            continue;
+        };
+        let section = &obj.sections[section_index];
+        if section.kind != ObjSectionKind::Code {
+            continue;
+        }
+        let address = symbol.address as u32;
+        let end = if symbol.size_known && symbol.size > 0 {
+            (symbol.address + symbol.size) as u32
+        } else {
+            (section.address + section.size) as u32
+        };
+        if let Some(frame_size) = prologue_frame_size(section, address, end) {
+            frame_sizes.push((SectionAddress::new(section_index, address), frame_size));
+        }
+    }
+    let count = frame_sizes.len();
+    obj.frame_sizes.extend(frame_sizes);
+    Ok(count)
+}
+
+/// Searches the start of a function for a `stwu r1, -N(r1)` instruction, as emitted by both
+/// MWCC (before `mflr r0`) and GCC (after `mflr r0`). Returns `None` for leaf functions without
+/// a frame, dynamically sized frames (`stwux r1, r1, rX`) and nonstandard prologues.
+fn prologue_frame_size(section: &ObjSection, address: u32, end: u32) -> Option<u32> {
+    let end = end.min(address + PROLOGUE_SEARCH_LIMIT * 4);
+    let mut ins_addr = address;
+    while ins_addr < end {
+        let ins = read_u32(section, ins_addr)?;
+        let opcode = ins >> 26;
+        let rs = (ins >> 21) & 0x1F;
+        let ra = (ins >> 16) & 0x1F;
+        match opcode {
+            // stwu r1, -N(r1)
+            37 if rs == 1 && ra == 1 => {
+                let simm = ins as u16 as i16;
+                return if simm < 0 { Some(simm.unsigned_abs() as u32) } else { None };
+            }
+            // stwux r1, r1, rX
+            31 if rs == 1 && ra == 1 && (ins >> 1) & 0x3FF == 183 => return None,
+            // Any branch ends the prologue
+            16 | 18 | 19 => return None,
+            _ => {}
+        }
+        ins_addr += 4;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section_data, test_symbol, ObjArchitecture, ObjKind, ObjSymbolKind};
+
+    #[test]
+    fn test_detect_frame_sizes() {
+        let functions: [(&str, &[u32]); 4] = [
+            // MWCC: stwu r1, -0x20(r1); mflr r0; stw r0, 0x24(r1)
+            ("mwcc", &[0x9421FFE0, 0x7C0802A6, 0x90010024, 0x4E800020]),
+            // GCC: mflr r0; stwu r1, -0x10(r1); stw r0, 0x14(r1)
+            ("gcc", &[0x7C0802A6, 0x9421FFF0, 0x90010014, 0x4E800020]),
+            // Leaf: li r3, 0; blr
+            ("leaf", &[0x38600000, 0x4E800020, 0x60000000, 0x60000000]),
+            // alloca: stwux r1, r1, r12
+            ("alloca", &[0x7C21616E, 0x7C0802A6, 0x90010004, 0x4E800020]),
+        ];
+        let data: Vec<u8> = functions
+            .iter()
+            .flat_map(|(_, words)| words.iter().flat_map(|w| w.to_be_bytes()))
+            .collect();
+        let symbols = functions
+            .iter()
+            .enumerate()
+            .map(|(i, (name, words))| {
+                test_symbol(
+                    name,
+                    Some(0),
+                    0x80003100 + i as u64 * 0x10,
+                    words.len() as u64 * 4,
+                    ObjSymbolKind::Function,
+                )
+            })
+            .collect();
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            symbols,
+            vec![test_section_data(".text", ObjSectionKind::Code, 0x80003100, data)],
+        );
+        assert_eq!(detect_frame_sizes(&mut obj).unwrap(), 2);
+        assert_eq!(obj.frame_sizes.get(&SectionAddress::new(0, 0x80003100)), Some(&0x20));
+        assert_eq!(obj.frame_sizes.get(&SectionAddress::new(0, 0x80003110)), Some(&0x10));
+        assert_eq!(obj.frame_sizes.get(&SectionAddress::new(0, 0x80003120)), None);
+        assert_eq!(obj.frame_sizes.get(&SectionAddress::new(0, 0x80003130)), None);
+    }
+}
@@ -11,12 +11,19 @@ use crate::{
     },
 };
 
+pub mod cache;
 pub mod cfa;
 pub mod executor;
+pub mod extab;
+pub mod frame;
 pub mod objects;
 pub mod pass;
+pub mod progress;
 pub mod signatures;
 pub mod slices;
+pub mod stats;
+pub mod tail_calls;
+pub mod thunks;
 pub mod tracker;
 pub mod vm;
 
@@ -1,13 +1,20 @@
 use anyhow::Result;
+use itertools::Itertools;
 
 use crate::{
-    obj::{ObjDataKind, ObjInfo, ObjSectionKind, ObjSymbolKind, SymbolIndex},
-    util::split::is_linker_generated_label,
+    obj::{
+        relocation_targets, ObjDataKind, ObjInfo, ObjReloc, ObjRelocKind, ObjSectionKind,
+        ObjSymbol, ObjSymbolKind, SectionIndex, SymbolIndex,
+    },
+    util::{
+        config::{auto_symbol_naming, is_auto_symbol},
+        split::is_linker_generated_label,
+    },
 };
 
 pub fn detect_objects(obj: &mut ObjInfo) -> Result<()> {
     for (section_index, section) in
-        obj.sections.iter_mut().filter(|(_, s)| s.kind != ObjSectionKind::Code)
+        obj.sections.iter_mut().filter(|(_, s)| s.kind != ObjSectionKind::Code && !s.verbatim)
     {
         let section_end = (section.address + section.size) as u32;
 
@@ -20,8 +27,13 @@ pub fn detect_objects(obj: &mut ObjInfo) -> Result<()> {
             let expected_size = match symbol.data_kind {
                 ObjDataKind::Byte => 1,
                 ObjDataKind::Byte2 | ObjDataKind::Short => 2,
-                ObjDataKind::Byte4 | ObjDataKind::Float | ObjDataKind::Int => 4,
-                ObjDataKind::Byte8 | ObjDataKind::Double => 8,
+                ObjDataKind::Byte4
+                | ObjDataKind::Float
+                | ObjDataKind::Int
+                | ObjDataKind::JumpTable
+                | ObjDataKind::PointerTable => 4,
+                ObjDataKind::Byte8 | ObjDataKind::Double | ObjDataKind::PairedSingle => 8,
+                ObjDataKind::Quadword => 16,
                 _ => 0,
             };
             if !symbol.size_known {
@@ -63,13 +75,267 @@ pub fn detect_objects(obj: &mut ObjInfo) -> Result<()> {
     Ok(())
 }
 
+/// Merges runs of adjacent auto-generated objects with the same data kind into arrays, as long
+/// as only the first element of each run is referenced by relocations.
+/// Returns the number of arrays created.
+pub fn detect_data_arrays(obj: &mut ObjInfo) -> Result<usize> {
+    type Run<'a> = (SymbolIndex, &'a ObjSymbol, usize);
+    fn finish(runs: &mut Vec<(SymbolIndex, usize)>, run: Option<Run>) {
+        if let Some((idx, _, count)) = run {
+            if count > 1 {
+                runs.push((idx, count));
+            }
+        }
+    }
+
+    let referenced = relocation_targets(&obj.sections);
+    let mut runs = vec![];
+    for (section_index, _) in
+        obj.sections.iter().filter(|(_, s)| s.kind != ObjSectionKind::Code && !s.verbatim)
+    {
+        let mut current: Option<Run> = None;
+        for (idx, symbol) in obj.symbols.for_section(section_index) {
+            if symbol.kind != ObjSymbolKind::Object {
+                continue;
+            }
+            let element_size = match symbol.data_kind.array_element_size() {
+                Some(size) if symbol.size == size as u64 && is_auto_symbol(symbol) => size as u64,
+                _ => {
+                    finish(&mut runs, current.take());
+                    continue;
+                }
+            };
+            // Includes any labels inside the element
+            let referenced = obj
+                .symbols
+                .for_section_range(
+                    section_index,
+                    symbol.address as u32..(symbol.address + element_size) as u32,
+                )
+                .any(|(idx, _)| referenced.contains(&idx));
+            if let Some((_, first, count)) = &mut current {
+                if !referenced
+                    && first.data_kind == symbol.data_kind
+                    && first.align == symbol.align
+                    && symbol.address == first.address + *count as u64 * element_size
+                {
+                    *count += 1;
+                    continue;
+                }
+            }
+            finish(&mut runs, current.take());
+            current = Some((idx, symbol, 1));
+        }
+        finish(&mut runs, current.take());
+    }
+
+    let count = runs.len();
+    for (idx, run_count) in runs {
+        log::debug!("Merging {} objects into array {}", run_count, obj.symbols[idx].name);
+        obj.symbols.merge_adjacent_data_with(idx, run_count, |idx| referenced.contains(&idx))?;
+    }
+    Ok(count)
+}
+
+/// The fewest consecutive pointers that are considered a table rather than
+/// coincidentally adjacent pointers (e.g. two pointer fields in a struct).
+const MIN_POINTER_TABLE_ENTRIES: usize = 3;
+
+/// Classifies runs of consecutive 4-byte absolute relocations in data sections as pointer
+/// tables (vtables, function pointer tables), and ensures each entry points at a symbol.
+/// Runs are broken at referenced or named symbols, so adjacent tables stay separate.
+/// Returns the number of tables found.
+pub fn detect_pointer_tables(obj: &mut ObjInfo) -> Result<usize> {
+    let referenced = relocation_targets(&obj.sections);
+    let mut tables = vec![];
+    for (section_index, section) in obj.sections.iter().filter(|(_, s)| {
+        matches!(s.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) && !s.verbatim
+    }) {
+        let mut run: Option<(u32, usize)> = None;
+        for (address, reloc) in section.relocations.iter() {
+            let is_entry = reloc.kind == ObjRelocKind::Absolute
+                && address % 4 == 0
+                && reloc.module.map_or(true, |module_id| module_id == obj.module_id)
+                && obj.symbols[reloc.target_symbol].section.is_some();
+            // Referenced or named symbols inside a run start a new table
+            let is_boundary = obj
+                .symbols
+                .at_section_address(section_index, address)
+                .any(|(idx, symbol)| referenced.contains(&idx) || !is_auto_symbol(symbol));
+            if let Some((start, count)) = &mut run {
+                if is_entry && !is_boundary && address == *start + *count as u32 * 4 {
+                    *count += 1;
+                    continue;
+                }
+            }
+            tables.extend(run.take().map(|(start, count)| (section_index, start, count)));
+            if is_entry {
+                run = Some((address, 1));
+            }
+        }
+        tables.extend(run.take().map(|(start, count)| (section_index, start, count)));
+    }
+    tables.retain(|&(section_index, start, count)| {
+        count >= MIN_POINTER_TABLE_ENTRIES
+            && !is_inside_object(obj, section_index, start)
+            // Don't resize named symbols with a known size
+            && !obj.symbols.at_section_address(section_index, start).any(|(_, symbol)| {
+                symbol.size_known && symbol.size != count as u64 * 4 && !is_auto_symbol(symbol)
+            })
+    });
+
+    let naming = auto_symbol_naming();
+    for &(section_index, start, count) in &tables {
+        log::debug!("Found pointer table @ {:#010X} ({} entries)", start, count);
+        // Entries pointing into the middle of a symbol get their own symbol
+        for address in (start..start + count as u32 * 4).step_by(4) {
+            let reloc = obj.sections[section_index].relocations.at(address).unwrap().clone();
+            let target = &obj.symbols[reloc.target_symbol];
+            if reloc.addend == 0 && target.kind != ObjSymbolKind::Section {
+                continue;
+            }
+            let target_section_index = target.section.unwrap();
+            let target_address = reloc.target_address(&obj.symbols) as u32;
+            let existing = obj
+                .symbols
+                .at_section_address(target_section_index, target_address)
+                .find(|(_, s)| s.kind != ObjSymbolKind::Section)
+                .map(|(idx, _)| idx);
+            let target_symbol = match existing {
+                Some(idx) => idx,
+                None => {
+                    let target_section = &obj.sections[target_section_index];
+                    let name = if target_section.kind == ObjSectionKind::Code {
+                        naming.label_name(obj.module_id, &target_section.name, target_address)
+                    } else {
+                        naming.data_name(obj.module_id, &target_section.name, target_address)
+                    };
+                    obj.symbols.add_direct(ObjSymbol {
+                        name,
+                        address: target_address as u64,
+                        section: Some(target_section_index),
+                        ..Default::default()
+                    })?
+                }
+            };
+            obj.sections[section_index].relocations.replace(address, ObjReloc {
+                target_symbol,
+                addend: 0,
+                ..reloc
+            });
+        }
+    }
+
+    let referenced = relocation_targets(&obj.sections);
+    for &(section_index, start, count) in &tables {
+        let size = count as u64 * 4;
+        // Absorb unreferenced auto symbols inside the table
+        let interior = obj
+            .symbols
+            .for_section_range(section_index, start + 1..start + size as u32)
+            .filter(|(idx, symbol)| !referenced.contains(idx) && is_auto_symbol(symbol))
+            .map(|(idx, _)| idx)
+            .collect_vec();
+        for idx in interior {
+            obj.symbols.remove(idx)?;
+        }
+
+        let existing = obj
+            .symbols
+            .at_section_address(section_index, start)
+            .find(|(_, s)| s.kind != ObjSymbolKind::Section)
+            .map(|(idx, s)| (idx, s.clone()));
+        if let Some((idx, existing)) = existing {
+            obj.symbols.replace(idx, ObjSymbol {
+                kind: ObjSymbolKind::Object,
+                size,
+                size_known: true,
+                data_kind: ObjDataKind::PointerTable,
+                ..existing
+            })?;
+        } else {
+            obj.symbols.add_direct(ObjSymbol {
+                name: naming.data_name(obj.module_id, &obj.sections[section_index].name, start),
+                address: start as u64,
+                section: Some(section_index),
+                size,
+                size_known: true,
+                kind: ObjSymbolKind::Object,
+                data_kind: ObjDataKind::PointerTable,
+                ..Default::default()
+            })?;
+        }
+    }
+    Ok(tables.len())
+}
+
+/// Whether `address` lies inside (not at the start of) a sized symbol.
+fn is_inside_object(obj: &ObjInfo, section_index: SectionIndex, address: u32) -> bool {
+    obj.symbols.for_section_range(section_index, ..address).any(|(_, symbol)| {
+        symbol.kind != ObjSymbolKind::Section
+            && symbol.size_known
+            && symbol.address + symbol.size > address as u64
+    })
+}
+
+/// Upper bound for alignment inferred for data symbols.
+pub const MAX_INFERRED_DATA_ALIGN: u32 = 8;
+/// Upper bound for alignment inferred for functions.
+pub const MAX_INFERRED_CODE_ALIGN: u32 = 4;
+
+/// The largest power of two (up to `max_align`) that `address` is aligned to.
+pub fn alignment_hint(address: u64, max_align: u32) -> u32 {
+    let natural = if address == 0 { u32::MAX } else { 1 << address.trailing_zeros().min(31) };
+    natural.min(max_align)
+}
+
+/// Sets a tentative alignment on symbols without one, based on the alignment of their address.
+/// Explicit alignment (e.g. from symbols.txt) is never changed, so inferred values can be
+/// overridden by setting `align` there. Common BSS symbols are skipped, since their alignment
+/// determines their placement. Returns the number of symbols updated.
+pub fn infer_symbol_alignment(obj: &mut ObjInfo) -> Result<usize> {
+    let common_bss = obj.sections.common_bss_start();
+    let mut updates = vec![];
+    for (section_index, section) in obj.sections.iter() {
+        let max_align = if section.kind == ObjSectionKind::Code {
+            MAX_INFERRED_CODE_ALIGN
+        } else {
+            MAX_INFERRED_DATA_ALIGN
+        };
+        for (idx, symbol) in obj.symbols.for_section(section_index) {
+            if symbol.align.is_some()
+                || !(symbol.is_code() || symbol.is_data())
+                || matches!(common_bss, Some(addr)
+                    if addr.section == section_index && symbol.address as u32 >= addr.address)
+            {
+                continue;
+            }
+            let align = alignment_hint(symbol.address, max_align);
+            if align < 4 {
+                continue;
+            }
+            log::debug!(
+                "Inferred alignment {} for {} @ {:#010X}",
+                align,
+                symbol.name,
+                symbol.address
+            );
+            updates.push((idx, align));
+        }
+    }
+    let count = updates.len();
+    for (idx, align) in updates {
+        let symbol = ObjSymbol { align: Some(align), ..obj.symbols[idx].clone() };
+        obj.symbols.replace(idx, symbol)?;
+    }
+    Ok(count)
+}
+
 pub fn detect_strings(obj: &mut ObjInfo) -> Result<()> {
     let mut symbols_set = Vec::<(SymbolIndex, ObjDataKind, usize)>::new();
-    for (section_index, section) in obj
-        .sections
-        .iter()
-        .filter(|(_, s)| matches!(s.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData))
-    {
+    for (section_index, section) in obj.sections.iter().filter(|(_, s)| {
+        matches!(s.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData) && !s.verbatim
+    }) {
         enum StringResult {
             None,
             String { length: usize, terminated: bool },
@@ -161,3 +427,123 @@ pub fn detect_strings(obj: &mut ObjInfo) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{
+        test_section, test_symbol, ObjArchitecture, ObjKind, ObjRelocations, ObjSection,
+    };
+
+    #[test]
+    fn test_detect_pointer_tables() {
+        let section = |name: &str, kind, address, size: u64, relocations| ObjSection {
+            relocations,
+            ..test_section(name, kind, address, size)
+        };
+        let absolute = |target_symbol, addend| ObjReloc {
+            kind: ObjRelocKind::Absolute,
+            target_symbol,
+            addend,
+            module: None,
+        };
+        // Functions 0-8; the last vtable entry points past the end of function 8
+        let mut symbols = (0..9u64)
+            .map(|i| {
+                test_symbol(
+                    &format!("fn_{:08X}", 0x80003100 + i * 0x10),
+                    Some(0),
+                    0x80003100 + i * 0x10,
+                    0x10,
+                    ObjSymbolKind::Function,
+                )
+            })
+            .collect_vec();
+        let object =
+            |name: &str, address| test_symbol(name, Some(1), address, 0, ObjSymbolKind::Object);
+        symbols.push(object("__vt__3Foo", 0x80004000)); // 9
+        symbols.push(object("lbl_80004008", 0x80004008)); // 10
+        symbols.push(object("sPair", 0x80004030)); // 11
+
+        // A 10-entry vtable, then two coincidentally adjacent pointers
+        let mut relocations =
+            (0..9u32).map(|i| (0x80004000 + i * 4, absolute(i as SymbolIndex, 0))).collect_vec();
+        relocations.push((0x80004024, absolute(8, 0x10)));
+        relocations.push((0x80004030, absolute(0, 0)));
+        relocations.push((0x80004034, absolute(1, 0)));
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            symbols,
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003100, 0xA0, Default::default()),
+                section(
+                    ".data",
+                    ObjSectionKind::Data,
+                    0x80004000,
+                    0x40,
+                    ObjRelocations::new(relocations).unwrap(),
+                ),
+            ],
+        );
+
+        assert_eq!(detect_pointer_tables(&mut obj).unwrap(), 1);
+        let (_, vtable) = obj.symbols.by_name("__vt__3Foo").unwrap().unwrap();
+        assert_eq!(vtable.data_kind, ObjDataKind::PointerTable);
+        assert_eq!(vtable.kind, ObjSymbolKind::Object);
+        assert_eq!(vtable.size, 0x28);
+        assert!(vtable.size_known);
+        // Unreferenced auto symbols inside the table are absorbed
+        assert!(obj.symbols.by_name("lbl_80004008").unwrap().is_none());
+        // Every entry targets a symbol directly
+        let (label_idx, label) = obj.symbols.by_name("lbl_80003190").unwrap().unwrap();
+        assert_eq!(label.section, Some(0));
+        let reloc = obj.sections[1].relocations.at(0x80004024).unwrap();
+        assert_eq!((reloc.target_symbol, reloc.addend), (label_idx, 0));
+        // Two adjacent pointers aren't a table
+        let (_, pair) = obj.symbols.by_name("sPair").unwrap().unwrap();
+        assert_eq!(pair.data_kind, ObjDataKind::Unknown);
+    }
+
+    #[test]
+    fn test_alignment_hint() {
+        assert_eq!(alignment_hint(0x80001020, 32), 32);
+        assert_eq!(alignment_hint(0x80001004, 32), 4);
+        assert_eq!(alignment_hint(0x80001020, MAX_INFERRED_DATA_ALIGN), 8);
+        assert_eq!(alignment_hint(0x80001002, 32), 2);
+    }
+
+    #[test]
+    fn test_infer_symbol_alignment() {
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x100);
+        let symbol = |name: &str, section, address, kind, align| ObjSymbol {
+            align,
+            ..test_symbol(name, Some(section), address, 4, kind)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("func", 0, 0x80003020, ObjSymbolKind::Function, None),
+                symbol("wide", 1, 0x80004020, ObjSymbolKind::Object, None),
+                symbol("word", 1, 0x80004024, ObjSymbolKind::Object, None),
+                symbol("half", 1, 0x80004032, ObjSymbolKind::Object, None),
+                symbol("explicit", 1, 0x80004040, ObjSymbolKind::Object, Some(4)),
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000),
+                section(".data", ObjSectionKind::Data, 0x80004000),
+            ],
+        );
+
+        assert_eq!(infer_symbol_alignment(&mut obj).unwrap(), 3);
+        let align = |name: &str| obj.symbols.by_name(name).unwrap().unwrap().1.align;
+        assert_eq!(align("func"), Some(4));
+        assert_eq!(align("wide"), Some(8));
+        assert_eq!(align("word"), Some(4));
+        assert_eq!(align("half"), None);
+        assert_eq!(align("explicit"), Some(4));
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, RwLock,
+    },
+};
+
+/// A long-running analysis phase.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ProgressPhase {
+    /// Locating function boundaries.
+    FunctionDiscovery,
+    /// Matching known function signatures.
+    SignatureMatching,
+    /// Scanning code and data for relocations.
+    RelocationAnalysis,
+    /// Resolving relocation targets to symbols.
+    RelocationResolution,
+}
+
+impl fmt::Display for ProgressPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProgressPhase::FunctionDiscovery => "Function discovery",
+            ProgressPhase::SignatureMatching => "Signature matching",
+            ProgressPhase::RelocationAnalysis => "Relocation analysis",
+            ProgressPhase::RelocationResolution => "Relocation resolution",
+        })
+    }
+}
+
+/// Receives the phase, the number of items processed and the current total.
+/// The total may grow while a phase runs, e.g. as new functions are discovered.
+pub type ProgressCallback = Box<dyn Fn(ProgressPhase, usize, usize) + Send + Sync>;
+
+static PROGRESS_ENABLED: AtomicBool = AtomicBool::new(false);
+static PROGRESS_CALLBACK: RwLock<Option<ProgressCallback>> = RwLock::new(None);
+
+/// Sets the callback invoked by analysis passes to report progress, or removes it.
+pub fn set_progress_callback(callback: Option<ProgressCallback>) {
+    let enabled = callback.is_some();
+    *PROGRESS_CALLBACK.write().unwrap() = callback;
+    PROGRESS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether a progress callback is set. Passes can skip computing totals when it isn't.
+#[inline]
+pub fn progress_enabled() -> bool { PROGRESS_ENABLED.load(Ordering::Relaxed) }
+
+#[inline]
+pub fn report_progress(phase: ProgressPhase, current: usize, total: usize) {
+    if !progress_enabled() {
+        return;
+    }
+    if let Some(callback) = PROGRESS_CALLBACK.read().unwrap().as_ref() {
+        callback(phase, current.min(total), total);
+    }
+}
+
+/// A callback that logs each phase in 10% steps.
+pub fn log_progress() -> ProgressCallback {
+    let last = Mutex::new(None::<(ProgressPhase, usize)>);
+    Box::new(move |phase, current, total| {
+        let percent = if total == 0 { 100 } else { current * 100 / total };
+        let step = percent / 10;
+        let mut last = last.lock().unwrap();
+        if matches!(*last, Some((last_phase, last_step)) if last_phase == phase && last_step == step)
+        {
+            return;
+        }
+        *last = Some((phase, step));
+        log::info!("{}: {}% ({}/{})", phase, step * 10, current, total);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_report_progress() {
+        // Not set: nothing happens
+        report_progress(ProgressPhase::FunctionDiscovery, 1, 2);
+
+        // Only record calls from this thread, other tests may run analysis concurrently
+        let calls = Arc::new(Mutex::new(vec![]));
+        let calls_ref = calls.clone();
+        let thread_id = thread::current().id();
+        set_progress_callback(Some(Box::new(move |phase, current, total| {
+            if thread::current().id() == thread_id {
+                calls_ref.lock().unwrap().push((phase, current, total));
+            }
+        })));
+        assert!(progress_enabled());
+        report_progress(ProgressPhase::SignatureMatching, 1, 4);
+        // Current is clamped to the total
+        report_progress(ProgressPhase::SignatureMatching, 5, 4);
+        set_progress_callback(None);
+        assert!(!progress_enabled());
+        report_progress(ProgressPhase::SignatureMatching, 4, 4);
+
+        assert_eq!(*calls.lock().unwrap(), vec![
+            (ProgressPhase::SignatureMatching, 1, 4),
+            (ProgressPhase::SignatureMatching, 4, 4),
+        ]);
+    }
+}
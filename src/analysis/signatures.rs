@@ -1,16 +1,21 @@
 use anyhow::{anyhow, Result};
 use itertools::Itertools;
+use rayon::prelude::*;
 
 use crate::{
     analysis::{
         cfa::{AnalyzerState, SectionAddress},
+        progress::{report_progress, ProgressPhase},
         read_address,
     },
     obj::{
         ObjInfo, ObjSectionKind, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags,
         ObjSymbolKind,
     },
-    util::signatures::{apply_signature, check_signatures, check_signatures_str, parse_signatures},
+    util::signatures::{
+        apply_signature, check_signatures, check_signatures_str, parse_signatures,
+        FunctionSignature,
+    },
 };
 
 const SIGNATURES: &[(&str, &str)] = &[
@@ -209,10 +214,9 @@ fn apply_signature_for_symbol(obj: &mut ObjInfo, name: &str, sig_str: &str) -> R
         let Some(section_index) = symbol.section else {
             continue;
         };
-        let addr = symbol.address as u32;
-        let section = &obj.sections[section_index];
-        if let Some(signature) = check_signatures_str(section, addr, sig_str)? {
-            apply_signature(obj, SectionAddress::new(section_index, addr), &signature)?;
+        let addr = SectionAddress::new(section_index, symbol.address as u32);
+        if let Some(signature) = check_signatures_str(obj, addr, sig_str)? {
+            apply_signature(obj, addr, &signature)?;
         }
     }
     Ok(())
@@ -234,8 +238,8 @@ fn apply_ctors_signatures(obj: &mut ObjInfo) -> Result<()> {
         return Ok(());
     };
     let Some(signature) = check_signatures_str(
-        &obj.sections[target.section],
-        target.address,
+        obj,
+        target,
         include_str!("../../assets/signatures/__init_cpp_exceptions.yml"),
     )?
     else {
@@ -294,8 +298,8 @@ fn apply_dtors_signatures(obj: &mut ObjInfo) -> Result<()> {
     // First entry of dtors is __destroy_global_chain
     if let Some(dgc_target) = dgc_target {
         if let Some(signature) = check_signatures_str(
-            &obj.sections[dgc_target.section],
-            dgc_target.address,
+            obj,
+            dgc_target,
             include_str!("../../assets/signatures/__destroy_global_chain.yml"),
         )? {
             apply_signature(obj, dgc_target, &signature)?;
@@ -323,8 +327,8 @@ fn apply_dtors_signatures(obj: &mut ObjInfo) -> Result<()> {
     // Second entry of dtors is __fini_cpp_exceptions
     if let Some(fce_target) = fce_target {
         if let Some(signature) = check_signatures_str(
-            &obj.sections[fce_target.section],
-            fce_target.address,
+            obj,
+            fce_target,
             include_str!("../../assets/signatures/__fini_cpp_exceptions.yml"),
         )? {
             apply_signature(obj, fce_target, &signature)?;
@@ -378,13 +382,10 @@ fn apply_init_user_signatures(obj: &mut ObjInfo) -> Result<()> {
     let mut analyzer = AnalyzerState::default();
     analyzer.process_function_at(obj, SectionAddress::new(section_index, symbol.address as u32))?;
     for (addr, _) in analyzer.functions {
-        let section = &obj.sections[addr.section];
-        if let Some(signature) = check_signatures_str(
-            section,
-            addr.address,
-            include_str!("../../assets/signatures/__init_cpp.yml"),
-        )? {
-            apply_signature(obj, SectionAddress::new(section_index, addr.address), &signature)?;
+        if let Some(signature) =
+            check_signatures_str(obj, addr, include_str!("../../assets/signatures/__init_cpp.yml"))?
+        {
+            apply_signature(obj, addr, &signature)?;
             break;
         }
     }
@@ -393,19 +394,20 @@ fn apply_init_user_signatures(obj: &mut ObjInfo) -> Result<()> {
 
 pub fn apply_signatures(obj: &mut ObjInfo) -> Result<()> {
     if let Some(entry) = obj.entry.map(|n| n as u32) {
-        let (entry_section_index, entry_section) = obj.sections.at_address(entry)?;
-        if let Some(signature) = check_signatures_str(
-            entry_section,
-            entry,
-            include_str!("../../assets/signatures/__start.yml"),
-        )? {
-            apply_signature(obj, SectionAddress::new(entry_section_index, entry), &signature)?;
+        let (entry_section_index, _) = obj.sections.at_address(entry)?;
+        let entry = SectionAddress::new(entry_section_index, entry);
+        if let Some(signature) =
+            check_signatures_str(obj, entry, include_str!("../../assets/signatures/__start.yml"))?
+        {
+            apply_signature(obj, entry, &signature)?;
         }
     }
 
-    for &(name, sig_str) in SIGNATURES {
+    for (i, &(name, sig_str)) in SIGNATURES.iter().enumerate() {
+        report_progress(ProgressPhase::SignatureMatching, i, SIGNATURES.len());
         apply_signature_for_symbol(obj, name, sig_str)?
     }
+    report_progress(ProgressPhase::SignatureMatching, SIGNATURES.len(), SIGNATURES.len());
 
     apply_init_user_signatures(obj)?;
     apply_ctors_signatures(obj)?;
@@ -415,28 +417,44 @@ pub fn apply_signatures(obj: &mut ObjInfo) -> Result<()> {
 
 pub fn apply_signatures_post(obj: &mut ObjInfo) -> Result<()> {
     log::debug!("Checking post CFA signatures");
-    for &(_name, sig_str) in POST_SIGNATURES {
-        let signatures = parse_signatures(sig_str)?;
-        let mut found_signature = None;
-        'outer: for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
-            for (symbol_index, symbol) in obj
-                .symbols
+    let signatures = POST_SIGNATURES
+        .iter()
+        .map(|&(_name, sig_str)| parse_signatures(sig_str))
+        .collect::<Result<Vec<_>>>()?;
+    apply_post_signatures(obj, &signatures)
+}
+
+/// Finds the first code symbol matching each set of signatures and applies the matches.
+/// Matching runs in parallel on the global thread pool, sized by `--threads`. All matches
+/// are found against the symbol table before any are applied, so a signature can't match a
+/// symbol created by another one. The matches are applied serially in address order, so the
+/// result doesn't depend on scheduling.
+fn apply_post_signatures(obj: &mut ObjInfo, signatures: &[Vec<FunctionSignature>]) -> Result<()> {
+    let candidates = obj
+        .sections
+        .by_kind(ObjSectionKind::Code)
+        .flat_map(|(section_index, _)| {
+            obj.symbols
                 .for_section(section_index)
                 .filter(|(_, sym)| sym.kind == ObjSymbolKind::Function)
-            {
-                if let Some(signature) =
-                    check_signatures(section, symbol.address as u32, &signatures)?
-                {
-                    found_signature = Some((symbol_index, signature));
-                    break 'outer;
-                }
-            }
-        }
-        if let Some((symbol_index, signature)) = found_signature {
-            let symbol = &obj.symbols[symbol_index];
-            let symbol_addr = SectionAddress::new(symbol.section.unwrap(), symbol.address as u32);
-            apply_signature(obj, symbol_addr, &signature)?;
-        }
+                .map(move |(_, sym)| SectionAddress::new(section_index, sym.address as u32))
+        })
+        .collect_vec();
+    let matches = signatures
+        .par_iter()
+        .map(|signatures| {
+            candidates
+                .par_iter()
+                .find_map_first(|&addr| {
+                    check_signatures(obj, addr, signatures)
+                        .map(|m| m.map(|signature| (addr, signature)))
+                        .transpose()
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (addr, signature) in matches.into_iter().flatten().sorted_by_key(|(addr, _)| *addr) {
+        apply_signature(obj, addr, &signature)?;
     }
     Ok(())
 }
@@ -469,3 +487,84 @@ pub fn update_ctors_dtors(obj: &mut ObjInfo) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+    use crate::{
+        obj::{test_section_data, test_symbol, ObjArchitecture, ObjDataKind, ObjKind, ObjSection},
+        util::signatures::OutSymbol,
+    };
+
+    fn signature(name: &str, words: &[u32]) -> FunctionSignature {
+        let data = words.iter().flat_map(|w| [w.to_be_bytes(), (!0u32).to_be_bytes()]).flatten();
+        FunctionSignature {
+            symbol: 0,
+            hash: String::new(),
+            signature: STANDARD.encode(data.collect::<Vec<u8>>()),
+            symbols: vec![OutSymbol {
+                kind: ObjSymbolKind::Function,
+                name: name.to_string(),
+                size: words.len() as u32 * 4,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                section: Some(".text".to_string()),
+                data_kind: ObjDataKind::Unknown,
+            }],
+            relocations: vec![],
+        }
+    }
+
+    fn obj(functions: &[[u32; 4]]) -> ObjInfo {
+        let data = functions.iter().flatten().flat_map(|w| w.to_be_bytes()).collect::<Vec<u8>>();
+        let symbols = (0..functions.len())
+            .map(|i| {
+                let address = 0x80003100 + i as u64 * 0x10;
+                test_symbol(
+                    &format!("fn_{address:08X}"),
+                    Some(0),
+                    address,
+                    0x10,
+                    ObjSymbolKind::Function,
+                )
+            })
+            .collect();
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            symbols,
+            vec![ObjSection {
+                virtual_address: Some(0x80003100),
+                ..test_section_data(".text", ObjSectionKind::Code, 0x80003100, data)
+            }],
+        )
+    }
+
+    #[test]
+    fn test_apply_post_signatures_parallel() {
+        // li r3, N; li r4, N; nop; blr
+        let functions =
+            (0..64).map(|i| [0x38600000 | i, 0x38800000 | i, 0x60000000, 0x4E800020]).collect_vec();
+        let signatures = vec![
+            vec![signature("late", &functions[50]), signature("unused", &[0x60000000; 4])],
+            vec![signature("early", &functions[3])],
+            vec![signature("missing", &[0x38600000, 0x38800001, 0x60000000, 0x4E800020])],
+        ];
+        let symbols = |threads| {
+            let mut obj = obj(&functions);
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| apply_post_signatures(&mut obj, &signatures)).unwrap();
+            obj.symbols
+                .iter()
+                .map(|(_, s)| (s.name.clone(), s.address, s.size, s.flags))
+                .collect_vec()
+        };
+        let serial = symbols(1);
+        assert_eq!(symbols(4), serial);
+        let names = serial.iter().map(|(name, ..)| name.as_str()).collect_vec();
+        assert!(names.contains(&"late") && names.contains(&"early"));
+        assert!(!names.contains(&"missing") && !names.contains(&"unused"));
+    }
+}
@@ -15,7 +15,7 @@ use crate::{
         vm::{section_address_for, BranchTarget, GprValue, StepResult, VM},
         RelocationTarget,
     },
-    obj::{ObjInfo, ObjKind, ObjSection, ObjSymbolKind},
+    obj::{ObjInfo, ObjKind, ObjSection},
 };
 
 #[derive(Debug, Default, Clone)]
@@ -32,6 +32,9 @@ pub struct FunctionSlices {
     pub has_rfi: bool,
     pub finalized: bool,
     pub has_r1_load: bool, // Possibly instead of a prologue
+    /// Control flow is not followed past this size, in case it ran into data.
+    pub max_size: Option<u32>,
+    pub capped: bool,
 }
 
 pub enum TailCallResult {
@@ -218,6 +221,28 @@ impl FunctionSlices {
     ) -> Result<ExecCbResult<bool>> {
         let ExecCbData { executor, vm, result, ins_addr, section, ins, block_start } = data;
 
+        // Stop at the size limit, leaving the remainder for separate analysis
+        if let Some(max_size) = self.max_size {
+            if ins_addr.section == function_start.section
+                && ins_addr.address.saturating_sub(function_start.address) >= max_size
+            {
+                if !self.capped {
+                    log::warn!(
+                        "Function {:#010X} exceeds the maximum size of {:#X}, capping it",
+                        function_start,
+                        max_size
+                    );
+                    self.capped = true;
+                }
+                if ins_addr == block_start {
+                    self.blocks.remove(&ins_addr);
+                } else {
+                    self.blocks.insert(block_start, Some(ins_addr));
+                }
+                return Ok(ExecCbResult::EndBlock);
+            }
+        }
+
         // Track discovered prologue(s) and epilogue(s)
         // HACK: ProDG sometimes uses LR as a storage register for int-to-float conversions
         // To our heuristic, this looks like a prologue, so first check LR for the magic number.
@@ -385,7 +410,7 @@ impl FunctionSlices {
                                 let last_function = obj
                                     .symbols
                                     .for_section_range(addr.section, ..addr.address)
-                                    .rfind(|(_, symbol)| symbol.kind == ObjSymbolKind::Function);
+                                    .rfind(|(_, symbol)| symbol.is_code());
                                 match last_function {
                                     Some((_, symbol))
                                         if symbol.address + symbol.size > addr.address as u64 =>
@@ -668,6 +693,7 @@ impl FunctionSlices {
         // Perform CFA on jump target to determine more
         let mut slices = FunctionSlices {
             function_references: self.function_references.clone(),
+            max_size: self.max_size,
             ..Default::default()
         };
         if let Ok(result) =
@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use crate::{
+    analysis::cfa::SectionAddress,
+    obj::{ObjInfo, ObjRelocKind},
+};
+
+/// Summary of the relocations in an object, for diagnosing rebuild mismatches.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelocationStats {
+    /// Number of relocations of each kind.
+    pub by_kind: BTreeMap<ObjRelocKind, usize>,
+    /// Relocations whose target resolves to a symbol via
+    /// [`ObjSymbols::for_relocation`](crate::obj::ObjSymbols::for_relocation).
+    pub resolved: usize,
+    /// Relocations whose target has no suitable symbol.
+    pub unresolved: usize,
+    /// Relocations targeting another module or a symbol without a section, which can't be
+    /// resolved within the object.
+    pub external: usize,
+}
+
+impl RelocationStats {
+    pub fn new(obj: &ObjInfo) -> Result<Self> {
+        let mut stats = Self::default();
+        for (_, section) in obj.sections.iter() {
+            for (_, reloc) in section.relocations.iter() {
+                *stats.by_kind.entry(reloc.kind).or_default() += 1;
+                if reloc.module.is_some_and(|id| id != obj.module_id) {
+                    stats.external += 1;
+                    continue;
+                }
+                let target = &obj.symbols[reloc.target_symbol];
+                let Some(target_section) = target.section else {
+                    stats.external += 1;
+                    continue;
+                };
+                let target_addr = SectionAddress::new(
+                    target_section,
+                    (target.address as i64 + reloc.addend) as u32,
+                );
+                let in_sda = obj.sections[target_section].is_small_data();
+                if obj.symbols.for_relocation(target_addr, reloc.kind, in_sda)?.is_some() {
+                    stats.resolved += 1;
+                } else {
+                    stats.unresolved += 1;
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    pub fn total(&self) -> usize { self.by_kind.values().sum() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{
+        test_section, test_symbol, ObjArchitecture, ObjKind, ObjReloc, ObjRelocations, ObjSection,
+        ObjSectionKind, ObjSymbolKind,
+    };
+
+    #[test]
+    fn test_relocation_stats() {
+        let symbol = |name: &str, address, section, size, kind| {
+            test_symbol(name, section, address, size, kind)
+        };
+        let reloc =
+            |kind, target_symbol, addend, module| ObjReloc { kind, target_symbol, addend, module };
+        let section = |name: &str, kind, address, relocations| ObjSection {
+            relocations: ObjRelocations::new(relocations).unwrap(),
+            ..test_section(name, kind, address, 0x20)
+        };
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("fn_a", 0x80003000, Some(0), 0x10, ObjSymbolKind::Function),
+                symbol("fn_b", 0x80003010, Some(0), 0x10, ObjSymbolKind::Function),
+                symbol("sData", 0x80004000, Some(1), 4, ObjSymbolKind::Object),
+                symbol("_SDA_BASE_", 0x80408000, None, 0, ObjSymbolKind::Unknown),
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000, vec![
+                    (0x80003000, reloc(ObjRelocKind::PpcAddr16Ha, 2, 0, None)),
+                    (0x80003004, reloc(ObjRelocKind::PpcAddr16Lo, 2, 0, None)),
+                    (0x80003008, reloc(ObjRelocKind::PpcRel24, 1, 0, None)),
+                    (0x8000300C, reloc(ObjRelocKind::PpcRel24, 1, 0, None)),
+                    (0x80003010, reloc(ObjRelocKind::PpcRel24, 0, 0, None)),
+                    // ABS target
+                    (0x80003014, reloc(ObjRelocKind::PpcAddr16Lo, 3, 0, None)),
+                ]),
+                section(".data", ObjSectionKind::Data, 0x80004000, vec![
+                    // Past the end of sData
+                    (0x80004010, reloc(ObjRelocKind::Absolute, 2, 8, None)),
+                    // Another module
+                    (0x80004014, reloc(ObjRelocKind::Absolute, 0, 0, Some(1))),
+                ]),
+            ],
+        );
+
+        let stats = RelocationStats::new(&obj).unwrap();
+        assert_eq!(stats.by_kind.into_iter().collect::<Vec<_>>(), [
+            (ObjRelocKind::Absolute, 2),
+            (ObjRelocKind::PpcAddr16Ha, 1),
+            (ObjRelocKind::PpcAddr16Lo, 2),
+            (ObjRelocKind::PpcRel24, 3),
+        ]);
+        assert_eq!((stats.resolved, stats.unresolved, stats.external), (5, 1, 2));
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::{
+    analysis::{cfa::SectionAddress, read_u32},
+    obj::{ObjInfo, ObjRelocKind, ObjSectionKind, ObjSymbolKind},
+    util::config::is_auto_symbol,
+};
+
+/// Splits functions that analysis merged across a tail call: an unconditional `b` out of the
+/// function, directly followed by an address that code outside of the function branches to.
+/// Only auto-named functions that aren't locked are split. Returns the number of functions
+/// created.
+///
+/// Genuine internal branches to the instruction after a `b` look the same, so this is opt-in.
+pub fn split_tail_calls(obj: &mut ObjInfo) -> Result<usize> {
+    // Branch target -> branch sources
+    let mut references = BTreeMap::<SectionAddress, Vec<SectionAddress>>::new();
+    for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        if section.verbatim {
+            continue;
+        }
+        for address in (section.address as u32..(section.address + section.size) as u32).step_by(4)
+        {
+            let source = SectionAddress::new(section_index, address);
+            if let Some((target, _)) = branch_target(obj, source) {
+                references.entry(target).or_default().push(source);
+            }
+        }
+    }
+
+    let mut splits = vec![];
+    for (symbol_idx, symbol) in obj.symbols.by_kind(ObjSymbolKind::Function) {
+        let Some(section_index) = symbol.section else { continue };
+        let section = &obj.sections[section_index];
+        if section.kind != ObjSectionKind::Code || section.verbatim || !symbol.size_known {
+            continue;
+        }
+        // Keep the bounds of user-defined functions
+        if symbol.flags.is_locked() || !is_auto_symbol(symbol) {
+            continue;
+        }
+        let start = SectionAddress::new(section_index, symbol.address as u32);
+        let end = start + symbol.size as u32;
+        let outside = |addr: SectionAddress, end: SectionAddress| {
+            addr.section != section_index || addr < start || addr >= end
+        };
+        for address in (start.address..end.address.saturating_sub(4)).step_by(4) {
+            let next = SectionAddress::new(section_index, address + 4);
+            // Unconditional branch (without link) leaving the first part of the function
+            let Some((target, false)) =
+                branch_target(obj, SectionAddress::new(section_index, address))
+            else {
+                continue;
+            };
+            if !outside(target, next) {
+                continue;
+            }
+            // Following address is branched to from outside of the function
+            if references.get(&next).is_some_and(|sources| sources.iter().any(|&s| outside(s, end)))
+            {
+                splits.push((symbol_idx, next.address));
+            }
+        }
+    }
+
+    // Split from the end, so that the original symbol always contains the next split address
+    let count = splits.len();
+    for (symbol_idx, address) in splits.into_iter().sorted().rev() {
+        let new_idx = obj.symbols.split_function(symbol_idx, address, obj.module_id)?;
+        log::debug!(
+            "Split tail call in {} at {:#010X}, created {}",
+            obj.symbols[symbol_idx].name,
+            address,
+            obj.symbols[new_idx].name
+        );
+    }
+    Ok(count)
+}
+
+/// Returns the target of an unconditional `b`/`bl` at `addr` and whether it links.
+pub fn branch_target(obj: &ObjInfo, addr: SectionAddress) -> Option<(SectionAddress, bool)> {
+    let section = &obj.sections[addr.section];
+    let ins = read_u32(section, addr.address)?;
+    // b, bl (not absolute)
+    if ins >> 26 != 18 || ins & 2 != 0 {
+        return None;
+    }
+    let link = ins & 1 != 0;
+    if let Some(reloc) = section.relocations.at(addr.address) {
+        if reloc.kind != ObjRelocKind::PpcRel24 || reloc.module.is_some() {
+            return None;
+        }
+        let target = obj.symbols.get(reloc.target_symbol)?;
+        let address = (target.address as i64 + reloc.addend) as u32;
+        return Some((SectionAddress::new(target.section?, address), link));
+    }
+    let offset = ((ins & 0x03FF_FFFC) << 6) as i32 >> 6;
+    let address = addr.address.wrapping_add_signed(offset);
+    let section_index = if section.contains(address) {
+        addr.section
+    } else {
+        obj.sections.at_address(address).ok()?.0
+    };
+    Some((SectionAddress::new(section_index, address), link))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section_data, test_symbol, ObjArchitecture, ObjKind};
+
+    #[test]
+    fn test_split_tail_calls() {
+        let code: [u32; 11] = [
+            0x38600001, // fn_a: li r3, 1
+            0x48000014, // b fn_c
+            0x38600002, // (merged) li r3, 2
+            0x4E800020, // blr
+            0x4BFFFFF9, // fn_caller: bl 0x80003108
+            0x4E800020, // blr
+            0x38600003, // fn_c: li r3, 3
+            0x4E800020, // blr
+            0x48000008, // fn_d: b 0x80003128
+            0x38600004, // li r3, 4
+            0x4E800020, // blr
+        ];
+        let data = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        let function = |name: &str, address, size| {
+            test_symbol(name, Some(0), address, size, ObjSymbolKind::Function)
+        };
+        let obj = |first: &str| {
+            ObjInfo::new(
+                ObjKind::Executable,
+                ObjArchitecture::PowerPc,
+                "test".to_string(),
+                vec![
+                    function(first, 0x80003100, 0x10),
+                    function("fn_80003110", 0x80003110, 0x8),
+                    function("fn_80003118", 0x80003118, 0x8),
+                    function("fn_80003120", 0x80003120, 0xC),
+                ],
+                vec![test_section_data(".text", ObjSectionKind::Code, 0x80003100, data.clone())],
+            )
+        };
+
+        // User-defined functions aren't split
+        assert_eq!(split_tail_calls(&mut obj("GameMain")).unwrap(), 0);
+
+        let mut obj = obj("fn_80003100");
+        assert_eq!(split_tail_calls(&mut obj).unwrap(), 1);
+        let functions = obj
+            .symbols
+            .by_kind(ObjSymbolKind::Function)
+            .map(|(_, s)| (s.address, s.size))
+            .sorted()
+            .collect_vec();
+        assert_eq!(functions, [
+            (0x80003100, 0x8),
+            (0x80003108, 0x8),
+            (0x80003110, 0x8),
+            (0x80003118, 0x8),
+            (0x80003120, 0xC),
+        ]);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use itertools::Itertools;
+use memchr::memmem;
+
+use crate::{
+    analysis::{cfa::SectionAddress, read_u32, tail_calls::branch_target},
+    obj::{ObjInfo, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind},
+    util::config::is_auto_symbol,
+};
+
+// Runtime.PPCEABI.H.a ptmf.c
+const PTMF_SCALL: [u32; 10] = [
+    0x800C0000, // lwz r0, 0(r12)
+    0x816C0004, // lwz r11, 4(r12)
+    0x818C0008, // lwz r12, 8(r12)
+    0x7C630214, // add r3, r3, r0
+    0x2C0B0000, // cmpwi r11, 0
+    0x4180000C, // blt 0xC
+    0x7D83602E, // lwzx r12, r3, r12
+    0x7D8C582E, // lwzx r12, r12, r11
+    0x7D8903A6, // mtctr r12
+    0x4E800420, // bctr
+];
+
+/// Recognizes MWCC pointer-to-member-function call stubs (`__ptmf_scall`) and this-adjusting
+/// thunks, and types them as functions with consistent names. Symbols with known names are
+/// kept. Returns the number of symbols created or updated.
+///
+/// A this-adjusting thunk is `addi r3, r3, -N; b target` and is named `@N@target`, as MWCC
+/// names them. A genuine function can have the same shape, but thunks are only referenced from
+/// vtables, so candidates that are called directly or that lie inside another function are
+/// skipped. Candidates whose target still has an auto-generated name are skipped as well.
+pub fn detect_thunks(obj: &mut ObjInfo) -> Result<usize> {
+    let mut called = BTreeSet::<SectionAddress>::new();
+    for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        if section.verbatim {
+            continue;
+        }
+        for address in (section.address as u32..(section.address + section.size) as u32).step_by(4)
+        {
+            if let Some((target, true)) =
+                branch_target(obj, SectionAddress::new(section_index, address))
+            {
+                called.insert(target);
+            }
+        }
+    }
+
+    let ptmf_scall = PTMF_SCALL.iter().flat_map(|ins| ins.to_be_bytes()).collect_vec();
+    let mut thunks = vec![];
+    for (section_index, section) in obj.sections.by_kind(ObjSectionKind::Code) {
+        if section.verbatim {
+            continue;
+        }
+        if let Some(pos) = memmem::find(&section.data, &ptmf_scall).filter(|pos| pos % 4 == 0) {
+            let start = SectionAddress::new(section_index, section.address as u32 + pos as u32);
+            log::debug!("Found __ptmf_scall @ {:#010X}", start);
+            thunks.push((start, "__ptmf_scall".to_string(), ObjSymbolFlags::Global));
+        }
+        let end = (section.address + section.size) as u32;
+        for address in (section.address as u32..end.saturating_sub(4)).step_by(4) {
+            let start = SectionAddress::new(section_index, address);
+            let Some(offset) = read_u32(section, address).and_then(this_adjustment) else {
+                continue;
+            };
+            let Some((target, false)) = branch_target(obj, start + 4) else {
+                continue;
+            };
+            if called.contains(&start) {
+                continue;
+            }
+            let inside_function = obj
+                .symbols
+                .for_section_range_overlapping(section_index, address..address + 8)
+                .any(|(_, s)| s.is_code() && s.address < address as u64);
+            if inside_function {
+                continue;
+            }
+            let Some((_, target_symbol)) = obj.symbols.kind_at_section_address(
+                target.section,
+                target.address,
+                ObjSymbolKind::Function,
+            )?
+            else {
+                continue;
+            };
+            // `@N@fn_80003100` wouldn't be recognized as auto-generated, and would go stale once
+            // the target is named
+            if is_auto_symbol(target_symbol) {
+                continue;
+            }
+            let name = format!("@{}@{}", offset, target_symbol.name);
+            log::debug!("Found thunk {} @ {:#010X}", name, start);
+            thunks.push((start, name, ObjSymbolFlags::Weak));
+        }
+    }
+
+    let mut count = 0;
+    for (start, name, scope) in thunks {
+        let size = if name == "__ptmf_scall" { PTMF_SCALL.len() as u64 * 4 } else { 8 };
+        let existing = obj
+            .symbols
+            .at_section_address(start.section, start.address)
+            .filter(|(_, s)| s.kind != ObjSymbolKind::Section)
+            .collect_vec();
+        if existing.iter().any(|(_, s)| !is_auto_symbol(s)) {
+            continue;
+        }
+        let symbol = ObjSymbol {
+            name,
+            address: start.address as u64,
+            section: Some(start.section),
+            size,
+            size_known: true,
+            flags: ObjSymbolFlagSet(scope.into()),
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        };
+        match existing.first() {
+            Some(&(symbol_idx, existing)) => {
+                let symbol = ObjSymbol { sort_index: existing.sort_index, ..symbol };
+                obj.symbols.replace(symbol_idx, symbol)?;
+            }
+            None => {
+                obj.symbols.add_direct(symbol)?;
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Returns N for `addi r3, r3, -N`, where N > 0.
+fn this_adjustment(ins: u32) -> Option<u32> {
+    let simm = ins as u16 as i16;
+    (ins & 0xFFFF0000 == 0x38630000 && simm < 0).then(|| simm.unsigned_abs() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section_data, test_symbol, ObjArchitecture, ObjKind};
+
+    #[test]
+    fn test_detect_thunks() {
+        let mut code = vec![
+            0x38600001, // __dt__3FooFv: li r3, 1
+            0x4E800020, // blr
+            0x3863FFFC, // lbl_80003108: addi r3, r3, -4
+            0x4BFFFFF4, // b __dt__3FooFv
+            0x3863FFF8, // fn_80003110: addi r3, r3, -8
+            0x4BFFFFEC, // b __dt__3FooFv
+            0x4BFFFFF9, // fn_80003118: bl fn_80003110
+            0x4E800020, // blr
+        ];
+        code.extend(PTMF_SCALL);
+        code.extend([
+            0x3863FFFC, // lbl_80003148: addi r3, r3, -4
+            0x4BFFFFCC, // b fn_80003118
+        ]);
+        let data = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        let symbol = |name: &str, address, kind| test_symbol(name, Some(0), address, 8, kind);
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("__dt__3FooFv", 0x80003100, ObjSymbolKind::Function),
+                // Misclassified as data
+                symbol("lbl_80003108", 0x80003108, ObjSymbolKind::Object),
+                symbol("fn_80003110", 0x80003110, ObjSymbolKind::Function),
+                symbol("fn_80003118", 0x80003118, ObjSymbolKind::Function),
+                // Target is auto-named
+                symbol("lbl_80003148", 0x80003148, ObjSymbolKind::Object),
+            ],
+            vec![test_section_data(".text", ObjSectionKind::Code, 0x80003100, data)],
+        );
+        assert_eq!(detect_thunks(&mut obj).unwrap(), 2);
+        let functions = obj
+            .symbols
+            .by_kind(ObjSymbolKind::Function)
+            .map(|(_, s)| (s.name.as_str(), s.address, s.size))
+            .sorted_by_key(|&(_, address, _)| address)
+            .collect_vec();
+        assert_eq!(functions, [
+            ("__dt__3FooFv", 0x80003100, 0x8),
+            ("@4@__dt__3FooFv", 0x80003108, 0x8),
+            // Called directly, so it's a genuine function
+            ("fn_80003110", 0x80003110, 0x8),
+            ("fn_80003118", 0x80003118, 0x8),
+            ("__ptmf_scall", 0x80003120, 0x28),
+        ]);
+        assert!(obj.symbols.by_name("@4@__dt__3FooFv").unwrap().unwrap().1.flags.is_weak());
+        let (_, skipped) = obj.symbols.by_name("lbl_80003148").unwrap().unwrap();
+        assert_eq!(skipped.kind, ObjSymbolKind::Object);
+    }
+}
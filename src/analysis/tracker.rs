@@ -13,14 +13,17 @@ use crate::{
     analysis::{
         cfa::SectionAddress,
         executor::{ExecCbData, ExecCbResult, Executor},
+        progress::{progress_enabled, report_progress, ProgressPhase},
         relocation_target_for, uniq_jump_table_entries,
-        vm::{is_store_op, BranchTarget, GprValue, StepResult, VM},
+        vm::{is_paired_single_op, is_store_op, BranchTarget, GprValue, StepResult, VM},
         RelocationTarget,
     },
     obj::{
         ObjDataKind, ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind,
-        ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, SectionIndex, SymbolIndex,
+        ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, RelocationDiagnostics, SectionIndex,
+        SymbolIndex,
     },
+    util::config::auto_symbol_naming,
 };
 
 #[derive(Debug, Copy, Clone)]
@@ -60,6 +63,7 @@ pub enum DataKind {
     Byte,
     Float,
     Double,
+    PairedSingle,
     // String,
     // String16,
 }
@@ -211,16 +215,26 @@ impl Tracker {
             let entry_addr = SectionAddress::new(section_index, entry as u32);
             self.process_function_by_address(obj, entry_addr)?;
         }
+        let total = if progress_enabled() {
+            obj.symbols.iter().filter(|(_, symbol)| symbol.is_code() && symbol.size_known).count()
+        } else {
+            0
+        };
         for (section_index, _) in obj.sections.by_kind(ObjSectionKind::Code) {
             for (_, symbol) in obj
                 .symbols
                 .for_section(section_index)
-                .filter(|(_, symbol)| symbol.kind == ObjSymbolKind::Function && symbol.size_known)
+                .filter(|(_, symbol)| symbol.is_code() && symbol.size_known)
             {
                 let addr = SectionAddress::new(section_index, symbol.address as u32);
                 if !self.processed_functions.insert(addr) {
                     continue;
                 }
+                report_progress(
+                    ProgressPhase::RelocationAnalysis,
+                    self.processed_functions.len(),
+                    total,
+                );
                 self.process_function(obj, symbol)?;
             }
         }
@@ -235,7 +249,7 @@ impl Tracker {
         if let Some((_, symbol)) = obj
             .symbols
             .at_section_address(addr.section, addr.address)
-            .find(|(_, symbol)| symbol.kind == ObjSymbolKind::Function && symbol.size_known)
+            .find(|(_, symbol)| symbol.is_code() && symbol.size_known)
         {
             self.process_function(obj, symbol)?;
         } else {
@@ -341,7 +355,10 @@ impl Tracker {
                 Ok(ExecCbResult::Continue)
             }
             StepResult::LoadStore { address, source, source_reg } => {
-                if !obj.blocked_relocation_sources.contains(ins_addr) {
+                // The 12-bit displacement of paired-single ops can't be relocated
+                if !obj.blocked_relocation_sources.contains(ins_addr)
+                    && (source.lo_addr.is_some() || !is_paired_single_op(ins.op))
+                {
                     if (source_reg == 2
                         && matches!(self.sda2_base, Some(v) if source.value == GprValue::Constant(v)))
                         || (source_reg == 13
@@ -658,8 +675,18 @@ impl Tracker {
             .or_else(|| check_symbol(self.sda_base, "_SDA_BASE_"))
     }
 
-    #[instrument(name = "apply", skip(self, obj))]
     pub fn apply(&self, obj: &mut ObjInfo, replace: bool) -> Result<()> {
+        self.apply_with_diagnostics(obj, replace, None)
+    }
+
+    /// Applies relocations, recording targets without a matching symbol into `diagnostics`.
+    #[instrument(name = "apply", skip(self, obj, diagnostics))]
+    pub fn apply_with_diagnostics(
+        &self,
+        obj: &mut ObjInfo,
+        replace: bool,
+        mut diagnostics: Option<&mut RelocationDiagnostics>,
+    ) -> Result<()> {
         fn apply_section_name(section: &mut ObjSection, name: &str) {
             let module_id = if let Some((_, b)) = section.name.split_once(':') {
                 b.parse::<u32>().unwrap_or(0)
@@ -672,6 +699,23 @@ impl Tracker {
             section.name = new_name;
         }
 
+        // TLS isn't supported, so references to it can't be relocated correctly
+        let mut tls_references = BTreeMap::<SectionIndex, usize>::new();
+        for addr in &self.hal_to {
+            if obj.sections.get(addr.section).is_some_and(|s| s.thread_local) {
+                *tls_references.entry(addr.section).or_default() += 1;
+            }
+        }
+        for (section_index, count) in tls_references {
+            log::warn!(
+                "Found {} @ha/@l reference(s) to thread-local section {}, \
+                TLS relocations are not supported",
+                count,
+                obj.sections[section_index].name
+            );
+        }
+
+        let naming = auto_symbol_naming();
         for (section_index, section) in obj.sections.iter_mut() {
             if !section.section_known {
                 if section.kind == ObjSectionKind::Code {
@@ -706,7 +750,10 @@ impl Tracker {
             }
         }
 
-        for (&addr, reloc) in &self.relocations {
+        for (i, (&addr, reloc)) in self.relocations.iter().enumerate() {
+            if i % 1024 == 0 {
+                report_progress(ProgressPhase::RelocationResolution, i, self.relocations.len());
+            }
             let Some((reloc_kind, target)) = reloc.kind_and_address() else {
                 // Skip external relocations, they already exist
                 continue;
@@ -743,15 +790,18 @@ impl Tracker {
                     DataKind::Byte => ObjDataKind::Byte,
                     DataKind::Float => ObjDataKind::Float,
                     DataKind::Double => ObjDataKind::Double,
+                    DataKind::PairedSingle => ObjDataKind::PairedSingle,
                 })
                 .unwrap_or_default();
             let (target_symbol, addend) = if let Some(symbol) =
                 self.special_symbol(obj, target.address, reloc_kind)
             {
                 (symbol, 0)
-            } else if let Some((symbol_idx, symbol)) =
-                obj.symbols.for_relocation(target, reloc_kind)?
-            {
+            } else if let Some((symbol_idx, symbol)) = obj.symbols.for_relocation(
+                target,
+                reloc_kind,
+                obj.sections.get(target.section).is_some_and(|s| s.is_small_data()),
+            )? {
                 let symbol_address = symbol.address;
                 // TODO meh
                 if data_kind != ObjDataKind::Unknown
@@ -762,16 +812,15 @@ impl Tracker {
                 }
                 (symbol_idx, target.address as i64 - symbol_address as i64)
             } else {
+                if let Some(diagnostics) = diagnostics.as_deref_mut() {
+                    diagnostics.record(&obj.symbols, target, reloc_kind);
+                }
                 // Create a new label
-                let name = if obj.module_id == 0 {
-                    format!("lbl_{:08X}", target.address)
+                let target_section = &obj.sections[target.section];
+                let name = if target_section.kind == ObjSectionKind::Code {
+                    naming.label_name(obj.module_id, &target_section.name, target.address)
                 } else {
-                    format!(
-                        "lbl_{}_{}_{:X}",
-                        obj.module_id,
-                        obj.sections[target.section].name.trim_start_matches('.'),
-                        target.address
-                    )
+                    naming.data_name(obj.module_id, &target_section.name, target.address)
                 };
                 let symbol_idx = obj.symbols.add_direct(ObjSymbol {
                     name,
@@ -807,6 +856,8 @@ impl Tracker {
                 }
             }
         }
+        let total = self.relocations.len();
+        report_progress(ProgressPhase::RelocationResolution, total, total);
         Ok(())
     }
 }
@@ -838,6 +889,14 @@ fn data_kind_from_op(op: Opcode) -> DataKind {
         Opcode::Lwzu => DataKind::Word,
         Opcode::Lwzux => DataKind::Word,
         Opcode::Lwzx => DataKind::Word,
+        Opcode::PsqL => DataKind::PairedSingle,
+        Opcode::PsqLu => DataKind::PairedSingle,
+        Opcode::PsqLux => DataKind::PairedSingle,
+        Opcode::PsqLx => DataKind::PairedSingle,
+        Opcode::PsqSt => DataKind::PairedSingle,
+        Opcode::PsqStu => DataKind::PairedSingle,
+        Opcode::PsqStux => DataKind::PairedSingle,
+        Opcode::PsqStx => DataKind::PairedSingle,
         Opcode::Stb => DataKind::Byte,
         Opcode::Stbu => DataKind::Byte,
         Opcode::Stbux => DataKind::Byte,
@@ -879,3 +938,49 @@ fn generate_special_symbol(obj: &mut ObjInfo, addr: u32, name: &str) -> Result<S
         true,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section_data, test_symbol, ObjArchitecture, ObjSymbolKind};
+
+    #[test]
+    fn test_paired_single_data_kind() {
+        let code: [u32; 4] = [
+            0x3C608000, // lis r3, lbl_80004000@ha
+            0x38634000, // addi r3, r3, lbl_80004000@l
+            0xE0230000, // psq_l f1, 0(r3), 0, qr0
+            0x4E800020, // blr
+        ];
+        let text = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        let section = |name: &str, kind, address, data: Vec<u8>| ObjSection {
+            align: 8,
+            virtual_address: Some(address),
+            ..test_section_data(name, kind, address, data)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![test_symbol("fn_80003100", Some(0), 0x80003100, 0x10, ObjSymbolKind::Function)],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003100, text),
+                section(".rodata", ObjSectionKind::ReadOnlyData, 0x80004000, vec![0x3F; 8]),
+            ],
+        );
+
+        let mut tracker = Tracker::new(&obj);
+        tracker.process(&obj).unwrap();
+        tracker.apply(&mut obj, false).unwrap();
+
+        let (_, symbol) = obj.symbols.at_section_address(1, 0x80004000).next().unwrap();
+        assert_eq!(symbol.data_kind, ObjDataKind::PairedSingle);
+        // The 12-bit displacement of psq_l itself isn't relocated
+        let relocations =
+            obj.sections[0].relocations.iter().map(|(a, r)| (a, r.kind)).collect::<Vec<_>>();
+        assert_eq!(relocations, [
+            (0x80003100, ObjRelocKind::PpcAddr16Ha),
+            (0x80003104, ObjRelocKind::PpcAddr16Lo),
+        ]);
+    }
+}
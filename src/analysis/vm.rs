@@ -544,7 +544,7 @@ impl VM {
                         source_reg: source as u8,
                     };
                 } else if let GprValue::Constant(base) = self.gpr[source].value {
-                    let address = base.wrapping_add(ins.field_simm() as u32);
+                    let address = base.wrapping_add(load_store_offset(&ins) as u32);
                     if let Some(target) = section_address_for(obj, ins_addr, address) {
                         if is_update_op(op) {
                             self.gpr[source].set_lo(
@@ -699,7 +699,10 @@ pub fn is_load_op(op: Opcode) -> bool {
 
 #[inline]
 pub fn is_loadf_op(op: Opcode) -> bool {
-    matches!(op, Opcode::Lfd | Opcode::Lfdu | Opcode::Lfs | Opcode::Lfsu)
+    matches!(
+        op,
+        Opcode::Lfd | Opcode::Lfdu | Opcode::Lfs | Opcode::Lfsu | Opcode::PsqL | Opcode::PsqLu
+    )
 }
 
 #[inline]
@@ -718,7 +721,31 @@ pub fn is_store_op(op: Opcode) -> bool {
 
 #[inline]
 pub fn is_storef_op(op: Opcode) -> bool {
-    matches!(op, Opcode::Stfd | Opcode::Stfdu | Opcode::Stfs | Opcode::Stfsu)
+    matches!(
+        op,
+        Opcode::Stfd
+            | Opcode::Stfdu
+            | Opcode::Stfs
+            | Opcode::Stfsu
+            | Opcode::PsqSt
+            | Opcode::PsqStu
+    )
+}
+
+/// Paired-single loads and stores, which only have a 12-bit displacement.
+#[inline]
+pub fn is_paired_single_op(op: Opcode) -> bool {
+    matches!(op, Opcode::PsqL | Opcode::PsqLu | Opcode::PsqSt | Opcode::PsqStu)
+}
+
+/// The displacement of a D-form load or store.
+#[inline]
+fn load_store_offset(ins: &Ins) -> i32 {
+    if is_paired_single_op(ins.op) {
+        ((ins.code as i32) << 20) >> 20
+    } else {
+        ins.field_simm() as i32
+    }
 }
 
 #[inline]
@@ -742,6 +769,8 @@ pub fn is_update_op(op: Opcode) -> bool {
             | Opcode::Lhzux
             | Opcode::Lwzu
             | Opcode::Lwzux
+            | Opcode::PsqLu
+            | Opcode::PsqStu
             | Opcode::Stbu
             | Opcode::Stbux
             | Opcode::Stfdu
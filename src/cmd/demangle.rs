@@ -1,20 +1,37 @@
-use anyhow::{anyhow, Result};
+use std::io::{stdin, stdout, BufRead, Write};
+
+use anyhow::Result;
 use argp::FromArgs;
-use cwdemangle::{demangle, DemangleOptions};
+use cwdemangle::DemangleOptions;
+use serde::Serialize;
+
+use crate::util::demangle::demangle;
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
-/// Demangle a CodeWarrior C++ symbol.
+/// Demangle CodeWarrior C++ symbols.
 #[argp(subcommand, name = "demangle")]
 pub struct Args {
     #[argp(positional)]
-    /// symbol to demangle
-    symbol: String,
+    /// symbols to demangle (default: one per line from stdin)
+    symbols: Vec<String>,
     #[argp(switch)]
     /// disable replacing `(void)` with `()`
     keep_void: bool,
     /// enable Metrowerks extensions
     #[argp(switch)]
     mw_extensions: bool,
+    #[argp(switch)]
+    /// print a JSON array of {mangled, demangled} pairs
+    json: bool,
+}
+
+/// Appended to names that couldn't be demangled, which are printed as-is.
+const NOT_DEMANGLED: &str = "(not demangled)";
+
+#[derive(Serialize)]
+struct DemangledName<'a> {
+    mangled: &'a str,
+    demangled: Option<String>,
 }
 
 pub fn run(args: Args) -> Result<()> {
@@ -22,11 +39,83 @@ pub fn run(args: Args) -> Result<()> {
         omit_empty_parameters: !args.keep_void,
         mw_extensions: args.mw_extensions,
     };
-    match demangle(args.symbol.as_str(), &options) {
-        Some(symbol) => {
-            println!("{symbol}");
-            Ok(())
+    let symbols =
+        if args.symbols.is_empty() { read_names(&mut stdin().lock())? } else { args.symbols };
+    write_demangled(&mut stdout().lock(), &symbols, &options, args.json)
+}
+
+/// Reads one name per line, skipping blank lines.
+fn read_names<R>(r: &mut R) -> Result<Vec<String>>
+where R: BufRead + ?Sized {
+    let mut names = vec![];
+    for line in r.lines() {
+        let line = line?;
+        let name = line.trim();
+        if !name.is_empty() {
+            names.push(name.to_string());
+        }
+    }
+    Ok(names)
+}
+
+fn write_demangled<W>(
+    w: &mut W,
+    names: &[String],
+    options: &DemangleOptions,
+    json: bool,
+) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    if json {
+        let pairs = names
+            .iter()
+            .map(|name| DemangledName { mangled: name, demangled: demangle(name, options) })
+            .collect::<Vec<_>>();
+        serde_json::to_writer_pretty(&mut *w, &pairs)?;
+        writeln!(w)?;
+        return Ok(());
+    }
+    for name in names {
+        match demangle(name, options) {
+            Some(demangled) => writeln!(w, "{demangled}")?,
+            None => writeln!(w, "{name} {NOT_DEMANGLED}")?,
         }
-        None => Err(anyhow!("Failed to demangle symbol")),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_demangled() {
+        let input = "BuildLight__9CGuiLightCFv\n\n  __ct__3FooFv\nmain\n";
+        let names = read_names(&mut input.as_bytes()).unwrap();
+        assert_eq!(names, ["BuildLight__9CGuiLightCFv", "__ct__3FooFv", "main"]);
+        let options = DemangleOptions { omit_empty_parameters: true, mw_extensions: false };
+        let write = |json| {
+            let mut out = vec![];
+            write_demangled(&mut out, &names, &options, json).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            write(false),
+            "CGuiLight::BuildLight() const\nFoo::Foo()\nmain (not demangled)\n"
+        );
+        let value: serde_json::Value = serde_json::from_str(&write(true)).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!([
+                {
+                    "mangled": "BuildLight__9CGuiLightCFv",
+                    "demangled": "CGuiLight::BuildLight() const",
+                },
+                { "mangled": "__ct__3FooFv", "demangled": "Foo::Foo()" },
+                { "mangled": "main", "demangled": null },
+            ])
+        );
     }
 }
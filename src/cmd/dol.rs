@@ -6,57 +6,74 @@ use std::{
     io::{Cursor, Seek, Write},
     mem::take,
     str::FromStr,
+    sync::Arc,
     time::Instant,
 };
 
 use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
-use cwdemangle::demangle;
 use itertools::Itertools;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use tracing::{debug, info, info_span};
 use typed_path::{Utf8NativePath, Utf8NativePathBuf, Utf8UnixPath, Utf8UnixPathBuf};
 use xxhash_rust::xxh3::xxh3_64;
 
 use crate::{
     analysis::{
+        cache::{analyze_cached, cache_key},
         cfa::{AnalyzerState, SectionAddress},
-        objects::{detect_objects, detect_strings},
+        extab::create_extab_symbols,
+        frame::detect_frame_sizes,
+        objects::{
+            detect_data_arrays, detect_objects, detect_pointer_tables, detect_strings,
+            infer_symbol_alignment,
+        },
         pass::{
             AnalysisPass, FindRelCtorsDtors, FindRelRodataData, FindSaveRestSleds,
             FindTRKInterruptVectorTable,
         },
         signatures::{apply_signatures, apply_signatures_post, update_ctors_dtors},
+        tail_calls::split_tail_calls,
+        thunks::detect_thunks,
         tracker::Tracker,
     },
     cmd::shasum::file_sha1_string,
     obj::{
-        best_match_for_reloc, ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSectionKind, ObjSymbol,
-        ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, ObjSymbolScope, SectionIndex, SymbolIndex,
+        default_section_aliases, ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSectionKind,
+        ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, ObjSymbolScope, RelocTieBreak,
+        RelocationDiagnostics, SectionIndex, SymbolIndex,
     },
     util::{
         asm::write_asm,
         bin2c::{bin2c, HeaderKind},
         comment::MWComment,
         config::{
-            apply_splits_file, apply_symbols_file, is_auto_symbol, signed_hex_serde,
-            write_splits_file, write_symbols_file, SectionAddressRef,
+            apply_relocation_ignore_rules, apply_splits_file, apply_symbols_file,
+            auto_symbol_naming, is_auto_symbol, parse_u32, set_auto_symbol_naming,
+            signed_hex_serde, write_splits_file, write_symbols_file, AutoSymbolNaming,
+            RelocationIgnoreRule, SectionAddressRef, SymbolSortMode,
         },
+        demangle::demangle,
         dep::DepFile,
         diff::{calc_diff_ranges, print_diff, process_code},
         dol::process_dol,
-        elf::{process_elf, write_elf},
+        elf::{process_elf, verify_elf, write_elf},
         file::{
             buf_copy_with_hash, buf_writer, check_hash_str, touch, verify_hash, FileIterator,
             FileReadInfo,
         },
-        lcf::{asm_path_for_unit, generate_ldscript, obj_path_for_unit},
+        lcf::{asm_path_for_unit, bin_path_for_unit, generate_ldscript, obj_path_for_unit},
         map::apply_map_file,
+        objdiff::apply_report_file,
         path::{check_path_buf, native_path},
         rel::{process_rel, process_rel_header, update_rel_section_alignment},
         rso::{process_rso, DOL_SECTION_ABS, DOL_SECTION_ETI, DOL_SECTION_NAMES},
-        split::{is_linker_generated_object, split_obj, update_splits},
+        split::{
+            is_linker_generated_object, split_by_function, split_obj, update_splits, PaddingStyle,
+        },
+        symbol_list::apply_symbol_list_file,
         IntoCow, ToCow,
     },
     vfs::{open_file, open_file_with_fs, open_fs, ArchiveKind, Vfs, VfsFile},
@@ -108,6 +125,34 @@ pub struct SplitArgs {
     #[argp(option, short = 'j')]
     /// number of threads to use (default: number of logical CPUs)
     jobs: Option<usize>,
+    #[argp(switch)]
+    /// always reanalyze the DOL instead of loading cached results
+    no_cache: bool,
+    #[argp(switch)]
+    /// report relocation targets without a matching symbol
+    reloc_diagnostics: bool,
+    #[argp(option, from_str_fn(parse_split_address))]
+    /// split the DOL function containing this address into two (repeatable)
+    split_at: Vec<u32>,
+    #[argp(option)]
+    /// symbols.txt ordering: address, name, section or stable (overrides config)
+    sort: Option<SymbolSortMode>,
+    #[argp(switch)]
+    /// emit one object per .init/.text function (overrides config)
+    one_unit_per_function: bool,
+    #[argp(switch)]
+    /// recompute all demangled symbol names (overrides config)
+    force_demangle: bool,
+    #[argp(switch)]
+    /// split functions merged across a tail call (overrides config)
+    split_tail_calls: bool,
+    #[argp(switch)]
+    /// re-read written objects and check them against the in-memory model
+    verify: bool,
+}
+
+fn parse_split_address(value: &str) -> Result<u32, String> {
+    parse_u32(value).map_err(|e| format!("Invalid address '{value}': {e}"))
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -224,6 +269,18 @@ pub struct ProjectConfig {
     /// and instead assumes that all symbols are known.
     #[serde(default, skip_serializing_if = "is_default")]
     pub symbols_known: bool,
+    /// Merges adjacent objects of the same data kind into arrays when only the first
+    /// element is referenced.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub detect_data_arrays: bool,
+    /// Classifies runs of consecutive absolute pointers in data sections as pointer tables.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub detect_pointer_tables: bool,
+    /// Sets a tentative alignment on symbols without one, based on their address.
+    /// Capped at 8 for data and 4 for code. Explicit alignment is never changed, and inferred
+    /// alignment only applies to the split objects; it isn't written to symbols.txt.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub infer_symbol_alignment: bool,
     /// Fills gaps between symbols to avoid linker realignment.
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub fill_gaps: bool,
@@ -237,6 +294,44 @@ pub struct ProjectConfig {
     /// will be used from the disc image directly without extraction.
     #[serde(default = "bool_true", skip_serializing_if = "is_true")]
     pub extract_objects: bool,
+    /// Naming scheme for auto-generated symbols.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub auto_symbol_naming: AutoSymbolNaming,
+    /// Ordering of symbols in symbols.txt.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub symbol_sort: SymbolSortMode,
+    /// Renames input sections to canonical names on load, e.g. `.rodata1: .rodata`.
+    /// Extends the built-in aliases; map a name to itself to disable a built-in alias.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub section_aliases: BTreeMap<String, String>,
+    /// Emits one object per `.init`/`.text` function, along with the data only it references.
+    /// The splits file is not affected.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub one_unit_per_function: bool,
+    /// Recomputes demangled names for all symbols, rather than only those missing one.
+    /// Useful after demangler improvements.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub force_demangle: bool,
+    /// Splits functions that analysis merged across a tail call, where a `b` out of the function
+    /// is followed by an address branched to from elsewhere. Can misfire on internal branches.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub split_tail_calls: bool,
+    /// Types `__ptmf_scall` stubs and this-adjusting thunks as functions and names the thunks
+    /// `@N@target`. Thunks are retyped and made weak, and are skipped while the target is unnamed.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub detect_thunks: bool,
+    /// Caps functions discovered by control flow analysis at this size, in case analysis runs
+    /// into data. Defaults to 0x10000.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub max_function_size: Option<u32>,
+    /// Symbol size differences that disappear when both sizes are rounded up to this alignment
+    /// aren't reported as conflicts. Defaults to 4.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub size_conflict_align: Option<u32>,
+    /// Chooses between symbols at a relocation target that are otherwise ranked equally:
+    /// `first` (default), `largest` or `smallest`.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub reloc_tie_break: RelocTieBreak,
 }
 
 impl Default for ProjectConfig {
@@ -253,14 +348,36 @@ impl Default for ProjectConfig {
             write_asm: true,
             common_start: None,
             symbols_known: false,
+            detect_data_arrays: false,
+            detect_pointer_tables: false,
+            infer_symbol_alignment: false,
             fill_gaps: true,
             export_all: true,
             object_base: None,
             extract_objects: true,
+            auto_symbol_naming: Default::default(),
+            symbol_sort: Default::default(),
+            section_aliases: Default::default(),
+            one_unit_per_function: false,
+            force_demangle: false,
+            split_tail_calls: false,
+            detect_thunks: false,
+            max_function_size: None,
+            size_conflict_align: None,
+            reloc_tie_break: Default::default(),
         }
     }
 }
 
+impl ProjectConfig {
+    /// The built-in section aliases, extended by the configured ones.
+    pub fn section_aliases(&self) -> BTreeMap<String, String> {
+        let mut aliases = default_section_aliases();
+        aliases.extend(self.section_aliases.iter().map(|(k, v)| (k.clone(), v.clone())));
+        aliases
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct ModuleConfig {
     /// Object name. If not specified, the file name without extension will be used.
@@ -276,6 +393,13 @@ pub struct ModuleConfig {
     pub symbols: Option<Utf8UnixPathBuf>,
     #[serde(with = "unix_path_serde_option", default, skip_serializing_if = "is_default")]
     pub map: Option<Utf8UnixPathBuf>,
+    /// objdiff report to seed function names from. Names already known are kept.
+    #[serde(with = "unix_path_serde_option", default, skip_serializing_if = "is_default")]
+    pub objdiff_report: Option<Utf8UnixPathBuf>,
+    /// Plain text list of `address name [size] [kind]` lines to seed symbol names from.
+    /// Names already known are kept.
+    #[serde(with = "unix_path_serde_option", default, skip_serializing_if = "is_default")]
+    pub symbol_list: Option<Utf8UnixPathBuf>,
     /// Forces the given symbols to be active (exported) in the linker script.
     #[serde(default, skip_serializing_if = "is_default")]
     pub force_active: Vec<String>,
@@ -290,6 +414,13 @@ pub struct ModuleConfig {
     pub block_relocations: Vec<BlockRelocationConfig>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub add_relocations: Vec<AddRelocationConfig>,
+    /// Marks symbols matching any rule as never targeted by relocations, e.g. imported padding.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relocation_ignore: Vec<RelocationIgnoreRule>,
+    /// How the original compiler padded gaps between symbols, used when filling gaps.
+    /// Detected from the `.comment` section by default.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub padding_style: PaddingStyle,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -481,12 +612,16 @@ fn apply_selfile(obj: &mut ObjInfo, buf: &[u8]) -> Result<()> {
                 section,
                 size: existing_symbol.size,
                 size_known: existing_symbol.size_known,
-                flags: ObjSymbolFlagSet(existing_symbol.flags.0 | ObjSymbolFlags::Exported),
+                flags: ObjSymbolFlagSet(existing_symbol.flags.0 | ObjSymbolFlags::ForceActive),
                 kind: existing_symbol.kind,
                 align: existing_symbol.align,
                 data_kind: existing_symbol.data_kind,
                 name_hash: existing_symbol.name_hash,
                 demangled_name_hash: existing_symbol.demangled_name_hash,
+                source_file: existing_symbol.source_file.clone(),
+                source_line: existing_symbol.source_line,
+                notes: existing_symbol.notes.clone(),
+                sort_index: existing_symbol.sort_index,
             })?;
         } else {
             log::debug!("Creating symbol {} at {:#010X}", symbol.name, address);
@@ -496,8 +631,8 @@ fn apply_selfile(obj: &mut ObjInfo, buf: &[u8]) -> Result<()> {
                     demangled_name: symbol.demangled_name.clone(),
                     address: address as u64,
                     section,
-                    flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::Exported),
-                    ..*symbol
+                    flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive),
+                    ..symbol.clone()
                 },
                 false,
             )?;
@@ -570,7 +705,7 @@ pub fn info(args: InfoArgs) -> Result<()> {
     Ok(())
 }
 
-struct ModuleInfo<'a> {
+pub(crate) struct ModuleInfo<'a> {
     obj: ObjInfo,
     config: &'a ModuleConfig,
     symbols_cache: Option<FileReadInfo>,
@@ -578,9 +713,9 @@ struct ModuleInfo<'a> {
 }
 
 type ModuleMapByName<'a> = BTreeMap<String, ModuleInfo<'a>>;
-type ModuleMapById<'a> = BTreeMap<u32, &'a ModuleInfo<'a>>;
+pub(crate) type ModuleMapById<'a> = BTreeMap<u32, &'a ModuleInfo<'a>>;
 
-fn update_symbols(
+pub(crate) fn update_symbols(
     obj: &mut ObjInfo,
     modules: &[&ModuleInfo<'_>],
     create_symbols: bool,
@@ -630,6 +765,7 @@ fn update_symbols(
         if let Some((symbol_index, symbol)) = obj.symbols.for_relocation(
             SectionAddress::new(target_section_index, rel_reloc.addend),
             rel_reloc.kind,
+            target_section.is_small_data(),
         )? {
             // Update symbol
             log::trace!(
@@ -638,7 +774,11 @@ fn update_symbols(
                 rel_reloc.addend,
                 symbol.name
             );
-            obj.symbols.flags(symbol_index).set_force_active(true);
+            let flags = obj.symbols.flags(symbol_index);
+            flags.set_force_active(true);
+            if source_module_id != obj.module_id {
+                flags.set_exported(true);
+            }
         } else if create_symbols {
             // Add label
             log::trace!(
@@ -646,21 +786,21 @@ fn update_symbols(
                 rel_reloc.target_section,
                 rel_reloc.addend
             );
-            let name = if obj.module_id == 0 {
-                format!("lbl_{:08X}", rel_reloc.addend)
+            let naming = auto_symbol_naming();
+            let name = if target_section.kind == ObjSectionKind::Code {
+                naming.label_name(obj.module_id, &target_section.name, rel_reloc.addend)
             } else {
-                format!(
-                    "lbl_{}_{}_{:X}",
-                    obj.module_id,
-                    target_section.name.trim_start_matches('.'),
-                    rel_reloc.addend
-                )
+                naming.data_name(obj.module_id, &target_section.name, rel_reloc.addend)
             };
+            let mut flags = ObjSymbolFlagSet(ObjSymbolFlags::ForceActive.into());
+            if source_module_id != obj.module_id {
+                flags.set_exported(true);
+            }
             obj.symbols.add_direct(ObjSymbol {
                 name,
                 address: rel_reloc.addend as u64,
                 section: Some(target_section_index),
-                flags: ObjSymbolFlagSet(ObjSymbolFlags::Exported.into()),
+                flags,
                 ..Default::default()
             })?;
         }
@@ -669,7 +809,7 @@ fn update_symbols(
     Ok(())
 }
 
-fn create_relocations(
+pub(crate) fn create_relocations(
     obj: &mut ObjInfo,
     modules: &ModuleMapById<'_>,
     dol_obj: &ObjInfo,
@@ -703,7 +843,7 @@ fn create_relocations(
                 .obj
         };
 
-        let (target_section_index, _target_section) = if rel_reloc.module_id == 0 {
+        let (target_section_index, target_section) = if rel_reloc.module_id == 0 {
             target_obj.sections.at_address(rel_reloc.addend).map_err(|_| {
                 anyhow!("Failed to locate DOL section at {:#010X}", rel_reloc.addend)
             })?
@@ -719,9 +859,10 @@ fn create_relocations(
             )?
         };
 
-        let Some((symbol_index, symbol)) = target_obj.symbols.for_relocation(
+        let Some((symbol_index, addend)) = target_obj.symbols.resolve_relocation(
             SectionAddress::new(target_section_index, rel_reloc.addend),
             rel_reloc.kind,
+            target_section.is_small_data(),
         )?
         else {
             bail!(
@@ -737,7 +878,7 @@ fn create_relocations(
         let reloc = ObjReloc {
             kind: rel_reloc.kind,
             target_symbol: symbol_index,
-            addend: rel_reloc.addend as i64 - symbol.address as i64,
+            addend,
             module: if rel_reloc.module_id == obj.module_id {
                 None
             } else {
@@ -814,26 +955,41 @@ struct AnalyzeResult {
     splits_cache: Option<FileReadInfo>,
 }
 
-fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<AnalyzeResult> {
+fn load_analyze_dol(
+    config: &ProjectConfig,
+    object_base: &ObjectBase,
+    cache_path: Option<&Utf8NativePath>,
+) -> Result<AnalyzeResult> {
     let object_path = object_base.join(&config.base.object);
     log::debug!("Loading {}", object_path);
-    let mut obj = {
+    let (mut obj, input_hash) = {
         let mut file = object_base.open(&config.base.object)?;
         let data = file.map()?;
         if let Some(hash_str) = &config.base.hash {
             verify_hash(data, hash_str)?;
         }
-        process_dol(data, config.base.name())?
+        (process_dol(data, config.base.name())?, Sha1::digest(data))
     };
     let mut dep = vec![object_path];
+    if let Some(align) = config.size_conflict_align {
+        obj.symbols.set_size_conflict_align(align);
+    }
+    obj.symbols.set_reloc_resolver(Arc::new(config.reloc_tie_break));
 
     if let Some(comment_version) = config.mw_comment_version {
         obj.mw_comment = Some(MWComment::new(comment_version)?);
     }
 
+    apply_section_aliases(&mut obj, config)?;
     if let Some(map_path) = &config.base.map {
         let map_path = map_path.with_encoding();
-        apply_map_file(&map_path, &mut obj, config.common_start, config.mw_comment_version)?;
+        apply_map_file(
+            &map_path,
+            &mut obj,
+            config.common_start,
+            config.mw_comment_version,
+            &config.section_aliases(),
+        )?;
         dep.push(map_path);
     }
 
@@ -855,24 +1011,55 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
         None
     };
 
+    if let Some(report_path) = &config.base.objdiff_report {
+        let report_path = report_path.with_encoding();
+        let count = apply_report_file(&report_path, &mut obj)?;
+        info!("Applied {} function names from {}", count, report_path);
+        dep.push(report_path);
+    }
+
+    if let Some(list_path) = &config.base.symbol_list {
+        let list_path = list_path.with_encoding();
+        let result = apply_symbol_list_file(&list_path, &mut obj)?;
+        for (line, address) in &result.unresolved {
+            log::warn!("{}:{}: {:#010X} is outside of any section", list_path, line, address);
+        }
+        info!("Applied {} symbol names from {}", result.applied, list_path);
+        dep.push(list_path);
+    }
+
+    apply_relocation_ignore(&mut obj, &config.base.relocation_ignore)?;
+
     // Apply block relocations from config
     apply_block_relocations(&mut obj, &config.base.block_relocations)?;
 
     if !config.symbols_known {
-        // TODO move before symbols?
-        debug!("Performing signature analysis");
-        apply_signatures(&mut obj)?;
-
-        if !config.quick_analysis {
-            let mut state = AnalyzerState::default();
-            debug!("Detecting function boundaries");
-            FindSaveRestSleds::execute(&mut state, &obj)?;
-            state.detect_functions(&obj)?;
-            FindTRKInterruptVectorTable::execute(&mut state, &obj)?;
-            state.apply(&mut obj)?;
-        }
+        let key = match cache_path {
+            Some(_) => {
+                let config_str = serde_yaml::to_string(config)?;
+                cache_key(&[&input_hash[..], config_str.as_bytes()], &obj)?
+            }
+            None => String::new(),
+        };
+        analyze_cached(cache_path.map(|path| (path, key.as_str())), &mut obj, |obj| {
+            // TODO move before symbols?
+            debug!("Performing signature analysis");
+            apply_signatures(obj)?;
+
+            if !config.quick_analysis {
+                let mut state = AnalyzerState::default();
+                if let Some(max_function_size) = config.max_function_size {
+                    state.max_function_size = max_function_size;
+                }
+                debug!("Detecting function boundaries");
+                FindSaveRestSleds::execute(&mut state, obj)?;
+                state.detect_functions(obj)?;
+                FindTRKInterruptVectorTable::execute(&mut state, obj)?;
+                state.apply(obj)?;
+            }
 
-        apply_signatures_post(&mut obj)?;
+            apply_signatures_post(obj)
+        })?;
     }
 
     if let Some(selfile) = &config.selfile {
@@ -887,6 +1074,22 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
         dep.push(selfile_path);
     }
 
+    if config.split_tail_calls {
+        let count = split_tail_calls(&mut obj)?;
+        debug!("Split {} functions merged across a tail call", count);
+    }
+
+    if config.detect_thunks {
+        let count = detect_thunks(&mut obj)?;
+        debug!("Detected {} thunks", count);
+    }
+
+    let count = detect_frame_sizes(&mut obj)?;
+    debug!("Detected stack frame sizes for {} functions", count);
+
+    let count = obj.symbols.demangle_all(config.force_demangle);
+    debug!("Demangled {} symbol names", count);
+
     // Create _ctors and _dtors symbols if missing
     update_ctors_dtors(&mut obj)?;
 
@@ -896,19 +1099,60 @@ fn load_analyze_dol(config: &ProjectConfig, object_base: &ObjectBase) -> Result<
     Ok(AnalyzeResult { obj, dep, symbols_cache, splits_cache })
 }
 
+fn apply_section_aliases(obj: &mut ObjInfo, config: &ProjectConfig) -> Result<()> {
+    let count = obj.apply_section_aliases(&config.section_aliases())?;
+    if count > 0 {
+        debug!("Renamed {} sections using section aliases", count);
+    }
+    Ok(())
+}
+
+/// Splits the function containing `address`, see [`crate::obj::ObjSymbols::split_function`].
+fn split_function_at(obj: &mut ObjInfo, address: u32) -> Result<()> {
+    let (section_index, _) = obj.sections.at_address(address)?;
+    let Some((symbol_idx, symbol)) = obj
+        .symbols
+        .for_section_range(section_index, ..address)
+        .rev()
+        .find(|(_, s)| s.is_code() && s.address + s.size > address as u64)
+    else {
+        bail!("No function contains {:#010X}", address);
+    };
+    let name = symbol.name.clone();
+    let new_idx = obj.symbols.split_function(symbol_idx, address, obj.module_id)?;
+    info!("Split {} at {:#010X}, created {}", name, address, obj.symbols[new_idx].name);
+    Ok(())
+}
+
 fn split_write_obj(
     module: &mut ModuleInfo,
     config: &ProjectConfig,
     base_dir: &Utf8NativePath,
     out_dir: &Utf8NativePath,
     no_update: bool,
+    reloc_diagnostics: bool,
+    verify: bool,
 ) -> Result<OutputModule> {
     debug!("Performing relocation analysis");
     let mut tracker = Tracker::new(&module.obj);
     tracker.process(&module.obj)?;
 
     debug!("Applying relocations");
-    tracker.apply(&mut module.obj, false)?;
+    let mut diagnostics = RelocationDiagnostics::default();
+    tracker.apply_with_diagnostics(
+        &mut module.obj,
+        false,
+        reloc_diagnostics.then_some(&mut diagnostics),
+    )?;
+    diagnostics.report(&module.obj.name);
+    if let Err(e) = module.obj.check_sda21_relocations() {
+        log::warn!("{}: {:#}", module.obj.name, e);
+    }
+
+    if !config.symbols_known && config.detect_pointer_tables {
+        debug!("Detecting pointer tables");
+        detect_pointer_tables(&mut module.obj)?;
+    }
 
     if !config.symbols_known && config.detect_objects {
         debug!("Detecting object boundaries");
@@ -920,18 +1164,29 @@ fn split_write_obj(
         detect_strings(&mut module.obj)?;
     }
 
+    if !config.symbols_known && config.detect_data_arrays {
+        debug!("Detecting data arrays");
+        detect_data_arrays(&mut module.obj)?;
+    }
+
     debug!("Adjusting splits");
     let module_id = module.obj.module_id;
     update_splits(
         &mut module.obj,
         if module_id == 0 { config.common_start } else { None },
         config.fill_gaps,
+        module.config.padding_style,
     )?;
 
     if !no_update {
         debug!("Writing configuration");
         if let Some(symbols_path) = &module.config.symbols {
-            write_symbols_file(&symbols_path.with_encoding(), &module.obj, module.symbols_cache)?;
+            write_symbols_file(
+                &symbols_path.with_encoding(),
+                &module.obj,
+                config.symbol_sort,
+                module.symbols_cache,
+            )?;
         }
         if let Some(splits_path) = &module.config.splits {
             write_splits_file(
@@ -943,6 +1198,18 @@ fn split_write_obj(
         }
     }
 
+    // Applied after writing symbols.txt, so that guesses aren't saved as explicit alignment
+    if !config.symbols_known && config.infer_symbol_alignment {
+        debug!("Inferring symbol alignment");
+        let count = infer_symbol_alignment(&mut module.obj)?;
+        info!("Inferred alignment for {} symbols", count);
+    }
+
+    if config.one_unit_per_function {
+        debug!("Splitting units by function");
+        split_by_function(&mut module.obj)?;
+    }
+
     debug!("Splitting {} objects", module.obj.link_order.len());
     let module_name = module.config.name().to_string();
     let split_objs = split_obj(&module.obj, Some(module_name.as_str()))?;
@@ -958,7 +1225,11 @@ fn split_write_obj(
             let (section_index, _) = module.obj.sections.at_address(e as u32).ok()?;
             let symbols =
                 module.obj.symbols.at_section_address(section_index, e as u32).collect_vec();
-            best_match_for_reloc(symbols, ObjRelocKind::PpcRel24).map(|(_, s)| s.name.clone())
+            module
+                .obj
+                .symbols
+                .best_match_for_reloc(symbols, ObjRelocKind::PpcRel24)
+                .map(|(_, s)| s.name.clone())
         })
     } else {
         module.obj.symbols.by_name("_prolog")?.map(|(_, s)| s.name.clone())
@@ -974,6 +1245,10 @@ fn split_write_obj(
     for (unit, split_obj) in module.obj.link_order.iter().zip(&split_objs) {
         let out_obj = write_elf(split_obj, config.export_all)?;
         let out_path = obj_dir.join(obj_path_for_unit(&unit.name));
+        if verify {
+            verify_elf(split_obj, &out_obj)
+                .with_context(|| format!("Failed to verify '{}'", out_path))?;
+        }
         out_config.units.push(OutputUnit {
             object: out_path.with_unix_encoding(),
             name: unit.name.clone(),
@@ -1063,6 +1338,19 @@ fn split_write_obj(
             write_asm(&mut w, split_obj)
                 .with_context(|| format!("Failed to write {}", out_path))?;
             w.flush()?;
+
+            // Verbatim sections are included from raw binary files
+            for (_, section) in split_obj.sections.iter().filter(|(_, s)| s.verbatim) {
+                if section.kind == ObjSectionKind::Bss {
+                    continue;
+                }
+                let bin_path =
+                    asm_dir.join(bin_path_for_unit(&unit.name, &section.name).with_encoding());
+                if let Some(parent) = bin_path.parent() {
+                    DirBuilder::new().recursive(true).create(parent)?;
+                }
+                write_if_changed(&bin_path, &section.data)?;
+            }
         }
     }
     Ok(out_config)
@@ -1095,15 +1383,20 @@ fn load_analyze_rel(
         verify_hash(data, hash_str)?;
     }
     let (header, mut module_obj) = process_rel(&mut Cursor::new(data), module_config.name())?;
+    if let Some(align) = config.size_conflict_align {
+        module_obj.symbols.set_size_conflict_align(align);
+    }
+    module_obj.symbols.set_reloc_resolver(Arc::new(config.reloc_tie_break));
 
     if let Some(comment_version) = config.mw_comment_version {
         module_obj.mw_comment = Some(MWComment::new(comment_version)?);
     }
 
     let mut dep = vec![object_path];
+    apply_section_aliases(&mut module_obj, config)?;
     if let Some(map_path) = &module_config.map {
         let map_path = map_path.with_encoding();
-        apply_map_file(&map_path, &mut module_obj, None, None)?;
+        apply_map_file(&map_path, &mut module_obj, None, None, &config.section_aliases())?;
         dep.push(map_path);
     }
 
@@ -1125,13 +1418,40 @@ fn load_analyze_rel(
         None
     };
 
+    if let Some(report_path) = &module_config.objdiff_report {
+        let report_path = report_path.with_encoding();
+        let count = apply_report_file(&report_path, &mut module_obj)?;
+        info!("Applied {} function names from {}", count, report_path);
+        dep.push(report_path);
+    }
+
+    if let Some(list_path) = &module_config.symbol_list {
+        let list_path = list_path.with_encoding();
+        let result = apply_symbol_list_file(&list_path, &mut module_obj)?;
+        for (line, address) in &result.unresolved {
+            log::warn!("{}:{}: {:#010X} is outside of any section", list_path, line, address);
+        }
+        info!("Applied {} symbol names from {}", result.applied, list_path);
+        dep.push(list_path);
+    }
+
+    apply_relocation_ignore(&mut module_obj, &module_config.relocation_ignore)?;
+
     // Apply block relocations from config
     apply_block_relocations(&mut module_obj, &module_config.block_relocations)?;
 
+    let count = create_extab_symbols(&mut module_obj)?;
+    if count > 0 {
+        debug!("Created symbols for {} extabindex entries", count);
+    }
+
     if !config.symbols_known {
         debug!("Analyzing module {}", module_obj.module_id);
         if !config.quick_analysis {
             let mut state = AnalyzerState::default();
+            if let Some(max_function_size) = config.max_function_size {
+                state.max_function_size = max_function_size;
+            }
             FindSaveRestSleds::execute(&mut state, &module_obj)?;
             state.detect_functions(&module_obj)?;
             FindRelCtorsDtors::execute(&mut state, &module_obj)?;
@@ -1142,6 +1462,22 @@ fn load_analyze_rel(
         apply_signatures_post(&mut module_obj)?;
     }
 
+    if config.split_tail_calls {
+        let count = split_tail_calls(&mut module_obj)?;
+        debug!("Split {} functions merged across a tail call", count);
+    }
+
+    if config.detect_thunks {
+        let count = detect_thunks(&mut module_obj)?;
+        debug!("Detected {} thunks", count);
+    }
+
+    let count = detect_frame_sizes(&mut module_obj)?;
+    debug!("Detected stack frame sizes for {} functions", count);
+
+    let count = module_obj.symbols.demangle_all(config.force_demangle);
+    debug!("Demangled {} symbol names", count);
+
     // Create _ctors and _dtors symbols if missing
     update_ctors_dtors(&mut module_obj)?;
 
@@ -1156,7 +1492,10 @@ fn load_analyze_rel(
 
 fn split(args: SplitArgs) -> Result<()> {
     if let Some(jobs) = args.jobs {
-        rayon::ThreadPoolBuilder::new().num_threads(jobs).build_global()?;
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .context("Failed to configure the thread pool (-j can't be combined with --threads)")?;
     }
 
     let command_start = Instant::now();
@@ -1165,6 +1504,19 @@ fn split(args: SplitArgs) -> Result<()> {
         let mut config_file = open_file(&args.config, true)?;
         serde_yaml::from_reader(config_file.as_mut())?
     };
+    set_auto_symbol_naming(config.auto_symbol_naming.clone())?;
+    if let Some(sort) = args.sort {
+        config.symbol_sort = sort;
+    }
+    if args.one_unit_per_function {
+        config.one_unit_per_function = true;
+    }
+    if args.force_demangle {
+        config.force_demangle = true;
+    }
+    if args.split_tail_calls {
+        config.split_tail_calls = true;
+    }
 
     let mut object_base = find_object_base(&config)?;
     if config.extract_objects && matches!(object_base, ObjectBase::Vfs(..)) {
@@ -1202,10 +1554,11 @@ fn split(args: SplitArgs) -> Result<()> {
         // DOL
         s.spawn(|_| {
             let _span = info_span!("module", name = %config.base.name()).entered();
-            dol_result =
-                Some(load_analyze_dol(&config, &object_base).with_context(|| {
-                    format!("While loading object '{}'", config.base.file_name())
-                }));
+            let cache_path = (!args.no_cache).then(|| args.out_dir.join("analysis_cache.json"));
+            dol_result = Some(
+                load_analyze_dol(&config, &object_base, cache_path.as_deref())
+                    .with_context(|| format!("While loading object '{}'", config.base.file_name())),
+            );
         });
         // Modules
         s.spawn(|_| {
@@ -1234,6 +1587,9 @@ fn split(args: SplitArgs) -> Result<()> {
             splits_cache: result.splits_cache,
         }
     };
+    for &address in &args.split_at {
+        split_function_at(&mut dol.obj, address)?;
+    }
     let mut function_count = dol.obj.symbols.by_kind(ObjSymbolKind::Function).count();
 
     let mut modules = ModuleMapByName::new();
@@ -1349,14 +1705,22 @@ fn split(args: SplitArgs) -> Result<()> {
             let _span =
                 info_span!("module", name = %config.base.name(), id = dol.obj.module_id).entered();
             dol_result = Some(
-                split_write_obj(&mut dol, &config, &args.out_dir, &args.out_dir, args.no_update)
-                    .with_context(|| {
-                        format!(
-                            "While processing object '{}' (module ID {})",
-                            config.base.file_name(),
-                            dol.obj.module_id
-                        )
-                    }),
+                split_write_obj(
+                    &mut dol,
+                    &config,
+                    &args.out_dir,
+                    &args.out_dir,
+                    args.no_update,
+                    args.reloc_diagnostics,
+                    args.verify,
+                )
+                .with_context(|| {
+                    format!(
+                        "While processing object '{}' (module ID {})",
+                        config.base.file_name(),
+                        dol.obj.module_id
+                    )
+                }),
             );
         });
         // Modules
@@ -1369,7 +1733,7 @@ fn split(args: SplitArgs) -> Result<()> {
                             info_span!("module", name = %module.config.name(), id = module.obj.module_id)
                                 .entered();
                         let out_dir = args.out_dir.join(module.config.name());
-                        split_write_obj(module, &config, &args.out_dir, &out_dir, args.no_update).with_context(
+                        split_write_obj(module, &config, &args.out_dir, &out_dir, args.no_update, args.reloc_diagnostics, args.verify).with_context(
                             || {
                                 format!(
                                     "While processing object '{}' (module {} ID {})",
@@ -1407,6 +1771,44 @@ fn split(args: SplitArgs) -> Result<()> {
         object_count
     );
 
+    // Report symbol size conflicts for triage
+    for module_info in std::iter::once(&dol).chain(modules.values()) {
+        let conflicts = module_info.obj.symbols.size_conflicts();
+        if conflicts.is_empty() {
+            continue;
+        }
+        log::warn!("{} symbol size conflicts in {}:", conflicts.len(), module_info.obj.name);
+        for conflict in conflicts {
+            log::warn!(
+                "  {}: was {:#X}, now {:#X}",
+                conflict.name,
+                conflict.old_size,
+                conflict.new_size
+            );
+        }
+    }
+
+    // Report hidden symbols that are visible to other modules
+    for module_info in std::iter::once(&dol).chain(modules.values()) {
+        let violations = module_info.obj.symbols.hidden_exports();
+        if violations.is_empty() {
+            continue;
+        }
+        log::warn!(
+            "{} hidden symbols exposed across modules in {}:",
+            violations.len(),
+            module_info.obj.name
+        );
+        for violation in violations {
+            log::warn!(
+                "  {}:{}{}",
+                violation.name,
+                if violation.exported { " exported" } else { "" },
+                if violation.force_active { " force_active" } else { "" }
+            );
+        }
+    }
+
     // Generate links
     for module_info in modules.values() {
         let mut links = get_links_map(module_info, &modules)?;
@@ -1621,6 +2023,7 @@ fn diff(args: DiffArgs) -> Result<()> {
     log::info!("Loading {}", args.config);
     let mut config_file = open_file(&args.config, true)?;
     let config: ProjectConfig = serde_yaml::from_reader(config_file.as_mut())?;
+    set_auto_symbol_naming(config.auto_symbol_naming.clone())?;
     let object_base = find_object_base(&config)?;
 
     log::info!("Loading {}", object_base.join(&config.base.object));
@@ -1638,7 +2041,8 @@ fn diff(args: DiffArgs) -> Result<()> {
     }
 
     log::info!("Loading {}", args.elf_file);
-    let linked_obj = process_elf(&args.elf_file)?;
+    let mut linked_obj = process_elf(&args.elf_file)?;
+    apply_section_aliases(&mut linked_obj, &config)?;
 
     let common_bss = obj.sections.common_bss_start();
     for (_, orig_sym) in obj.symbols.iter().filter(|(_, s)| {
@@ -1762,9 +2166,9 @@ fn diff(args: DiffArgs) -> Result<()> {
 
             // Disassemble and print the diff using objdiff-core if it's a function
             let mut handled = false;
-            if orig_sym.kind == ObjSymbolKind::Function
+            if orig_sym.is_code()
                 && orig_section.kind == ObjSectionKind::Code
-                && linked_sym.kind == ObjSymbolKind::Function
+                && linked_sym.is_code()
                 && linked_section.kind == ObjSectionKind::Code
             {
                 let config = objdiff_core::diff::DiffObjConfig::default();
@@ -1802,6 +2206,7 @@ fn apply(args: ApplyArgs) -> Result<()> {
     log::info!("Loading {}", args.config);
     let mut config_file = open_file(&args.config, true)?;
     let config: ProjectConfig = serde_yaml::from_reader(config_file.as_mut())?;
+    set_auto_symbol_naming(config.auto_symbol_naming.clone())?;
     let object_base = find_object_base(&config)?;
 
     log::info!("Loading {}", object_base.join(&config.base.object));
@@ -1823,7 +2228,8 @@ fn apply(args: ApplyArgs) -> Result<()> {
     };
 
     log::info!("Loading {}", args.elf_file);
-    let linked_obj = process_elf(&args.elf_file)?;
+    let mut linked_obj = process_elf(&args.elf_file)?;
+    apply_section_aliases(&mut linked_obj, &config)?;
 
     let mut replacements: Vec<(SymbolIndex, Option<ObjSymbol>)> = vec![];
     for (orig_idx, orig_sym) in obj.symbols.iter() {
@@ -1944,6 +2350,10 @@ fn apply(args: ApplyArgs) -> Result<()> {
                 data_kind: linked_sym.data_kind,
                 name_hash: linked_sym.name_hash,
                 demangled_name_hash: linked_sym.demangled_name_hash,
+                source_file: linked_sym.source_file.clone(),
+                source_line: linked_sym.source_line,
+                notes: linked_sym.notes.clone(),
+                sort_index: linked_sym.sort_index,
             })?;
         }
     }
@@ -1959,7 +2369,12 @@ fn apply(args: ApplyArgs) -> Result<()> {
     }
 
     let symbols_path = config.base.symbols.as_ref().unwrap();
-    write_symbols_file(&symbols_path.with_encoding(), &obj, Some(symbols_cache))?;
+    write_symbols_file(
+        &symbols_path.with_encoding(),
+        &obj,
+        config.symbol_sort,
+        Some(symbols_cache),
+    )?;
 
     Ok(())
 }
@@ -2013,6 +2428,15 @@ fn config(args: ConfigArgs) -> Result<()> {
     Ok(())
 }
 
+/// Applies the `RelocationIgnore` rules from module config `relocation_ignore`.
+fn apply_relocation_ignore(obj: &mut ObjInfo, rules: &[RelocationIgnoreRule]) -> Result<()> {
+    let count = apply_relocation_ignore_rules(obj, rules)?;
+    if count > 0 {
+        debug!("Marked {} symbols as relocation ignore", count);
+    }
+    Ok(())
+}
+
 /// Applies the blocked relocation ranges from module config `blocked_relocations`
 fn apply_block_relocations(
     obj: &mut ObjInfo,
@@ -0,0 +1,55 @@
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use argp::FromArgs;
+use typed_path::Utf8NativePathBuf;
+
+use crate::{
+    util::{
+        dol::{check_dol_section_counts, process_dol},
+        elf::{verify_elf, write_elf},
+        file::buf_writer,
+        path::native_path,
+    },
+    vfs::open_file,
+};
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Converts a DOL file to an ELF file.
+#[argp(subcommand, name = "dol2elf")]
+pub struct Args {
+    #[argp(positional, from_str_fn(native_path))]
+    /// path to input DOL
+    dol_file: Utf8NativePathBuf,
+    #[argp(positional, from_str_fn(native_path))]
+    /// path to output ELF
+    elf_file: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// re-read the written ELF and check it against the DOL
+    verify: bool,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    let obj = {
+        let mut file = open_file(&args.dol_file, true)?;
+        process_dol(file.map()?, "")?
+    };
+    check_dol_section_counts(&obj)?;
+    for (_, section) in obj.sections.iter() {
+        log::debug!(
+            "Section '{}' ({:?}): {:#010X}-{:#010X}",
+            section.name,
+            section.kind,
+            section.address,
+            section.address + section.size
+        );
+    }
+    let data = write_elf(&obj, false)?;
+    if args.verify {
+        verify_elf(&obj, &data).with_context(|| format!("Failed to verify '{}'", args.elf_file))?;
+    }
+    let mut out = buf_writer(&args.elf_file)?;
+    out.write_all(&data)?;
+    out.flush()?;
+    Ok(())
+}
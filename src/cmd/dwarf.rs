@@ -19,8 +19,8 @@ use typed_path::Utf8NativePathBuf;
 use crate::{
     util::{
         dwarf::{
-            process_compile_unit, process_cu_tag, process_overlay_branch, read_debug_section,
-            should_skip_tag, tag_type_string, AttributeKind, TagKind,
+            header_string, process_compile_unit, process_cu_tag, process_overlay_branch,
+            read_debug_section, should_skip_tag, tag_type_string, AttributeKind, TagKind,
         },
         file::buf_writer,
         path::native_path,
@@ -59,6 +59,10 @@ pub struct DumpArgs {
     /// Attempt to reconstruct tags that have been removed by the linker, e.g.
     /// tags from unused functions or functions that have been inlined away.
     include_erased: bool,
+    #[argp(switch)]
+    /// Only emit type declarations (structs, unions, enums and typedefs),
+    /// ordered so that the output can be compiled as a header.
+    emit_headers: bool,
 }
 
 pub fn run(args: Args) -> Result<()> {
@@ -172,6 +176,11 @@ where
         log::debug!("{}: {:?}", addr, tag);
     }
 
+    if args.emit_headers {
+        write!(w, "{}", header_string(&info)?)?;
+        return Ok(());
+    }
+
     let mut units = Vec::<String>::new();
     if let Some((_, mut tag)) = info.tags.first_key_value() {
         loop {
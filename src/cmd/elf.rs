@@ -17,19 +17,24 @@ use object::{
 use typed_path::{Utf8NativePath, Utf8NativePathBuf};
 
 use crate::{
+    analysis::stats::RelocationStats,
     obj::ObjKind,
     util::{
         asm::write_asm,
         comment::{CommentSym, MWComment},
-        config::{write_splits_file, write_symbols_file},
-        elf::{process_elf, write_elf},
+        config::{parse_u32, write_splits_file, write_symbols_file, SymbolSortMode},
+        elf::{apply_elf_source_info, process_elf, verify_elf, write_elf},
         file::{buf_writer, process_rsp},
+        flat::link_flat,
+        ghidra::write_ghidra_symbols,
         path::native_path,
         reader::{Endian, FromReader},
         signatures::{compare_signature, generate_signature, FunctionSignature},
-        split::split_obj,
+        split::{split_by_source_file, split_obj},
+        symbol_dump::write_symbol_dump,
         IntoCow, ToCow,
     },
+    vfs::open_file,
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -49,6 +54,10 @@ enum SubCommand {
     Signatures(SignaturesArgs),
     Split(SplitArgs),
     Info(InfoArgs),
+    References(ReferencesArgs),
+    Overlaps(OverlapsArgs),
+    Stats(StatsArgs),
+    Link(LinkArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -85,6 +94,9 @@ pub struct SplitArgs {
     #[argp(positional, from_str_fn(native_path))]
     /// output directory
     out_dir: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// re-read written objects and check them against the in-memory model
+    verify: bool,
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -97,6 +109,32 @@ pub struct ConfigArgs {
     #[argp(positional, from_str_fn(native_path))]
     /// output directory
     out_dir: Utf8NativePathBuf,
+    #[argp(option)]
+    /// symbols.txt ordering: address (default), name or section
+    sort: Option<SymbolSortMode>,
+    #[argp(option, from_str_fn(native_path))]
+    /// also write the full symbol table as JSON to this file
+    dump_symbols: Option<Utf8NativePathBuf>,
+    #[argp(option)]
+    /// restrict the symbol dump to this section (name or index)
+    section: Option<String>,
+    #[argp(option, from_str_fn(native_path))]
+    /// also write the symbol table for Ghidra's ImportSymbolsScript.py to this file
+    ghidra_symbols: Option<Utf8NativePathBuf>,
+    #[argp(option, from_str_fn(parse_section_address))]
+    /// runtime address of a section in the Ghidra symbols, e.g. `.text=0x805A2000` (repeatable)
+    ghidra_section: Vec<(String, u32)>,
+    #[argp(switch)]
+    /// attach DWARF source files to symbols and split sections without splits by source file
+    source_info: bool,
+}
+
+fn parse_section_address(value: &str) -> Result<(String, u32), String> {
+    let (section, address) = value
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid section address '{value}', expected SECTION=ADDRESS"))?;
+    let address = parse_u32(address).map_err(|e| format!("Invalid address '{address}': {e}"))?;
+    Ok((section.to_string(), address))
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -123,6 +161,64 @@ pub struct InfoArgs {
     input: Utf8NativePathBuf,
 }
 
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Prints all relocations that reference a symbol.
+#[argp(subcommand, name = "references")]
+pub struct ReferencesArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input file
+    elf_file: Utf8NativePathBuf,
+    #[argp(positional)]
+    /// symbol name
+    symbol: String,
+    #[argp(option)]
+    /// only print references from this section (name or index)
+    section: Option<String>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Prints pairs of symbols whose address ranges overlap.
+#[argp(subcommand, name = "overlaps")]
+pub struct OverlapsArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input file
+    elf_file: Utf8NativePathBuf,
+    #[argp(option)]
+    /// only print overlaps in this section (name or index)
+    section: Option<String>,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Prints relocation counts by kind, and how many resolve to a symbol.
+#[argp(subcommand, name = "stats")]
+pub struct StatsArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input file
+    elf_file: Utf8NativePathBuf,
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Applies all relocations and writes a linked image.
+#[argp(subcommand, name = "link")]
+pub struct LinkArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// input file
+    elf_file: Utf8NativePathBuf,
+    #[argp(positional, from_str_fn(native_path))]
+    /// output file
+    out_file: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// write a flat binary image, with BSS zeroed (required)
+    flat: bool,
+    #[argp(option, from_str_fn(parse_address))]
+    /// load address of the image (default: lowest section address, or 0 for relocatable objects)
+    base: Option<u32>,
+}
+
+fn parse_address(value: &str) -> Result<u32, String> {
+    parse_u32(value).map_err(|e| format!("Invalid address '{value}': {e}"))
+}
+
 pub fn run(args: Args) -> Result<()> {
     match args.command {
         SubCommand::Config(c_args) => config(c_args),
@@ -131,16 +227,45 @@ pub fn run(args: Args) -> Result<()> {
         SubCommand::Split(c_args) => split(c_args),
         SubCommand::Signatures(c_args) => signatures(c_args),
         SubCommand::Info(c_args) => info(c_args),
+        SubCommand::References(c_args) => references(c_args),
+        SubCommand::Overlaps(c_args) => overlaps(c_args),
+        SubCommand::Stats(c_args) => stats(c_args),
+        SubCommand::Link(c_args) => link(c_args),
     }
 }
 
 fn config(args: ConfigArgs) -> Result<()> {
     log::info!("Loading {}", args.in_file);
-    let obj = process_elf(&args.in_file)?;
+    let mut obj = process_elf(&args.in_file)?;
+    if args.source_info {
+        let mut file = open_file(&args.in_file, true)?;
+        let count = apply_elf_source_info(&mut obj, file.map()?)?;
+        log::info!("Attached DWARF source info to {} symbols", count);
+        let count = split_by_source_file(&mut obj)?;
+        log::info!("Created {} splits by source file", count);
+    }
 
     DirBuilder::new().recursive(true).create(&args.out_dir)?;
-    write_symbols_file(&args.out_dir.join("symbols.txt"), &obj, None)?;
+    write_symbols_file(
+        &args.out_dir.join("symbols.txt"),
+        &obj,
+        args.sort.unwrap_or_default(),
+        None,
+    )?;
     write_splits_file(&args.out_dir.join("splits.txt"), &obj, false, None)?;
+    if let Some(dump_path) = &args.dump_symbols {
+        let section =
+            args.section.as_deref().map(|s| obj.sections.by_name_or_index(s)).transpose()?;
+        let mut w = buf_writer(dump_path)?;
+        write_symbol_dump(&mut w, &obj, section)?;
+        w.flush()?;
+    }
+    if let Some(ghidra_path) = &args.ghidra_symbols {
+        let section_addresses = args.ghidra_section.iter().cloned().collect();
+        let mut w = buf_writer(ghidra_path)?;
+        write_ghidra_symbols(&mut w, &obj, &section_addresses)?;
+        w.flush()?;
+    }
     Ok(())
 }
 
@@ -188,6 +313,10 @@ fn split(args: SplitArgs) -> Result<()> {
     let split_objs = split_obj(&obj, None)?;
     for (unit, split_obj) in obj.link_order.iter().zip(&split_objs) {
         let out_obj = write_elf(split_obj, false)?;
+        if args.verify {
+            verify_elf(split_obj, &out_obj)
+                .with_context(|| format!("Failed to verify '{}'", unit.name))?;
+        }
         match file_map.entry(unit.name.clone()) {
             hash_map::Entry::Vacant(e) => e.insert(out_obj),
             hash_map::Entry::Occupied(_) => bail!("Duplicate file {}", unit.name),
@@ -467,6 +596,88 @@ fn signatures(args: SignaturesArgs) -> Result<()> {
     Ok(())
 }
 
+fn references(args: ReferencesArgs) -> Result<()> {
+    let obj = process_elf(&args.elf_file)?;
+    let (symbol_index, symbol) = obj
+        .symbols
+        .by_name(&args.symbol)?
+        .ok_or_else(|| anyhow!("Symbol '{}' not found", args.symbol))?;
+    let section = args.section.as_deref().map(|s| obj.sections.by_name_or_index(s)).transpose()?;
+    let mut references = obj.references_to(symbol_index);
+    if let Some(section) = section {
+        references.retain(|r| r.source.section == section);
+    }
+    if references.is_empty() {
+        println!("No references to {}", symbol.name);
+        return Ok(());
+    }
+    for reference in &references {
+        let target = match reference.offset {
+            0 => symbol.name.clone(),
+            offset if offset < 0 => format!("{}-{:#X}", symbol.name, offset.unsigned_abs()),
+            offset => format!("{}+{:#X}", symbol.name, offset),
+        };
+        println!(
+            "{} {:#010X} {} ({:?}) -> {}",
+            obj.sections[reference.source.section].name,
+            reference.source.address,
+            obj.symbols.format_address(reference.source),
+            reference.kind,
+            target
+        );
+    }
+    println!("{} references to {}", references.len(), symbol.name);
+    Ok(())
+}
+
+fn overlaps(args: OverlapsArgs) -> Result<()> {
+    let obj = process_elf(&args.elf_file)?;
+    let section = args.section.as_deref().map(|s| obj.sections.by_name_or_index(s)).transpose()?;
+    let mut overlaps = obj.symbols.find_overlaps();
+    if let Some(section) = section {
+        overlaps.retain(|&(a_index, _)| obj.symbols[a_index].section == Some(section));
+    }
+    for &(a_index, b_index) in &overlaps {
+        let (a, b) = (&obj.symbols[a_index], &obj.symbols[b_index]);
+        println!(
+            "{} {:#010X}-{:#010X} {} overlaps {:#010X}-{:#010X} {} (starting at {}+{:#X})",
+            obj.sections[a.section.unwrap()].name,
+            a.address,
+            a.address + a.size,
+            a.name,
+            b.address,
+            b.address + b.size,
+            b.name,
+            a.name,
+            b.address - a.address
+        );
+    }
+    println!("{} overlapping symbol pairs", overlaps.len());
+    Ok(())
+}
+
+fn stats(args: StatsArgs) -> Result<()> {
+    let obj = process_elf(&args.elf_file)?;
+    let stats = RelocationStats::new(&obj)?;
+    for (kind, count) in &stats.by_kind {
+        println!("{:?}: {}", kind, count);
+    }
+    println!("{} relocations", stats.total());
+    println!("Resolved to a symbol: {}", stats.resolved);
+    println!("Unresolved: {}", stats.unresolved);
+    println!("External: {}", stats.external);
+    Ok(())
+}
+
+fn link(args: LinkArgs) -> Result<()> {
+    ensure!(args.flat, "Only flat binary output is supported, pass --flat");
+    let obj = process_elf(&args.elf_file)?;
+    let image = link_flat(&obj, args.base)?;
+    fs::write(&args.out_file, &image)
+        .with_context(|| format!("Failed to write output file: '{}'", args.out_file))?;
+    Ok(())
+}
+
 fn info(args: InfoArgs) -> Result<()> {
     let in_buf = fs::read(&args.input)
         .with_context(|| format!("Failed to open input file: '{}'", args.input))?;
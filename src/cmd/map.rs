@@ -1,17 +1,20 @@
-use std::fs::DirBuilder;
+use std::{fs::DirBuilder, io::stdout};
 
 use anyhow::{bail, ensure, Result};
 use argp::FromArgs;
-use cwdemangle::{demangle, DemangleOptions};
+use cwdemangle::DemangleOptions;
+use regex::Regex;
 use tracing::error;
 use typed_path::Utf8NativePathBuf;
 
 use crate::{
+    obj::SymbolRenameTransform,
     util::{
-        config::{write_splits_file, write_symbols_file},
-        map::{create_obj, process_map, SymbolEntry, SymbolRef},
+        config::{parse_u32, write_splits_file, write_symbols_file, SymbolSortMode},
+        demangle::demangle,
+        map::{create_obj, diff_maps, process_map, MapDiffKind, SymbolEntry, SymbolRef},
         path::native_path,
-        split::update_splits,
+        split::{update_splits, PaddingStyle},
     },
     vfs::open_file,
 };
@@ -30,6 +33,7 @@ enum SubCommand {
     Entries(EntriesArgs),
     Symbol(SymbolArgs),
     Config(ConfigArgs),
+    Diff(DiffArgs),
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -66,6 +70,49 @@ pub struct ConfigArgs {
     #[argp(positional, from_str_fn(native_path))]
     /// output directory for symbols.txt and splits.txt
     out_dir: Utf8NativePathBuf,
+    #[argp(option)]
+    /// remove a prefix from symbol names
+    strip_prefix: Option<String>,
+    #[argp(option)]
+    /// add a prefix to symbol names
+    add_prefix: Option<String>,
+    #[argp(option)]
+    /// regex to replace in symbol names (used with --replace-with)
+    replace_pattern: Option<String>,
+    #[argp(option)]
+    /// replacement for --replace-pattern matches (supports $1 style groups)
+    replace_with: Option<String>,
+    #[argp(switch)]
+    /// also rename auto-generated symbols and linker generated labels
+    rename_auto: bool,
+    #[argp(option)]
+    /// symbols.txt ordering: address (default), name or section
+    sort: Option<SymbolSortMode>,
+    #[argp(option, from_str_fn(parse_address))]
+    /// start address of common BSS (guessed from duplicate .bss TUs if omitted)
+    common_start: Option<u32>,
+    #[argp(option)]
+    /// linker .comment version; below 11 enables common BSS inflation correction
+    mw_comment_version: Option<u8>,
+}
+
+fn parse_address(value: &str) -> Result<u32, String> {
+    parse_u32(value).map_err(|e| format!("Invalid address '{value}': {e}"))
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Compares symbol addresses and sizes between two maps.
+#[argp(subcommand, name = "diff")]
+pub struct DiffArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// path to old map
+    old_map: Utf8NativePathBuf,
+    #[argp(positional, from_str_fn(native_path))]
+    /// path to new map
+    new_map: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// output JSON
+    json: bool,
 }
 
 pub fn run(args: Args) -> Result<()> {
@@ -73,6 +120,7 @@ pub fn run(args: Args) -> Result<()> {
         SubCommand::Entries(c_args) => entries(c_args),
         SubCommand::Symbol(c_args) => symbol(c_args),
         SubCommand::Config(c_args) => config(c_args),
+        SubCommand::Diff(c_args) => diff(c_args),
     }
 }
 
@@ -185,14 +233,116 @@ fn symbol(args: SymbolArgs) -> Result<()> {
 fn config(args: ConfigArgs) -> Result<()> {
     let mut file = open_file(&args.map_file, true)?;
     log::info!("Processing map...");
-    let entries = process_map(file.as_mut(), None, None)?;
+    let entries = process_map(file.as_mut(), args.common_start, args.mw_comment_version)?;
     let mut obj = create_obj(&entries)?;
-    if let Err(e) = update_splits(&mut obj, None, false) {
+    let replace = match (args.replace_pattern, args.replace_with) {
+        (Some(pattern), Some(replacement)) => Some((Regex::new(&pattern)?, replacement)),
+        (None, None) => None,
+        _ => bail!("--replace-pattern and --replace-with must be used together"),
+    };
+    let transform = SymbolRenameTransform {
+        strip_prefix: args.strip_prefix,
+        add_prefix: args.add_prefix,
+        replace,
+        include_auto: args.rename_auto,
+    };
+    if !transform.is_empty() {
+        let count = obj.symbols.rename_all(&transform)?;
+        log::info!("Renamed {} symbols", count);
+    }
+    if let Err(e) = update_splits(&mut obj, None, false, PaddingStyle::Auto) {
         error!("Failed to update splits: {}", e)
     }
     DirBuilder::new().recursive(true).create(&args.out_dir)?;
-    write_symbols_file(&args.out_dir.join("symbols.txt"), &obj, None)?;
+    write_symbols_file(
+        &args.out_dir.join("symbols.txt"),
+        &obj,
+        args.sort.unwrap_or_default(),
+        None,
+    )?;
     write_splits_file(&args.out_dir.join("splits.txt"), &obj, false, None)?;
     log::info!("Done!");
     Ok(())
 }
+
+fn diff(args: DiffArgs) -> Result<()> {
+    let old = {
+        let mut file = open_file(&args.old_map, true)?;
+        process_map(file.as_mut(), None, None)?
+    };
+    let new = {
+        let mut file = open_file(&args.new_map, true)?;
+        process_map(file.as_mut(), None, None)?
+    };
+    let diff = diff_maps(&old, &new);
+    if args.json {
+        serde_json::to_writer_pretty(stdout(), &diff)?;
+        println!();
+        return Ok(());
+    }
+
+    println!("Sections:");
+    for section in &diff.sections {
+        println!(
+            "  {:<16} {:#10X} -> {:#10X} ({})",
+            section.name,
+            section.old_size,
+            section.new_size,
+            format_delta(section.old_size, section.new_size)
+        );
+    }
+    println!("\nSymbols:");
+    for symbol in &diff.symbols {
+        let unit = symbol.unit.as_deref().unwrap_or("(generated)");
+        match symbol.kind {
+            MapDiffKind::Added => println!(
+                "  + {} {} @ {:#010X} size {:#X} [{}]",
+                symbol.section,
+                symbol.name,
+                symbol.new_address.unwrap_or_default(),
+                symbol.new_size.unwrap_or_default(),
+                unit
+            ),
+            MapDiffKind::Removed => println!(
+                "  - {} {} @ {:#010X} size {:#X} [{}]",
+                symbol.section,
+                symbol.name,
+                symbol.old_address.unwrap_or_default(),
+                symbol.old_size.unwrap_or_default(),
+                unit
+            ),
+            MapDiffKind::Changed => {
+                let old_address = symbol.old_address.unwrap_or_default();
+                let new_address = symbol.new_address.unwrap_or_default();
+                let old_size = symbol.old_size.unwrap_or_default();
+                let new_size = symbol.new_size.unwrap_or_default();
+                let mut changes = vec![];
+                if let Some(new_name) = &symbol.new_name {
+                    changes.push(format!("renamed to {}", new_name));
+                }
+                if old_address != new_address {
+                    changes.push(format!("moved {:#010X} -> {:#010X}", old_address, new_address));
+                }
+                if old_size != new_size {
+                    changes.push(format!(
+                        "size {:#X} -> {:#X} ({})",
+                        old_size,
+                        new_size,
+                        format_delta(old_size, new_size)
+                    ));
+                }
+                let changes = changes.join(", ");
+                println!("  ~ {} {} {} [{}]", symbol.section, symbol.name, changes, unit);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn format_delta(old: u32, new: u32) -> String {
+    if new >= old {
+        format!("+{:#X}", new - old)
+    } else {
+        format!("-{:#X}", old - new)
+    }
+}
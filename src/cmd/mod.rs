@@ -3,11 +3,13 @@ pub mod ar;
 pub mod demangle;
 pub mod disc;
 pub mod dol;
+pub mod dol2elf;
 pub mod dwarf;
 pub mod elf;
 pub mod elf2dol;
 pub mod map;
 pub mod nlzss;
+pub mod obj;
 pub mod rarc;
 pub mod rel;
 pub mod rso;
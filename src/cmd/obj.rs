@@ -0,0 +1,140 @@
+use std::io::{stdout, Write};
+
+use anyhow::Result;
+use argp::FromArgs;
+use serde::Serialize;
+use typed_path::Utf8NativePathBuf;
+
+use crate::{
+    obj::{ObjInfo, ObjSymbolKind, SymbolIndex},
+    util::{elf::process_elf, path::native_path},
+};
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Commands for comparing objects.
+#[argp(subcommand, name = "obj")]
+pub struct Args {
+    #[argp(subcommand)]
+    command: SubCommand,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argp(subcommand)]
+enum SubCommand {
+    Diff(DiffArgs),
+}
+
+#[derive(FromArgs, PartialEq, Eq, Debug)]
+/// Compares the symbols of two ELF files.
+#[argp(subcommand, name = "diff")]
+pub struct DiffArgs {
+    #[argp(positional, from_str_fn(native_path))]
+    /// original file
+    left_file: Utf8NativePathBuf,
+    #[argp(positional, from_str_fn(native_path))]
+    /// modified file
+    right_file: Utf8NativePathBuf,
+    #[argp(switch)]
+    /// print the differences as JSON
+    json: bool,
+}
+
+pub fn run(args: Args) -> Result<()> {
+    match args.command {
+        SubCommand::Diff(c_args) => diff(c_args),
+    }
+}
+
+#[derive(Serialize)]
+struct DiffSymbol<'a> {
+    name: &'a str,
+    section: Option<&'a str>,
+    address: u64,
+    size: u64,
+    kind: ObjSymbolKind,
+}
+
+impl<'a> DiffSymbol<'a> {
+    fn new(obj: &'a ObjInfo, index: SymbolIndex) -> Self {
+        let symbol = &obj.symbols[index];
+        Self {
+            name: &symbol.name,
+            section: symbol.section.map(|s| obj.sections[s].name.as_str()),
+            address: symbol.address,
+            size: symbol.size,
+            kind: symbol.kind,
+        }
+    }
+}
+
+impl std::fmt::Display for DiffSymbol<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:#010X} size {:#X} {:?} {}",
+            self.section.unwrap_or("ABS"),
+            self.address,
+            self.size,
+            self.kind,
+            self.name
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct DiffChange<'a> {
+    old: DiffSymbol<'a>,
+    new: DiffSymbol<'a>,
+}
+
+#[derive(Serialize)]
+struct DiffOutput<'a> {
+    added: Vec<DiffSymbol<'a>>,
+    removed: Vec<DiffSymbol<'a>>,
+    changed: Vec<DiffChange<'a>>,
+}
+
+fn diff(args: DiffArgs) -> Result<()> {
+    let left = process_elf(&args.left_file)?;
+    let right = process_elf(&args.right_file)?;
+    write_diff(&mut stdout().lock(), &left, &right, args.json)
+}
+
+fn write_diff<W>(w: &mut W, left: &ObjInfo, right: &ObjInfo, json: bool) -> Result<()>
+where W: Write + ?Sized {
+    let diff = left.symbols.diff(&left.sections, &right.symbols, &right.sections);
+    let output = DiffOutput {
+        added: diff.added.iter().map(|&i| DiffSymbol::new(right, i)).collect(),
+        removed: diff.removed.iter().map(|&i| DiffSymbol::new(left, i)).collect(),
+        changed: diff
+            .changed
+            .iter()
+            .map(|&(l, r)| DiffChange {
+                old: DiffSymbol::new(left, l),
+                new: DiffSymbol::new(right, r),
+            })
+            .collect(),
+    };
+    if json {
+        serde_json::to_writer_pretty(&mut *w, &output)?;
+        writeln!(w)?;
+        return Ok(());
+    }
+    for symbol in &output.removed {
+        writeln!(w, "- {symbol}")?;
+    }
+    for symbol in &output.added {
+        writeln!(w, "+ {symbol}")?;
+    }
+    for change in &output.changed {
+        writeln!(w, "~ {} -> {}", change.old, change.new)?;
+    }
+    writeln!(
+        w,
+        "{} added, {} removed, {} changed",
+        output.added.len(),
+        output.removed.len(),
+        output.changed.len()
+    )?;
+    Ok(())
+}
@@ -33,19 +33,20 @@ use crate::{
         SectionIndex as ObjSectionIndex,
     },
     util::{
-        config::{is_auto_symbol, read_splits_sections, SectionDef},
+        config::{is_auto_symbol, read_splits_sections, set_auto_symbol_naming, SectionDef},
         dol::process_dol,
         elf::{to_obj_reloc_kind, write_elf},
-        file::{buf_writer, process_rsp, verify_hash, FileIterator},
+        file::{buf_writer, for_each_file, process_rsp, verify_hash, FileIterator},
+        ncompress::{compress, parse_compression},
         nested::NestedMap,
         path::native_path,
         rel::{
-            print_relocations, process_rel, process_rel_header, process_rel_sections, write_rel,
-            RelHeader, RelReloc, RelSectionHeader, RelWriteInfo, PERMITTED_SECTIONS,
+            print_relocations, process_rel, process_rel_header, process_rel_sections, verify_rel,
+            write_rel, RelHeader, RelReloc, RelSectionHeader, RelWriteInfo, PERMITTED_SECTIONS,
         },
         IntoCow, ToCow,
     },
-    vfs::open_file,
+    vfs::{open_file, CompressionKind, VfsFile},
 };
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -69,11 +70,14 @@ enum SubCommand {
 #[argp(subcommand, name = "info")]
 pub struct InfoArgs {
     #[argp(positional, from_str_fn(native_path))]
-    /// REL file
-    rel_file: Utf8NativePathBuf,
+    /// REL file(s)
+    rel_files: Vec<Utf8NativePathBuf>,
     #[argp(switch, short = 'r')]
     /// print relocations
     relocations: bool,
+    #[argp(switch)]
+    /// report malformed files and continue with the rest (fails only if all files fail)
+    keep_going: bool,
 }
 
 #[derive(FromArgs, PartialEq, Eq, Debug)]
@@ -110,6 +114,12 @@ pub struct MakeArgs {
     #[argp(switch, short = 'q')]
     /// only print errors
     quiet: bool,
+    #[argp(option, from_str_fn(parse_compression))]
+    /// (optional) compress output RELs (yaz0, yay0)
+    compress: Option<CompressionKind>,
+    #[argp(switch)]
+    /// re-read written RELs and check them against the input ELFs
+    verify: bool,
 }
 
 pub fn run(args: Args) -> Result<()> {
@@ -190,11 +200,83 @@ struct LoadedModule<'a> {
     path: Utf8NativePathBuf,
 }
 
+/// A symbol exported by one of the modules being linked.
+#[derive(Copy, Clone, Debug)]
+struct ExportedSymbol {
+    module_id: u32,
+    index: SymbolIndex,
+    weak: bool,
+}
+
+type SymbolMap<'a> = FxHashMap<&'a [u8], Vec<ExportedSymbol>>;
+
+fn build_symbol_map<'a>(modules: &'a [LoadedModule]) -> Result<SymbolMap<'a>> {
+    let mut symbol_map = SymbolMap::default();
+    for module_info in modules.iter() {
+        let _span = info_span!("file", path = %module_info.path).entered();
+        for symbol in module_info.file.symbols() {
+            if symbol.scope() == object::SymbolScope::Dynamic {
+                symbol_map.entry(symbol.name_bytes()?).or_default().push(ExportedSymbol {
+                    module_id: module_info.module_id,
+                    index: symbol.index(),
+                    weak: symbol.is_weak(),
+                });
+            }
+        }
+    }
+    Ok(symbol_map)
+}
+
+/// Selects the definition of an imported symbol. A strong definition takes precedence
+/// over weak ones, but strong definitions in multiple modules are ambiguous.
+fn select_export(name: &str, candidates: &[ExportedSymbol]) -> Result<ExportedSymbol> {
+    let mut strong = candidates.iter().filter(|s| !s.weak);
+    match (strong.next(), strong.next()) {
+        (Some(a), Some(b)) => bail!(
+            "Ambiguous symbol {} exported by modules {} and {}",
+            name,
+            a.module_id,
+            b.module_id
+        ),
+        (Some(a), None) => Ok(*a),
+        (None, _) => candidates
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("Failed to find symbol {} in any module", name)),
+    }
+}
+
+/// Resolves the relocations of every module except the DOL (the first module),
+/// returning the number of imported symbols and the relocations for each module.
+fn link_modules(
+    modules: &[LoadedModule],
+    existing_headers: &BTreeMap<u32, RelInfo>,
+) -> Result<(usize, Vec<Vec<RelReloc>>)> {
+    let symbol_map = build_symbol_map(modules)?;
+    let mut resolved = 0usize;
+    let mut relocations = Vec::<Vec<RelReloc>>::with_capacity(modules.len().saturating_sub(1));
+    for module_info in modules.iter().skip(1) {
+        let _span = info_span!("file", path = %module_info.path).entered();
+        let mut module_relocations = Vec::new();
+        resolved += resolve_relocations(
+            &module_info.file,
+            existing_headers,
+            module_info.module_id,
+            &symbol_map,
+            modules,
+            &mut module_relocations,
+        )
+        .with_context(|| format!("While resolving relocations in '{}'", module_info.path))?;
+        relocations.push(module_relocations);
+    }
+    Ok((resolved, relocations))
+}
+
 fn resolve_relocations(
     module: &File,
     existing_headers: &BTreeMap<u32, RelInfo>,
     module_id: u32,
-    symbol_map: &FxHashMap<&[u8], (u32, SymbolIndex)>,
+    symbol_map: &SymbolMap,
     modules: &[LoadedModule],
     relocations: &mut Vec<RelReloc>,
 ) -> Result<usize> {
@@ -219,25 +301,26 @@ fn resolve_relocations(
             };
             let (target_module_id, target_symbol) = if reloc_target.is_undefined() {
                 resolved += 1;
-                symbol_map
+                let candidates = symbol_map
                     .get(reloc_target.name_bytes()?)
-                    .map(|&(module_id, symbol_idx)| {
-                        let module = modules.iter().find(|m| m.module_id == module_id).unwrap();
-                        (module_id, module.file.symbol_by_index(symbol_idx).unwrap())
-                    })
-                    .ok_or_else(|| {
-                        anyhow!(
-                            "Failed to find symbol {} in any module",
-                            reloc_target.name().unwrap_or("[invalid]")
-                        )
-                    })?
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let export = select_export(reloc_target.name().unwrap_or("[invalid]"), candidates)?;
+                let module = find_module(modules, export.module_id)?;
+                (export.module_id, module.file.symbol_by_index(export.index)?)
             } else {
                 (module_id, reloc_target)
             };
-            let target_section_index = target_symbol.section_index().unwrap();
+            let target_section_index = target_symbol.section_index().ok_or_else(|| {
+                anyhow!(
+                    "Relocation at {:#X} targets symbol {} without a section",
+                    address,
+                    target_symbol.name().unwrap_or("[invalid]")
+                )
+            })?;
             let target_section =
                 if let Some((_, sections, _)) = existing_headers.get(&target_module_id) {
-                    let module = modules.iter().find(|m| m.module_id == module_id).unwrap();
+                    let module = find_module(modules, target_module_id)?;
                     match_section_index(&module.file, target_section_index, sections)?
                 } else {
                     target_section_index.0
@@ -258,6 +341,16 @@ fn resolve_relocations(
     Ok(resolved)
 }
 
+fn find_module<'a, 'b>(
+    modules: &'a [LoadedModule<'b>],
+    module_id: u32,
+) -> Result<&'a LoadedModule<'b>> {
+    modules
+        .iter()
+        .find(|m| m.module_id == module_id)
+        .ok_or_else(|| anyhow!("Failed to locate module {}", module_id))
+}
+
 type RelInfo = (RelHeader, Vec<RelSectionHeader>, Option<Vec<SectionDef>>);
 
 fn make(args: MakeArgs) -> Result<()> {
@@ -271,6 +364,7 @@ fn make(args: MakeArgs) -> Result<()> {
             let mut file = open_file(config_path, true)?;
             serde_yaml::from_reader(file.as_mut())?
         };
+        set_auto_symbol_naming(config.auto_symbol_naming.clone())?;
         let object_base = find_object_base(&config)?;
         for module_config in &config.modules {
             let module_name = module_config.name();
@@ -317,36 +411,9 @@ fn make(args: MakeArgs) -> Result<()> {
         })
         .collect::<Result<Vec<_>>>()?;
 
-    // Create symbol map
-    let start = Instant::now();
-    let mut symbol_map = FxHashMap::<&[u8], (u32, SymbolIndex)>::default();
-    for module_info in modules.iter() {
-        let _span = info_span!("file", path = %module_info.path).entered();
-        for symbol in module_info.file.symbols() {
-            if symbol.scope() == object::SymbolScope::Dynamic {
-                symbol_map
-                    .entry(symbol.name_bytes()?)
-                    .or_insert((module_info.module_id, symbol.index()));
-            }
-        }
-    }
-
     // Resolve relocations
-    let mut resolved = 0usize;
-    let mut relocations = Vec::<Vec<RelReloc>>::with_capacity(modules.len() - 1);
-    relocations.resize_with(modules.len() - 1, Vec::new);
-    for (module_info, relocations) in modules.iter().skip(1).zip(&mut relocations) {
-        let _span = info_span!("file", path = %module_info.path).entered();
-        resolved += resolve_relocations(
-            &module_info.file,
-            &existing_headers,
-            module_info.module_id,
-            &symbol_map,
-            &modules,
-            relocations,
-        )
-        .with_context(|| format!("While resolving relocations in '{}'", module_info.path))?;
-    }
+    let start = Instant::now();
+    let (resolved, relocations) = link_modules(&modules, &existing_headers)?;
 
     if !args.quiet {
         let duration = start.elapsed();
@@ -391,8 +458,22 @@ fn make(args: MakeArgs) -> Result<()> {
         }
         let rel_path = module_info.path.with_extension("rel");
         let mut w = buf_writer(&rel_path)?;
-        write_rel(&mut w, &info, &module_info.file, relocations)
-            .with_context(|| format!("Failed to write '{}'", rel_path))?;
+        if args.compress.is_some() || args.verify {
+            let mut data = Cursor::new(Vec::new());
+            let count = write_rel(&mut data, &info, &module_info.file, relocations)
+                .with_context(|| format!("Failed to write '{}'", rel_path))?;
+            if args.verify {
+                verify_rel(data.get_ref(), &module_info.file, count)
+                    .with_context(|| format!("Failed to verify '{}'", rel_path))?;
+            }
+            match args.compress {
+                Some(kind) => w.write_all(&compress(kind, data.get_ref())?)?,
+                None => w.write_all(data.get_ref())?,
+            }
+        } else {
+            write_rel(&mut w, &info, &module_info.file, relocations)
+                .with_context(|| format!("Failed to write '{}'", rel_path))?;
+        }
         w.flush()?;
     }
 
@@ -407,8 +488,20 @@ fn make(args: MakeArgs) -> Result<()> {
 }
 
 fn info(args: InfoArgs) -> Result<()> {
-    let mut file = open_file(&args.rel_file, true)?;
-    let (header, mut module_obj) = process_rel(file.as_mut(), "")?;
+    let report = for_each_file(&args.rel_files, args.keep_going, |path, file| {
+        if args.rel_files.len() > 1 {
+            println!("\n{}:", path);
+        }
+        info_file(file, args.relocations)
+    })?;
+    if args.keep_going {
+        report.finish()?;
+    }
+    Ok(())
+}
+
+fn info_file(file: &mut dyn VfsFile, relocations: bool) -> Result<()> {
+    let (header, mut module_obj) = process_rel(file, "")?;
 
     let mut state = AnalyzerState::default();
     state.detect_functions(&module_obj)?;
@@ -463,10 +556,10 @@ fn info(args: InfoArgs) -> Result<()> {
         );
     }
 
-    if args.relocations {
+    if relocations {
         println!("\nRelocations:");
         println!("    [Source] section:address RelocType -> [Target] module:section:address");
-        print_relocations(file.as_mut(), &header)?;
+        print_relocations(file, &header)?;
     }
     Ok(())
 }
@@ -518,6 +611,8 @@ fn merge(args: MergeArgs) -> Result<()> {
                 virtual_address: mod_section.virtual_address,
                 file_offset: mod_section.file_offset,
                 section_known: mod_section.section_known,
+                thread_local: mod_section.thread_local,
+                verbatim: mod_section.verbatim,
                 splits: mod_section.splits.clone(),
             });
             section_map.nested_insert(module.module_id, mod_section.elf_index, offset)?;
@@ -535,6 +630,10 @@ fn merge(args: MergeArgs) -> Result<()> {
                     data_kind: mod_symbol.data_kind,
                     name_hash: mod_symbol.name_hash,
                     demangled_name_hash: mod_symbol.demangled_name_hash,
+                    source_file: mod_symbol.source_file.clone(),
+                    source_line: mod_symbol.source_line,
+                    notes: mod_symbol.notes.clone(),
+                    sort_index: mod_symbol.sort_index,
                 })?;
             }
             offset += align32(mod_section.size as u32);
@@ -557,14 +656,15 @@ fn merge(args: MergeArgs) -> Result<()> {
                 section_map[&(rel_reloc.target_section as u32)] + rel_reloc.addend
             };
             let (source_section_index, _) = obj.sections.at_address(source_addr)?;
-            let (target_section_index, _) = obj.sections.at_address(target_addr)?;
-
-            let (symbol_idx, addend) = if let Some((symbol_idx, symbol)) =
-                obj.symbols.for_relocation(
-                    SectionAddress::new(target_section_index, target_addr),
-                    rel_reloc.kind,
-                )? {
-                (symbol_idx, target_addr as i64 - symbol.address as i64)
+            let (target_section_index, target_section) = obj.sections.at_address(target_addr)?;
+            let in_sda = target_section.is_small_data();
+
+            let (symbol_idx, addend) = if let Some(result) = obj.symbols.resolve_relocation(
+                SectionAddress::new(target_section_index, target_addr),
+                rel_reloc.kind,
+                in_sda,
+            )? {
+                result
             } else {
                 // Create a new label
                 let symbol_idx = obj.symbols.add_direct(ObjSymbol {
@@ -617,8 +717,7 @@ fn merge(args: MergeArgs) -> Result<()> {
 fn link_relocations(obj: &mut ObjInfo) -> Result<()> {
     for (_, section) in obj.sections.iter_mut() {
         for (source_address, reloc) in section.relocations.iter() {
-            let target_address =
-                (obj.symbols[reloc.target_symbol].address as i64 + reloc.addend) as u32;
+            let target_address = reloc.target_address(&obj.symbols) as u32;
             let ins_ref =
                 array_ref_mut!(section.data, (source_address as u64 - section.address) as usize, 4);
             let mut ins = u32::from_be_bytes(*ins_ref);
@@ -660,3 +759,382 @@ fn link_relocations(obj: &mut ObjInfo) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use object::{
+        elf,
+        write::{Object as WriteObject, Relocation, Symbol, SymbolSection},
+        BinaryFormat, RelocationFlags, SectionKind, SymbolFlags, SymbolKind, SymbolScope,
+    };
+
+    use super::*;
+    use crate::{
+        cmd::dol::{create_relocations, update_symbols, ModuleMapById},
+        obj::{test_symbol, ObjArchitecture, ObjKind, ObjSymbolKind},
+        util::{
+            elf::parse_elf,
+            reader::{Endian, FromReader},
+        },
+    };
+
+    /// Builds a relocatable object with a `.text` section exporting `exports` (name, offset)
+    /// and calling each of `imports` with a `bl`.
+    fn build_module(exports: &[(&str, u64)], imports: &[&str]) -> Vec<u8> {
+        let mut obj = WriteObject::new(BinaryFormat::Elf, Architecture::PowerPc, Endianness::Big);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        // bl 0 for each import, then blr
+        let mut data = vec![];
+        for _ in imports {
+            data.extend_from_slice(&0x48000001u32.to_be_bytes());
+        }
+        data.extend_from_slice(&0x4E800020u32.to_be_bytes());
+        data.resize(data.len().max(0x20), 0);
+        obj.append_section_data(text, &data, 4);
+        for &(name, offset) in exports {
+            obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: offset,
+                size: 4,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Dynamic,
+                weak: false,
+                section: SymbolSection::Section(text),
+                flags: SymbolFlags::None,
+            });
+        }
+        for (idx, &name) in imports.iter().enumerate() {
+            let symbol = obj.add_symbol(Symbol {
+                name: name.as_bytes().to_vec(),
+                value: 0,
+                size: 0,
+                kind: SymbolKind::Text,
+                scope: SymbolScope::Dynamic,
+                weak: false,
+                section: SymbolSection::Undefined,
+                flags: SymbolFlags::None,
+            });
+            obj.add_relocation(text, Relocation {
+                offset: idx as u64 * 4,
+                symbol,
+                addend: 0,
+                flags: RelocationFlags::Elf { r_type: elf::R_PPC_REL24 },
+            })
+            .unwrap();
+        }
+        obj.write().unwrap()
+    }
+
+    /// Builds a relocatable object whose `.text` loads `table + 4` with an `@ha`/`@l` pair and
+    /// whose `.data` holds a pointer to `table + 8`, so that relocations target the middle of
+    /// a symbol.
+    fn build_addend_module() -> Vec<u8> {
+        let mut obj = WriteObject::new(BinaryFormat::Elf, Architecture::PowerPc, Endianness::Big);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        let data = obj.add_section(vec![], b".data".to_vec(), SectionKind::Data);
+        // lis r3, 0; addi r3, r3, 0; blr
+        let mut code = vec![];
+        for ins in [0x3C600000u32, 0x38630000, 0x4E800020] {
+            code.extend_from_slice(&ins.to_be_bytes());
+        }
+        obj.append_section_data(text, &code, 4);
+        obj.append_section_data(data, &[0; 0x14], 4);
+        let table = obj.add_symbol(Symbol {
+            name: b"table".to_vec(),
+            value: 0,
+            size: 0x10,
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Dynamic,
+            weak: false,
+            section: SymbolSection::Section(data),
+            flags: SymbolFlags::None,
+        });
+        for (section, offset, r_type, addend) in [
+            (text, 2, elf::R_PPC_ADDR16_HA, 4),
+            (text, 6, elf::R_PPC_ADDR16_LO, 4),
+            (data, 0x10, elf::R_PPC_ADDR32, 8),
+        ] {
+            obj.add_relocation(section, Relocation {
+                offset,
+                symbol: table,
+                addend,
+                flags: RelocationFlags::Elf { r_type },
+            })
+            .unwrap();
+        }
+        obj.write().unwrap()
+    }
+
+    fn load_module(module_id: u32, data: &[u8]) -> LoadedModule {
+        LoadedModule {
+            module_id,
+            file: load_obj(data).unwrap(),
+            path: Utf8NativePathBuf::from(format!("module{}.plf", module_id)),
+        }
+    }
+
+    fn read_imports(data: &[u8]) -> Vec<u32> {
+        let header = process_rel_header(&mut Cursor::new(data)).unwrap();
+        let mut reader = Cursor::new(data);
+        reader.set_position(header.imp_offset as u64);
+        (0..header.imp_size / 8)
+            .map(|_| {
+                let module_id = u32::from_reader(&mut reader, Endian::Big).unwrap();
+                let _offset = u32::from_reader(&mut reader, Endian::Big).unwrap();
+                module_id
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_link_modules() {
+        let dol = build_module(&[("OSReport", 0)], &[]);
+        let module_a = build_module(&[("funcA", 0x8)], &[]);
+        let module_b = build_module(&[("funcB", 0x10)], &["funcA", "OSReport"]);
+        let modules =
+            vec![load_module(0, &dol), load_module(1, &module_a), load_module(2, &module_b)];
+
+        let (resolved, relocations) = link_modules(&modules, &BTreeMap::new()).unwrap();
+        assert_eq!(resolved, 2);
+        assert!(relocations[0].is_empty());
+        let text_index = modules[1].file.section_by_name(".text").unwrap().index().0 as u8;
+        let to_a = relocations[1].iter().find(|r| r.address == 0).unwrap();
+        assert_eq!(to_a.module_id, 1);
+        assert_eq!(to_a.target_section, text_index);
+        assert_eq!(to_a.addend, 0x8);
+        let to_dol = relocations[1].iter().find(|r| r.address == 4).unwrap();
+        assert_eq!(to_dol.module_id, 0);
+
+        let info = RelWriteInfo {
+            module_id: 2,
+            version: 3,
+            name_offset: None,
+            name_size: None,
+            align: None,
+            bss_align: None,
+            section_count: None,
+            quiet: true,
+            section_align: None,
+            section_exec: None,
+        };
+        let mut out = Cursor::new(Vec::new());
+        write_rel(&mut out, &info, &modules[2].file, relocations[1].clone()).unwrap();
+        // Version 3 RELs list other modules first and the DOL last
+        assert_eq!(read_imports(out.get_ref()), vec![1, 0]);
+
+        let (_, obj) = process_rel(&mut Cursor::new(out.get_ref()), "module2").unwrap();
+        let imported = obj.unresolved_relocations.iter().find(|r| r.module_id == 1).unwrap();
+        assert_eq!(imported.target_section, text_index);
+        assert_eq!(imported.addend, 0x8);
+    }
+
+    #[test]
+    fn test_link_modules_existing_headers() {
+        let dol = build_module(&[], &[]);
+        let module_a = build_module(&[("funcA", 0x8)], &[]);
+        let module_b = build_module(&[], &["funcA"]);
+        let modules =
+            vec![load_module(0, &dol), load_module(1, &module_a), load_module(2, &module_b)];
+
+        // Only the target module has an existing REL, so its sections are matched against
+        // module A's file rather than the importing module's
+        let info = RelWriteInfo {
+            module_id: 1,
+            version: 3,
+            name_offset: None,
+            name_size: None,
+            align: None,
+            bss_align: None,
+            section_count: None,
+            quiet: true,
+            section_align: None,
+            section_exec: None,
+        };
+        let mut out = Cursor::new(Vec::new());
+        write_rel(&mut out, &info, &modules[1].file, vec![]).unwrap();
+        let mut reader = Cursor::new(out.get_ref());
+        let header = process_rel_header(&mut reader).unwrap();
+        let sections = process_rel_sections(&mut reader, &header).unwrap();
+        let existing_headers = BTreeMap::from([(1, (header, sections, None))]);
+
+        let (_, relocations) = link_modules(&modules, &existing_headers).unwrap();
+        let text_index = modules[1].file.section_by_name(".text").unwrap().index().0 as u8;
+        let to_a = relocations[1].iter().find(|r| r.address == 0).unwrap();
+        assert_eq!(to_a.module_id, 1);
+        assert_eq!(to_a.target_section, text_index);
+        assert_eq!(to_a.addend, 0x8);
+    }
+
+    #[test]
+    fn test_link_modules_absolute_target() {
+        let mut obj = WriteObject::new(BinaryFormat::Elf, Architecture::PowerPc, Endianness::Big);
+        let text = obj.add_section(vec![], b".text".to_vec(), SectionKind::Text);
+        obj.append_section_data(text, &0x48000001u32.to_be_bytes(), 4);
+        let symbol = obj.add_symbol(Symbol {
+            name: b"absolute".to_vec(),
+            value: 0x80003100,
+            size: 0,
+            kind: SymbolKind::Text,
+            scope: SymbolScope::Compilation,
+            weak: false,
+            section: SymbolSection::Absolute,
+            flags: SymbolFlags::None,
+        });
+        obj.add_relocation(text, Relocation {
+            offset: 0,
+            symbol,
+            addend: 0,
+            flags: RelocationFlags::Elf { r_type: elf::R_PPC_REL24 },
+        })
+        .unwrap();
+        let module = obj.write().unwrap();
+
+        // A malformed module is an error rather than a panic
+        let dol = build_module(&[], &[]);
+        let modules = vec![load_module(0, &dol), load_module(1, &module)];
+        let err = link_modules(&modules, &BTreeMap::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("targets symbol absolute without a section"));
+    }
+
+    #[test]
+    fn test_link_modules_ambiguous() {
+        let dol = build_module(&[], &[]);
+        let module_a = build_module(&[("shared", 0)], &[]);
+        let module_b = build_module(&[("shared", 0)], &[]);
+        let module_c = build_module(&[], &["shared"]);
+        let module_d = build_module(&[], &["missing"]);
+
+        let modules = vec![
+            load_module(0, &dol),
+            load_module(1, &module_a),
+            load_module(2, &module_b),
+            load_module(3, &module_c),
+        ];
+        let err = link_modules(&modules, &BTreeMap::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("Ambiguous symbol shared"));
+
+        let modules =
+            vec![load_module(0, &dol), load_module(1, &module_a), load_module(4, &module_d)];
+        let err = link_modules(&modules, &BTreeMap::new()).unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to find symbol missing"));
+    }
+
+    /// Loads a REL and resolves its internal relocations to symbols with the same passes
+    /// `dol split` uses, then writes the result as an ELF and reloads it. Every relocation must
+    /// still resolve to its original target. Returns the number of relocations checked.
+    fn check_rel_round_trip(data: &[u8]) -> Result<usize> {
+        let (_, mut obj) = process_rel(&mut Cursor::new(data), "module")?;
+        // Cover each section with a single symbol, so that targets resolve with an addend
+        let symbols = obj
+            .sections
+            .iter()
+            .map(|(section_index, section)| {
+                test_symbol(
+                    &format!("section{}", section.elf_index),
+                    Some(section_index),
+                    section.address,
+                    section.size,
+                    ObjSymbolKind::Object,
+                )
+            })
+            .collect::<Vec<_>>();
+        for symbol in symbols {
+            obj.symbols.add_direct(symbol)?;
+        }
+
+        // Relocations to other modules can't be resolved without them
+        let module_id = obj.module_id;
+        obj.unresolved_relocations.retain(|r| r.module_id == module_id);
+        // (source section, address) -> (target section, target address)
+        let mut expected = BTreeMap::<(String, u32), (String, i64)>::new();
+        for rel_reloc in &obj.unresolved_relocations {
+            let (_, source_section) = obj
+                .sections
+                .get_elf_index(rel_reloc.section as ObjSectionIndex)
+                .with_context(|| format!("Missing source section {}", rel_reloc.section))?;
+            let (_, target_section) = obj
+                .sections
+                .get_elf_index(rel_reloc.target_section as ObjSectionIndex)
+                .with_context(|| format!("Missing target section {}", rel_reloc.target_section))?;
+            expected.insert(
+                (source_section.name.clone(), rel_reloc.address),
+                (target_section.name.clone(), rel_reloc.addend as i64),
+            );
+        }
+        let dol_obj =
+            ObjInfo::new(ObjKind::Executable, ObjArchitecture::PowerPc, "".into(), vec![], vec![]);
+        update_symbols(&mut obj, &[], true)?;
+        create_relocations(&mut obj, &ModuleMapById::new(), &dol_obj)?;
+
+        let reloaded = parse_elf(&write_elf(&obj, false)?)?;
+        let mut checked = 0;
+        for (_, section) in reloaded.sections.iter() {
+            for (address, reloc) in section.relocations.iter() {
+                // 16-bit relocations point at the low half of the instruction
+                let (target_section, target) =
+                    expected.get(&(section.name.clone(), address & !3)).with_context(|| {
+                        format!("Unexpected relocation at {}:{:#X}", section.name, address)
+                    })?;
+                let symbol = &reloaded.symbols[reloc.target_symbol];
+                let section_name =
+                    symbol.section.map_or("ABS", |idx| reloaded.sections[idx].name.as_str());
+                let actual = reloc.target_address(&reloaded.symbols);
+                ensure!(
+                    section_name == target_section && actual == *target,
+                    "Relocation at {}:{:#X} drifted: expected {}:{:#X}, found {}:{:#X} ({}+{:#X})",
+                    section.name,
+                    address,
+                    target_section,
+                    target,
+                    section_name,
+                    actual,
+                    symbol.name,
+                    reloc.addend
+                );
+                checked += 1;
+            }
+        }
+        ensure!(
+            checked == expected.len(),
+            "Expected {} relocations, found {}",
+            expected.len(),
+            checked
+        );
+        Ok(checked)
+    }
+
+    #[test]
+    fn test_rel_round_trip() {
+        let dol = build_module(&[], &[]);
+        let module = build_addend_module();
+        let modules = vec![load_module(0, &dol), load_module(1, &module)];
+        let (_, relocations) = link_modules(&modules, &BTreeMap::new()).unwrap();
+        let info = RelWriteInfo {
+            module_id: 1,
+            version: 3,
+            name_offset: None,
+            name_size: None,
+            align: None,
+            bss_align: None,
+            section_count: None,
+            quiet: true,
+            section_align: None,
+            section_exec: None,
+        };
+        let mut out = Cursor::new(Vec::new());
+        write_rel(&mut out, &info, &modules[1].file, relocations[0].clone()).unwrap();
+        assert_eq!(check_rel_round_trip(out.get_ref()).unwrap(), 3);
+    }
+
+    /// Run with `DTK_TEST_REL=path/to/module.rel cargo test -- --ignored test_rel_round_trip_file`
+    #[test]
+    #[ignore]
+    fn test_rel_round_trip_file() {
+        let path = std::env::var("DTK_TEST_REL").expect("DTK_TEST_REL not set");
+        let data = fs::read(path).unwrap();
+        let (_, obj) = process_rel(&mut Cursor::new(&data), "module").unwrap();
+        let internal =
+            obj.unresolved_relocations.iter().filter(|r| r.module_id == obj.module_id).count();
+        assert_eq!(check_rel_round_trip(&data).unwrap(), internal);
+    }
+}
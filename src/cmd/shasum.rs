@@ -1,18 +1,23 @@
 use std::{
     fs::File,
-    io::{stdout, BufRead, Read, Write},
+    io::{stdout, BufRead, Cursor, Read, Write},
 };
 
 use anyhow::{anyhow, bail, Context, Result};
 use argp::FromArgs;
 use owo_colors::{OwoColorize, Stream};
+use serde::Serialize;
 use sha1::{Digest, Sha1};
 use typed_path::{Utf8NativePath, Utf8NativePathBuf};
 
 use crate::{
+    obj::{ObjInfo, ObjSectionKind},
     util::{
+        dol::process_dol,
+        elf::parse_elf,
         file::{buf_writer, process_rsp, touch},
         path::native_path,
+        rel::process_rel,
     },
     vfs::open_file,
 };
@@ -34,6 +39,15 @@ pub struct Args {
     #[argp(switch, short = 'q')]
     /// only print failures and a summary
     quiet: bool,
+    #[argp(switch)]
+    /// (hash) print a hash for each section of a DOL, REL or ELF
+    sections: bool,
+    #[argp(switch)]
+    /// (hash) output section hashes as JSON
+    json: bool,
+    #[argp(switch)]
+    /// (hash) zero relocated bits before hashing sections
+    mask_relocs: bool,
 }
 
 const DEFAULT_BUF_SIZE: usize = 8192;
@@ -57,14 +71,100 @@ pub fn run(args: Args) -> Result<()> {
         } else {
             Box::new(stdout())
         };
-        for path in process_rsp(&args.files)? {
-            let mut file = open_file(&path, false)?;
-            hash(w.as_mut(), file.as_mut(), &path)?
+        if args.sections {
+            let mut files = vec![];
+            for path in process_rsp(&args.files)? {
+                let mut file = open_file(&path, false)?;
+                let obj = load_obj(file.map()?, &path)?;
+                let sections = section_hashes(&obj, args.mask_relocs)?;
+                if !args.json {
+                    for section in &sections {
+                        writeln!(
+                            w,
+                            "{}  {:#010X}  {}:{}",
+                            section.sha1,
+                            section.size,
+                            path.with_unix_encoding(),
+                            section.name
+                        )?;
+                    }
+                }
+                files.push(FileSectionHashes {
+                    path: path.with_unix_encoding().to_string(),
+                    sections,
+                });
+            }
+            if args.json {
+                serde_json::to_writer_pretty(&mut w, &files)?;
+                writeln!(w)?;
+            }
+        } else {
+            for path in process_rsp(&args.files)? {
+                let mut file = open_file(&path, false)?;
+                hash(w.as_mut(), file.as_mut(), &path)?
+            }
         }
+        w.flush()?;
     }
     Ok(())
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct FileSectionHashes {
+    pub path: String,
+    pub sections: Vec<SectionHash>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct SectionHash {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+    /// SHA-1 of the section data. BSS sections hash their size instead.
+    pub sha1: String,
+}
+
+/// Loads a DOL, REL or ELF. RELs are detected by file extension.
+fn load_obj(data: &[u8], path: &Utf8NativePath) -> Result<ObjInfo> {
+    if data.starts_with(b"\x7FELF") {
+        return parse_elf(data);
+    }
+    let name = path.file_stem().unwrap_or_default();
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("rel")) {
+        let (_, obj) = process_rel(&mut Cursor::new(data), name)?;
+        Ok(obj)
+    } else {
+        process_dol(data, name)
+    }
+}
+
+/// Hashes each section using the loaded section data, so that differences in file layout
+/// and padding between sections don't affect the result.
+/// With `mask_relocs`, the bits written by relocations are zeroed first, so that sections
+/// only differing in relocation targets hash equal.
+pub fn section_hashes(obj: &ObjInfo, mask_relocs: bool) -> Result<Vec<SectionHash>> {
+    let mut result = Vec::with_capacity(obj.sections.len() as usize);
+    for (_, section) in obj.sections.iter() {
+        let hash = if section.kind == ObjSectionKind::Bss {
+            Sha1::digest(section.size.to_be_bytes())
+        } else if mask_relocs {
+            Sha1::digest(section.masked_data(obj.endian))
+        } else {
+            Sha1::digest(&section.data)
+        };
+        let mut hash_buf = [0u8; 40];
+        let hash_str = base16ct::lower::encode_str(&hash, &mut hash_buf)
+            .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
+        result.push(SectionHash {
+            name: section.name.clone(),
+            address: section.address,
+            size: section.size,
+            sha1: hash_str.to_string(),
+        });
+    }
+    Ok(result)
+}
+
 fn check<R>(args: &Args, reader: &mut R) -> Result<()>
 where R: BufRead + ?Sized {
     let mut matches = 0usize;
@@ -150,3 +250,65 @@ where R: Read + ?Sized {
         .map_err(|e| anyhow!("Failed to encode hash: {e}"))?;
     Ok(hash_str.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{
+        test_section_data, ObjArchitecture, ObjKind, ObjReloc, ObjRelocKind, ObjRelocations,
+    };
+
+    fn test_obj(data: Vec<u8>) -> ObjInfo {
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![],
+            vec![
+                test_section_data(".text", ObjSectionKind::Code, 0x80003100, vec![
+                    0x4E, 0x80, 0x00, 0x20,
+                ]),
+                test_section_data(".data", ObjSectionKind::Data, 0x80003200, data),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_section_hashes() {
+        let a = section_hashes(&test_obj(vec![1, 2, 3, 4]), false).unwrap();
+        let b = section_hashes(&test_obj(vec![1, 2, 3, 5]), false).unwrap();
+        assert_eq!(a.len(), 2);
+        assert_eq!(a[0], b[0]);
+        assert_eq!(a[1].name, ".data");
+        assert_ne!(a[1].sha1, b[1].sha1);
+        assert_eq!(a[1].sha1, "12dada1fff4d4787ade3333147202c3b443e376f");
+    }
+
+    #[test]
+    fn test_section_hashes_mask_relocs() {
+        let reloc = |kind| ObjReloc { kind, target_symbol: 0, addend: 0, module: None };
+        let obj = |bl: u32, addi: u32, ptr: u32| {
+            let mut obj = test_obj(ptr.to_be_bytes().to_vec());
+            let text = &mut obj.sections[0];
+            text.data = [bl, addi].iter().flat_map(|w| w.to_be_bytes()).collect();
+            text.size = 8;
+            text.relocations = ObjRelocations::new(vec![
+                (0x80003100, reloc(ObjRelocKind::PpcRel24)),
+                (0x80003104, reloc(ObjRelocKind::PpcAddr16Lo)),
+            ])
+            .unwrap();
+            obj.sections[1].relocations =
+                ObjRelocations::new(vec![(0x80003200, reloc(ObjRelocKind::Absolute))]).unwrap();
+            obj
+        };
+        // bl, addi r3, r3, x@l and a pointer, relocated against different targets
+        let a = obj(0x48000101, 0x38631234, 0x80004000);
+        let b = obj(0x48000F01, 0x38635678, 0x80005000);
+        assert_ne!(section_hashes(&a, false).unwrap(), section_hashes(&b, false).unwrap());
+        assert_eq!(section_hashes(&a, true).unwrap(), section_hashes(&b, true).unwrap());
+
+        // Bits outside of the relocation still count (addi r4 instead of r3)
+        let c = obj(0x48000101, 0x38831234, 0x80004000);
+        assert_ne!(section_hashes(&a, true).unwrap()[0], section_hashes(&c, true).unwrap()[0]);
+    }
+}
@@ -83,6 +83,21 @@ struct TopLevel {
     /// Disable color output. (env: NO_COLOR)
     #[argp(switch)]
     no_color: bool,
+    /// Maximum decompressed size in bytes for Yaz0/Yay0 inputs. (Default: 512 MiB)
+    #[argp(option)]
+    max_decompressed_size: Option<usize>,
+    /// Minimum confidence (0.0-1.0) for signature matches. (Default: 1.0, exact matches only)
+    #[argp(option, from_str_fn(util::signatures::parse_confidence))]
+    min_confidence: Option<f32>,
+    /// Number of threads used for parallel work. (Default: number of logical CPUs)
+    #[argp(option)]
+    threads: Option<usize>,
+    /// Log progress of long-running analysis passes.
+    #[argp(switch)]
+    progress: bool,
+    /// Accept little-endian ELF inputs.
+    #[argp(switch)]
+    allow_little_endian: bool,
 }
 
 #[derive(FromArgs, Debug)]
@@ -93,11 +108,13 @@ enum SubCommand {
     Demangle(cmd::demangle::Args),
     Disc(cmd::disc::Args),
     Dol(cmd::dol::Args),
+    Dol2Elf(cmd::dol2elf::Args),
     Dwarf(cmd::dwarf::Args),
     Elf(cmd::elf::Args),
     Elf2Dol(cmd::elf2dol::Args),
     Map(cmd::map::Args),
     Nlzss(cmd::nlzss::Args),
+    Obj(cmd::obj::Args),
     Rarc(cmd::rarc::Args),
     Rel(cmd::rel::Args),
     Rso(cmd::rso::Args),
@@ -156,6 +173,19 @@ fn main() {
             .init();
     }
 
+    if let Some(size) = args.max_decompressed_size {
+        util::ncompress::set_max_decompressed_size(size);
+    }
+    if let Some(confidence) = args.min_confidence {
+        util::signatures::set_min_confidence(confidence);
+    }
+    if args.progress {
+        analysis::progress::set_progress_callback(Some(analysis::progress::log_progress()));
+    }
+    if args.allow_little_endian {
+        util::elf::set_allow_little_endian(true);
+    }
+
     let mut result = Ok(());
     if let Some(dir) = &args.chdir {
         result = env::set_current_dir(dir).map_err(|e| {
@@ -163,17 +193,27 @@ fn main() {
                 .context(format!("Failed to change working directory to '{}'", dir.display()))
         });
     }
+    if let Some(threads) = args.threads {
+        result = result.and_then(|_| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build_global()
+                .map_err(|e| Error::new(e).context("Failed to configure the thread pool"))
+        });
+    }
     result = result.and_then(|_| match args.command {
         SubCommand::Alf(c_args) => cmd::alf::run(c_args),
         SubCommand::Ar(c_args) => cmd::ar::run(c_args),
         SubCommand::Demangle(c_args) => cmd::demangle::run(c_args),
         SubCommand::Disc(c_args) => cmd::disc::run(c_args),
         SubCommand::Dol(c_args) => cmd::dol::run(c_args),
+        SubCommand::Dol2Elf(c_args) => cmd::dol2elf::run(c_args),
         SubCommand::Dwarf(c_args) => cmd::dwarf::run(c_args),
         SubCommand::Elf(c_args) => cmd::elf::run(c_args),
         SubCommand::Elf2Dol(c_args) => cmd::elf2dol::run(c_args),
         SubCommand::Map(c_args) => cmd::map::run(c_args),
         SubCommand::Nlzss(c_args) => cmd::nlzss::run(c_args),
+        SubCommand::Obj(c_args) => cmd::obj::run(c_args),
         SubCommand::Rarc(c_args) => cmd::rarc::run(c_args),
         SubCommand::Rel(c_args) => cmd::rel::run(c_args),
         SubCommand::Rso(c_args) => cmd::rso::run(c_args),
@@ -14,18 +14,21 @@ use anyhow::{anyhow, bail, ensure, Result};
 use objdiff_core::obj::split_meta::SplitMeta;
 pub use relocations::{ObjReloc, ObjRelocKind, ObjRelocations};
 pub use sections::{
-    section_kind_for_section, ObjSection, ObjSectionKind, ObjSections, SectionIndex,
+    check_section_overlap, default_section_aliases, is_thread_local_name, section_kind_for_section,
+    ObjSection, ObjSectionKind, ObjSections, SectionIndex, DEFAULT_SECTION_ALIASES,
 };
 pub use splits::{ObjSplit, ObjSplits};
 pub use symbols::{
-    best_match_for_reloc, ObjDataKind, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
-    ObjSymbolScope, ObjSymbols, SymbolIndex,
+    best_match_for_reloc_with, relocation_targets, DefaultRelocResolver, DuplicateSymbolError,
+    HiddenExport, ObjDataKind, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
+    ObjSymbolScope, ObjSymbols, RelocResolver, RelocTieBreak, RelocationDiagnostics,
+    RelocationMiss, RelocationMissReason, SizeConflict, SymbolIndex, SymbolRenameTransform,
 };
 
 use crate::{
     analysis::cfa::SectionAddress,
     obj::addresses::AddressRanges,
-    util::{comment::MWComment, rel::RelReloc},
+    util::{comment::MWComment, reader::Endian, rel::RelReloc},
 };
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -41,6 +44,17 @@ pub enum ObjArchitecture {
     PowerPc,
 }
 
+/// A relocation that resolves to a symbol or somewhere inside of it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ObjReference {
+    /// Address of the relocation.
+    pub source: SectionAddress,
+    pub kind: ObjRelocKind,
+    /// Offset of the relocation target from the start of the symbol. Negative for a direct
+    /// reference with a negative addend.
+    pub offset: i64,
+}
+
 /// Translation unit information.
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ObjUnit {
@@ -57,11 +71,15 @@ pub struct ObjUnit {
 pub struct ObjInfo {
     pub kind: ObjKind,
     pub architecture: ObjArchitecture,
+    /// Byte order of section data and ELF structures. Big endian for GameCube/Wii.
+    pub endian: Endian,
     pub name: String,
     pub symbols: ObjSymbols,
     pub sections: ObjSections,
     pub entry: Option<u64>,
     pub mw_comment: Option<MWComment>,
+    /// Producer strings from a non-CodeWarrior `.comment` section, in order
+    pub producers: Vec<String>,
     pub split_meta: Option<SplitMeta>,
 
     // Linker generated
@@ -80,6 +98,8 @@ pub struct ObjInfo {
 
     // From .ctors, .dtors and extab
     pub known_functions: BTreeMap<SectionAddress, Option<u32>>,
+    /// Stack frame sizes of functions with a standard `stwu r1, -N(r1)` prologue
+    pub frame_sizes: BTreeMap<SectionAddress, u32>,
 
     // REL
     /// Module ID (0 for main)
@@ -95,14 +115,18 @@ impl ObjInfo {
         symbols: Vec<ObjSymbol>,
         sections: Vec<ObjSection>,
     ) -> Self {
+        let mut symbols = ObjSymbols::new(kind, symbols);
+        symbols.reserve_sections(sections.len());
         Self {
             kind,
             architecture,
+            endian: Endian::Big,
             name,
-            symbols: ObjSymbols::new(kind, symbols),
+            symbols,
             sections: ObjSections::new(kind, sections),
             entry: None,
             mw_comment: Default::default(),
+            producers: vec![],
             split_meta: None,
             sda2_base: None,
             sda_base: None,
@@ -115,12 +139,13 @@ impl ObjInfo {
             blocked_relocation_sources: Default::default(),
             blocked_relocation_targets: Default::default(),
             known_functions: Default::default(),
+            frame_sizes: Default::default(),
             module_id: 0,
             unresolved_relocations: vec![],
         }
     }
 
-    pub fn add_symbol(&mut self, in_symbol: ObjSymbol, replace: bool) -> Result<SymbolIndex> {
+    pub fn add_symbol(&mut self, mut in_symbol: ObjSymbol, replace: bool) -> Result<SymbolIndex> {
         match in_symbol.name.as_str() {
             "_SDA_BASE_" => self.sda_base = Some(in_symbol.address as u32),
             "_SDA2_BASE_" => self.sda2_base = Some(in_symbol.address as u32),
@@ -131,9 +156,65 @@ impl ObjInfo {
             "__ArenaHi" => self.arena_hi = Some(in_symbol.address as u32),
             _ => {}
         }
+        // Promote a label through `set_symbol_kind`, so relocations targeting it are re-checked.
+        // If they aren't valid for the incoming kind, the label keeps its kind.
+        if let Some((symbol_index, existing)) = self.symbols.merge_target(&in_symbol)? {
+            if existing.kind == ObjSymbolKind::Unknown && !existing.flags.is_locked() {
+                if let Err(e) = self.set_symbol_kind(symbol_index, in_symbol.kind) {
+                    log::warn!("{:#}", e);
+                    in_symbol.kind = ObjSymbolKind::Unknown;
+                }
+            }
+        }
         self.symbols.add(in_symbol, replace)
     }
 
+    /// Changes the kind of a symbol, ensuring that all relocations targeting
+    /// the symbol are still valid for the new kind.
+    pub fn set_symbol_kind(
+        &mut self,
+        symbol_index: SymbolIndex,
+        kind: ObjSymbolKind,
+    ) -> Result<()> {
+        let symbol = self
+            .symbols
+            .get(symbol_index)
+            .ok_or_else(|| anyhow!("Invalid symbol index {}", symbol_index))?;
+        if symbol.kind == kind {
+            return Ok(());
+        }
+        ensure!(
+            !symbol.flags.is_locked(),
+            "Can't change kind of {} to {:?}: symbol is locked",
+            symbol.name,
+            kind
+        );
+        let new_symbol = ObjSymbol { kind, ..symbol.clone() };
+        let in_sda = new_symbol
+            .section
+            .and_then(|section_index| self.sections.get(section_index))
+            .is_some_and(|section| section.is_small_data());
+        for (_, section) in self.sections.iter() {
+            for (address, reloc) in section.relocations.iter() {
+                if reloc.target_symbol != symbol_index
+                    || reloc.module.is_some_and(|module_id| module_id != self.module_id)
+                {
+                    continue;
+                }
+                ensure!(
+                    new_symbol.referenced_by(reloc.kind, in_sda),
+                    "Can't change kind of {} to {:?}: referenced by {:?} relocation at {} {:#010X}",
+                    new_symbol.name,
+                    kind,
+                    reloc.kind,
+                    section.name,
+                    address
+                );
+            }
+        }
+        self.symbols.replace(symbol_index, new_symbol)
+    }
+
     pub fn add_split(
         &mut self,
         section_index: SectionIndex,
@@ -310,6 +391,231 @@ impl ObjInfo {
             .all(|(_, _, _, split)| split.autogenerated)
     }
 
+    /// Ensures every section targeted by an `@ha`/`@hi`/`@lo` relocation has a section symbol
+    /// at its base, so section-relative relocations can be emitted against it.
+    /// Returns the number of symbols created.
+    pub fn ensure_section_symbols(&mut self) -> Result<usize> {
+        let mut target_sections = BTreeSet::<SectionIndex>::new();
+        for (_, section) in self.sections.iter() {
+            for (_, reloc) in section.relocations.iter() {
+                if !matches!(
+                    reloc.kind,
+                    ObjRelocKind::PpcAddr16Ha
+                        | ObjRelocKind::PpcAddr16Hi
+                        | ObjRelocKind::PpcAddr16Lo
+                ) || reloc.module.is_some_and(|module_id| module_id != self.module_id)
+                {
+                    continue;
+                }
+                if let Some(section_index) = self.symbols[reloc.target_symbol].section {
+                    target_sections.insert(section_index);
+                }
+            }
+        }
+
+        let mut created = 0;
+        for section_index in target_sections {
+            let section = &self.sections[section_index];
+            if self
+                .symbols
+                .at_section_address(section_index, section.address as u32)
+                .any(|(_, symbol)| symbol.kind == ObjSymbolKind::Section)
+            {
+                continue;
+            }
+            log::debug!("Adding section symbol for {}", section.name);
+            self.symbols.add_direct(ObjSymbol {
+                name: section.name.clone(),
+                address: section.address,
+                section: Some(section_index),
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Local.into()),
+                kind: ObjSymbolKind::Section,
+                ..Default::default()
+            })?;
+            created += 1;
+        }
+        Ok(created)
+    }
+
+    /// Finds all relocations that resolve to the given symbol, including those targeting
+    /// its interior through another symbol (e.g. a section symbol plus addend).
+    /// Relocations against other modules are ignored.
+    pub fn references_to(&self, symbol_index: SymbolIndex) -> Vec<ObjReference> {
+        let symbol = &self.symbols[symbol_index];
+        let start = symbol.address as i64;
+        let end = start + max(symbol.size, 1) as i64;
+        let mut references = vec![];
+        for (section_index, section) in self.sections.iter() {
+            for (address, reloc) in section.relocations.iter() {
+                if reloc.module.is_some_and(|id| id != self.module_id) {
+                    continue;
+                }
+                let offset = if reloc.target_symbol == symbol_index {
+                    reloc.addend
+                } else {
+                    // Undefined symbols have no interior, and would otherwise all match each other
+                    let target = &self.symbols[reloc.target_symbol];
+                    let target_address = reloc.target_address(&self.symbols);
+                    if symbol.section.is_none()
+                        || target.section != symbol.section
+                        || !(start..end).contains(&target_address)
+                    {
+                        continue;
+                    }
+                    target_address - start
+                };
+                references.push(ObjReference {
+                    source: SectionAddress::new(section_index, address),
+                    kind: reloc.kind,
+                    offset,
+                });
+            }
+        }
+        references
+    }
+
+    /// Checks that every SDA21 relocation targets a small data section and lies within
+    /// the signed 16-bit range of its base: `_SDA_BASE_` for `.sdata`/`.sbss`,
+    /// `_SDA2_BASE_` for `.sdata2`/`.sbss2` and r0 for `.PPC.EMB.sdata0`/`.PPC.EMB.sbss0`.
+    /// Ranges aren't checked against unknown bases. All violations are reported together.
+    pub fn check_sda21_relocations(&self) -> Result<()> {
+        let mut errors = vec![];
+        for (section_index, section) in self.sections.iter() {
+            for (address, reloc) in section.relocations.iter() {
+                if reloc.kind != ObjRelocKind::PpcEmbSda21
+                    || reloc.module.is_some_and(|id| id != self.module_id)
+                {
+                    continue;
+                }
+                let target = &self.symbols[reloc.target_symbol];
+                let Some(target_section_index) = target.section else {
+                    // Absolute symbols are addressed relative to r0
+                    continue;
+                };
+                let source =
+                    self.symbols.format_address(SectionAddress::new(section_index, address));
+                let (base_name, base) = match self.sections[target_section_index].name.as_str() {
+                    ".sdata" | ".sbss" => ("_SDA_BASE_", self.sda_base),
+                    ".sdata2" | ".sbss2" => ("_SDA2_BASE_", self.sda2_base),
+                    ".PPC.EMB.sdata0" | ".PPC.EMB.sbss0" => ("r0", Some(0)),
+                    name => {
+                        errors.push(format!(
+                            "SDA21 relocation at {} targets {} in section {}, expected .sdata, \
+                             .sbss, .sdata2, .sbss2, .PPC.EMB.sdata0 or .PPC.EMB.sbss0",
+                            source, target.name, name
+                        ));
+                        continue;
+                    }
+                };
+                let Some(base) = base else {
+                    continue;
+                };
+                let offset = reloc.target_address(&self.symbols) - base as i64;
+                if !(i16::MIN as i64..=i16::MAX as i64).contains(&offset) {
+                    errors.push(format!(
+                        "SDA21 relocation at {} to {} is out of range: offset {}{:#X} from {} \
+                         ({:#010X}) does not fit in 16 bits",
+                        source,
+                        target.name,
+                        if offset < 0 { "-" } else { "" },
+                        offset.unsigned_abs(),
+                        base_name,
+                        base
+                    ));
+                }
+            }
+        }
+        ensure!(
+            errors.is_empty(),
+            "{} invalid SDA21 relocation(s):\n{}",
+            errors.len(),
+            errors.join("\n")
+        );
+        Ok(())
+    }
+
+    /// Synthesizes missing `_SDA_BASE_` and `_SDA2_BASE_` symbols from the small data section
+    /// layout: each base lies 0x8000 past the start of `.sdata` and `.sdata2` respectively.
+    /// `.sbss`/`.sbss2` alone don't determine the base, so nothing is synthesized without the
+    /// data section. Existing bases must match the layout. Returns the number of symbols added.
+    pub fn synthesize_sda_bases(&mut self) -> Result<usize> {
+        let mut added = 0;
+        for (name, section_name) in [("_SDA_BASE_", ".sdata"), ("_SDA2_BASE_", ".sdata2")] {
+            let Some((_, section)) = self.sections.by_name(section_name)? else {
+                continue;
+            };
+            let expected = section.address as u32 + 0x8000;
+            let existing = match name {
+                "_SDA_BASE_" => self.sda_base,
+                _ => self.sda2_base,
+            }
+            .or_else(|| self.symbols.by_name(name).ok().flatten().map(|(_, s)| s.address as u32));
+            if let Some(existing) = existing {
+                ensure!(
+                    existing == expected,
+                    "{} at {:#010X} doesn't match the expected {:#010X} ({} + 0x8000)",
+                    name,
+                    existing,
+                    expected,
+                    section.name
+                );
+                continue;
+            }
+            self.add_symbol(
+                ObjSymbol {
+                    name: name.to_string(),
+                    address: expected as u64,
+                    size_known: true,
+                    flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                    ..Default::default()
+                },
+                true,
+            )?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Renames sections using `aliases` (input name to canonical name), along with their
+    /// section symbols. Section indices are unchanged. The section kind is updated when the
+    /// canonical name implies one. Returns the number of sections renamed.
+    pub fn apply_section_aliases(&mut self, aliases: &BTreeMap<String, String>) -> Result<usize> {
+        let mut renamed = 0;
+        for section_index in 0..self.sections.len() {
+            let name = &self.sections[section_index].name;
+            let Some(canonical) = aliases.get(name).filter(|&canonical| canonical != name) else {
+                continue;
+            };
+            ensure!(
+                self.sections.iter().all(|(_, s)| s.name != *canonical),
+                "Can't rename section {} to {}: section already exists",
+                name,
+                canonical
+            );
+            log::debug!("Renaming section {} to {}", name, canonical);
+            let section = &mut self.sections[section_index];
+            let old_name = std::mem::replace(&mut section.name, canonical.clone());
+            if let Ok(kind) = section_kind_for_section(canonical) {
+                section.kind = kind;
+            }
+            section.thread_local = is_thread_local_name(canonical);
+            let section_symbols = self
+                .symbols
+                .for_section(section_index)
+                .filter(|(_, s)| s.kind == ObjSymbolKind::Section && s.name == old_name)
+                .map(|(idx, _)| idx)
+                .collect::<Vec<_>>();
+            for symbol_index in section_symbols {
+                let mut symbol = self.symbols[symbol_index].clone();
+                symbol.name.clone_from(canonical);
+                self.symbols.replace(symbol_index, symbol)?;
+            }
+            renamed += 1;
+        }
+        Ok(renamed)
+    }
+
     /// Calculate the total size of all code sections.
     pub fn code_size(&self) -> u32 {
         self.sections
@@ -335,3 +641,352 @@ impl ObjInfo {
             .sum()
     }
 }
+
+/// Creates a section for tests, zero-filled unless it's BSS.
+#[cfg(test)]
+pub fn test_section(name: &str, kind: ObjSectionKind, address: u64, size: u64) -> ObjSection {
+    let data = if kind == ObjSectionKind::Bss { vec![] } else { vec![0; size as usize] };
+    ObjSection { size, ..test_section_data(name, kind, address, data) }
+}
+
+/// Creates a section for tests with the given contents.
+#[cfg(test)]
+pub fn test_section_data(
+    name: &str,
+    kind: ObjSectionKind,
+    address: u64,
+    data: Vec<u8>,
+) -> ObjSection {
+    ObjSection {
+        name: name.to_string(),
+        kind,
+        address,
+        size: data.len() as u64,
+        data,
+        align: 4,
+        elf_index: 0,
+        relocations: Default::default(),
+        virtual_address: None,
+        file_offset: 0,
+        section_known: true,
+        thread_local: false,
+        verbatim: false,
+        splits: Default::default(),
+    }
+}
+
+/// Creates a symbol for tests. The size is known if it's non-zero.
+#[cfg(test)]
+pub fn test_symbol(
+    name: &str,
+    section: Option<SectionIndex>,
+    address: u64,
+    size: u64,
+    kind: ObjSymbolKind,
+) -> ObjSymbol {
+    ObjSymbol {
+        name: name.to_string(),
+        address,
+        section,
+        size,
+        size_known: size > 0,
+        kind,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_section_symbols() {
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![test_symbol("sValue", Some(1), 0x8, 4, ObjSymbolKind::Object)],
+            vec![
+                test_section(".text", ObjSectionKind::Code, 0, 0x10),
+                test_section(".sdata", ObjSectionKind::Data, 0, 0x10),
+            ],
+        );
+        obj.sections[0]
+            .relocations
+            .insert(0x0, ObjReloc {
+                kind: ObjRelocKind::PpcAddr16Ha,
+                target_symbol: 0,
+                addend: 0,
+                module: None,
+            })
+            .unwrap();
+
+        assert_eq!(obj.ensure_section_symbols().unwrap(), 1);
+        let (_, symbol) = obj
+            .symbols
+            .at_section_address(1, 0)
+            .find(|(_, s)| s.kind == ObjSymbolKind::Section)
+            .unwrap();
+        assert_eq!(symbol.name, ".sdata");
+        assert!(symbol.flags.is_local());
+        // .text isn't targeted by any relocation
+        assert!(obj.symbols.at_section_address(0, 0).next().is_none());
+
+        // Running the pass again doesn't add duplicates
+        assert_eq!(obj.ensure_section_symbols().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_references_to() {
+        let symbol = |name: &str, address, size, section, kind| {
+            test_symbol(name, Some(section), address, size, kind)
+        };
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x20);
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("funcA", 0x80003000, 0x10, 0, ObjSymbolKind::Function),
+                symbol("funcB", 0x80003010, 0x10, 0, ObjSymbolKind::Function),
+                symbol("sTable", 0x80004000, 0x10, 1, ObjSymbolKind::Object),
+                symbol("lbl_80004010", 0x80004010, 0x10, 1, ObjSymbolKind::Object),
+                symbol(".data", 0x80004000, 0, 1, ObjSymbolKind::Section),
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000),
+                section(".data", ObjSectionKind::Data, 0x80004000),
+            ],
+        );
+        let reloc =
+            |kind, target_symbol, addend| ObjReloc { kind, target_symbol, addend, module: None };
+        let relocations = &mut obj.sections[0].relocations;
+        relocations.insert(0x80003004, reloc(ObjRelocKind::PpcAddr16Ha, 2, 0)).unwrap();
+        relocations.insert(0x80003008, reloc(ObjRelocKind::PpcAddr16Lo, 2, 0)).unwrap();
+        // Through the section symbol, into the middle of sTable
+        relocations.insert(0x80003014, reloc(ObjRelocKind::PpcAddr16Lo, 4, 8)).unwrap();
+        // Other symbols
+        relocations.insert(0x80003018, reloc(ObjRelocKind::PpcAddr16Lo, 3, 0)).unwrap();
+        relocations.insert(0x8000301C, reloc(ObjRelocKind::PpcRel24, 0, 0)).unwrap();
+
+        let references = obj.references_to(2);
+        assert_eq!(references.iter().map(|r| (r.source.address, r.offset)).collect::<Vec<_>>(), [
+            (0x80003004, 0),
+            (0x80003008, 0),
+            (0x80003014, 8)
+        ]);
+        let callers =
+            references.iter().map(|r| obj.symbols.format_address(r.source)).collect::<Vec<_>>();
+        assert_eq!(callers, ["funcA+0x4", "funcA+0x8", "funcB+0x4"]);
+
+        // Undefined symbols are only referenced directly, possibly with a negative addend
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.o".to_string(),
+            vec![
+                test_symbol("printf", None, 0, 0, ObjSymbolKind::Unknown),
+                test_symbol("puts", None, 0, 0, ObjSymbolKind::Unknown),
+            ],
+            vec![section(".text", ObjSectionKind::Code, 0)],
+        );
+        let relocations = &mut obj.sections[0].relocations;
+        relocations.insert(0x0, reloc(ObjRelocKind::PpcRel24, 1, 0)).unwrap();
+        relocations.insert(0x4, reloc(ObjRelocKind::PpcAddr16Lo, 0, -4)).unwrap();
+        let references = obj.references_to(0);
+        assert_eq!(references.iter().map(|r| (r.source.address, r.offset)).collect::<Vec<_>>(), [
+            (0x4, -4)
+        ]);
+    }
+
+    #[test]
+    fn test_apply_section_aliases_kind() {
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.o".to_string(),
+            vec![],
+            vec![test_section(".sdata.2", ObjSectionKind::Data, 0, 8)],
+        );
+        assert_eq!(obj.apply_section_aliases(&default_section_aliases()).unwrap(), 1);
+        assert_eq!(obj.sections[0].name, ".sdata2");
+        assert_eq!(obj.sections[0].kind, ObjSectionKind::ReadOnlyData);
+    }
+
+    #[test]
+    fn test_set_symbol_kind() {
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![test_symbol("sLabel", Some(1), 0x80400000, 0, ObjSymbolKind::Unknown)],
+            vec![
+                test_section(".text", ObjSectionKind::Code, 0x80003000, 0x10),
+                test_section(".sdata", ObjSectionKind::Data, 0x80400000, 0x10),
+            ],
+        );
+        obj.sections[0]
+            .relocations
+            .insert(0x80003000, ObjReloc {
+                kind: ObjRelocKind::PpcEmbSda21,
+                target_symbol: 0,
+                addend: 0,
+                module: None,
+            })
+            .unwrap();
+
+        // Functions can't be referenced by SDA21 relocations
+        let err = obj.set_symbol_kind(0, ObjSymbolKind::Function).unwrap_err().to_string();
+        assert!(err.contains("Can't change kind of sLabel to Function"), "{err}");
+        assert_eq!(obj.symbols[0].kind, ObjSymbolKind::Unknown);
+        // Adding one merges into the label, which keeps its kind
+        let function = test_symbol("fn_a", Some(1), 0x80400000, 0, ObjSymbolKind::Function);
+        assert_eq!(obj.add_symbol(function, false).unwrap(), 0);
+        assert_eq!(obj.symbols.count(), 1);
+        assert_eq!(obj.symbols[0].kind, ObjSymbolKind::Unknown);
+
+        // Objects can, so the label is promoted rather than duplicated
+        let object = test_symbol("sData", Some(1), 0x80400000, 4, ObjSymbolKind::Object);
+        assert_eq!(obj.add_symbol(object, false).unwrap(), 0);
+        assert_eq!(obj.symbols.count(), 1);
+        assert_eq!(obj.symbols[0].name, "sLabel");
+        assert_eq!(obj.symbols[0].kind, ObjSymbolKind::Object);
+    }
+
+    #[test]
+    fn test_add_symbol_locked_label() {
+        let label = ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Locked.into()),
+            ..test_symbol("sLocked", Some(0), 0x80400000, 0, ObjSymbolKind::Unknown)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![label],
+            vec![test_section(".sdata", ObjSectionKind::Data, 0x80400000, 0x10)],
+        );
+        let err = obj.set_symbol_kind(0, ObjSymbolKind::Object).unwrap_err().to_string();
+        assert!(err.contains("symbol is locked"), "{err}");
+
+        // Adding the same symbol still fills in the unknown kind
+        let object = test_symbol("sLocked", Some(0), 0x80400000, 4, ObjSymbolKind::Object);
+        assert_eq!(obj.add_symbol(object, false).unwrap(), 0);
+        assert_eq!(obj.symbols.count(), 1);
+        assert_eq!(obj.symbols[0].kind, ObjSymbolKind::Object);
+        assert!(obj.symbols[0].flags.is_locked());
+    }
+
+    #[test]
+    fn test_check_sda21_relocations() {
+        let symbol = |name: &str, address, section| {
+            test_symbol(name, Some(section), address, 4, ObjSymbolKind::Object)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("sNear", 0x80400010, 1),
+                symbol("sFar", 0x80408010, 1),
+                symbol("gData", 0x80300000, 2),
+            ],
+            vec![
+                test_section(".text", ObjSectionKind::Code, 0x80003000, 0x10),
+                test_section(".sdata", ObjSectionKind::Data, 0x80400000, 0x10000),
+                test_section(".data", ObjSectionKind::Data, 0x80300000, 0x10),
+            ],
+        );
+        obj.sda_base = Some(0x80400000 + 0x8000);
+        let reloc = |target_symbol| ObjReloc {
+            kind: ObjRelocKind::PpcEmbSda21,
+            target_symbol,
+            addend: 0,
+            module: None,
+        };
+
+        obj.sections[0].relocations.insert(0x80003000, reloc(0)).unwrap();
+        obj.check_sda21_relocations().unwrap();
+
+        obj.sections[0].relocations.insert(0x80003004, reloc(1)).unwrap();
+        let err = obj.check_sda21_relocations().unwrap_err().to_string();
+        assert!(err.contains("to sFar is out of range: offset 0x8010 from _SDA_BASE_"), "{err}");
+
+        obj.sda_base = Some(0x80408020);
+        let err = obj.check_sda21_relocations().unwrap_err().to_string();
+        assert!(err.contains("to sNear is out of range: offset -0x8010"), "{err}");
+
+        obj.sda_base = Some(0x80404000);
+        obj.sections[0].relocations.insert(0x80003008, reloc(2)).unwrap();
+        let err = obj.check_sda21_relocations().unwrap_err().to_string();
+        assert!(err.contains("targets gData in section .data"), "{err}");
+
+        // All violations are reported at once
+        obj.sda_base = Some(0x80408020);
+        let err = obj.check_sda21_relocations().unwrap_err().to_string();
+        assert!(err.starts_with("2 invalid SDA21 relocation(s)"), "{err}");
+        assert!(err.contains("to sNear is out of range"), "{err}");
+        assert!(err.contains("targets gData in section .data"), "{err}");
+
+        // r0-relative small data is addressed from 0
+        obj.sections[2].name = ".PPC.EMB.sdata0".to_string();
+        obj.sections[2].address = 0x7000;
+        obj.symbols.replace(2, ObjSymbol { address: 0x7000, ..obj.symbols[2].clone() }).unwrap();
+        obj.sda_base = Some(0x80404000);
+        obj.check_sda21_relocations().unwrap();
+    }
+
+    #[test]
+    fn test_synthesize_sda_bases() {
+        let section =
+            |name: &str, address| test_section(name, ObjSectionKind::Data, address, 0x100);
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![ObjSymbol {
+                name: "_SDA2_BASE_".to_string(),
+                address: 0x80418000,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                ..Default::default()
+            }],
+            vec![
+                section(".sdata", 0x80400000),
+                section(".sdata2", 0x80410000),
+                section(".sbss", 0x80420000),
+            ],
+        );
+
+        // Only the missing _SDA_BASE_ is added
+        assert_eq!(obj.synthesize_sda_bases().unwrap(), 1);
+        assert_eq!(obj.sda_base, Some(0x80408000));
+        let (_, sda_base) = obj.symbols.by_name("_SDA_BASE_").unwrap().unwrap();
+        assert_eq!(sda_base.address, 0x80408000);
+        assert_eq!(sda_base.section, None);
+        // Linker generated, so only referenced by @ha/@h/@l relocations
+        assert!(sda_base.referenced_by(ObjRelocKind::PpcAddr16Ha, false));
+        assert!(!sda_base.referenced_by(ObjRelocKind::PpcEmbSda21, false));
+        assert_eq!(obj.synthesize_sda_bases().unwrap(), 0);
+
+        // Existing bases must match the layout
+        obj.sda2_base = Some(0x80420000);
+        let err = obj.synthesize_sda_bases().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "_SDA2_BASE_ at 0x80420000 doesn't match the expected 0x80418000 (.sdata2 + 0x8000)"
+        );
+
+        // .sbss alone doesn't determine the base
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![],
+            vec![section(".sdata2", 0x80410000), section(".sbss", 0x80420000)],
+        );
+        assert_eq!(obj.synthesize_sda_bases().unwrap(), 1);
+        assert_eq!(obj.sda_base, None);
+        assert_eq!(obj.sda2_base, Some(0x80418000));
+    }
+}
@@ -9,9 +9,12 @@ use anyhow::Result;
 use object::elf;
 use serde::{Deserialize, Serialize};
 
-use crate::obj::SymbolIndex;
+use crate::{
+    obj::{ObjSymbols, SymbolIndex},
+    util::reader::Endian,
+};
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub enum ObjRelocKind {
     Absolute,
     PpcAddr16Hi,
@@ -22,6 +25,21 @@ pub enum ObjRelocKind {
     PpcEmbSda21,
 }
 
+impl ObjRelocKind {
+    /// The bits of the relocated word written by this relocation.
+    pub fn mask(self) -> u32 {
+        match self {
+            ObjRelocKind::Absolute => 0xFFFFFFFF,
+            ObjRelocKind::PpcAddr16Hi | ObjRelocKind::PpcAddr16Ha | ObjRelocKind::PpcAddr16Lo => {
+                0xFFFF
+            }
+            ObjRelocKind::PpcRel24 => 0x3FFFFFC,
+            ObjRelocKind::PpcRel14 => 0xFFFC,
+            ObjRelocKind::PpcEmbSda21 => 0x1FFFFF,
+        }
+    }
+}
+
 impl Serialize for ObjRelocKind {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where S: serde::Serializer {
@@ -66,8 +84,19 @@ pub struct ObjReloc {
 }
 
 impl ObjReloc {
+    /// The address this relocation resolves to: the target symbol's address plus the addend.
+    #[inline]
+    pub fn target_address(&self, symbols: &ObjSymbols) -> i64 {
+        symbols[self.target_symbol].address as i64 + self.addend
+    }
+
     /// Calculates the ELF r_offset and r_type for a relocation.
-    pub fn to_elf(&self, addr: u32) -> (u64, u32) {
+    /// 16-bit relocations target the low half of the instruction, which depends on byte order.
+    pub fn to_elf(&self, addr: u32, endian: Endian) -> (u64, u32) {
+        let half_offset = match endian {
+            Endian::Big => 2,
+            Endian::Little => 0,
+        };
         let mut r_offset = addr as u64;
         let r_type = match self.kind {
             ObjRelocKind::Absolute => {
@@ -78,15 +107,15 @@ impl ObjReloc {
                 }
             }
             ObjRelocKind::PpcAddr16Hi => {
-                r_offset = (r_offset & !3) + 2;
+                r_offset = (r_offset & !3) + half_offset;
                 elf::R_PPC_ADDR16_HI
             }
             ObjRelocKind::PpcAddr16Ha => {
-                r_offset = (r_offset & !3) + 2;
+                r_offset = (r_offset & !3) + half_offset;
                 elf::R_PPC_ADDR16_HA
             }
             ObjRelocKind::PpcAddr16Lo => {
-                r_offset = (r_offset & !3) + 2;
+                r_offset = (r_offset & !3) + half_offset;
                 elf::R_PPC_ADDR16_LO
             }
             ObjRelocKind::PpcRel24 => {
@@ -1,6 +1,6 @@
 use std::{
     cmp::min,
-    collections::Bound,
+    collections::{BTreeMap, Bound},
     ops::{Index, IndexMut, Range, RangeBounds},
 };
 
@@ -10,6 +10,7 @@ use itertools::Itertools;
 use crate::{
     analysis::cfa::SectionAddress,
     obj::{ObjKind, ObjRelocations, ObjSplit, ObjSplits, ObjSymbol},
+    util::reader::Endian,
 };
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -34,6 +35,10 @@ pub struct ObjSection {
     pub virtual_address: Option<u64>,
     pub file_offset: u64,
     pub section_known: bool,
+    /// Thread-local storage (`SHF_TLS`), e.g. `.tdata` or `.tbss`.
+    pub thread_local: bool,
+    /// Copied verbatim: excluded from analysis and emitted as a single opaque unit.
+    pub verbatim: bool,
     pub splits: ObjSplits,
 }
 
@@ -70,6 +75,12 @@ impl ObjSections {
         self.sections.get_mut(index as usize)
     }
 
+    /// Whether `index` refers to a section, including zero-length sections. Symbol queries for
+    /// a valid section without symbols return nothing, just like for an invalid index.
+    pub fn is_valid_section(&self, index: SectionIndex) -> bool {
+        (index as usize) < self.sections.len()
+    }
+
     pub fn get_elf_index(&self, elf_index: SectionIndex) -> Option<(SectionIndex, &ObjSection)> {
         self.iter().find(|&(_, s)| s.elf_index == elf_index)
     }
@@ -125,6 +136,27 @@ impl ObjSections {
             .map_err(|_| anyhow!("Multiple sections with name {}", name))
     }
 
+    /// Resolves a section given by name or numeric index, e.g. from a command line option.
+    pub fn by_name_or_index(&self, value: &str) -> Result<SectionIndex> {
+        if let Ok(index) = value.parse::<SectionIndex>() {
+            ensure!(
+                self.get(index).is_some(),
+                "Section index {} out of range (object has {} sections)",
+                index,
+                self.len()
+            );
+            return Ok(index);
+        }
+        match self.by_name(value)? {
+            Some((index, _)) => Ok(index),
+            None => bail!(
+                "Unknown section '{}' (available: {})",
+                value,
+                self.iter().map(|(_, s)| s.name.as_str()).join(", ")
+            ),
+        }
+    }
+
     pub fn push(&mut self, section: ObjSection) -> SectionIndex {
         let index = self.sections.len();
         self.sections.push(section);
@@ -226,14 +258,55 @@ impl ObjSection {
         start_in_range && end_in_range
     }
 
+    /// The section data with the bits written by relocations zeroed, so that it can be compared
+    /// regardless of where the relocation targets were placed.
+    pub fn masked_data(&self, endian: Endian) -> Vec<u8> {
+        let mut data = self.data.clone();
+        for (address, reloc) in self.relocations.iter() {
+            let offset = (address as u64 - self.address) as usize;
+            let Some(bytes) = data.get_mut(offset..offset + 4) else {
+                continue;
+            };
+            let bytes: &mut [u8; 4] = bytes.try_into().unwrap();
+            *bytes = match endian {
+                Endian::Big => (u32::from_be_bytes(*bytes) & !reloc.kind.mask()).to_be_bytes(),
+                Endian::Little => (u32::from_le_bytes(*bytes) & !reloc.kind.mask()).to_le_bytes(),
+            };
+        }
+        data
+    }
+
+    /// Whether this section is addressed relative to a small data base (`_SDA_BASE_` or `_SDA2_BASE_`).
+    pub fn is_small_data(&self) -> bool {
+        matches!(self.name.as_str(), ".sdata" | ".sbss" | ".sdata2" | ".sbss2")
+    }
+
     pub fn rename(&mut self, name: String) -> Result<()> {
         self.kind = section_kind_for_section(&name)?;
+        self.thread_local = is_thread_local_name(&name);
         self.name = name;
         self.section_known = true;
         Ok(())
     }
 }
 
+/// Built-in aliases from toolchain-specific section names to the canonical names used in
+/// configuration files.
+pub const DEFAULT_SECTION_ALIASES: &[(&str, &str)] = &[
+    (".extab", "extab"),
+    (".extabindex", "extabindex"),
+    (".sdata.2", ".sdata2"),
+    (".sbss.2", ".sbss2"),
+];
+
+/// [`DEFAULT_SECTION_ALIASES`] as a map from alias to canonical name.
+pub fn default_section_aliases() -> BTreeMap<String, String> {
+    DEFAULT_SECTION_ALIASES
+        .iter()
+        .map(|&(alias, canonical)| (alias.to_string(), canonical.to_string()))
+        .collect()
+}
+
 pub fn section_kind_for_section(section_name: &str) -> Result<ObjSectionKind> {
     Ok(match section_name {
         ".init" | ".text" | ".dbgtext" | ".vmtext" => ObjSectionKind::Code,
@@ -242,6 +315,67 @@ pub fn section_kind_for_section(section_name: &str) -> Result<ObjSectionKind> {
         }
         ".bss" | ".sbss" | ".sbss2" => ObjSectionKind::Bss,
         ".data" | ".sdata" => ObjSectionKind::Data,
+        ".tdata" | ".thread_data" => ObjSectionKind::Data,
+        ".tbss" | ".thread_bss" => ObjSectionKind::Bss,
         name => bail!("Unknown section {name}"),
     })
 }
+
+/// Whether a section name conventionally denotes thread-local storage.
+pub fn is_thread_local_name(name: &str) -> bool {
+    [".tdata", ".tbss", ".thread_data", ".thread_bss"].iter().any(|base| {
+        name.strip_prefix(base).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    })
+}
+
+/// Ensures that no two sections occupy the same address range. Sections may be adjacent,
+/// e.g. `.bss` directly following `.data`. Symbols and splits are associated with sections
+/// by address, which assumes disjoint sections.
+pub fn check_section_overlap(sections: &[ObjSection]) -> Result<()> {
+    let mut sorted = sections.iter().filter(|s| s.size > 0).collect::<Vec<_>>();
+    sorted.sort_by_key(|s| s.address);
+    for (prev, next) in sorted.iter().zip(sorted.iter().skip(1)) {
+        let (prev_end, next_end) = (prev.address + prev.size, next.address + next.size);
+        ensure!(
+            prev_end <= next.address,
+            "Section '{}' ({:#010X}-{:#010X}) overlaps section '{}' ({:#010X}-{:#010X}) \
+             at {:#010X}-{:#010X}",
+            prev.name,
+            prev.address,
+            prev_end,
+            next.name,
+            next.address,
+            next_end,
+            next.address,
+            prev_end.min(next_end)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::test_section;
+
+    #[test]
+    fn test_check_section_overlap() {
+        // Adjacent sections, including BSS directly after data
+        let sections = vec![
+            test_section(".text", ObjSectionKind::Code, 0x80003100, 0x100),
+            test_section(".data", ObjSectionKind::Data, 0x80003200, 0x100),
+            test_section(".bss", ObjSectionKind::Bss, 0x80003300, 0x100),
+        ];
+        check_section_overlap(&sections).unwrap();
+
+        let sections = vec![
+            test_section(".text", ObjSectionKind::Code, 0x80003100, 0x100),
+            test_section(".bss", ObjSectionKind::Bss, 0x800031E0, 0x100),
+        ];
+        assert_eq!(
+            check_section_overlap(&sections).unwrap_err().to_string(),
+            "Section '.text' (0x80003100-0x80003200) overlaps section '.bss' \
+             (0x800031E0-0x800032E0) at 0x800031E0-0x80003200"
+        );
+    }
+}
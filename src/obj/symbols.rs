@@ -1,26 +1,37 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt,
     hash::{Hash, Hasher},
-    ops::{Index, RangeBounds},
+    ops::{Bound, Index, Range, RangeBounds},
+    sync::Arc,
 };
 
 use anyhow::{anyhow, bail, ensure, Result};
+use cwdemangle::DemangleOptions;
 use flagset::{flags, FlagSet};
 use itertools::Itertools;
-use serde::{Deserialize, Serialize};
+use regex::Regex;
+use serde::{ser::SerializeStruct, Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
 use crate::{
     analysis::cfa::SectionAddress,
     obj::{sections::SectionIndex, ObjKind, ObjRelocKind, ObjSections},
     util::{
-        config::{is_auto_jump_table, is_auto_label, is_auto_symbol, parse_u32},
+        align_up,
+        config::{
+            auto_symbol_naming, is_auto_jump_table, is_auto_label, is_auto_symbol, parse_u32,
+        },
+        demangle::demangle,
         nested::NestedVec,
         split::is_linker_generated_label,
     },
 };
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
 pub enum ObjSymbolScope {
     #[default]
     Unknown,
@@ -38,8 +49,8 @@ flags! {
         Weak,
         Common,
         Hidden,
-        /// Force symbol to be exported (force active)
-        Exported,
+        /// Force symbol to be kept by the linker (FORCEACTIVE)
+        ForceActive,
         /// Symbol isn't referenced by any relocations
         RelocationIgnore,
         /// Symbol won't be written to symbols file
@@ -51,6 +62,18 @@ flags! {
         NoExport,
         /// Symbol does not contain any relocations
         NoReloc,
+        /// Symbol is referenced from another module, and must retain its name
+        Exported,
+        /// Symbol can be referenced by any relocation kind, bypassing the
+        /// heuristics in [`ObjSymbol::referenced_by`]. `RelocationIgnore` takes precedence.
+        AllowAnyReloc,
+        /// Symbol is authoritative: merging won't change its name, kind or size
+        Locked,
+        /// Protected visibility (STV_PROTECTED): visible to other modules, but references
+        /// within the defining module can't be preempted
+        Protected,
+        /// Internal visibility (STV_INTERNAL): hidden, and never called from other modules
+        Internal,
     }
 }
 
@@ -83,8 +106,20 @@ impl ObjSymbolFlagSet {
     #[inline]
     pub fn is_weak(&self) -> bool { self.0.contains(ObjSymbolFlags::Weak) }
 
+    /// Hidden or internal visibility.
+    #[inline]
+    pub fn is_hidden(&self) -> bool {
+        self.0.contains(ObjSymbolFlags::Hidden) || self.0.contains(ObjSymbolFlags::Internal)
+    }
+
+    #[inline]
+    pub fn is_protected(&self) -> bool { self.0.contains(ObjSymbolFlags::Protected) }
+
+    #[inline]
+    pub fn is_internal(&self) -> bool { self.0.contains(ObjSymbolFlags::Internal) }
+
     #[inline]
-    pub fn is_hidden(&self) -> bool { self.0.contains(ObjSymbolFlags::Hidden) }
+    pub fn is_force_active(&self) -> bool { self.0.contains(ObjSymbolFlags::ForceActive) }
 
     #[inline]
     pub fn is_exported(&self) -> bool { self.0.contains(ObjSymbolFlags::Exported) }
@@ -104,6 +139,12 @@ impl ObjSymbolFlagSet {
     #[inline]
     pub fn is_no_reloc(&self) -> bool { self.0.contains(ObjSymbolFlags::NoReloc) }
 
+    #[inline]
+    pub fn is_allow_any_reloc(&self) -> bool { self.0.contains(ObjSymbolFlags::AllowAnyReloc) }
+
+    #[inline]
+    pub fn is_locked(&self) -> bool { self.0.contains(ObjSymbolFlags::Locked) }
+
     #[inline]
     pub fn set_scope(&mut self, scope: ObjSymbolScope) {
         match scope {
@@ -128,22 +169,54 @@ impl ObjSymbolFlagSet {
     #[inline]
     pub fn set_force_active(&mut self, value: bool) {
         if value {
-            self.0 = (self.0 & !ObjSymbolFlags::NoExport) | ObjSymbolFlags::Exported;
+            self.0 = (self.0 & !ObjSymbolFlags::NoExport) | ObjSymbolFlags::ForceActive;
+        } else {
+            self.0 &= !ObjSymbolFlags::ForceActive;
+        }
+    }
+
+    #[inline]
+    pub fn set_exported(&mut self, value: bool) {
+        if value {
+            self.0 |= ObjSymbolFlags::Exported;
         } else {
             self.0 &= !ObjSymbolFlags::Exported;
         }
     }
 
+    #[inline]
+    pub fn set_relocation_ignore(&mut self, value: bool) {
+        if value {
+            self.0 |= ObjSymbolFlags::RelocationIgnore;
+        } else {
+            self.0 &= !ObjSymbolFlags::RelocationIgnore;
+        }
+    }
+
+    /// Keeps the symbol out of the generated symbols file. It remains available to analysis,
+    /// including relocation resolution.
+    #[inline]
+    pub fn set_no_write(&mut self, value: bool) {
+        if value {
+            self.0 |= ObjSymbolFlags::NoWrite;
+        } else {
+            self.0 &= !ObjSymbolFlags::NoWrite;
+        }
+    }
+
     /// Special flags to keep when merging symbols.
     #[inline]
     pub fn keep_flags(&self) -> FlagSet<ObjSymbolFlags> {
         self.0
-            & (ObjSymbolFlags::Exported
+            & (ObjSymbolFlags::ForceActive
+                | ObjSymbolFlags::Exported
                 | ObjSymbolFlags::NoWrite
                 | ObjSymbolFlags::RelocationIgnore
                 | ObjSymbolFlags::Stripped
                 | ObjSymbolFlags::NoExport
-                | ObjSymbolFlags::NoReloc)
+                | ObjSymbolFlags::NoReloc
+                | ObjSymbolFlags::AllowAnyReloc
+                | ObjSymbolFlags::Locked)
     }
 }
 
@@ -164,7 +237,7 @@ pub enum ObjSymbolKind {
     Section,
 }
 
-#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ObjDataKind {
     #[default]
     Unknown,
@@ -180,6 +253,42 @@ pub enum ObjDataKind {
     String16Table,
     Int,
     Short,
+    /// A switch jump table: an array of code addresses.
+    JumpTable,
+    /// An array of absolute pointers, such as a vtable.
+    PointerTable,
+    /// A pair of single-precision floats, as loaded by `psq_l` on Gekko/Broadway.
+    PairedSingle,
+    /// A 16-byte value, such as an aligned vector.
+    Quadword,
+}
+
+impl ObjDataKind {
+    /// The size of a single element of this data kind, if fixed.
+    /// String kinds return the size of a single character.
+    pub fn element_size(&self) -> Option<u32> {
+        match self {
+            ObjDataKind::Byte | ObjDataKind::String => Some(1),
+            ObjDataKind::Byte2 | ObjDataKind::Short | ObjDataKind::String16 => Some(2),
+            ObjDataKind::Byte4
+            | ObjDataKind::Float
+            | ObjDataKind::Int
+            | ObjDataKind::JumpTable
+            | ObjDataKind::PointerTable => Some(4),
+            ObjDataKind::Byte8 | ObjDataKind::Double | ObjDataKind::PairedSingle => Some(8),
+            ObjDataKind::Quadword => Some(16),
+            ObjDataKind::Unknown | ObjDataKind::StringTable | ObjDataKind::String16Table => None,
+        }
+    }
+
+    /// The element size of arrays of this data kind. Strings are variable length, so
+    /// unlike [`ObjDataKind::element_size`], they return `None`.
+    pub fn array_element_size(&self) -> Option<u32> {
+        match self {
+            ObjDataKind::String | ObjDataKind::String16 => None,
+            _ => self.element_size(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Eq, PartialEq)]
@@ -197,6 +306,50 @@ pub struct ObjSymbol {
     /// ALF hashes
     pub name_hash: Option<u32>,
     pub demangled_name_hash: Option<u32>,
+    /// Source file from DWARF, if known.
+    pub source_file: Option<String>,
+    /// Source line from DWARF, if known.
+    pub source_line: Option<u32>,
+    /// Free-form user notes, emitted as comments. Not used by analysis or linking.
+    pub notes: Option<String>,
+    /// Position in the symbols file the symbol was loaded from, for
+    /// [`SymbolSortMode::Stable`](crate::util::config::SymbolSortMode::Stable). Not persisted.
+    pub sort_index: Option<u32>,
+}
+
+/// The symbol's scope is stored canonically in `flags`, but is also
+/// serialized as a derived `scope` field for external consumers.
+impl Serialize for ObjSymbol {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+        let mut s = serializer.serialize_struct("ObjSymbol", 16)?;
+        s.serialize_field("name", &self.name)?;
+        s.serialize_field("demangled_name", &self.demangled_name)?;
+        s.serialize_field("address", &self.address)?;
+        s.serialize_field("section", &self.section)?;
+        s.serialize_field("size", &self.size)?;
+        s.serialize_field("size_known", &self.size_known)?;
+        s.serialize_field("flags", &self.flags)?;
+        s.serialize_field("scope", &self.flags.scope())?;
+        s.serialize_field("kind", &self.kind)?;
+        s.serialize_field("align", &self.align)?;
+        s.serialize_field("data_kind", &self.data_kind)?;
+        s.serialize_field("name_hash", &self.name_hash)?;
+        s.serialize_field("demangled_name_hash", &self.demangled_name_hash)?;
+        match &self.source_file {
+            Some(source_file) => s.serialize_field("source_file", source_file)?,
+            None => s.skip_field("source_file")?,
+        }
+        match &self.source_line {
+            Some(source_line) => s.serialize_field("source_line", source_line)?,
+            None => s.skip_field("source_line")?,
+        }
+        match &self.notes {
+            Some(notes) => s.serialize_field("notes", notes)?,
+            None => s.skip_field("notes")?,
+        }
+        s.end()
+    }
 }
 
 pub type SymbolIndex = u32;
@@ -204,19 +357,224 @@ pub type SymbolIndex = u32;
 #[derive(Debug, Clone)]
 pub struct ObjSymbols {
     obj_kind: ObjKind,
-    symbols: Vec<ObjSymbol>,
+    /// Removed symbols leave a `None` slot behind, so that existing
+    /// [`SymbolIndex`] values remain valid.
+    symbols: Vec<Option<ObjSymbol>>,
     symbols_by_address: BTreeMap<u32, Vec<SymbolIndex>>,
     symbols_by_name: HashMap<String, Vec<SymbolIndex>>,
     symbols_by_section: Vec<BTreeMap<u32, Vec<SymbolIndex>>>,
+    size_conflicts: Vec<SizeConflict>,
+    size_conflict_align: u32,
+    reloc_resolver: Arc<dyn RelocResolver>,
+    /// Names the demangler failed on, skipped by [`Self::demangle_all`] unless forced.
+    demangle_failures: HashSet<String>,
+    /// Upper bound of all symbol sizes, which limits how far back [`Self::at_address`] scans.
+    max_symbol_size: u64,
+}
+
+#[derive(Debug)]
+pub struct DuplicateSymbolError {
+    /// The symbol being renamed, or `None` if the symbol is being added.
+    pub index: Option<SymbolIndex>,
+    /// The existing global symbol with the same name.
+    pub existing: SymbolIndex,
+    pub name: String,
+}
+
+impl fmt::Display for DuplicateSymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(index) => write!(
+                f,
+                "symbol {} can't be renamed to {}: conflicts with global symbol {}",
+                index, self.name, self.existing
+            ),
+            None => write!(
+                f,
+                "duplicate definition of global symbol {}: conflicts with global symbol {}",
+                self.name, self.existing
+            ),
+        }
+    }
+}
+
+impl Error for DuplicateSymbolError {}
+
+/// A symbol size disagreement encountered while merging symbols.
+#[derive(Debug, Clone)]
+pub struct SizeConflict {
+    pub symbol: SymbolIndex,
+    pub name: String,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+/// A hidden symbol that would be visible to other modules.
+#[derive(Debug, Clone)]
+pub struct HiddenExport {
+    pub symbol: SymbolIndex,
+    pub name: String,
+    /// The symbol is referenced from another module.
+    pub exported: bool,
+    /// The symbol is a global or weak symbol kept by the linker.
+    pub force_active: bool,
+}
+
+/// Differences between two symbol tables, from [`ObjSymbols::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolDiff {
+    /// Symbols only in the other table.
+    pub added: Vec<SymbolIndex>,
+    /// Symbols only in this table.
+    pub removed: Vec<SymbolIndex>,
+    /// Matched pairs (this, other) whose name, section, address, size or kind differ.
+    pub changed: Vec<(SymbolIndex, SymbolIndex)>,
+}
+
+impl SymbolDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Renames symbols in bulk. Applied in order: prefix stripping, regex substitution,
+/// then prefix addition.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolRenameTransform {
+    /// Prefix removed from symbol names that start with it.
+    pub strip_prefix: Option<String>,
+    /// Prefix added to every symbol name.
+    pub add_prefix: Option<String>,
+    /// Regex and replacement applied to symbol names.
+    pub replace: Option<(Regex, String)>,
+    /// Also rename auto-generated symbols and linker generated labels.
+    pub include_auto: bool,
+}
+
+impl SymbolRenameTransform {
+    pub fn is_empty(&self) -> bool {
+        self.strip_prefix.is_none() && self.add_prefix.is_none() && self.replace.is_none()
+    }
+
+    /// Returns the new name for a symbol, or `None` if it's unchanged or skipped.
+    pub fn apply(&self, symbol: &ObjSymbol) -> Option<String> {
+        if symbol.name.is_empty()
+            || symbol.kind == ObjSymbolKind::Section
+            || (!self.include_auto
+                && (is_auto_symbol(symbol) || is_linker_generated_label(&symbol.name)))
+        {
+            return None;
+        }
+        let mut name = symbol.name.as_str();
+        if let Some(prefix) = &self.strip_prefix {
+            name = name.strip_prefix(prefix.as_str()).unwrap_or(name);
+        }
+        let mut name = match &self.replace {
+            Some((regex, replacement)) => {
+                regex.replace_all(name, replacement.as_str()).into_owned()
+            }
+            None => name.to_string(),
+        };
+        if let Some(prefix) = &self.add_prefix {
+            name.insert_str(0, prefix);
+        }
+        (!name.is_empty() && name != symbol.name).then_some(name)
+    }
+}
+
+/// Why [`ObjSymbols::for_relocation`] found no symbol for a relocation target.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RelocationMissReason {
+    /// Symbols exist at or around the target, but none can be referenced by the relocation.
+    Filtered,
+    /// No symbol exists at or around the target.
+    NotFound,
+}
+
+/// A relocation target with no matching symbol.
+#[derive(Debug, Clone)]
+pub struct RelocationMiss {
+    pub target: SectionAddress,
+    pub reloc_kind: ObjRelocKind,
+    pub reason: RelocationMissReason,
+}
+
+/// Relocation targets that [`ObjSymbols::for_relocation`] couldn't resolve.
+#[derive(Debug, Clone, Default)]
+pub struct RelocationDiagnostics {
+    pub misses: Vec<RelocationMiss>,
+}
+
+impl RelocationDiagnostics {
+    pub fn record(
+        &mut self,
+        symbols: &ObjSymbols,
+        target: SectionAddress,
+        reloc_kind: ObjRelocKind,
+    ) {
+        let reason = symbols.relocation_miss_reason(target);
+        self.misses.push(RelocationMiss { target, reloc_kind, reason });
+    }
+
+    pub fn is_empty(&self) -> bool { self.misses.is_empty() }
+
+    pub fn len(&self) -> usize { self.misses.len() }
+
+    /// Logs a summary of all misses, followed by each miss and its reason.
+    pub fn report(&self, name: &str) {
+        if self.misses.is_empty() {
+            return;
+        }
+        let filtered =
+            self.misses.iter().filter(|m| m.reason == RelocationMissReason::Filtered).count();
+        log::warn!(
+            "{}: {} relocations had no matching symbol ({} filtered, {} not found)",
+            name,
+            self.misses.len(),
+            filtered,
+            self.misses.len() - filtered
+        );
+        for miss in &self.misses {
+            let reason = match miss.reason {
+                RelocationMissReason::Filtered => "no symbol referenceable by relocation",
+                RelocationMissReason::NotFound => "no symbol present",
+            };
+            log::warn!("  {} ({:?}): {}", miss.target, miss.reloc_kind, reason);
+        }
+    }
 }
 
 impl ObjSymbols {
     pub fn new(obj_kind: ObjKind, symbols: Vec<ObjSymbol>) -> Self {
+        let mut result = Self {
+            obj_kind,
+            symbols: symbols.into_iter().map(Some).collect(),
+            symbols_by_address: Default::default(),
+            symbols_by_name: Default::default(),
+            symbols_by_section: vec![],
+            size_conflicts: vec![],
+            size_conflict_align: 4,
+            reloc_resolver: Arc::new(DefaultRelocResolver),
+            demangle_failures: Default::default(),
+            max_symbol_size: 0,
+        };
+        result.rebuild_indices();
+        result
+    }
+
+    fn rebuild_indices(&mut self) {
         let mut symbols_by_address = BTreeMap::<u32, Vec<SymbolIndex>>::new();
-        let mut symbols_by_section: Vec<BTreeMap<u32, Vec<SymbolIndex>>> = vec![];
+        // Keep sections without symbols tracked
+        let mut symbols_by_section: Vec<BTreeMap<u32, Vec<SymbolIndex>>> =
+            vec![BTreeMap::new(); self.symbols_by_section.len()];
         let mut symbols_by_name = HashMap::<String, Vec<SymbolIndex>>::new();
-        for (idx, symbol) in symbols.iter().enumerate() {
+        let mut max_symbol_size = 0u64;
+        for (idx, symbol) in self.symbols.iter().enumerate() {
+            let Some(symbol) = symbol else {
+                continue;
+            };
             let idx = idx as SymbolIndex;
+            max_symbol_size = max_symbol_size.max(symbol.size);
             symbols_by_address.nested_push(symbol.address as u32, idx);
             if let Some(section_idx) = symbol.section {
                 let section_idx = section_idx as usize;
@@ -228,7 +586,7 @@ impl ObjSymbols {
                 debug_assert!(
                     symbol.address == 0
                         || symbol.flags.is_common()
-                        || obj_kind == ObjKind::Executable,
+                        || self.obj_kind == ObjKind::Executable,
                     "ABS symbol in relocatable object"
                 );
             }
@@ -236,40 +594,257 @@ impl ObjSymbols {
                 symbols_by_name.nested_push(symbol.name.clone(), idx);
             }
         }
-        Self { obj_kind, symbols, symbols_by_address, symbols_by_name, symbols_by_section }
+        self.symbols_by_address = symbols_by_address;
+        self.symbols_by_name = symbols_by_name;
+        self.symbols_by_section = symbols_by_section;
+        self.max_symbol_size = max_symbol_size;
     }
 
-    pub fn add(&mut self, in_symbol: ObjSymbol, replace: bool) -> Result<SymbolIndex> {
-        let opt = if in_symbol.flags.is_stripped() {
+    /// Adds many symbols without merging. All symbols are validated before any are added,
+    /// so on error the symbol table is unchanged.
+    pub fn extend(
+        &mut self,
+        symbols: impl IntoIterator<Item = ObjSymbol>,
+    ) -> Result<Vec<SymbolIndex>> {
+        let symbols = symbols.into_iter().collect_vec();
+        for symbol in &symbols {
+            validate_common(symbol)?;
+            ensure!(
+                symbol.section.is_some()
+                    || symbol.address == 0
+                    || symbol.flags.is_common()
+                    || self.obj_kind == ObjKind::Executable,
+                "ABS symbol in relocatable object: {}",
+                symbol.name
+            );
+        }
+        let start = self.symbols.len();
+        self.symbols.extend(symbols.into_iter().map(Some));
+        self.index_symbols_from(start);
+        Ok((start as SymbolIndex..self.symbols.len() as SymbolIndex).collect())
+    }
+
+    /// Indexes the symbols from `start` onwards as a batch. The new entries are sorted by key
+    /// and grouped, so each index is updated once per key rather than once per symbol.
+    fn index_symbols_from(&mut self, start: usize) {
+        let new_symbols = (start..)
+            .zip(&self.symbols[start..])
+            .filter_map(|(idx, symbol)| Some((idx as SymbolIndex, symbol.as_ref()?)))
+            .collect_vec();
+
+        let mut by_address =
+            new_symbols.iter().map(|(idx, symbol)| (symbol.address as u32, *idx)).collect_vec();
+        by_address.sort_unstable();
+        merge_index(&mut self.symbols_by_address, group_sorted(by_address));
+
+        let mut by_section = new_symbols
+            .iter()
+            .filter_map(|(idx, symbol)| Some((symbol.section?, symbol.address as u32, *idx)))
+            .collect_vec();
+        by_section.sort_unstable();
+        for (section_idx, group) in &by_section.into_iter().chunk_by(|&(section, ..)| section) {
+            let section_idx = section_idx as usize;
+            if section_idx >= self.symbols_by_section.len() {
+                self.symbols_by_section.resize_with(section_idx + 1, BTreeMap::new);
+            }
+            let group = group_sorted(group.map(|(_, address, idx)| (address, idx)));
+            merge_index(&mut self.symbols_by_section[section_idx], group);
+        }
+
+        self.symbols_by_name.reserve(new_symbols.len());
+        for (idx, symbol) in &new_symbols {
+            self.max_symbol_size = self.max_symbol_size.max(symbol.size);
+            if !symbol.name.is_empty() {
+                self.symbols_by_name.nested_push(symbol.name.clone(), *idx);
+            }
+        }
+    }
+
+    /// Finds the existing symbol that [`ObjSymbols::add`] would merge `in_symbol` into.
+    ///
+    /// The existing symbol is selected in the following order of precedence:
+    /// 1. A symbol of the same kind.
+    /// 2. An auto-generated label, which is replaced by the incoming symbol.
+    /// 3. A named label (`Unknown` kind), if the incoming symbol's kind is known.
+    ///    The label's kind is promoted, even if `replace` is false.
+    /// 4. A locked symbol, which only has its unknown fields filled in.
+    ///
+    /// Section symbols only replace auto-generated labels, and are never merged into named
+    /// labels or locked symbols.
+    pub fn merge_target(&self, in_symbol: &ObjSymbol) -> Result<Option<(SymbolIndex, &ObjSymbol)>> {
+        Ok(if in_symbol.flags.is_stripped() {
             // Stripped symbols don't overwrite existing symbols
             None
         } else if let Some(section_index) = in_symbol.section {
-            self.at_section_address(section_index, in_symbol.address as u32).find(|(_, symbol)| {
-                symbol.kind == in_symbol.kind ||
-                    // Replace auto symbols with real symbols
-                    (symbol.kind == ObjSymbolKind::Unknown && is_auto_symbol(symbol))
-            })
+            let address = in_symbol.address as u32;
+            // Locked symbols only absorb symbols of the same name or auto-named ones,
+            // distinct aliases are kept
+            let mergeable = |symbol: &ObjSymbol| {
+                !symbol.flags.is_locked()
+                    || symbol.name == in_symbol.name
+                    || is_auto_symbol(in_symbol)
+            };
+            self.at_section_address(section_index, address)
+                .find(|(_, symbol)| symbol.kind == in_symbol.kind && mergeable(symbol))
+                .or_else(|| {
+                    self.at_section_address(section_index, address).find(|(_, symbol)| {
+                        mergeable(symbol)
+                            && symbol.kind == ObjSymbolKind::Unknown
+                            // Replace auto symbols with real symbols
+                            && (is_auto_symbol(symbol)
+                                // Promote labels to the incoming kind
+                                || !matches!(
+                                    in_symbol.kind,
+                                    ObjSymbolKind::Unknown | ObjSymbolKind::Section
+                                ))
+                    })
+                })
+                .or_else(|| {
+                    // Merge into a locked symbol rather than adding a conflicting one
+                    self.at_section_address(section_index, address).find(|(_, symbol)| {
+                        symbol.flags.is_locked()
+                            && symbol.kind != ObjSymbolKind::Section
+                            && mergeable(symbol)
+                    })
+                })
         } else if self.obj_kind == ObjKind::Executable {
             // TODO hmmm
             self.iter_abs().find(|(_, symbol)| symbol.name == in_symbol.name)
         } else {
             bail!("ABS symbol in relocatable object: {:?}", in_symbol);
-        };
+        })
+    }
+
+    /// Adds a symbol, merging it with an existing symbol at the same address if possible.
+    /// See [`ObjSymbols::merge_target`] for how the existing symbol is selected; otherwise,
+    /// a new symbol is created.
+    ///
+    /// When merging into a label (`Unknown` kind), the label takes the incoming kind. Use
+    /// [`ObjInfo::add_symbol`](super::ObjInfo::add_symbol) to also check that relocations
+    /// targeting the label remain valid for the new kind.
+    ///
+    /// When merging, a global (strong) definition always wins over a weak one,
+    /// regardless of insertion order. Adding a global symbol with the same name
+    /// as a global symbol at a different address is a duplicate definition, and returns a
+    /// [`DuplicateSymbolError`]. Re-adding a global symbol at its own address merges as usual.
+    pub fn add(&mut self, in_symbol: ObjSymbol, replace: bool) -> Result<SymbolIndex> {
+        validate_common(&in_symbol)?;
+        if let Some((existing, _)) = self.conflicting_global(&in_symbol, None) {
+            return Err(DuplicateSymbolError { index: None, existing, name: in_symbol.name }.into());
+        }
+        let opt = self.merge_target(&in_symbol)?;
+        // Default the size from the data kind, unless merging with a sized symbol.
+        // Strings are variable length, so their element size can't be used.
+        let mut in_symbol = in_symbol;
+        if !in_symbol.size_known
+            && in_symbol.size == 0
+            && !opt.is_some_and(|(_, existing)| existing.size_known)
+            && !matches!(in_symbol.data_kind, ObjDataKind::String | ObjDataKind::String16)
+        {
+            if let Some(size) = in_symbol.data_kind.element_size() {
+                in_symbol.size = size as u64;
+                in_symbol.size_known = true;
+            }
+        }
         let target_symbol_idx = if let Some((symbol_idx, existing)) = opt {
+            let ignored_unchanged = ignored_fields_unchanged(existing, &in_symbol);
+            let existing_scope = existing.flags.scope();
+            let in_scope = in_symbol.flags.scope();
+            // Keep a strong definition when merging in a weak one
+            let keep_strong =
+                existing_scope == ObjSymbolScope::Global && in_scope == ObjSymbolScope::Weak;
             let replace = replace
                 // Replace auto symbols with known symbols
                 || (is_auto_symbol(existing) && !is_auto_symbol(&in_symbol))
                 // Replace lbl_ with jumptable_
-                || (is_auto_label(existing) && is_auto_jump_table(&in_symbol));
-            let size =
-                if existing.size_known && in_symbol.size_known && existing.size != in_symbol.size {
-                    // TODO fix this and restore to warning
+                || (is_auto_label(existing) && is_auto_jump_table(&in_symbol))
+                // Replace weak definitions with strong ones
+                || (existing_scope == ObjSymbolScope::Weak && in_scope == ObjSymbolScope::Global);
+            // Use the stricter alignment when both are known
+            let align = match (in_symbol.align, existing.align) {
+                (Some(in_align), Some(existing_align)) if in_align != existing_align => {
                     log::debug!(
-                        "Conflicting size for {}: was {:#X}, now {:#X}",
+                        "Conflicting alignment for {}: was {:#X}, now {:#X}",
+                        existing.name,
+                        existing_align,
+                        in_align
+                    );
+                    Some(in_align.max(existing_align))
+                }
+                (in_align, existing_align) => in_align.or(existing_align),
+            };
+            if existing.flags.is_locked() {
+                // Only fill in fields that are unknown, keeping the incoming flags
+                let mut flags = ObjSymbolFlagSet(existing.flags.0 | in_symbol.flags.0);
+                flags.set_scope(if existing_scope == ObjSymbolScope::Unknown {
+                    in_scope
+                } else {
+                    existing_scope
+                });
+                let mut new_symbol = ObjSymbol {
+                    align,
+                    flags,
+                    name_hash: existing.name_hash.or(in_symbol.name_hash),
+                    demangled_name_hash: existing
+                        .demangled_name_hash
+                        .or(in_symbol.demangled_name_hash),
+                    source_file: existing.source_file.clone().or(in_symbol.source_file),
+                    source_line: existing.source_line.or(in_symbol.source_line),
+                    notes: existing.notes.clone().or(in_symbol.notes),
+                    sort_index: existing.sort_index.or(in_symbol.sort_index),
+                    ..existing.clone()
+                };
+                if existing.kind == ObjSymbolKind::Unknown {
+                    new_symbol.kind = in_symbol.kind;
+                }
+                if existing.data_kind == ObjDataKind::Unknown {
+                    new_symbol.data_kind = in_symbol.data_kind;
+                }
+                if !existing.size_known && in_symbol.size_known {
+                    new_symbol.size = in_symbol.size;
+                    new_symbol.size_known = true;
+                }
+                if in_symbol.name != existing.name
+                    || (in_symbol.kind != ObjSymbolKind::Unknown
+                        && in_symbol.kind != new_symbol.kind)
+                    || (in_symbol.size_known && in_symbol.size != new_symbol.size)
+                {
+                    log::info!(
+                        "Symbol {} is locked, ignoring {} (kind {:?}, size {:#X})",
                         existing.name,
-                        existing.size,
+                        in_symbol.name,
+                        in_symbol.kind,
                         in_symbol.size
                     );
+                }
+                if !ignored_unchanged || !existing.semantic_eq(&new_symbol) {
+                    self.replace(symbol_idx, new_symbol)?;
+                }
+                return Ok(symbol_idx);
+            }
+            let mut size_conflict = None;
+            let size =
+                if existing.size_known && in_symbol.size_known && existing.size != in_symbol.size {
+                    // Differences that disappear when rounded to the alignment are trivial
+                    let conflict_align = align.unwrap_or(1).max(self.size_conflict_align);
+                    if align_up(existing.size as u32, conflict_align)
+                        == align_up(in_symbol.size as u32, conflict_align)
+                    {
+                        log::debug!(
+                            "Conflicting size for {}: was {:#X}, now {:#X}",
+                            existing.name,
+                            existing.size,
+                            in_symbol.size
+                        );
+                    } else {
+                        // Reported once after splitting, see `size_conflicts`
+                        size_conflict = Some(SizeConflict {
+                            symbol: symbol_idx,
+                            name: existing.name.clone(),
+                            old_size: existing.size,
+                            new_size: in_symbol.size,
+                        });
+                    }
                     if replace {
                         in_symbol.size
                     } else {
@@ -281,17 +856,25 @@ impl ObjSymbols {
                     existing.size
                 };
             if !replace {
-                // Not replacing existing symbol, but update size
-                if in_symbol.size_known && !existing.size_known {
-                    self.replace(symbol_idx, ObjSymbol {
-                        size: in_symbol.size,
-                        size_known: true,
-                        ..existing.clone()
-                    })?;
+                // Not replacing existing symbol, but update size, alignment and kind
+                let update_size = in_symbol.size_known && !existing.size_known;
+                let kind = if existing.kind == ObjSymbolKind::Unknown {
+                    in_symbol.kind
+                } else {
+                    existing.kind
+                };
+                if update_size || align != existing.align || kind != existing.kind {
+                    let mut new_symbol = ObjSymbol { align, kind, ..existing.clone() };
+                    if update_size {
+                        new_symbol.size = in_symbol.size;
+                        new_symbol.size_known = true;
+                    }
+                    self.replace(symbol_idx, new_symbol)?;
                 }
+                self.record_size_conflict(size_conflict);
                 return Ok(symbol_idx);
             }
-            let new_symbol = ObjSymbol {
+            let mut new_symbol = ObjSymbol {
                 name: in_symbol.name,
                 demangled_name: in_symbol.demangled_name,
                 address: in_symbol.address,
@@ -300,18 +883,31 @@ impl ObjSymbols {
                 size_known: existing.size_known || in_symbol.size != 0,
                 flags: ObjSymbolFlagSet(in_symbol.flags.0 | existing.flags.keep_flags()),
                 kind: in_symbol.kind,
-                align: in_symbol.align.or(existing.align),
+                align,
                 data_kind: match in_symbol.data_kind {
                     ObjDataKind::Unknown => existing.data_kind,
                     kind => kind,
                 },
                 name_hash: in_symbol.name_hash.or(existing.name_hash),
                 demangled_name_hash: in_symbol.demangled_name_hash.or(existing.demangled_name_hash),
+                source_file: in_symbol.source_file.or_else(|| existing.source_file.clone()),
+                source_line: in_symbol.source_line.or(existing.source_line),
+                notes: in_symbol.notes.or_else(|| existing.notes.clone()),
+                // Keep the position the symbol was first loaded at
+                sort_index: existing.sort_index.or(in_symbol.sort_index),
             };
-            if existing != &new_symbol {
+            if keep_strong {
+                new_symbol.name.clone_from(&existing.name);
+                new_symbol.demangled_name.clone_from(&existing.demangled_name);
+                new_symbol.name_hash = existing.name_hash;
+                new_symbol.demangled_name_hash = existing.demangled_name_hash;
+                new_symbol.flags.set_scope(ObjSymbolScope::Global);
+            }
+            if !ignored_unchanged || !existing.semantic_eq(&new_symbol) {
                 log::debug!("Replacing {:?} with {:?}", existing, new_symbol);
                 self.replace(symbol_idx, new_symbol)?;
             }
+            self.record_size_conflict(size_conflict);
             symbol_idx
         } else {
             let target_symbol_idx = self.symbols.len() as SymbolIndex;
@@ -328,6 +924,10 @@ impl ObjSymbols {
                 data_kind: in_symbol.data_kind,
                 name_hash: in_symbol.name_hash,
                 demangled_name_hash: in_symbol.demangled_name_hash,
+                source_file: in_symbol.source_file,
+                source_line: in_symbol.source_line,
+                notes: in_symbol.notes,
+                sort_index: in_symbol.sort_index,
             })?;
             target_symbol_idx
         };
@@ -335,99 +935,513 @@ impl ObjSymbols {
     }
 
     pub fn add_direct(&mut self, in_symbol: ObjSymbol) -> Result<SymbolIndex> {
+        validate_common(&in_symbol)?;
+        ensure!(
+            in_symbol.section.is_some()
+                || in_symbol.address == 0
+                || in_symbol.flags.is_common()
+                || self.obj_kind == ObjKind::Executable,
+            "ABS symbol in relocatable object"
+        );
         let symbol_idx = self.symbols.len() as SymbolIndex;
-        self.symbols_by_address.nested_push(in_symbol.address as u32, symbol_idx);
-        if let Some(section_idx) = in_symbol.section {
+        self.index_symbol(symbol_idx, &in_symbol);
+        self.symbols.push(Some(in_symbol));
+        Ok(symbol_idx)
+    }
+
+    /// Finds a strong global symbol, other than `exclude`, that conflicts with `symbol` by name.
+    /// Unlike [`is_global_conflict`], weak definitions are allowed alongside a strong one.
+    fn conflicting_global(
+        &self,
+        symbol: &ObjSymbol,
+        exclude: Option<SymbolIndex>,
+    ) -> Option<(SymbolIndex, &ObjSymbol)> {
+        if symbol.flags.scope() != ObjSymbolScope::Global {
+            return None;
+        }
+        self.for_name(&symbol.name).find(|&(other_idx, other)| {
+            Some(other_idx) != exclude
+                && other.flags.scope() == ObjSymbolScope::Global
+                && is_global_conflict(symbol, other)
+        })
+    }
+
+    fn index_symbol(&mut self, symbol_idx: SymbolIndex, symbol: &ObjSymbol) {
+        self.max_symbol_size = self.max_symbol_size.max(symbol.size);
+        self.symbols_by_address.nested_push(symbol.address as u32, symbol_idx);
+        if let Some(section_idx) = symbol.section {
             let section_idx = section_idx as usize;
             if section_idx >= self.symbols_by_section.len() {
                 self.symbols_by_section.resize_with(section_idx + 1, BTreeMap::new);
             }
-            self.symbols_by_section[section_idx].nested_push(in_symbol.address as u32, symbol_idx);
-        } else {
-            ensure!(
-                in_symbol.address == 0
-                    || in_symbol.flags.is_common()
-                    || self.obj_kind == ObjKind::Executable,
-                "ABS symbol in relocatable object"
-            );
+            self.symbols_by_section[section_idx].nested_push(symbol.address as u32, symbol_idx);
         }
-        if !in_symbol.name.is_empty() {
-            self.symbols_by_name.nested_push(in_symbol.name.clone(), symbol_idx);
+        if !symbol.name.is_empty() {
+            self.symbols_by_name.nested_push(symbol.name.clone(), symbol_idx);
         }
-        self.symbols.push(in_symbol);
-        Ok(symbol_idx)
     }
 
-    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
-        self.symbols.iter().enumerate().map(|(i, s)| (i as SymbolIndex, s))
-    }
+    /// Checks the consistency of the symbol table: every entry in the lookup indices must point
+    /// to a live symbol with a matching address, section and name (and vice versa), relocatable
+    /// objects must not contain ABS symbols (other than common symbols), and no two global
+    /// symbols at different locations may share a name. All violations are reported.
+    pub fn validate(&self) -> Result<()> {
+        fn check_index(
+            symbols: &ObjSymbols,
+            violations: &mut Vec<String>,
+            index: SymbolIndex,
+            what: impl FnOnce() -> String,
+            matches: impl FnOnce(&ObjSymbol) -> bool,
+        ) {
+            match symbols.get(index) {
+                None => {
+                    violations.push(format!("{}: symbol {} is missing or removed", what(), index))
+                }
+                Some(symbol) if !matches(symbol) => violations.push(format!(
+                    "{}: symbol {} ({}) is at {}:{:#010X}",
+                    what(),
+                    index,
+                    symbol.name,
+                    symbol.section.map_or("ABS".to_string(), |s| s.to_string()),
+                    symbol.address
+                )),
+                _ => {}
+            }
+        }
 
-    pub fn count(&self) -> SymbolIndex { self.symbols.len() as SymbolIndex }
+        let mut violations = vec![];
+        for (&address, symbol_idxs) in &self.symbols_by_address {
+            for &index in symbol_idxs {
+                check_index(
+                    self,
+                    &mut violations,
+                    index,
+                    || format!("Address index {:#010X}", address),
+                    |symbol| symbol.address as u32 == address,
+                );
+            }
+        }
+        for (section_idx, section_symbols) in self.symbols_by_section.iter().enumerate() {
+            for (&address, symbol_idxs) in section_symbols {
+                for &index in symbol_idxs {
+                    check_index(
+                        self,
+                        &mut violations,
+                        index,
+                        || format!("Section index {}:{:#010X}", section_idx, address),
+                        |symbol| {
+                            symbol.section == Some(section_idx as SectionIndex)
+                                && symbol.address as u32 == address
+                        },
+                    );
+                }
+            }
+        }
+        for (name, symbol_idxs) in &self.symbols_by_name {
+            for &index in symbol_idxs {
+                check_index(
+                    self,
+                    &mut violations,
+                    index,
+                    || format!("Name index {}", name),
+                    |symbol| &symbol.name == name,
+                );
+            }
+        }
 
-    pub fn at_section_address(
-        &self,
-        section_idx: SectionIndex,
-        addr: u32,
-    ) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
-        self.symbols_by_section
-            .get(section_idx as usize)
-            .and_then(|v| v.get(&addr))
-            .into_iter()
-            .flatten()
-            .map(move |&idx| (idx, &self.symbols[idx as usize]))
-            // "Stripped" symbols don't actually exist at the address
-            .filter(|(_, sym)| !sym.flags.is_stripped())
+        let mut globals = HashMap::<&str, (SymbolIndex, &ObjSymbol)>::new();
+        for (index, symbol) in self.iter() {
+            let address = symbol.address as u32;
+            if !self.symbols_by_address.get(&address).is_some_and(|v| v.contains(&index)) {
+                violations.push(format!(
+                    "Symbol {} ({}) missing from address index at {:#010X}",
+                    index, symbol.name, address
+                ));
+            }
+            if let Some(section_idx) = symbol.section {
+                if !self
+                    .symbols_by_section
+                    .get(section_idx as usize)
+                    .and_then(|m| m.get(&address))
+                    .is_some_and(|v| v.contains(&index))
+                {
+                    violations.push(format!(
+                        "Symbol {} ({}) missing from section index at {}:{:#010X}",
+                        index, symbol.name, section_idx, address
+                    ));
+                }
+            } else if symbol.address != 0
+                && !symbol.flags.is_common()
+                && self.obj_kind != ObjKind::Executable
+            {
+                violations.push(format!(
+                    "Symbol {} ({}) is ABS in a relocatable object",
+                    index, symbol.name
+                ));
+            }
+            if !symbol.name.is_empty()
+                && !self.symbols_by_name.get(&symbol.name).is_some_and(|v| v.contains(&index))
+            {
+                violations
+                    .push(format!("Symbol {} ({}) missing from name index", index, symbol.name));
+            }
+            if symbol.flags.scope() == ObjSymbolScope::Global
+                && !symbol.flags.is_stripped()
+                && !symbol.name.is_empty()
+            {
+                if let Some(&(other_index, other)) = globals.get(symbol.name.as_str()) {
+                    if other.section != symbol.section || other.address != symbol.address {
+                        violations.push(format!(
+                            "Duplicate global symbol {}: symbols {} ({:#010X}) and {} ({:#010X})",
+                            symbol.name, other_index, other.address, index, symbol.address
+                        ));
+                    }
+                } else {
+                    globals.insert(&symbol.name, (index, symbol));
+                }
+            }
+        }
+
+        ensure!(
+            violations.is_empty(),
+            "Symbol table has {} violation(s):\n  {}",
+            violations.len(),
+            violations.join("\n  ")
+        );
+        Ok(())
     }
 
-    pub fn kind_at_section_address(
-        &self,
-        section_idx: SectionIndex,
-        addr: u32,
-        kind: ObjSymbolKind,
-    ) -> Result<Option<(SymbolIndex, &ObjSymbol)>> {
-        self.at_section_address(section_idx, addr)
-            .filter(|(_, sym)| sym.kind == kind)
-            .at_most_one()
-            .map_err(|e| {
-                let symbols = e.map(|(_, s)| s).collect_vec();
-                for symbol in symbols {
-                    log::error!("{:?}", symbol);
+    /// Finds pairs of sized symbols in the same section whose address ranges intersect.
+    /// Zero-size labels and section symbols are ignored. Pairs are ordered by address.
+    pub fn find_overlaps(&self) -> Vec<(SymbolIndex, SymbolIndex)> {
+        let mut overlaps = vec![];
+        for section_symbols in &self.symbols_by_section {
+            // Symbols that haven't ended yet, as (end, index)
+            let mut open = Vec::<(u64, SymbolIndex)>::new();
+            for (&address, symbol_idxs) in section_symbols {
+                open.retain(|&(end, _)| end > address as u64);
+                for &index in symbol_idxs {
+                    let Some(symbol) = self.get(index) else {
+                        continue;
+                    };
+                    if symbol.size == 0 || symbol.kind == ObjSymbolKind::Section {
+                        continue;
+                    }
+                    overlaps.extend(open.iter().map(|&(_, other)| (other, index)));
+                    open.push((symbol.address + symbol.size, index));
                 }
-                anyhow!("Multiple symbols of kind {:?} at address {:#010X}", kind, addr)
-            })
+            }
+        }
+        overlaps
     }
 
-    // Iterate over all in address ascending order, excluding ABS symbols
-    pub fn iter_ordered(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
-        self.symbols_by_section
-            .iter()
-            .flat_map(|v| v.iter().map(|(_, v)| v))
-            .flat_map(move |v| v.iter().map(move |u| (*u, &self.symbols[*u as usize])))
+    /// Size conflicts encountered in [`ObjSymbols::add`], excluding trivial ones.
+    pub fn size_conflicts(&self) -> &[SizeConflict] { &self.size_conflicts }
+
+    fn record_size_conflict(&mut self, conflict: Option<SizeConflict>) {
+        let Some(conflict) = conflict else {
+            return;
+        };
+        // The same symbol is often added again from several sources with the same sizes
+        if !self.size_conflicts.iter().any(|c| {
+            c.symbol == conflict.symbol
+                && c.old_size == conflict.old_size
+                && c.new_size == conflict.new_size
+        }) {
+            self.size_conflicts.push(conflict);
+        }
     }
 
-    // Iterate over all ABS symbols
-    pub fn iter_abs(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
-        debug_assert!(self.obj_kind == ObjKind::Executable);
-        self.symbols_by_address
-            .iter()
-            .flat_map(|(_, v)| v.iter().map(|&u| (u, &self.symbols[u as usize])))
-            .filter(|(_, s)| s.section.is_none())
+    /// The alignment that conflicting sizes are rounded to before comparison.
+    pub fn size_conflict_align(&self) -> u32 { self.size_conflict_align }
+
+    /// Sets the alignment that conflicting sizes are rounded to before comparison.
+    /// Conflicts that disappear after rounding are considered trivial. Defaults to 4.
+    pub fn set_size_conflict_align(&mut self, align: u32) {
+        self.size_conflict_align = align.max(1);
     }
 
-    // Iterate over range in address ascending order, excluding ABS symbols
-    pub fn for_section_range<R>(
+    /// Sets the ranking used to choose between symbols at a relocation target.
+    /// Defaults to [`DefaultRelocResolver`].
+    pub fn set_reloc_resolver(&mut self, resolver: Arc<dyn RelocResolver>) {
+        self.reloc_resolver = resolver;
+    }
+
+    /// The ranking used to choose between symbols at a relocation target.
+    pub fn reloc_resolver(&self) -> Arc<dyn RelocResolver> { self.reloc_resolver.clone() }
+
+    /// Chooses between `symbols` at the same address with the configured [`RelocResolver`].
+    pub fn best_match_for_reloc<'a>(
         &self,
-        section_index: SectionIndex,
-        range: R,
-    ) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)>
-    where
+        symbols: Vec<(SymbolIndex, &'a ObjSymbol)>,
+        reloc_kind: ObjRelocKind,
+    ) -> Option<(SymbolIndex, &'a ObjSymbol)> {
+        best_match_for_reloc_with(symbols, reloc_kind, self.reloc_resolver.as_ref())
+    }
+
+    /// Collects hidden symbols that would be exposed across modules, either by being
+    /// exported or by being a non-local symbol with `ForceActive` set. Symbols generated
+    /// by split (gap symbols and other `NoWrite` symbols) are hidden and kept on purpose.
+    pub fn hidden_exports(&self) -> Vec<HiddenExport> {
+        self.iter()
+            .filter(|(_, symbol)| {
+                symbol.flags.is_hidden()
+                    && !symbol.flags.is_stripped()
+                    && !symbol.flags.is_no_write()
+                    && !symbol.name.starts_with("gap_")
+            })
+            .filter_map(|(symbol_idx, symbol)| {
+                let exported = symbol.flags.is_exported();
+                let force_active = symbol.flags.is_force_active() && !symbol.flags.is_local();
+                (exported || force_active).then(|| HiddenExport {
+                    symbol: symbol_idx,
+                    name: symbol.name.clone(),
+                    exported,
+                    force_active,
+                })
+            })
+            .collect()
+    }
+
+    /// Compares this symbol table against `other`, which may come from an object with a different
+    /// section layout. Symbols are matched by section name and address, preferring one with the
+    /// same name, then any remaining symbols by name. Section symbols and unnamed symbols are
+    /// ignored.
+    pub fn diff(
+        &self,
+        sections: &ObjSections,
+        other: &ObjSymbols,
+        other_sections: &ObjSections,
+    ) -> SymbolDiff {
+        let is_candidate =
+            |symbol: &ObjSymbol| symbol.kind != ObjSymbolKind::Section && !symbol.name.is_empty();
+        let section_map = sections
+            .iter()
+            .filter_map(|(index, section)| {
+                let (other_index, _) = other_sections.by_name(&section.name).ok().flatten()?;
+                Some((index, other_index))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut matches = HashMap::<SymbolIndex, SymbolIndex>::new();
+        let mut matched = HashSet::<SymbolIndex>::new();
+        // 0: same address and name, 1: same address, 2: same name
+        for pass in 0..3 {
+            for (index, symbol) in self.iter().filter(|(_, s)| is_candidate(s)) {
+                if matches.contains_key(&index) {
+                    continue;
+                }
+                let candidates = match (pass, symbol.section) {
+                    (0 | 1, Some(section)) => match section_map.get(&section) {
+                        Some(&other_section) => other
+                            .at_section_address(other_section, symbol.address as u32)
+                            .collect_vec(),
+                        None => continue,
+                    },
+                    (0 | 1, None) => continue,
+                    _ => other.for_name(&symbol.name).collect_vec(),
+                };
+                let found = candidates.into_iter().find(|&(other_index, other_symbol)| {
+                    is_candidate(other_symbol)
+                        && !matched.contains(&other_index)
+                        && (pass == 1 || other_symbol.name == symbol.name)
+                });
+                if let Some((other_index, _)) = found {
+                    matches.insert(index, other_index);
+                    matched.insert(other_index);
+                }
+            }
+        }
+
+        let mut diff = SymbolDiff::default();
+        for (index, symbol) in self.iter().filter(|(_, s)| is_candidate(s)) {
+            let Some(&other_index) = matches.get(&index) else {
+                diff.removed.push(index);
+                continue;
+            };
+            let other_symbol = &other[other_index];
+            let section_name = symbol.section.map(|i| sections[i].name.as_str());
+            let other_section_name = other_symbol.section.map(|i| other_sections[i].name.as_str());
+            if symbol.name != other_symbol.name
+                || section_name != other_section_name
+                || symbol.address != other_symbol.address
+                || symbol.size != other_symbol.size
+                || symbol.kind != other_symbol.kind
+            {
+                diff.changed.push((index, other_index));
+            }
+        }
+        diff.added = other
+            .iter()
+            .filter(|&(index, symbol)| is_candidate(symbol) && !matched.contains(&index))
+            .map(|(index, _)| index)
+            .collect();
+        diff
+    }
+
+    /// Removes a symbol, leaving a tombstone in its slot. Indices of other symbols are unaffected.
+    pub fn remove(&mut self, index: SymbolIndex) -> Result<()> {
+        let symbol =
+            self.symbols.get_mut(index as usize).and_then(Option::take).ok_or_else(|| {
+                anyhow!("Symbol index {} out of bounds or already removed", index)
+            })?;
+        self.symbols_by_address.nested_remove(&(symbol.address as u32), &index);
+        if let Some(section_idx) = symbol.section {
+            if let Some(map) = self.symbols_by_section.get_mut(section_idx as usize) {
+                map.nested_remove(&(symbol.address as u32), &index);
+            }
+        }
+        if !symbol.name.is_empty() {
+            self.symbols_by_name.nested_remove(&symbol.name, &index);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, index: SymbolIndex) -> Option<&ObjSymbol> {
+        self.symbols.get(index as usize).and_then(Option::as_ref)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(i, s)| s.as_ref().map(|s| (i as SymbolIndex, s)))
+    }
+
+    /// Iterate over symbols with the given scope.
+    pub fn iter_by_scope(
+        &self,
+        scope: ObjSymbolScope,
+    ) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        self.iter().filter(move |(_, s)| s.flags.scope() == scope)
+    }
+
+    /// Iterate over global and weak symbols. Symbols with unknown scope are excluded.
+    pub fn iter_globals(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        self.iter().filter(|(_, s)| {
+            matches!(s.flags.scope(), ObjSymbolScope::Global | ObjSymbolScope::Weak)
+        })
+    }
+
+    /// Iterate over local symbols. Symbols with unknown scope are excluded.
+    pub fn iter_locals(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        self.iter_by_scope(ObjSymbolScope::Local)
+    }
+
+    /// The number of symbols, excluding removed symbols.
+    pub fn count(&self) -> SymbolIndex {
+        self.symbols.iter().filter(|s| s.is_some()).count() as SymbolIndex
+    }
+
+    /// The number of symbol slots, including removed symbols.
+    /// Suitable for sizing tables indexed by [`SymbolIndex`].
+    pub fn slot_count(&self) -> SymbolIndex { self.symbols.len() as SymbolIndex }
+
+    pub fn at_section_address(
+        &self,
+        section_idx: SectionIndex,
+        addr: u32,
+    ) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        self.symbols_by_section
+            .get(section_idx as usize)
+            .and_then(|v| v.get(&addr))
+            .into_iter()
+            .flatten()
+            .map(move |&idx| (idx, &self[idx]))
+            // "Stripped" symbols don't actually exist at the address
+            .filter(|(_, sym)| !sym.flags.is_stripped())
+    }
+
+    pub fn kind_at_section_address(
+        &self,
+        section_idx: SectionIndex,
+        addr: u32,
+        kind: ObjSymbolKind,
+    ) -> Result<Option<(SymbolIndex, &ObjSymbol)>> {
+        self.at_section_address(section_idx, addr)
+            .filter(|(_, sym)| sym.kind == kind)
+            .at_most_one()
+            .map_err(|e| {
+                let symbols = e.map(|(_, s)| s).collect_vec();
+                for symbol in symbols {
+                    log::error!("{:?}", symbol);
+                }
+                anyhow!("Multiple symbols of kind {:?} at address {:#010X}", kind, addr)
+            })
+    }
+
+    // Iterate over all in address ascending order, excluding ABS symbols
+    pub fn iter_ordered(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        self.symbols_by_section
+            .iter()
+            .flat_map(|v| v.iter().map(|(_, v)| v))
+            .flat_map(move |v| v.iter().map(move |u| (*u, &self[*u])))
+    }
+
+    // Iterate over all ABS symbols
+    pub fn iter_abs(&self) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        debug_assert!(self.obj_kind == ObjKind::Executable);
+        self.symbols_by_address
+            .iter()
+            .flat_map(|(_, v)| v.iter().map(|&u| (u, &self[u])))
+            .filter(|(_, s)| s.section.is_none())
+    }
+
+    // Iterate over range in address ascending order, excluding ABS symbols
+    pub fn for_section_range<R>(
+        &self,
+        section_index: SectionIndex,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)>
+    where
         R: RangeBounds<u32> + Clone,
     {
         self.symbols_by_section
             .get(section_index as usize)
             .into_iter()
             .flat_map(move |v| v.range(range.clone()))
-            .flat_map(move |(_, v)| v.iter().map(move |u| (*u, &self.symbols[*u as usize])))
+            .flat_map(move |(_, v)| v.iter().map(move |u| (*u, &self[*u])))
+    }
+
+    // Iterate over symbols overlapping range in address ascending order, excluding ABS symbols.
+    // Includes sized symbols starting before the range that extend into it.
+    pub fn for_section_range_overlapping(
+        &self,
+        section_index: SectionIndex,
+        range: Range<u32>,
+    ) -> impl DoubleEndedIterator<Item = (SymbolIndex, &ObjSymbol)> {
+        // Walk back to the nearest preceding sized symbol
+        let mut preceding = vec![];
+        for (symbol_idx, symbol) in self.for_section_range(section_index, ..range.start).rev() {
+            if symbol.size == 0 {
+                continue;
+            }
+            if symbol.address + symbol.size <= range.start as u64 {
+                break;
+            }
+            preceding.push((symbol_idx, symbol));
+        }
+        preceding.into_iter().rev().chain(self.for_section_range(section_index, range))
+    }
+
+    /// The nearest symbol at or before `addr` within its section, excluding ABS symbols.
+    /// Of multiple symbols at the same address, the last one added is returned.
+    pub fn nearest_preceding(&self, addr: SectionAddress) -> Option<(SymbolIndex, &ObjSymbol)> {
+        self.symbols_by_section
+            .get(addr.section as usize)?
+            .range(..=addr.address)
+            .rev()
+            .find_map(|(_, v)| v.last())
+            .map(|&idx| (idx, &self[idx]))
+    }
+
+    /// The nearest symbol strictly after `addr` within its section, excluding ABS symbols.
+    /// Of multiple symbols at the same address, the first one added is returned.
+    pub fn nearest_following(&self, addr: SectionAddress) -> Option<(SymbolIndex, &ObjSymbol)> {
+        self.symbols_by_section
+            .get(addr.section as usize)?
+            .range((Bound::Excluded(addr.address), Bound::Unbounded))
+            .find_map(|(_, v)| v.first())
+            .map(|&idx| (idx, &self[idx]))
     }
 
     pub fn indexes_for_range<R>(
@@ -449,7 +1463,28 @@ impl ObjSymbols {
             .get(section_idx as usize)
             .into_iter()
             .flat_map(|v| v.iter().map(|(_, v)| v))
-            .flat_map(move |v| v.iter().map(move |u| (*u, &self.symbols[*u as usize])))
+            .flat_map(move |v| v.iter().map(move |u| (*u, &self[*u])))
+    }
+
+    /// The number of section indices tracked, including sections without symbols.
+    pub fn section_count(&self) -> usize { self.symbols_by_section.len() }
+
+    /// Tracks at least `count` sections, so that sections without symbols (e.g. zero-length
+    /// sections) are included in [`ObjSymbols::section_count`].
+    pub fn reserve_sections(&mut self, count: usize) {
+        if count > self.symbols_by_section.len() {
+            self.symbols_by_section.resize_with(count, BTreeMap::new);
+        }
+    }
+
+    /// Whether the given section contains any symbols.
+    pub fn has_section_symbols(&self, idx: usize) -> bool {
+        self.symbols_by_section.get(idx).is_some_and(|v| !v.is_empty())
+    }
+
+    /// The number of symbols in the given section, or 0 if out of range.
+    pub fn section_symbol_count(&self, idx: usize) -> usize {
+        self.symbols_by_section.get(idx).map_or(0, |v| v.values().map(Vec::len).sum())
     }
 
     pub fn for_name(
@@ -459,7 +1494,7 @@ impl ObjSymbols {
         self.symbols_by_name
             .get(name)
             .into_iter()
-            .flat_map(move |v| v.iter().map(move |u| (*u, &self.symbols[*u as usize])))
+            .flat_map(move |v| v.iter().map(move |u| (*u, &self[*u])))
     }
 
     pub fn by_name(&self, name: &str) -> Result<Option<(SymbolIndex, &ObjSymbol)>> {
@@ -524,7 +1559,11 @@ impl ObjSymbols {
     }
 
     pub fn replace(&mut self, index: SymbolIndex, symbol: ObjSymbol) -> Result<()> {
-        let symbol_ref = &mut self.symbols[index as usize];
+        let symbol_ref = self
+            .symbols
+            .get_mut(index as usize)
+            .and_then(Option::as_mut)
+            .ok_or_else(|| anyhow!("Can't replace removed symbol {}", index))?;
         ensure!(symbol_ref.address == symbol.address, "Can't modify address with replace_symbol");
         ensure!(symbol_ref.section == symbol.section, "Can't modify section with replace_symbol");
         if symbol_ref.name != symbol.name {
@@ -535,63 +1574,567 @@ impl ObjSymbols {
                 self.symbols_by_name.nested_push(symbol.name.clone(), index);
             }
         }
+        self.max_symbol_size = self.max_symbol_size.max(symbol.size);
         *symbol_ref = symbol;
         Ok(())
     }
 
-    // Try to find a previous sized symbol that encompasses the target
+    /// Splits a function in two at `at`, for when analysis merged two adjacent functions.
+    /// The original function is shrunk to end at `at`, and a new function covering the
+    /// remainder is added with an auto-generated name for module `module_id`. `at` must be
+    /// 4-byte aligned and strictly inside the function. Returns the index of the new function.
+    pub fn split_function(
+        &mut self,
+        idx: SymbolIndex,
+        at: u32,
+        module_id: u32,
+    ) -> Result<SymbolIndex> {
+        let symbol =
+            self.get(idx).ok_or_else(|| anyhow!("Can't split removed symbol {}", idx))?.clone();
+        ensure!(
+            symbol.is_code(),
+            "Can't split {}: not a function ({:?})",
+            symbol.name,
+            symbol.kind
+        );
+        ensure!(at % 4 == 0, "Can't split {} at unaligned address {:#010X}", symbol.name, at);
+        let end = symbol.address + symbol.size;
+        ensure!(
+            (at as u64) > symbol.address && (at as u64) < end,
+            "Can't split {} at {:#010X}: outside of function {:#010X}..{:#010X}",
+            symbol.name,
+            at,
+            symbol.address,
+            end
+        );
+        let new_idx = self.add(
+            ObjSymbol {
+                name: auto_symbol_naming().function_name(module_id, at),
+                address: at as u64,
+                section: symbol.section,
+                size: end - at as u64,
+                size_known: true,
+                kind: ObjSymbolKind::Function,
+                ..Default::default()
+            },
+            false,
+        )?;
+        self.replace(idx, ObjSymbol {
+            size: at as u64 - symbol.address,
+            size_known: true,
+            ..symbol
+        })?;
+        Ok(new_idx)
+    }
+
+    /// Merges `count` contiguous object symbols starting at `idx` into a single array symbol.
+    /// The symbols must share a fixed-size data kind and alignment, and each must be exactly
+    /// one element in size. Merging is refused if any symbol inside the array (other than the
+    /// first) is the target of a relocation.
+    pub fn merge_adjacent_data(
+        &mut self,
+        sections: &ObjSections,
+        idx: SymbolIndex,
+        count: usize,
+    ) -> Result<()> {
+        let referenced = relocation_targets(sections);
+        self.merge_adjacent_data_with(idx, count, |index| referenced.contains(&index))
+    }
+
+    /// See [`ObjSymbols::merge_adjacent_data`]. `is_referenced` reports whether a symbol is the
+    /// target of a relocation.
+    pub fn merge_adjacent_data_with(
+        &mut self,
+        idx: SymbolIndex,
+        count: usize,
+        is_referenced: impl Fn(SymbolIndex) -> bool,
+    ) -> Result<()> {
+        ensure!(count >= 2, "Can't merge fewer than 2 symbols");
+        let first = self.get(idx).ok_or_else(|| anyhow!("Can't merge removed symbol {}", idx))?;
+        ensure!(
+            first.kind == ObjSymbolKind::Object,
+            "Can't merge {}: not an object ({:?})",
+            first.name,
+            first.kind
+        );
+        let section_idx =
+            first.section.ok_or_else(|| anyhow!("Can't merge ABS symbol {}", first.name))?;
+        let element_size = first.data_kind.array_element_size().ok_or_else(|| {
+            anyhow!("Can't merge {}: data kind {:?} has no fixed size", first.name, first.data_kind)
+        })? as u64;
+        ensure!(
+            first.size == element_size,
+            "Can't merge {}: size {:#X} doesn't match element size {:#X}",
+            first.name,
+            first.size,
+            element_size
+        );
+
+        let start = first.address;
+        let end = start + element_size * count as u64;
+        let mut merged = vec![];
+        for i in 1..count as u64 {
+            let address = start + i * element_size;
+            let (index, symbol) = self
+                .at_section_address(section_idx, address as u32)
+                .find(|(_, s)| s.kind == ObjSymbolKind::Object)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Symbols after {} aren't contiguous: none at {:#010X}",
+                        first.name,
+                        address
+                    )
+                })?;
+            ensure!(
+                symbol.data_kind == first.data_kind
+                    && symbol.align == first.align
+                    && symbol.size == element_size,
+                "Can't merge {} into {}: data kind, alignment or size differ",
+                symbol.name,
+                first.name
+            );
+            merged.push(index);
+        }
+        if let Some((_, symbol)) = self
+            .for_section_range(section_idx, start as u32 + 1..end as u32)
+            .find(|&(index, _)| is_referenced(index))
+        {
+            bail!("Can't merge into {}: {} is referenced by a relocation", first.name, symbol.name);
+        }
+
+        let symbol = ObjSymbol { size: end - start, size_known: true, ..first.clone() };
+        for index in merged {
+            self.remove(index)?;
+        }
+        self.replace(idx, symbol)
+    }
+
+    /// Renames a symbol. A global or weak symbol must not share a name with another global or
+    /// weak symbol at a different location, otherwise a [`DuplicateSymbolError`] is returned.
+    /// Local symbols may freely collide.
+    pub fn rename(&mut self, index: SymbolIndex, new_name: String) -> Result<()> {
+        let symbol =
+            self.get(index).ok_or_else(|| anyhow!("Can't rename removed symbol {}", index))?;
+        if symbol.name == new_name {
+            return Ok(());
+        }
+        if let Some((existing, _)) = self
+            .for_name(&new_name)
+            .find(|&(other_index, other)| other_index != index && is_global_conflict(symbol, other))
+        {
+            return Err(
+                DuplicateSymbolError { index: Some(index), existing, name: new_name }.into()
+            );
+        }
+        let demangled_name = demangle(&new_name, &DemangleOptions::default());
+        self.replace(index, ObjSymbol { name: new_name, demangled_name, ..symbol.clone() })
+    }
+
+    /// Renames all symbols matched by `transform`. Renames that would leave a renamed global or
+    /// weak symbol conflicting with another one (see [`ObjSymbols::rename`]) are rejected
+    /// with a [`DuplicateSymbolError`] before any symbol is modified. Conflicts between symbols
+    /// that aren't renamed are left alone. Returns the number of symbols renamed.
+    pub fn rename_all(&mut self, transform: &SymbolRenameTransform) -> Result<usize> {
+        let renames = self
+            .iter()
+            .filter_map(|(index, symbol)| transform.apply(symbol).map(|name| (index, name)))
+            .collect_vec();
+
+        // Check uniqueness against the final names, so renames may swap or chain freely
+        let new_names =
+            renames.iter().map(|(index, name)| (*index, name)).collect::<HashMap<_, _>>();
+        let mut globals = HashMap::<&String, Vec<SymbolIndex>>::new();
+        for (index, symbol) in self.iter_globals() {
+            if symbol.name.is_empty() {
+                continue;
+            }
+            let name = new_names.get(&index).copied().unwrap_or(&symbol.name);
+            let renamed = new_names.contains_key(&index);
+            let others = globals.entry(name).or_default();
+            if let Some(&existing) = others.iter().find(|&&other| {
+                (renamed || new_names.contains_key(&other))
+                    && is_global_conflict(symbol, &self[other])
+            }) {
+                // Report the renamed symbol as the one being renamed
+                let (index, existing) = if renamed { (index, existing) } else { (existing, index) };
+                return Err(DuplicateSymbolError {
+                    index: Some(index),
+                    existing,
+                    name: name.clone(),
+                }
+                .into());
+            }
+            others.push(index);
+        }
+
+        let count = renames.len();
+        for (index, new_name) in renames {
+            let demangled_name = demangle(&new_name, &DemangleOptions::default());
+            let symbol = self[index].clone();
+            self.replace(index, ObjSymbol { name: new_name, demangled_name, ..symbol })?;
+        }
+        Ok(count)
+    }
+
+    /// Collapses multiple zero-size auto-generated labels at the same address into one.
+    /// `jumptable_` labels are preferred, then the lowest name, so the result is deterministic.
+    /// Known alignment, data kind and special flags are merged into the kept label.
+    /// Returns the number of symbols removed.
+    pub fn coalesce_labels(&mut self) -> Result<usize> {
+        let mut groups = vec![];
+        for section_symbols in &self.symbols_by_section {
+            for symbol_idxs in section_symbols.values() {
+                let labels = symbol_idxs
+                    .iter()
+                    .copied()
+                    .filter(|&idx| {
+                        let symbol = &self[idx];
+                        symbol.kind == ObjSymbolKind::Unknown
+                            && symbol.size == 0
+                            && !symbol.flags.is_stripped()
+                            && is_auto_symbol(symbol)
+                    })
+                    .collect_vec();
+                if labels.len() > 1 {
+                    groups.push(labels);
+                }
+            }
+        }
+        let mut removed = 0;
+        for labels in groups {
+            let keep_idx = labels
+                .iter()
+                .copied()
+                .min_by(|&a, &b| {
+                    let (a, b) = (&self[a], &self[b]);
+                    is_auto_jump_table(b)
+                        .cmp(&is_auto_jump_table(a))
+                        .then_with(|| a.name.cmp(&b.name))
+                })
+                .unwrap();
+            let mut new_symbol = self[keep_idx].clone();
+            for &idx in &labels {
+                let other = &self[idx];
+                new_symbol.align = match (new_symbol.align, other.align) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    (a, b) => a.or(b),
+                };
+                if new_symbol.data_kind == ObjDataKind::Unknown {
+                    new_symbol.data_kind = other.data_kind;
+                }
+                new_symbol.flags.0 |= other.flags.keep_flags();
+            }
+            log::debug!(
+                "Coalescing labels at {:#010X} into {}: {}",
+                new_symbol.address,
+                new_symbol.name,
+                labels
+                    .iter()
+                    .filter(|&&idx| idx != keep_idx)
+                    .map(|&idx| &self[idx].name)
+                    .join(", ")
+            );
+            self.replace(keep_idx, new_symbol)?;
+            for idx in labels {
+                if idx != keep_idx {
+                    self.remove(idx)?;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Try to find a previous sized symbol that encompasses the target.
+    ///
+    /// `in_sda` indicates that the target is in a small data section. In this case,
+    /// interior labels are only used for `@ha`/`@h`/`@l` relocations if no
+    /// containing symbol is found.
     pub fn for_relocation(
         &self,
         target_addr: SectionAddress,
         reloc_kind: ObjRelocKind,
+        in_sda: bool,
     ) -> Result<Option<(SymbolIndex, &ObjSymbol)>> {
+        if in_sda {
+            if let Some(result) = self.find_for_relocation(target_addr, reloc_kind, true) {
+                return Ok(Some(result));
+            }
+        }
+        Ok(self.find_for_relocation(target_addr, reloc_kind, false))
+    }
+
+    /// Like [`ObjSymbols::for_relocation`], but returns the addend from the matched symbol to
+    /// the target, so that `symbol.address + addend` reconstructs the original target.
+    pub fn resolve_relocation(
+        &self,
+        target_addr: SectionAddress,
+        reloc_kind: ObjRelocKind,
+        in_sda: bool,
+    ) -> Result<Option<(SymbolIndex, i64)>> {
+        Ok(self.for_relocation(target_addr, reloc_kind, in_sda)?.map(|(symbol_idx, symbol)| {
+            (symbol_idx, target_addr.address as i64 - symbol.address as i64)
+        }))
+    }
+
+    /// Determines why [`ObjSymbols::for_relocation`] found no symbol for the target: either
+    /// every symbol containing it was rejected by [`ObjSymbol::referenced_by`], or none exist.
+    pub fn relocation_miss_reason(&self, target_addr: SectionAddress) -> RelocationMissReason {
+        // Yields symbols at the target and preceding sized symbols that extend into it
+        let mut containing = self.for_section_range_overlapping(
+            target_addr.section,
+            target_addr.address..target_addr.address.saturating_add(1),
+        );
+        if containing.next().is_some() {
+            RelocationMissReason::Filtered
+        } else {
+            RelocationMissReason::NotFound
+        }
+    }
+
+    fn find_for_relocation(
+        &self,
+        target_addr: SectionAddress,
+        reloc_kind: ObjRelocKind,
+        in_sda: bool,
+    ) -> Option<(SymbolIndex, &ObjSymbol)> {
         // ensure!(self.obj_kind == ObjKind::Executable);
-        let mut result = None;
-        for (_addr, symbol_idxs) in self.indexes_for_range(..=target_addr.address).rev() {
+        // Search the target section first, then fall back to ABS symbols
+        let section_symbols = self
+            .symbols_by_section
+            .get(target_addr.section as usize)
+            .into_iter()
+            .flat_map(|v| v.range(..=target_addr.address).rev())
+            .map(|(k, v)| (*k, v.as_slice()));
+        self.walk_for_relocation(section_symbols, target_addr, reloc_kind, in_sda).or_else(|| {
+            let abs_symbols =
+                self.indexes_for_range(..=target_addr.address).rev().filter(|(_, symbol_idxs)| {
+                    symbol_idxs.iter().any(|&idx| self[idx].section.is_none())
+                });
+            self.walk_for_relocation(abs_symbols, target_addr, reloc_kind, in_sda)
+        })
+    }
+
+    fn walk_for_relocation<'a>(
+        &'a self,
+        iter: impl Iterator<Item = (u32, &'a [SymbolIndex])>,
+        target_addr: SectionAddress,
+        reloc_kind: ObjRelocKind,
+        in_sda: bool,
+    ) -> Option<(SymbolIndex, &'a ObjSymbol)> {
+        for (_addr, symbol_idxs) in iter {
             let symbols = symbol_idxs
                 .iter()
-                .map(|&idx| (idx, &self.symbols[idx as usize]))
+                .map(|&idx| (idx, &self[idx]))
                 .filter(|(_, sym)| {
                     (sym.section.is_none() || sym.section == Some(target_addr.section))
-                        && sym.referenced_by(reloc_kind)
+                        && sym.referenced_by(reloc_kind, in_sda)
                 })
                 .collect_vec();
-            let Some((symbol_idx, symbol)) = best_match_for_reloc(symbols, reloc_kind) else {
+            let Some((symbol_idx, symbol)) = self.best_match_for_reloc(symbols, reloc_kind) else {
                 continue;
             };
             if symbol.address == target_addr.address as u64 {
-                result = Some((symbol_idx, symbol));
-                break;
+                return Some((symbol_idx, symbol));
             }
             if symbol.size > 0 {
                 if symbol.address + symbol.size > target_addr.address as u64 {
-                    result = Some((symbol_idx, symbol));
+                    return Some((symbol_idx, symbol));
                 }
                 break;
             }
         }
-        Ok(result)
+        None
+    }
+
+    /// Locate the symbol containing the target address, independent of any relocation kind.
+    /// Function and object symbols are preferred over labels, and `RelocationIgnore` symbols
+    /// are skipped. If multiple sized symbols contain the target, the one with the smallest
+    /// range is returned.
+    pub fn at_address(&self, target_addr: SectionAddress) -> Option<(SymbolIndex, &ObjSymbol)> {
+        let target = target_addr.address as u64;
+        let mut candidates = vec![];
+        for (symbol_idx, symbol) in
+            self.for_section_range(target_addr.section, ..=target_addr.address).rev()
+        {
+            // No symbol starting further back is large enough to contain the target
+            if symbol.address + self.max_symbol_size <= target && symbol.address < target {
+                break;
+            }
+            if symbol.flags.is_stripped() || symbol.flags.is_relocation_ignore() {
+                continue;
+            }
+            // Keep scanning past symbols that end before the target, since a larger
+            // symbol further back may still contain it
+            if symbol.address + symbol.size > target
+                || (symbol.size == 0 && symbol.address == target)
+            {
+                candidates.push((symbol_idx, symbol));
+            }
+        }
+        let sized = candidates
+            .iter()
+            .filter(|(_, s)| s.size > 0 && (s.is_code() || s.is_data()))
+            .collect_vec();
+        if sized.len() > 1 {
+            log::debug!(
+                "Multiple sized symbols contain {:#010X}: {}",
+                target,
+                sized.iter().map(|(_, s)| s.name.as_str()).join(", ")
+            );
+        }
+        candidates.into_iter().min_by_key(|(_, symbol)| {
+            let is_label = !symbol.is_code() && !symbol.is_data();
+            (is_label, symbol.size == 0, symbol.size)
+        })
+    }
+
+    /// Formats the target as `name` or `name+0x..` using the symbol found by
+    /// [`ObjSymbols::at_address`], preferring the demangled name. Falls back to the raw
+    /// address when no symbol covers the target.
+    pub fn format_address(&self, target_addr: SectionAddress) -> String {
+        let Some((_, symbol)) = self.at_address(target_addr) else {
+            return format!("{:#010X}", target_addr.address);
+        };
+        let name = symbol.demangled_name.as_deref().unwrap_or(&symbol.name);
+        let offset = target_addr.address as u64 - symbol.address;
+        if offset == 0 {
+            name.to_string()
+        } else {
+            format!("{}+{:#X}", name, offset)
+        }
     }
 
     #[inline]
     pub fn flags(&mut self, idx: SymbolIndex) -> &mut ObjSymbolFlagSet {
-        &mut self.symbols[idx as usize].flags
+        &mut self.symbols[idx as usize].as_mut().expect("Symbol was removed").flags
+    }
+
+    /// Sets whether relocations may target the symbol, see [`ObjSymbol::referenced_by`].
+    #[inline]
+    pub fn set_relocation_ignore(&mut self, idx: SymbolIndex, value: bool) {
+        self.flags(idx).set_relocation_ignore(value);
+    }
+
+    /// Fills in missing demangled names, calling the demangler once per distinct name.
+    /// Names that fail to demangle are remembered and not retried.
+    /// With `force`, existing demangled names and failures are recomputed as well, which picks up
+    /// demangler improvements. Returns the number of names passed to the demangler.
+    pub fn demangle_all(&mut self, force: bool) -> usize {
+        let options = DemangleOptions::default();
+        if force {
+            self.demangle_failures.clear();
+        }
+        let mut cache = HashMap::<String, Option<String>>::new();
+        for symbol in self.symbols.iter_mut().flatten() {
+            if symbol.name.is_empty()
+                || (!force && symbol.demangled_name.is_some())
+                || self.demangle_failures.contains(&symbol.name)
+            {
+                continue;
+            }
+            symbol.demangled_name = cache
+                .entry(symbol.name.clone())
+                .or_insert_with(|| demangle(&symbol.name, &options))
+                .clone();
+        }
+        let count = cache.len();
+        self.demangle_failures
+            .extend(cache.into_iter().filter(|(_, d)| d.is_none()).map(|(name, _)| name));
+        count
     }
 }
 
+/// Whether merging `in_symbol` into `existing` keeps the derived fields ignored by
+/// [`ObjSymbol::semantic_eq`]: each is either unset in `in_symbol` or already equal.
+fn ignored_fields_unchanged(existing: &ObjSymbol, in_symbol: &ObjSymbol) -> bool {
+    fn unchanged<T: PartialEq>(existing: &Option<T>, incoming: &Option<T>) -> bool {
+        incoming.is_none() || incoming == existing
+    }
+    unchanged(&existing.demangled_name, &in_symbol.demangled_name)
+        && unchanged(&existing.source_file, &in_symbol.source_file)
+        && unchanged(&existing.source_line, &in_symbol.source_line)
+}
+
+/// Collects all symbols targeted by relocations.
+pub fn relocation_targets(sections: &ObjSections) -> HashSet<SymbolIndex> {
+    sections
+        .iter()
+        .flat_map(|(_, section)| section.relocations.iter().map(|(_, reloc)| reloc.target_symbol))
+        .collect()
+}
+
 impl Index<SymbolIndex> for ObjSymbols {
     type Output = ObjSymbol;
 
-    fn index(&self, index: SymbolIndex) -> &Self::Output { &self.symbols[index as usize] }
+    fn index(&self, index: SymbolIndex) -> &Self::Output {
+        self.symbols[index as usize].as_ref().expect("Symbol was removed")
+    }
 }
 
 impl ObjSymbol {
+    /// The number of elements, if this symbol is an array of a fixed-size data kind.
+    #[inline]
+    pub fn array_len(&self) -> Option<u64> {
+        let element_size = self.data_kind.array_element_size()? as u64;
+        (self.size > element_size && self.size % element_size == 0)
+            .then_some(self.size / element_size)
+    }
+
+    /// The number of entries, if this symbol is a jump table.
+    #[inline]
+    pub fn jump_table_entries(&self) -> Option<u32> {
+        (self.data_kind == ObjDataKind::JumpTable).then_some((self.size / 4) as u32)
+    }
+
+    /// Whether this symbol is executable code.
+    #[inline]
+    pub fn is_code(&self) -> bool { self.kind == ObjSymbolKind::Function }
+
+    /// Whether this symbol is data. Labels with a known data kind are considered data.
+    #[inline]
+    pub fn is_data(&self) -> bool {
+        match self.kind {
+            ObjSymbolKind::Object => true,
+            ObjSymbolKind::Unknown => self.data_kind != ObjDataKind::Unknown,
+            ObjSymbolKind::Function | ObjSymbolKind::Section => false,
+        }
+    }
+
+    /// Compares the fields that are written out or used by analysis, ignoring volatile ones
+    /// and those derived from other fields or from debug info: `size_known`, the demangled name
+    /// and the source location.
+    pub fn semantic_eq(&self, other: &ObjSymbol) -> bool {
+        self.name == other.name
+            && self.address == other.address
+            && self.section == other.section
+            && self.size == other.size
+            && self.flags == other.flags
+            && self.kind == other.kind
+            && self.align == other.align
+            && self.data_kind == other.data_kind
+            && self.name_hash == other.name_hash
+            && self.demangled_name_hash == other.demangled_name_hash
+            && self.notes == other.notes
+            && self.sort_index == other.sort_index
+    }
+
     /// Whether this symbol can be referenced by the given relocation kind.
-    pub fn referenced_by(&self, reloc_kind: ObjRelocKind) -> bool {
+    /// `in_sda` indicates that the symbol is in a small data section.
+    ///
+    /// Checks are applied in order:
+    /// 1. `RelocationIgnore` and `Stripped` symbols are never referenced.
+    /// 2. `AllowAnyReloc` symbols are referenced by any relocation kind.
+    /// 3. Otherwise, heuristics based on the symbol name and kind are used.
+    pub fn referenced_by(&self, reloc_kind: ObjRelocKind, in_sda: bool) -> bool {
         if self.flags.is_relocation_ignore() || self.flags.is_stripped() {
             return false;
         }
 
+        if self.flags.is_allow_any_reloc() {
+            return true;
+        }
+
         if is_linker_generated_label(&self.name) {
             // Linker generated labels will only be referenced by @ha/@h/@l relocations
             return matches!(
@@ -601,6 +2144,12 @@ impl ObjSymbol {
         }
 
         match self.kind {
+            // Small data is addressed relative to the SDA base, so @ha/@h/@l relocations
+            // should reference the containing object rather than an interior label
+            ObjSymbolKind::Unknown if in_sda => !matches!(
+                reloc_kind,
+                ObjRelocKind::PpcAddr16Ha | ObjRelocKind::PpcAddr16Hi | ObjRelocKind::PpcAddr16Lo
+            ),
             ObjSymbolKind::Unknown => true,
             ObjSymbolKind::Function => !matches!(reloc_kind, ObjRelocKind::PpcEmbSda21),
             ObjSymbolKind::Object => {
@@ -619,50 +2168,1469 @@ impl ObjSymbol {
     }
 }
 
-pub fn best_match_for_reloc(
-    mut symbols: Vec<(SymbolIndex, &ObjSymbol)>,
-    reloc_kind: ObjRelocKind,
-) -> Option<(SymbolIndex, &ObjSymbol)> {
-    if symbols.len() == 1 {
-        return symbols.into_iter().next();
+/// Whether two symbols sharing a name are conflicting definitions: both global or weak,
+/// not stripped, and at different locations.
+fn is_global_conflict(a: &ObjSymbol, b: &ObjSymbol) -> bool {
+    [a, b].iter().all(|s| {
+        matches!(s.flags.scope(), ObjSymbolScope::Global | ObjSymbolScope::Weak)
+            && !s.flags.is_stripped()
+    }) && (a.section != b.section || a.address != b.address)
+}
+
+/// Common symbols must be ABS, sized, and have a power-of-two alignment if set.
+fn validate_common(symbol: &ObjSymbol) -> Result<()> {
+    if !symbol.flags.is_common() {
+        return Ok(());
     }
-    symbols.sort_by_key(|&(_, symbol)| {
-        let mut rank = match symbol.kind {
-            ObjSymbolKind::Function | ObjSymbolKind::Object => {
-                // HACK: These are generally not referenced directly, so reduce their rank
-                if matches!(
-                    symbol.name.as_str(),
-                    "__save_gpr" | "__restore_gpr" | "__save_fpr" | "__restore_fpr"
-                ) {
-                    return 0;
-                }
-                match reloc_kind {
-                    ObjRelocKind::PpcAddr16Hi
-                    | ObjRelocKind::PpcAddr16Ha
-                    | ObjRelocKind::PpcAddr16Lo => 1,
-                    ObjRelocKind::Absolute
-                    | ObjRelocKind::PpcRel24
-                    | ObjRelocKind::PpcRel14
-                    | ObjRelocKind::PpcEmbSda21 => 2,
-                }
+    ensure!(symbol.section.is_none(), "Common symbol {} must not have a section", symbol.name);
+    ensure!(symbol.size > 0, "Common symbol {} must have a non-zero size", symbol.name);
+    if let Some(align) = symbol.align {
+        ensure!(
+            align.is_power_of_two(),
+            "Common symbol {} has invalid alignment {:#X}",
+            symbol.name,
+            align
+        );
+    }
+    Ok(())
+}
+
+/// Ranks symbols at the same address as relocation targets. Symbols that compare as
+/// [`Ordering::Less`] are preferred; of equally ranked symbols, the first one added wins.
+pub trait RelocResolver: fmt::Debug + Send + Sync {
+    fn compare(&self, a: &ObjSymbol, b: &ObjSymbol, reloc_kind: ObjRelocKind) -> Ordering;
+}
+
+/// Prefers the symbol kind suited to the relocation kind (e.g. functions for branches), then
+/// sized symbols, then named symbols over auto-generated ones.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DefaultRelocResolver;
+
+impl RelocResolver for DefaultRelocResolver {
+    fn compare(&self, a: &ObjSymbol, b: &ObjSymbol, reloc_kind: ObjRelocKind) -> Ordering {
+        reloc_rank(b, reloc_kind)
+            .cmp(&reloc_rank(a, reloc_kind))
+            .then_with(|| is_auto_symbol(a).cmp(&is_auto_symbol(b)))
+    }
+}
+
+/// Breaks ties between symbols that [`DefaultRelocResolver`] ranks equally.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RelocTieBreak {
+    /// The first symbol added wins.
+    #[default]
+    First,
+    /// The largest symbol wins, e.g. an enclosing object over its first member.
+    Largest,
+    /// The smallest symbol wins.
+    Smallest,
+}
+
+impl RelocResolver for RelocTieBreak {
+    fn compare(&self, a: &ObjSymbol, b: &ObjSymbol, reloc_kind: ObjRelocKind) -> Ordering {
+        DefaultRelocResolver.compare(a, b, reloc_kind).then_with(|| match self {
+            RelocTieBreak::First => Ordering::Equal,
+            RelocTieBreak::Largest => b.size.cmp(&a.size),
+            RelocTieBreak::Smallest => a.size.cmp(&b.size),
+        })
+    }
+}
+
+pub fn best_match_for_reloc_with<'a>(
+    mut symbols: Vec<(SymbolIndex, &'a ObjSymbol)>,
+    reloc_kind: ObjRelocKind,
+    resolver: &dyn RelocResolver,
+) -> Option<(SymbolIndex, &'a ObjSymbol)> {
+    if symbols.len() == 1 {
+        return symbols.into_iter().next();
+    }
+    symbols.sort_by(|&(_, a), &(_, b)| resolver.compare(a, b, reloc_kind));
+    symbols.into_iter().next()
+}
+
+/// Groups `(key, index)` pairs sorted by key into a lookup index.
+fn group_sorted<K: Ord + Copy>(
+    pairs: impl IntoIterator<Item = (K, SymbolIndex)>,
+) -> BTreeMap<K, Vec<SymbolIndex>> {
+    let groups = pairs.into_iter().chunk_by(|&(key, _)| key);
+    // Built in bulk, as the keys are already sorted
+    groups.into_iter().map(|(key, group)| (key, group.map(|(_, idx)| idx).collect())).collect()
+}
+
+/// Merges newly added symbols into a lookup index. Their indices are all higher than the
+/// existing ones, so each entry stays sorted.
+fn merge_index<K: Ord>(
+    index: &mut BTreeMap<K, Vec<SymbolIndex>>,
+    new: BTreeMap<K, Vec<SymbolIndex>>,
+) {
+    if index.is_empty() {
+        *index = new;
+        return;
+    }
+    for (key, indices) in new {
+        index.entry(key).or_default().extend(indices);
+    }
+}
+
+fn reloc_rank(symbol: &ObjSymbol, reloc_kind: ObjRelocKind) -> i32 {
+    let mut rank = match symbol.kind {
+        ObjSymbolKind::Function | ObjSymbolKind::Object => {
+            // HACK: These are generally not referenced directly, so reduce their rank
+            if matches!(
+                symbol.name.as_str(),
+                "__save_gpr" | "__restore_gpr" | "__save_fpr" | "__restore_fpr"
+            ) {
+                return 0;
             }
-            // Label
-            ObjSymbolKind::Unknown => match reloc_kind {
+            match reloc_kind {
                 ObjRelocKind::PpcAddr16Hi
                 | ObjRelocKind::PpcAddr16Ha
-                | ObjRelocKind::PpcAddr16Lo
-                    if !symbol.name.starts_with("..") =>
-                {
-                    3
-                }
-                _ => 1,
+                | ObjRelocKind::PpcAddr16Lo => 1,
+                // Branches resolve to the function entry over any label at the same address
+                ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14 if symbol.is_code() => 3,
+                ObjRelocKind::Absolute
+                | ObjRelocKind::PpcRel24
+                | ObjRelocKind::PpcRel14
+                | ObjRelocKind::PpcEmbSda21 => 2,
+            }
+        }
+        // Label
+        ObjSymbolKind::Unknown => match reloc_kind {
+            ObjRelocKind::PpcAddr16Hi | ObjRelocKind::PpcAddr16Ha | ObjRelocKind::PpcAddr16Lo
+                if !symbol.name.starts_with("..") =>
+            {
+                3
+            }
+            _ => 1,
+        },
+        ObjSymbolKind::Section => -1,
+    };
+    if symbol.size > 0 {
+        rank += 1;
+    }
+    rank
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        obj::{test_section, test_symbol, ObjReloc, ObjSectionKind},
+        util::signatures::OutSymbol,
+    };
+
+    #[test]
+    fn test_data_kind_round_trip() {
+        for data_kind in [
+            ObjDataKind::Unknown,
+            ObjDataKind::Byte,
+            ObjDataKind::Byte2,
+            ObjDataKind::Byte4,
+            ObjDataKind::Byte8,
+            ObjDataKind::Float,
+            ObjDataKind::Double,
+            ObjDataKind::String,
+            ObjDataKind::String16,
+            ObjDataKind::StringTable,
+            ObjDataKind::String16Table,
+            ObjDataKind::Int,
+            ObjDataKind::Short,
+            ObjDataKind::JumpTable,
+            ObjDataKind::PointerTable,
+            ObjDataKind::PairedSingle,
+            ObjDataKind::Quadword,
+        ] {
+            let json = serde_json::to_string(&data_kind).unwrap();
+            assert_eq!(serde_json::from_str::<ObjDataKind>(&json).unwrap(), data_kind);
+
+            let symbol = OutSymbol {
+                kind: ObjSymbolKind::Object,
+                name: "lbl_80000000".to_string(),
+                size: 4,
+                flags: Default::default(),
+                section: Some(".data".to_string()),
+                data_kind,
+            };
+            let yaml = serde_yaml::to_string(&symbol).unwrap();
+            assert_eq!(serde_yaml::from_str::<OutSymbol>(&yaml).unwrap(), symbol);
+        }
+    }
+
+    #[test]
+    fn test_best_match_for_reloc_prefers_named() {
+        let auto = ObjSymbol {
+            name: "lbl_80001000".to_string(),
+            address: 0x80001000,
+            section: Some(0),
+            ..Default::default()
+        };
+        let named = ObjSymbol {
+            name: "someLabel".to_string(),
+            address: 0x80001000,
+            section: Some(0),
+            ..Default::default()
+        };
+        for reloc_kind in [ObjRelocKind::Absolute, ObjRelocKind::PpcAddr16Lo] {
+            let symbols = vec![(0, &auto), (1, &named)];
+            let (index, symbol) =
+                best_match_for_reloc_with(symbols, reloc_kind, &DefaultRelocResolver).unwrap();
+            assert_eq!(index, 1);
+            assert_eq!(symbol.name, "someLabel");
+        }
+    }
+
+    #[test]
+    fn test_custom_reloc_resolver() {
+        #[derive(Debug)]
+        struct PreferSourceFile(&'static str);
+
+        impl RelocResolver for PreferSourceFile {
+            fn compare(&self, a: &ObjSymbol, b: &ObjSymbol, reloc_kind: ObjRelocKind) -> Ordering {
+                let is_other = |s: &ObjSymbol| s.source_file.as_deref() != Some(self.0);
+                DefaultRelocResolver
+                    .compare(a, b, reloc_kind)
+                    .then_with(|| is_other(a).cmp(&is_other(b)))
+            }
+        }
+
+        let symbol = |name: &str, source_file: &str| ObjSymbol {
+            source_file: Some(source_file.to_string()),
+            ..test_symbol(name, Some(0), 0x80001000, 4, ObjSymbolKind::Object)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("sValueA", "a.c"),
+            symbol("sValueB", "b.c"),
+        ]);
+        let target = SectionAddress::new(0, 0x80001000);
+        let (index, _) =
+            symbols.for_relocation(target, ObjRelocKind::Absolute, false).unwrap().unwrap();
+        assert_eq!(index, 0);
+
+        symbols.set_reloc_resolver(Arc::new(PreferSourceFile("b.c")));
+        let (index, _) =
+            symbols.for_relocation(target, ObjRelocKind::Absolute, false).unwrap().unwrap();
+        assert_eq!(index, 1);
+    }
+
+    #[test]
+    fn test_reloc_tie_break() {
+        let object =
+            |name: &str, size| test_symbol(name, Some(0), 0x80001000, size, ObjSymbolKind::Object);
+        let objects = vec![object("sMember", 4), object("sStruct", 0x10), object("sByte", 1)];
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, objects);
+        let target = SectionAddress::new(0, 0x80001000);
+        for (tie_break, expected) in [
+            (RelocTieBreak::First, "sMember"),
+            (RelocTieBreak::Largest, "sStruct"),
+            (RelocTieBreak::Smallest, "sByte"),
+        ] {
+            symbols.set_reloc_resolver(Arc::new(tie_break));
+            let (_, symbol) =
+                symbols.for_relocation(target, ObjRelocKind::Absolute, false).unwrap().unwrap();
+            assert_eq!(symbol.name, expected, "{tie_break:?}");
+            let candidates = symbols.at_section_address(0, 0x80001000).collect_vec();
+            let (_, symbol) =
+                symbols.best_match_for_reloc(candidates, ObjRelocKind::Absolute).unwrap();
+            assert_eq!(symbol.name, expected, "{tie_break:?}");
+        }
+    }
+
+    #[test]
+    fn test_best_match_for_reloc_branch_to_function() {
+        let function = ObjSymbol {
+            name: "fn_80001000".to_string(),
+            address: 0x80001000,
+            section: Some(0),
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        };
+        let label = test_symbol("someLabel", Some(0), 0x80001000, 0x10, ObjSymbolKind::Unknown);
+        for reloc_kind in [ObjRelocKind::PpcRel24, ObjRelocKind::PpcRel14] {
+            let symbols = vec![(0, &label), (1, &function)];
+            let (index, _) =
+                best_match_for_reloc_with(symbols, reloc_kind, &DefaultRelocResolver).unwrap();
+            assert_eq!(index, 1);
+        }
+    }
+
+    #[test]
+    fn test_rename_all_strip_prefix() {
+        let mut symbols = (0..100)
+            .map(|i| ObjSymbol {
+                name: format!("game_func{}", i),
+                address: 0x80003100 + i * 4,
+                section: Some(0),
+                size: 4,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                kind: ObjSymbolKind::Function,
+                ..Default::default()
+            })
+            .collect_vec();
+        symbols.push(ObjSymbol {
+            name: "lbl_80004000".to_string(),
+            address: 0x80004000,
+            section: Some(0),
+            ..Default::default()
+        });
+        symbols.push(ObjSymbol {
+            name: "_ctors".to_string(),
+            address: 0x80004100,
+            section: Some(0),
+            ..Default::default()
+        });
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, symbols);
+
+        let transform =
+            SymbolRenameTransform { strip_prefix: Some("game_".to_string()), ..Default::default() };
+        assert_eq!(symbols.rename_all(&transform).unwrap(), 100);
+        for i in 0..100 {
+            let (index, symbol) = symbols.by_name(&format!("func{}", i)).unwrap().unwrap();
+            assert_eq!(index, i as SymbolIndex);
+            assert_eq!(symbol.address, 0x80003100 + i * 4);
+            assert_eq!(symbols.for_name(&format!("game_func{}", i)).count(), 0);
+        }
+        // Auto symbols and linker generated labels are skipped
+        assert!(symbols.by_name("lbl_80004000").unwrap().is_some());
+        assert!(symbols.by_name("_ctors").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_iter_by_scope() {
+        let symbol = |name: &str, flags: Option<ObjSymbolFlags>| ObjSymbol {
+            flags: ObjSymbolFlagSet(flags.map(Into::into).unwrap_or_default()),
+            ..test_symbol(name, Some(0), 0x80003100, 0, ObjSymbolKind::Unknown)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("global", Some(ObjSymbolFlags::Global)),
+            symbol("local", Some(ObjSymbolFlags::Local)),
+            symbol("weak", Some(ObjSymbolFlags::Weak)),
+            symbol("unknown", None),
+            symbol("removed", Some(ObjSymbolFlags::Local)),
+        ]);
+        symbols.remove(4).unwrap();
+        fn names<'a>(iter: impl Iterator<Item = (SymbolIndex, &'a ObjSymbol)>) -> Vec<&'a str> {
+            iter.map(|(_, s)| s.name.as_str()).collect()
+        }
+        assert_eq!(names(symbols.iter_globals()), ["global", "weak"]);
+        assert_eq!(names(symbols.iter_globals().rev()), ["weak", "global"]);
+        assert_eq!(names(symbols.iter_locals()), ["local"]);
+        assert_eq!(names(symbols.iter_by_scope(ObjSymbolScope::Weak)), ["weak"]);
+        assert_eq!(names(symbols.iter_by_scope(ObjSymbolScope::Unknown)), ["unknown"]);
+    }
+
+    #[test]
+    fn test_extend() {
+        let symbol = |name: &str, section, address| {
+            test_symbol(name, section, address, 0, ObjSymbolKind::Unknown)
+        };
+        let mut symbols =
+            ObjSymbols::new(ObjKind::Relocatable, vec![symbol("first", Some(0), 0x100)]);
+        let indices = symbols
+            .extend([symbol("second", Some(0), 0x100), symbol("third", Some(2), 0x200)])
+            .unwrap();
+        assert_eq!(indices, [1, 2]);
+        assert_eq!(symbols.at_section_address(0, 0x100).map(|(idx, _)| idx).collect_vec(), [0, 1]);
+        assert_eq!(symbols.by_name("third").unwrap().map(|(idx, _)| idx), Some(2));
+        symbols.validate().unwrap();
+
+        // Invalid input leaves the table unchanged
+        let result = symbols.extend([symbol("fourth", Some(0), 0x300), symbol("abs", None, 4)]);
+        assert!(result.is_err());
+        assert_eq!(symbols.slot_count(), 3);
+        assert!(symbols.by_name("fourth").unwrap().is_none());
+    }
+
+    /// Run with `cargo test --release -- --ignored bench_extend --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_extend() {
+        const COUNT: u32 = 50000;
+        let in_symbols = (0..COUNT)
+            .map(|i| {
+                let address = 0x80003100 + i * 8;
+                let name = format!("fn_{address:08X}");
+                test_symbol(
+                    &name,
+                    Some(i as SectionIndex % 4),
+                    address as u64,
+                    8,
+                    ObjSymbolKind::Function,
+                )
+            })
+            .collect_vec();
+
+        let start = std::time::Instant::now();
+        let mut looped = ObjSymbols::new(ObjKind::Executable, vec![]);
+        for symbol in in_symbols.clone() {
+            looped.add_direct(symbol).unwrap();
+        }
+        let looped_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let mut extended = ObjSymbols::new(ObjKind::Executable, vec![]);
+        extended.extend(in_symbols).unwrap();
+        let extend_time = start.elapsed();
+
+        extended.validate().unwrap();
+        assert_eq!(extended.count(), looped.count());
+        println!("{COUNT} symbols: add_direct {looped_time:?}, extend {extend_time:?}");
+    }
+
+    #[test]
+    fn test_rename() {
+        let symbol = |name: &str, address, flags| ObjSymbol {
+            flags: ObjSymbolFlagSet(flags),
+            ..test_symbol(name, Some(0), address, 0, ObjSymbolKind::Unknown)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("fn_80003100", 0x80003100, ObjSymbolFlags::Global.into()),
+            symbol("foo", 0x80003200, ObjSymbolFlags::Global.into()),
+            symbol("@1", 0x80003300, ObjSymbolFlags::Local.into()),
+            symbol("removed", 0x80003400, ObjSymbolFlags::Local.into()),
+        ]);
+        symbols.remove(3).unwrap();
+
+        // The name index and demangled name follow the new name
+        symbols.rename(0, "Init__3FooFv".to_string()).unwrap();
+        assert!(symbols.by_name("fn_80003100").unwrap().is_none());
+        assert_eq!(symbols.by_name("Init__3FooFv").unwrap().map(|(idx, _)| idx), Some(0));
+        assert_eq!(symbols[0].demangled_name.as_deref(), Some("Foo::Init()"));
+
+        // Renaming to the current name is a no-op
+        symbols.rename(1, "foo".to_string()).unwrap();
+
+        // Globals can't collide with other globals
+        let err = symbols.rename(1, "Init__3FooFv".to_string()).unwrap_err();
+        let err = err.downcast_ref::<DuplicateSymbolError>().unwrap();
+        assert_eq!((err.index, err.existing), (Some(1), 0));
+        assert_eq!(symbols[1].name, "foo");
+
+        // Locals may collide freely
+        symbols.rename(2, "foo".to_string()).unwrap();
+        assert_eq!(symbols.for_name("foo").map(|(idx, _)| idx).collect_vec(), [1, 2]);
+
+        // Removed symbols can't be renamed
+        assert!(symbols.rename(3, "bar".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_rename_weak() {
+        let symbol = |name: &str, address, flags: ObjSymbolFlags| ObjSymbol {
+            flags: ObjSymbolFlagSet(flags.into()),
+            ..test_symbol(name, Some(0), address, 0, ObjSymbolKind::Unknown)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("foo", 0x80003100, ObjSymbolFlags::Global),
+            symbol("bar", 0x80003200, ObjSymbolFlags::Weak),
+            symbol("baz", 0x80003300, ObjSymbolFlags::Weak),
+        ]);
+
+        // Weak symbols can't collide with globals, in either direction
+        let err = symbols.rename(1, "foo".to_string()).unwrap_err();
+        let err = err.downcast_ref::<DuplicateSymbolError>().unwrap();
+        assert_eq!((err.index, err.existing), (Some(1), 0));
+        let err = symbols.rename(0, "bar".to_string()).unwrap_err();
+        let err = err.downcast_ref::<DuplicateSymbolError>().unwrap();
+        assert_eq!((err.index, err.existing), (Some(0), 1));
+
+        // Or with other weak symbols
+        assert!(symbols.rename(2, "bar".to_string()).is_err());
+
+        // rename_all applies the same check
+        let transform = SymbolRenameTransform {
+            replace: Some((Regex::new("^bar$").unwrap(), "foo".to_string())),
+            ..Default::default()
+        };
+        let err = symbols.rename_all(&transform).unwrap_err();
+        let err = err.downcast_ref::<DuplicateSymbolError>().unwrap();
+        assert_eq!((err.index, err.existing), (Some(1), 0));
+        assert!(symbols.by_name("bar").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_rename_all_collision() {
+        let global = |name: &str, address| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, Some(0), address, 0, ObjSymbolKind::Unknown)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            global("game_foo", 0x80003100),
+            global("foo", 0x80003200),
+        ]);
+        let transform =
+            SymbolRenameTransform { strip_prefix: Some("game_".to_string()), ..Default::default() };
+        let err = symbols.rename_all(&transform).unwrap_err();
+        let err = err.downcast_ref::<DuplicateSymbolError>().unwrap();
+        assert_eq!((err.index, err.existing), (Some(0), 1));
+        // No symbols were renamed
+        assert!(symbols.by_name("game_foo").unwrap().is_some());
+
+        // Renaming the existing symbol out of the way is allowed
+        let transform = SymbolRenameTransform {
+            replace: Some((Regex::new("^(game_)?foo$").unwrap(), "${1}bar".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(symbols.rename_all(&transform).unwrap(), 2);
+        assert!(symbols.by_name("game_bar").unwrap().is_some());
+        assert!(symbols.by_name("bar").unwrap().is_some());
+
+        // Existing duplicates don't block unrelated renames
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            global("dup", 0x80003100),
+            global("dup", 0x80003200),
+            global("game_baz", 0x80003300),
+        ]);
+        assert_eq!(symbols.rename_all(&transform).unwrap(), 0);
+        let transform =
+            SymbolRenameTransform { strip_prefix: Some("game_".to_string()), ..Default::default() };
+        assert_eq!(symbols.rename_all(&transform).unwrap(), 1);
+        assert!(symbols.by_name("baz").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_relocation_diagnostics() {
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![ObjSymbol {
+            name: "lbl_80400000".to_string(),
+            address: 0x80400000,
+            section: Some(0),
+            size: 0x10,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::RelocationIgnore.into()),
+            ..Default::default()
+        }]);
+        let mut diagnostics = RelocationDiagnostics::default();
+        for target in [SectionAddress::new(0, 0x80400004), SectionAddress::new(0, 0x80400010)] {
+            let result = symbols.for_relocation(target, ObjRelocKind::Absolute, false).unwrap();
+            assert!(result.is_none());
+            diagnostics.record(&symbols, target, ObjRelocKind::Absolute);
+        }
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics.misses[0].reason, RelocationMissReason::Filtered);
+        assert_eq!(diagnostics.misses[1].reason, RelocationMissReason::NotFound);
+    }
+
+    #[test]
+    fn test_for_relocation_sda_array() {
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            test_symbol("sArray", Some(0), 0x80400000, 0x20, ObjSymbolKind::Object),
+            ObjSymbol {
+                name: "lbl_80400008".to_string(),
+                address: 0x80400008,
+                section: Some(0),
+                ..Default::default()
+            },
+        ]);
+        let target = SectionAddress::new(0, 0x80400008);
+
+        // Interior label is preferred outside of small data
+        let (index, _) =
+            symbols.for_relocation(target, ObjRelocKind::PpcAddr16Lo, false).unwrap().unwrap();
+        assert_eq!(index, 1);
+
+        // In small data, @ha/@l relocations resolve to the containing array
+        for reloc_kind in [ObjRelocKind::PpcAddr16Ha, ObjRelocKind::PpcAddr16Lo] {
+            let (index, _) = symbols.for_relocation(target, reloc_kind, true).unwrap().unwrap();
+            assert_eq!(index, 0);
+        }
+
+        // SDA21 relocations may still reference the label
+        let (index, _) =
+            symbols.for_relocation(target, ObjRelocKind::PpcEmbSda21, true).unwrap().unwrap();
+        assert_eq!(index, 1);
+
+        // Without a containing object, fall back to the label
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![ObjSymbol {
+            name: "lbl_80400008".to_string(),
+            address: 0x80400008,
+            section: Some(0),
+            ..Default::default()
+        }]);
+        let (index, _) =
+            symbols.for_relocation(target, ObjRelocKind::PpcAddr16Lo, true).unwrap().unwrap();
+        assert_eq!(index, 0);
+    }
+
+    #[test]
+    fn test_for_relocation_abs_fallback() {
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            ObjSymbol {
+                name: "_SDA_BASE_".to_string(),
+                address: 0x80400000,
+                section: None,
+                ..Default::default()
             },
-            ObjSymbolKind::Section => -1,
+            ObjSymbol {
+                name: "lbl_80400000".to_string(),
+                address: 0x80400000,
+                section: Some(1),
+                ..Default::default()
+            },
+        ]);
+
+        // Sectioned symbols are preferred over ABS symbols
+        let (index, _) = symbols
+            .for_relocation(SectionAddress::new(1, 0x80400000), ObjRelocKind::Absolute, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(index, 1);
+
+        // ABS symbols are used when the section has no match
+        let (index, _) = symbols
+            .for_relocation(SectionAddress::new(0, 0x80400000), ObjRelocKind::Absolute, false)
+            .unwrap()
+            .unwrap();
+        assert_eq!(index, 0);
+    }
+
+    /// Run with `cargo test --release -- --ignored bench_for_relocation --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_for_relocation() {
+        const COUNT: u32 = 20000;
+        let mut in_symbols = vec![];
+        // Many ABS symbols preceding .text
+        for i in 0..COUNT {
+            in_symbols.push(ObjSymbol {
+                name: format!("abs_{i}"),
+                address: (0x80000000 + i * 4) as u64,
+                section: None,
+                ..Default::default()
+            });
+        }
+        // A large .text with thousands of labels
+        for i in 0..COUNT {
+            in_symbols.push(ObjSymbol {
+                name: format!("lbl_{:08X}", 0x80100000 + i * 8),
+                address: (0x80100000 + i * 8) as u64,
+                section: Some(1),
+                ..Default::default()
+            });
+        }
+        let symbols = ObjSymbols::new(ObjKind::Executable, in_symbols);
+        let start = std::time::Instant::now();
+        for i in 0..COUNT {
+            let target = SectionAddress::new(1, 0x80100000 + i * 8);
+            let (_, symbol) =
+                symbols.for_relocation(target, ObjRelocKind::PpcRel24, false).unwrap().unwrap();
+            assert_eq!(symbol.address, target.address as u64);
+        }
+        println!("{} lookups in {:?}", COUNT, start.elapsed());
+    }
+
+    #[test]
+    fn test_referenced_by_allow_any_reloc() {
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![test_symbol(
+            "sdaThunk",
+            Some(0),
+            0x80400000,
+            0x10,
+            ObjSymbolKind::Function,
+        )]);
+        let target = SectionAddress::new(0, 0x80400000);
+        let result = symbols.for_relocation(target, ObjRelocKind::PpcEmbSda21, false).unwrap();
+        assert!(result.is_none());
+
+        symbols.flags(0).0 |= ObjSymbolFlags::AllowAnyReloc;
+        let (index, _) =
+            symbols.for_relocation(target, ObjRelocKind::PpcEmbSda21, false).unwrap().unwrap();
+        assert_eq!(index, 0);
+
+        // RelocationIgnore takes precedence
+        symbols.flags(0).0 |= ObjSymbolFlags::RelocationIgnore;
+        let result = symbols.for_relocation(target, ObjRelocKind::PpcEmbSda21, false).unwrap();
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn test_add_promote_label() {
+        let symbol = |name: &str, kind| test_symbol(name, Some(0), 0x80001000, 0, kind);
+        let label = |name: &str| {
+            ObjSymbols::new(ObjKind::Executable, vec![symbol(name, ObjSymbolKind::Unknown)])
         };
-        if symbol.size > 0 {
-            rank += 1;
+        let object = symbol("gData", ObjSymbolKind::Object);
+        let kinds = |symbols: &ObjSymbols| {
+            symbols.iter().map(|(_, s)| (s.name.clone(), s.kind)).collect_vec()
+        };
+
+        // Auto labels are replaced by the incoming symbol
+        let mut symbols = label("lbl_80001000");
+        symbols.add(object.clone(), false).unwrap();
+        assert_eq!(kinds(&symbols), [("gData".to_string(), ObjSymbolKind::Object)]);
+
+        // Named labels are promoted, keeping their name unless replacing
+        let mut symbols = label("myLabel");
+        symbols.add(object.clone(), false).unwrap();
+        assert_eq!(kinds(&symbols), [("myLabel".to_string(), ObjSymbolKind::Object)]);
+        let mut symbols = label("myLabel");
+        symbols.add(object, true).unwrap();
+        assert_eq!(kinds(&symbols), [("gData".to_string(), ObjSymbolKind::Object)]);
+
+        // Section symbols are kept separate
+        let mut symbols = label("myLabel");
+        symbols.add(symbol(".data", ObjSymbolKind::Section), false).unwrap();
+        assert_eq!(kinds(&symbols), [
+            ("myLabel".to_string(), ObjSymbolKind::Unknown),
+            (".data".to_string(), ObjSymbolKind::Section),
+        ]);
+    }
+
+    #[test]
+    fn test_add_align() {
+        let symbol = |align| ObjSymbol {
+            align,
+            ..test_symbol("gData", Some(0), 0x80001000, 0x10, ObjSymbolKind::Object)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![symbol(Some(4))]);
+        // The stricter alignment wins, with or without replacing
+        symbols.add(symbol(Some(8)), false).unwrap();
+        assert_eq!(symbols[0].align, Some(8));
+        symbols.add(symbol(Some(4)), true).unwrap();
+        assert_eq!(symbols[0].align, Some(8));
+        // Unknown alignment keeps the existing one
+        symbols.add(symbol(None), true).unwrap();
+        assert_eq!(symbols[0].align, Some(8));
+        assert_eq!(symbols.count(), 1);
+    }
+
+    #[test]
+    fn test_at_address() {
+        let symbol =
+            |name: &str, address, size, kind| test_symbol(name, Some(0), address, size, kind);
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("table", 0x80001000, 0x100, ObjSymbolKind::Object),
+            symbol("entry", 0x80001010, 0x8, ObjSymbolKind::Object),
+            symbol("lbl_80001040", 0x80001040, 0, ObjSymbolKind::Unknown),
+            symbol("inner", 0x80001080, 0x10, ObjSymbolKind::Object),
+        ]);
+        let at = |symbols: &ObjSymbols, address| {
+            symbols.at_address(SectionAddress::new(0, address)).map(|(_, s)| s.name.clone())
+        };
+        // Found past a smaller symbol that ends before the target
+        assert_eq!(at(&symbols, 0x80001020).as_deref(), Some("table"));
+        // Sized symbols over labels, and the smallest range wins
+        assert_eq!(at(&symbols, 0x80001040).as_deref(), Some("table"));
+        assert_eq!(at(&symbols, 0x80001084).as_deref(), Some("inner"));
+        assert_eq!(at(&symbols, 0x80001100), None);
+
+        // RelocationIgnore symbols are skipped
+        symbols.set_relocation_ignore(3, true);
+        assert_eq!(at(&symbols, 0x80001084).as_deref(), Some("table"));
+
+        // The backward scan is bounded by the largest symbol size
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("a", 0x80001000, 0x4, ObjSymbolKind::Object),
+            symbol("lbl_80001100", 0x80001100, 0, ObjSymbolKind::Unknown),
+        ]);
+        assert_eq!(at(&symbols, 0x80001002).as_deref(), Some("a"));
+        assert_eq!(at(&symbols, 0x80001100).as_deref(), Some("lbl_80001100"));
+        assert_eq!(at(&symbols, 0x80001104), None);
+    }
+
+    #[test]
+    fn test_remove() {
+        let symbol =
+            |name: &str, address| test_symbol(name, Some(0), address, 0, ObjSymbolKind::Unknown);
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("a", 0x80000000),
+            symbol("b", 0x80000004),
+            symbol("c", 0x80000008),
+        ]);
+        symbols.remove(1).unwrap();
+        assert!(symbols.remove(1).is_err());
+        assert!(symbols.remove(3).is_err());
+
+        // Other indices stay valid
+        assert!(symbols.get(1).is_none());
+        assert_eq!(symbols[2].name, "c");
+        assert_eq!(symbols.iter().map(|(i, _)| i).collect_vec(), [0, 2]);
+        assert_eq!((symbols.count(), symbols.slot_count()), (2, 3));
+
+        // Removed from every index, without leaving empty entries behind
+        assert!(symbols.by_name("b").unwrap().is_none());
+        assert_eq!(symbols.at_section_address(0, 0x80000004).count(), 0);
+        let addresses = symbols.indexes_for_range(..).map(|(address, _)| address).collect_vec();
+        assert_eq!(addresses, [0x80000000, 0x80000008]);
+        assert_eq!(symbols.section_symbol_count(0), 2);
+
+        // New symbols don't reuse the slot
+        let index = symbols.add_direct(symbol("d", 0x80000004)).unwrap();
+        assert_eq!(index, 3);
+    }
+
+    #[test]
+    fn test_section_symbol_counts() {
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            ObjSymbol {
+                name: "a".to_string(),
+                address: 0x100,
+                section: Some(0),
+                ..Default::default()
+            },
+            ObjSymbol {
+                name: "b".to_string(),
+                address: 0x100,
+                section: Some(0),
+                ..Default::default()
+            },
+            ObjSymbol {
+                name: "c".to_string(),
+                address: 0x200,
+                section: Some(2),
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(symbols.section_count(), 3);
+        assert!(symbols.has_section_symbols(0));
+        assert!(!symbols.has_section_symbols(1));
+        assert!(!symbols.has_section_symbols(3));
+        assert_eq!(symbols.section_symbol_count(0), 2);
+        assert_eq!(symbols.section_symbol_count(1), 0);
+        assert_eq!(symbols.section_symbol_count(3), 0);
+
+        symbols.remove(2).unwrap();
+        assert!(!symbols.has_section_symbols(2));
+        assert_eq!(symbols.section_symbol_count(2), 0);
+
+        // Sections without symbols can be tracked explicitly
+        symbols.reserve_sections(5);
+        assert_eq!(symbols.section_count(), 5);
+        symbols.reserve_sections(1);
+        assert_eq!(symbols.section_count(), 5);
+    }
+
+    #[test]
+    fn test_coalesce_labels() {
+        let label = |name: &str| test_symbol(name, Some(0), 0x80012340, 0, ObjSymbolKind::Unknown);
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            ObjSymbol { align: Some(4), ..label("lbl_80012340") },
+            ObjSymbol { data_kind: ObjDataKind::Byte4, ..label("jumptable_80012340") },
+            ObjSymbol { align: Some(8), ..label("lbl_80012340_1") },
+            // Named symbols are left alone
+            label("someLabel"),
+        ]);
+        assert_eq!(symbols.coalesce_labels().unwrap(), 2);
+        let remaining = symbols.at_section_address(0, 0x80012340).collect_vec();
+        assert_eq!(remaining.len(), 2);
+        let (index, symbol) = symbols.by_name("jumptable_80012340").unwrap().unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(symbol.align, Some(8));
+        assert_eq!(symbol.data_kind, ObjDataKind::Byte4);
+        assert!(symbols.by_name("lbl_80012340").unwrap().is_none());
+        assert!(symbols.by_name("someLabel").unwrap().is_some());
+
+        // Nothing left to coalesce
+        assert_eq!(symbols.coalesce_labels().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_hidden_exports() {
+        let hidden = |name: &str, flags: FlagSet<ObjSymbolFlags>| ObjSymbol {
+            flags: ObjSymbolFlagSet(flags | ObjSymbolFlags::Hidden),
+            ..test_symbol(name, Some(0), 0x100, 0, ObjSymbolKind::Unknown)
+        };
+        let symbols = ObjSymbols::new(ObjKind::Relocatable, vec![
+            hidden("exported", ObjSymbolFlags::Global | ObjSymbolFlags::Exported),
+            hidden("force_active", ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive),
+            hidden("local_force_active", ObjSymbolFlags::Local | ObjSymbolFlags::ForceActive),
+            hidden("plain", ObjSymbolFlags::Global.into()),
+            hidden(
+                "gap_00_80004008_data",
+                ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive | ObjSymbolFlags::NoWrite,
+            ),
+            hidden("gap_01_80005000_bss", ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive),
+            hidden(
+                "generated",
+                ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive | ObjSymbolFlags::NoWrite,
+            ),
+            ObjSymbol {
+                name: "visible".to_string(),
+                address: 0x100,
+                section: Some(0),
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::Exported),
+                ..Default::default()
+            },
+        ]);
+        let violations = symbols.hidden_exports();
+        assert_eq!(violations.len(), 2);
+        assert_eq!(violations[0].name, "exported");
+        assert!(violations[0].exported && !violations[0].force_active);
+        assert_eq!(violations[1].name, "force_active");
+        assert!(!violations[1].exported && violations[1].force_active);
+    }
+
+    #[test]
+    fn test_for_section_range_overlapping() {
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            test_symbol("before", Some(0), 0x80, 0x10, ObjSymbolKind::Function),
+            test_symbol("func", Some(0), 0x100, 0x40, ObjSymbolKind::Function),
+            test_symbol("inner", Some(0), 0x180, 0x20, ObjSymbolKind::Function),
+            test_symbol("after", Some(0), 0x200, 0x20, ObjSymbolKind::Function),
+        ]);
+        let names = symbols
+            .for_section_range_overlapping(0, 0x120..0x200)
+            .map(|(_, s)| s.name.as_str())
+            .collect_vec();
+        assert_eq!(names, vec!["func", "inner"]);
+    }
+
+    #[test]
+    fn test_format_address() {
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            ObjSymbol {
+                name: "__ct__3FooFv".to_string(),
+                demangled_name: Some("Foo::Foo()".to_string()),
+                address: 0x80003100,
+                section: Some(0),
+                size: 0x20,
+                size_known: true,
+                kind: ObjSymbolKind::Function,
+                ..Default::default()
+            },
+            test_symbol("sValue", Some(0), 0x80003120, 0x8, ObjSymbolKind::Object),
+        ]);
+        let format = |address| symbols.format_address(SectionAddress::new(0, address));
+        assert_eq!(format(0x80003100), "Foo::Foo()");
+        assert_eq!(format(0x80003120), "sValue");
+        assert_eq!(format(0x80003124), "sValue+0x4");
+        assert_eq!(format(0x80003128), "0x80003128");
+        assert_eq!(symbols.format_address(SectionAddress::new(1, 0x80003100)), "0x80003100");
+    }
+
+    #[test]
+    fn test_find_overlaps() {
+        let symbol =
+            |name: &str, address, size, kind| test_symbol(name, Some(0), address, size, kind);
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol(".text", 0x80003000, 0x100, ObjSymbolKind::Section),
+            symbol("funcA", 0x80003000, 0x20, ObjSymbolKind::Function),
+            symbol("lbl_80003010", 0x80003010, 0, ObjSymbolKind::Unknown),
+            symbol("funcB", 0x80003020, 0x20, ObjSymbolKind::Function),
+        ]);
+        assert!(symbols.find_overlaps().is_empty());
+
+        // Data symbol colliding with the end of funcA and the start of funcB
+        let data = symbols
+            .add_direct(symbol("lbl_80003018", 0x80003018, 0x10, ObjSymbolKind::Object))
+            .unwrap();
+        assert_eq!(symbols.find_overlaps(), [(1, data), (data, 3)]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let symbol = |name: &str, address, section| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, section, address, 0, ObjSymbolKind::Unknown)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Relocatable, vec![
+            symbol("foo", 0x0, Some(0)),
+            symbol("bar", 0x10, Some(1)),
+            ObjSymbol {
+                size: 4,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Common.into()),
+                ..symbol("common", 4, None)
+            },
+        ]);
+        symbols.validate().unwrap();
+
+        // Index pointing to a nonexistent symbol
+        symbols.symbols_by_section.resize_with(3, BTreeMap::new);
+        symbols.symbols_by_section[2].nested_push(0x20, 5);
+        // Symbol moved without updating indices
+        symbols.symbols[1].as_mut().unwrap().address = 0x14;
+        // ABS symbol and duplicate global, bypassing the checks in add
+        for (index, symbol) in [(3, symbol("abs", 0x30, None)), (4, symbol("foo", 0x40, Some(0)))] {
+            symbols.symbols_by_address.nested_push(symbol.address as u32, index);
+            if let Some(section_idx) = symbol.section {
+                symbols.symbols_by_section[section_idx as usize]
+                    .nested_push(symbol.address as u32, index);
+            }
+            symbols.symbols_by_name.nested_push(symbol.name.clone(), index);
+            symbols.symbols.push(Some(symbol));
         }
-        -rank
-    });
-    symbols.into_iter().next()
+
+        let message = symbols.validate().unwrap_err().to_string();
+        assert!(message.contains("Section index 2:0x00000020: symbol 5 is missing"), "{message}");
+        assert!(message.contains("Address index 0x00000010: symbol 1 (bar)"), "{message}");
+        assert!(message.contains("Symbol 1 (bar) missing from address index"), "{message}");
+        assert!(message.contains("Symbol 3 (abs) is ABS"), "{message}");
+        assert!(message.contains("Duplicate global symbol foo"), "{message}");
+        assert!(!message.contains("common"), "{message}");
+    }
+
+    fn float_array_obj() -> (ObjSymbols, ObjSections) {
+        let symbols = ObjSymbols::new(
+            ObjKind::Executable,
+            (0..4)
+                .map(|i| ObjSymbol {
+                    name: format!("lbl_{:08X}", 0x80400000 + i * 4),
+                    address: 0x80400000 + i * 4,
+                    section: Some(1),
+                    size: 4,
+                    size_known: true,
+                    kind: ObjSymbolKind::Object,
+                    data_kind: ObjDataKind::Float,
+                    ..Default::default()
+                })
+                .collect(),
+        );
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x10);
+        let sections = ObjSections::new(ObjKind::Executable, vec![
+            section(".text", ObjSectionKind::Code, 0x80003100),
+            section(".data", ObjSectionKind::Data, 0x80400000),
+        ]);
+        (symbols, sections)
+    }
+
+    #[test]
+    fn test_merge_adjacent_data() {
+        let (mut symbols, mut sections) = float_array_obj();
+        let reloc =
+            ObjReloc { kind: ObjRelocKind::PpcAddr16Lo, target_symbol: 0, addend: 0, module: None };
+        sections[0].relocations.insert(0x80003102, reloc).unwrap();
+
+        // Not contiguous past the end
+        assert!(symbols.merge_adjacent_data(&sections, 0, 5).is_err());
+        symbols.merge_adjacent_data(&sections, 0, 4).unwrap();
+        let symbol = &symbols[0];
+        assert_eq!(symbol.size, 16);
+        assert_eq!(symbol.data_kind, ObjDataKind::Float);
+        assert_eq!(symbol.array_len(), Some(4));
+        for idx in 1..4 {
+            assert!(symbols.get(idx).is_none());
+        }
+        symbols.validate().unwrap();
+    }
+
+    #[test]
+    fn test_merge_adjacent_data_referenced() {
+        let (mut symbols, mut sections) = float_array_obj();
+        let reloc =
+            ObjReloc { kind: ObjRelocKind::PpcAddr16Lo, target_symbol: 2, addend: 0, module: None };
+        sections[0].relocations.insert(0x80003102, reloc).unwrap();
+
+        let err = symbols.merge_adjacent_data(&sections, 0, 4).unwrap_err();
+        assert!(err.to_string().contains("lbl_80400008 is referenced"), "{err}");
+        // Nothing was modified
+        assert_eq!(symbols[0].size, 4);
+        assert_eq!(symbols[0].array_len(), None);
+        assert!(symbols.get(2).is_some());
+        // Elements before the referenced one can still be merged
+        symbols.merge_adjacent_data(&sections, 0, 2).unwrap();
+        assert_eq!(symbols[0].array_len(), Some(2));
+    }
+
+    #[test]
+    fn test_split_function() {
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![test_symbol(
+            "twoFunctions",
+            Some(0),
+            0x80003100,
+            0x40,
+            ObjSymbolKind::Function,
+        )]);
+
+        // Unaligned, at the start, at the end and outside of the function
+        for at in [0x80003122, 0x80003100, 0x80003140, 0x80003200] {
+            assert!(symbols.split_function(0, at, 0).is_err(), "{at:#X}");
+        }
+        assert_eq!(symbols[0].size, 0x40);
+
+        let new_idx = symbols.split_function(0, 0x80003120, 0).unwrap();
+        assert_eq!(symbols[0].size, 0x20);
+        let new_symbol = &symbols[new_idx];
+        assert_eq!(new_symbol.name, "fn_80003120");
+        assert_eq!(new_symbol.address, 0x80003120);
+        assert_eq!(new_symbol.size, 0x20);
+        assert_eq!(new_symbol.kind, ObjSymbolKind::Function);
+        assert_eq!(new_symbol.section, Some(0));
+        symbols.validate().unwrap();
+
+        // Names in modules include the module ID
+        let new_idx = symbols.split_function(new_idx, 0x80003130, 2).unwrap();
+        assert_eq!(symbols[new_idx].name, "fn_2_80003130");
+    }
+
+    #[test]
+    fn test_symbol_serialize_scope() {
+        let symbol = ObjSymbol {
+            name: "weakSymbol".to_string(),
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Weak.into()),
+            ..Default::default()
+        };
+        let value = serde_json::to_value(&symbol).unwrap();
+        assert_eq!(value["scope"], "weak");
+        assert_eq!(value["name"], "weakSymbol");
+    }
+
+    #[test]
+    fn test_common_validation() {
+        let mut symbols = ObjSymbols::new(ObjKind::Relocatable, vec![]);
+        let common = ObjSymbol {
+            name: "commonSymbol".to_string(),
+            address: 4,
+            size: 8,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::Common),
+            kind: ObjSymbolKind::Object,
+            align: Some(4),
+            ..Default::default()
+        };
+
+        let err = symbols.add_direct(ObjSymbol { section: Some(0), ..common.clone() }).unwrap_err();
+        assert!(err.to_string().contains("commonSymbol"));
+        let err = symbols.add(ObjSymbol { size: 0, ..common.clone() }, false).unwrap_err();
+        assert!(err.to_string().contains("commonSymbol"));
+        let err = symbols.add_direct(ObjSymbol { align: Some(3), ..common.clone() }).unwrap_err();
+        assert!(err.to_string().contains("commonSymbol"));
+        assert_eq!(symbols.count(), 0);
+
+        symbols.add_direct(common).unwrap();
+        assert_eq!(symbols.count(), 1);
+    }
+
+    #[test]
+    fn test_data_kind_size() {
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![]);
+        for (address, data_kind, size) in [
+            (0x80000000, ObjDataKind::Float, 4),
+            (0x80000010, ObjDataKind::Double, 8),
+            (0x80000018, ObjDataKind::PairedSingle, 8),
+            (0x80000020, ObjDataKind::String, 0),
+            (0x80000030, ObjDataKind::StringTable, 0),
+            (0x80000040, ObjDataKind::Quadword, 16),
+        ] {
+            let index = symbols
+                .add(
+                    ObjSymbol {
+                        name: format!("lbl_{:08X}", address),
+                        address,
+                        section: Some(0),
+                        data_kind,
+                        ..Default::default()
+                    },
+                    false,
+                )
+                .unwrap();
+            assert_eq!(symbols[index].size, size);
+            assert_eq!(symbols[index].size_known, size != 0);
+        }
+    }
+
+    #[test]
+    fn test_weak_strong_merge() {
+        let weak = ObjSymbol {
+            name: "foo".to_string(),
+            address: 0x80001000,
+            section: Some(0),
+            size: 0x10,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Weak.into()),
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        };
+        let strong =
+            ObjSymbol { flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()), ..weak.clone() };
+
+        for order in [[&weak, &strong], [&strong, &weak]] {
+            for replace in [false, true] {
+                let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![]);
+                for symbol in order {
+                    symbols.add(symbol.clone(), replace).unwrap();
+                }
+                let symbols = symbols.iter().collect_vec();
+                assert_eq!(symbols.len(), 1);
+                assert_eq!(symbols[0].1.flags.scope(), ObjSymbolScope::Global);
+                assert_eq!(symbols[0].1.name, "foo");
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_strong_definition() {
+        let strong = ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol("foo", Some(0), 0x80001000, 0x10, ObjSymbolKind::Function)
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![]);
+        symbols.add(strong.clone(), false).unwrap();
+
+        // Re-adding the same definition merges
+        assert_eq!(symbols.add(strong.clone(), true).unwrap(), 0);
+
+        // A second strong definition elsewhere is an error
+        let err = symbols.add(ObjSymbol { address: 0x80002000, ..strong }, false).unwrap_err();
+        let err = err.downcast_ref::<DuplicateSymbolError>().unwrap();
+        assert_eq!((err.index, err.existing), (None, 0));
+        assert_eq!(symbols.for_name("foo").count(), 1);
+    }
+
+    #[test]
+    fn test_locked_symbol() {
+        let locked = ObjSymbol {
+            name: "GameMain".to_string(),
+            address: 0x80003000,
+            section: Some(0),
+            size: 0x40,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::Locked),
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        };
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![locked.clone()]);
+
+        for replace in [false, true] {
+            // Analysis thinks it's a smaller object
+            let idx = symbols
+                .add(
+                    ObjSymbol {
+                        name: "lbl_80003000".to_string(),
+                        size: 0x20,
+                        align: Some(8),
+                        kind: ObjSymbolKind::Object,
+                        ..locked.clone()
+                    },
+                    replace,
+                )
+                .unwrap();
+            assert_eq!(idx, 0);
+            assert_eq!(symbols.iter().count(), 1);
+            let symbol = &symbols[0];
+            assert_eq!(symbol.name, "GameMain");
+            assert_eq!(symbol.kind, ObjSymbolKind::Function);
+            assert_eq!((symbol.size, symbol.size_known), (0x40, true));
+            // Unknown fields are filled in
+            assert_eq!(symbol.align, Some(8));
+            assert!(symbol.flags.is_locked());
+        }
+
+        // Incoming flags are kept, the scope isn't changed
+        symbols
+            .add(
+                ObjSymbol {
+                    flags: ObjSymbolFlagSet(ObjSymbolFlags::Local | ObjSymbolFlags::ForceActive),
+                    ..locked.clone()
+                },
+                false,
+            )
+            .unwrap();
+        assert!(symbols[0].flags.is_force_active());
+        assert!(symbols[0].flags.is_global());
+
+        // Distinct aliases aren't merged into the locked symbol
+        let idx = symbols
+            .add(ObjSymbol { name: "GameMainAlias".to_string(), ..locked.clone() }, false)
+            .unwrap();
+        assert_ne!(idx, 0);
+        assert_eq!(symbols[0].name, "GameMain");
+        assert_eq!(symbols[idx].name, "GameMainAlias");
+    }
+
+    #[test]
+    fn test_semantic_eq() {
+        let symbol = ObjSymbol {
+            name: "__ct__3FooFv".to_string(),
+            address: 0x80003000,
+            section: Some(0),
+            size: 0x20,
+            size_known: true,
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            kind: ObjSymbolKind::Function,
+            ..Default::default()
+        };
+        let demangled = ObjSymbol {
+            demangled_name: Some("Foo::Foo()".to_string()),
+            source_file: Some("Foo.cpp".to_string()),
+            source_line: Some(12),
+            ..symbol.clone()
+        };
+        assert_ne!(symbol, demangled);
+        assert!(symbol.semantic_eq(&demangled));
+        assert!(!symbol.semantic_eq(&ObjSymbol { size: 0x24, ..symbol.clone() }));
+        assert!(!symbol.semantic_eq(&ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Weak.into()),
+            ..symbol.clone()
+        }));
+
+        assert!(symbol.semantic_eq(&ObjSymbol { size_known: false, ..symbol.clone() }));
+
+        // Merging in a symbol without derived fields keeps the existing ones
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![demangled.clone()]);
+        assert_eq!(symbols.add(symbol.clone(), true).unwrap(), 0);
+        assert_eq!(symbols[0], demangled);
+
+        // New values for derived fields are applied
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![symbol]);
+        assert_eq!(symbols.add(demangled.clone(), true).unwrap(), 0);
+        assert_eq!(symbols[0], demangled);
+    }
+
+    #[test]
+    fn test_diff() {
+        let symbol = |name: &str, address, size| {
+            test_symbol(name, Some(0), address, size, ObjSymbolKind::Function)
+        };
+        let text = test_section(".text", ObjSectionKind::Code, 0x80003100, 0x100);
+        let sections = ObjSections::new(ObjKind::Executable, vec![text.clone()]);
+        let left = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("kept", 0x80003100, 0x10),
+            symbol("resized", 0x80003110, 0x10),
+            symbol("removed", 0x80003120, 0x10),
+            symbol("moved", 0x80003130, 0x10),
+            symbol("fn_80003140", 0x80003140, 0x10),
+        ]);
+        let right = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("kept", 0x80003100, 0x10),
+            symbol("resized", 0x80003110, 0x20),
+            symbol("moved", 0x80003150, 0x10),
+            symbol("Renamed", 0x80003140, 0x10),
+            symbol("added", 0x80003160, 0x8),
+        ]);
+        let diff = left.diff(&sections, &right, &sections);
+        assert_eq!(diff.removed, [2]);
+        assert_eq!(diff.added, [4]);
+        // Matched by address first, then by name
+        assert_eq!(diff.changed, [(1, 1), (3, 2), (4, 3)]);
+        assert!(left.diff(&sections, &left, &sections).is_empty());
+
+        // Sections are matched by name, not index
+        let init = test_section(".init", ObjSectionKind::Code, 0x80003000, 0x100);
+        let other_sections = ObjSections::new(ObjKind::Executable, vec![init, text]);
+        let moved =
+            left.iter().map(|(_, s)| ObjSymbol { section: Some(1), ..s.clone() }).collect_vec();
+        let moved = ObjSymbols::new(ObjKind::Executable, moved);
+        assert!(left.diff(&sections, &moved, &other_sections).is_empty());
+    }
+
+    #[test]
+    fn test_demangle_all() {
+        let names = ["__dt__14TBox<PC5Actor>Fv", "get__tm__2_i__5StackFv", "__ct__3FooFv"];
+        let mut symbols = (0..300)
+            .map(|i| ObjSymbol {
+                name: names[i % names.len()].to_string(),
+                address: 0x80003100 + i as u64 * 4,
+                section: Some(0),
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Local.into()),
+                kind: ObjSymbolKind::Function,
+                ..Default::default()
+            })
+            .collect_vec();
+        symbols.push(ObjSymbol {
+            name: "foo__3BarFv".to_string(),
+            demangled_name: Some("custom".to_string()),
+            address: 0x80004000,
+            section: Some(0),
+            ..Default::default()
+        });
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, symbols);
+
+        // Each distinct name is demangled once, existing names are kept
+        assert_eq!(symbols.demangle_all(false), 3);
+        assert_eq!(symbols[0].demangled_name.as_deref(), Some("TBox<const Actor*>::~TBox()"));
+        assert_eq!(symbols[299].demangled_name.as_deref(), Some("Foo::Foo()"));
+        assert_eq!(symbols[300].demangled_name.as_deref(), Some("custom"));
+        assert_eq!(symbols.demangle_all(false), 0);
+
+        // Forcing recomputes all names
+        assert_eq!(symbols.demangle_all(true), 4);
+        assert_eq!(symbols[300].demangled_name.as_deref(), Some("Bar::foo()"));
+
+        // Failures are remembered until forced
+        symbols.add_direct(ObjSymbol { name: "main".to_string(), ..Default::default() }).unwrap();
+        assert_eq!(symbols.demangle_all(false), 1);
+        assert_eq!(symbols.demangle_all(false), 0);
+        assert_eq!(symbols.demangle_all(true), 5);
+    }
+
+    /// Run with `cargo test --release -- --ignored bench_demangle_all --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_demangle_all() {
+        const COUNT: usize = 100000;
+        // Many instances of a few template names, and C names that can't be demangled
+        let names = (0..100)
+            .map(|i| format!("func{i}__14TBox<PC5Actor>Fv"))
+            .chain((0..100).map(|i| format!("func_{i}")))
+            .collect_vec();
+        let in_symbols = (0..COUNT)
+            .map(|i| ObjSymbol {
+                name: names[i % names.len()].clone(),
+                address: 0x80003100 + i as u64 * 4,
+                section: Some(0),
+                kind: ObjSymbolKind::Function,
+                ..Default::default()
+            })
+            .collect_vec();
+
+        let options = DemangleOptions::default();
+        let start = std::time::Instant::now();
+        let demangled = in_symbols.iter().filter(|s| demangle(&s.name, &options).is_some()).count();
+        println!("{} demangler calls per symbol in {:?}", COUNT, start.elapsed());
+
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, in_symbols);
+        let start = std::time::Instant::now();
+        let calls = symbols.demangle_all(false);
+        println!("{} demangler calls with demangle_all in {:?}", calls, start.elapsed());
+        assert_eq!(calls, names.len());
+        assert_eq!(symbols.iter().filter(|(_, s)| s.demangled_name.is_some()).count(), demangled);
+
+        let start = std::time::Instant::now();
+        let calls = symbols.demangle_all(false);
+        println!("{} demangler calls on the second pass in {:?}", calls, start.elapsed());
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn test_nearest_symbols() {
+        let symbol = |name: &str, address, section| {
+            test_symbol(name, Some(section), address, 0x10, ObjSymbolKind::Unknown)
+        };
+        let symbols = ObjSymbols::new(ObjKind::Executable, vec![
+            symbol("first", 0x80003100, 0),
+            symbol("second", 0x80003110, 0),
+            symbol("third", 0x80003140, 0),
+            symbol("other", 0x80004000, 1),
+        ]);
+        let name = |result: Option<(SymbolIndex, &ObjSymbol)>| result.map(|(_, s)| s.name.clone());
+        let addr = |address| SectionAddress::new(0, address);
+
+        // Exactly at a symbol
+        assert_eq!(name(symbols.nearest_preceding(addr(0x80003110))).as_deref(), Some("second"));
+        assert_eq!(name(symbols.nearest_following(addr(0x80003110))).as_deref(), Some("third"));
+
+        // Between symbols
+        assert_eq!(name(symbols.nearest_preceding(addr(0x80003120))).as_deref(), Some("second"));
+        assert_eq!(name(symbols.nearest_following(addr(0x80003120))).as_deref(), Some("third"));
+
+        // Section boundaries
+        assert_eq!(symbols.nearest_preceding(addr(0x800030FC)), None);
+        assert_eq!(name(symbols.nearest_following(addr(0x800030FC))).as_deref(), Some("first"));
+        assert_eq!(name(symbols.nearest_preceding(addr(0x80004000))).as_deref(), Some("third"));
+        assert_eq!(symbols.nearest_following(addr(0x80003140)), None);
+    }
+
+    #[test]
+    fn test_size_conflicts() {
+        let symbol = |size| test_symbol("gData", Some(0), 0x80001000, size, ObjSymbolKind::Object);
+        let conflicts = |symbols: &ObjSymbols| {
+            symbols.size_conflicts().iter().map(|c| (c.old_size, c.new_size)).collect_vec()
+        };
+
+        // Differences within the alignment are trivial
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![symbol(0x10)]);
+        symbols.add(symbol(0xC), false).unwrap();
+        assert!(conflicts(&symbols).is_empty());
+
+        // Repeated conflicts are recorded once
+        symbols.add(symbol(0x20), false).unwrap();
+        symbols.add(symbol(0x20), false).unwrap();
+        assert_eq!(conflicts(&symbols), [(0x10, 0x20)]);
+
+        // A larger alignment makes the conflict trivial
+        let mut symbols = ObjSymbols::new(ObjKind::Executable, vec![symbol(0x10)]);
+        symbols.set_size_conflict_align(0x20);
+        symbols.add(symbol(0x20), false).unwrap();
+        assert!(conflicts(&symbols).is_empty());
+    }
 }
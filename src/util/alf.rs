@@ -9,6 +9,7 @@ use io::{Error, ErrorKind};
 use crate::{
     obj::{ObjSymbol, ObjSymbolKind, SectionIndex},
     util::{
+        config::auto_symbol_naming,
         dol::{DolLike, DolSection, DolSectionKind},
         reader::{
             read_string, read_vec, read_vec_args, struct_size, Endian, FromReader, DYNAMIC_SIZE,
@@ -211,9 +212,10 @@ impl AlfSymbol {
         let (name, name_hash) = if self.name.starts_with('#') {
             let hash_str = self.name.trim_start_matches('#');
             let hash = u32::from_str_radix(hash_str, 16)?;
+            let naming = auto_symbol_naming();
             let name = match self.kind {
-                AlfSymbolKind::Function => format!("fn_{:08X}", self.address),
-                AlfSymbolKind::Object => format!("lbl_{:08X}", self.address),
+                AlfSymbolKind::Function => naming.function_name(0, self.address),
+                AlfSymbolKind::Object => naming.data_name(0, "", self.address),
             };
             (name, Some(hash))
         } else {
@@ -239,6 +241,10 @@ impl AlfSymbol {
             data_kind: Default::default(),
             name_hash,
             demangled_name_hash,
+            source_file: None,
+            source_line: None,
+            notes: None,
+            sort_index: None,
         })
     }
 }
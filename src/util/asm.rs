@@ -9,11 +9,12 @@ use itertools::Itertools;
 use ppc750cl::{Argument, Ins, InsIter, Opcode};
 
 use crate::{
+    analysis::cfa::SectionAddress,
     obj::{
         ObjDataKind, ObjInfo, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSymbol,
         ObjSymbolKind, SymbolIndex,
     },
-    util::nested::NestedVec,
+    util::{lcf::bin_path_for_unit, nested::NestedVec},
 };
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -27,6 +28,8 @@ enum SymbolEntryKind {
 struct SymbolEntry {
     index: SymbolIndex,
     kind: SymbolEntryKind,
+    /// Stack frame size of a function, see [`ObjInfo::frame_sizes`].
+    frame_size: Option<u32>,
 }
 
 pub fn write_asm<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
@@ -43,22 +46,30 @@ where W: Write + ?Sized {
         writeln!(w, ".file \"{}\"", name.replace('\\', "\\\\"))?;
     }
 
-    // We'll append generated symbols to the end
-    let mut symbols: Vec<ObjSymbol> = obj.symbols.iter().map(|(_, s)| s.clone()).collect();
+    // We'll append generated symbols to the end. Removed symbols keep an empty slot, so that
+    // relocations and entries can index by SymbolIndex.
+    let mut symbols: Vec<ObjSymbol> = (0..obj.symbols.slot_count())
+        .map(|i| obj.symbols.get(i).cloned().unwrap_or_default())
+        .collect();
     let mut section_entries: Vec<BTreeMap<u32, Vec<SymbolEntry>>> = vec![];
     let mut section_relocations: Vec<BTreeMap<u32, ObjReloc>> = vec![];
     for (section_idx, section) in obj.sections.iter() {
         // Build symbol start/end entries
         let mut entries = BTreeMap::<u32, Vec<SymbolEntry>>::new();
         for (symbol_index, symbol) in obj.symbols.for_section(section_idx) {
-            entries.nested_push(symbol.address as u32, SymbolEntry {
+            let address = symbol.address as u32;
+            let frame_size =
+                obj.frame_sizes.get(&SectionAddress::new(section_idx, address)).copied();
+            entries.nested_push(address, SymbolEntry {
                 index: symbol_index,
                 kind: SymbolEntryKind::Start,
+                frame_size,
             });
             if symbol.size > 0 {
                 entries.nested_push((symbol.address + symbol.size) as u32, SymbolEntry {
                     index: symbol_index,
                     kind: SymbolEntryKind::End,
+                    frame_size: None,
                 });
             }
         }
@@ -66,7 +77,7 @@ where W: Write + ?Sized {
         let mut relocations = section.relocations.clone_map();
 
         // Generate local jump labels
-        if section.kind == ObjSectionKind::Code {
+        if section.kind == ObjSectionKind::Code && !section.verbatim {
             for (addr, ins) in InsIter::new(&section.data, section.address as u32) {
                 if let Some(address) = ins.branch_dest(addr) {
                     if ins.field_aa() || !section.contains(address) {
@@ -98,7 +109,11 @@ where W: Write + ?Sized {
                             size_known: true,
                             ..Default::default()
                         });
-                        vec.push(SymbolEntry { index: symbol_idx, kind: SymbolEntryKind::Label });
+                        vec.push(SymbolEntry {
+                            index: symbol_idx,
+                            kind: SymbolEntryKind::Label,
+                            frame_size: None,
+                        });
                         target_symbol_idx = Some(symbol_idx);
                     }
                     if let Some(symbol_idx) = target_symbol_idx {
@@ -157,7 +172,11 @@ where W: Write + ?Sized {
                     size_known: true,
                     ..Default::default()
                 });
-                vec.push(SymbolEntry { index: symbol_idx, kind: SymbolEntryKind::Label });
+                vec.push(SymbolEntry {
+                    index: symbol_idx,
+                    kind: SymbolEntryKind::Label,
+                    frame_size: None,
+                });
             }
         }
     }
@@ -200,6 +219,20 @@ where W: Write + ?Sized {
 
             write_section_header(w, section, subsection, current_address, section_end)?;
             match section.kind {
+                ObjSectionKind::Code | ObjSectionKind::Data | ObjSectionKind::ReadOnlyData
+                    if section.verbatim =>
+                {
+                    let path = bin_path_for_unit(&obj.name, &section.name);
+                    write_verbatim(
+                        w,
+                        &symbols,
+                        entries,
+                        section,
+                        path.as_str(),
+                        current_address,
+                        section_end,
+                    )?;
+                }
                 ObjSectionKind::Code | ObjSectionKind::Data | ObjSectionKind::ReadOnlyData => {
                     write_data(
                         w,
@@ -414,10 +447,22 @@ where
             if let Some(section_address) = section.virtual_address {
                 write!(w, " | {:#X}", section_address + symbol.address)?;
             }
-            writeln!(w, " | size: {:#X}", symbol.size)?;
+            write!(w, " | size: {:#X}", symbol.size)?;
+            if let Some(frame_size) = entry.frame_size {
+                write!(w, " | frame size: {:#X}", frame_size)?;
+            }
+            writeln!(w)?;
             if let Some(name) = &symbol.demangled_name {
                 writeln!(w, "# {name}")?;
             }
+            if let Some(text) = decode_string_comment(symbol, section)? {
+                writeln!(w, "# \"{text}\"")?;
+            }
+            if let Some(notes) = &symbol.notes {
+                for line in notes.lines() {
+                    writeln!(w, "# Note: {line}")?;
+                }
+            }
             write!(w, ".{symbol_kind} ")?;
             write_symbol_name(w, &symbol.name)?;
             writeln!(w, ", {scope}")?;
@@ -429,12 +474,21 @@ where
         }
     }
 
-    if matches!(entry.kind, SymbolEntryKind::Start | SymbolEntryKind::Label)
-        && symbol.flags.is_hidden()
-    {
-        write!(w, ".hidden ")?;
-        write_symbol_name(w, &symbol.name)?;
-        writeln!(w)?;
+    if matches!(entry.kind, SymbolEntryKind::Start | SymbolEntryKind::Label) {
+        let visibility = if symbol.flags.is_internal() {
+            Some(".internal")
+        } else if symbol.flags.is_hidden() {
+            Some(".hidden")
+        } else if symbol.flags.is_protected() {
+            Some(".protected")
+        } else {
+            None
+        };
+        if let Some(directive) = visibility {
+            write!(w, "{} ", directive)?;
+            write_symbol_name(w, &symbol.name)?;
+            writeln!(w)?;
+        }
     }
 
     if entry.kind == SymbolEntryKind::Start && section.name == "extab" {
@@ -698,6 +752,65 @@ where W: Write + ?Sized {
     Ok(())
 }
 
+/// Maximum number of characters in a decoded string comment before it's truncated.
+const STRING_COMMENT_MAX_CHARS: usize = 80;
+
+/// Decodes the contents of a string symbol for use as a comment.
+///
+/// 8-bit strings are decoded as Shift-JIS, which many Japanese titles use and which leaves
+/// ASCII text unchanged. Bytes that aren't valid Shift-JIS fall back to hex escapes.
+/// Wide strings are decoded as UTF-16BE. Embedded NULs and non-printable characters are
+/// escaped, and long strings are truncated with `...`.
+pub fn decode_string_comment(symbol: &ObjSymbol, section: &ObjSection) -> Result<Option<String>> {
+    let data = section.symbol_data(symbol)?;
+    let chars = match symbol.data_kind {
+        ObjDataKind::String => {
+            let data = data.strip_suffix(&[0]).unwrap_or(data);
+            decode_shift_jis(data)
+        }
+        ObjDataKind::String16 => {
+            let data = data
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes(c.try_into().unwrap()))
+                .collect::<Vec<u16>>();
+            let data = data.strip_suffix(&[0]).unwrap_or(&data);
+            std::char::decode_utf16(data.iter().cloned())
+                .map(|r| r.map_err(|e| e.unpaired_surrogate() as u32))
+                .collect()
+        }
+        _ => return Ok(None),
+    };
+    let mut out = String::new();
+    for (i, c) in chars.into_iter().enumerate() {
+        if i == STRING_COMMENT_MAX_CHARS {
+            out.push_str("...");
+            break;
+        }
+        match c {
+            Ok('\x00') => out.push_str("\\0"),
+            Ok('\x09') => out.push_str("\\t"),
+            Ok('\x0A') => out.push_str("\\n"),
+            Ok('\x0D') => out.push_str("\\r"),
+            Ok('\\') => out.push_str("\\\\"),
+            Ok('"') => out.push_str("\\\""),
+            Ok(c) if c.is_control() => out.push_str(&format!("\\u{{{:X}}}", c as u32)),
+            Ok(c) => out.push(c),
+            Err(b) => out.push_str(&format!("\\x{b:02X}")),
+        }
+    }
+    Ok(Some(out))
+}
+
+/// Decodes Shift-JIS text. If the data isn't valid Shift-JIS, non-ASCII bytes are returned as-is.
+fn decode_shift_jis(data: &[u8]) -> Vec<Result<char, u32>> {
+    match encoding_rs::SHIFT_JIS.decode_without_bom_handling_and_without_replacement(data) {
+        Some(str) => str.chars().map(Ok).collect(),
+        None => {
+            data.iter().map(|&b| if b.is_ascii() { Ok(b as char) } else { Err(b as u32) }).collect()
+        }
+    }
+}
+
 fn write_data_chunk<W>(w: &mut W, data: &[u8], data_kind: ObjDataKind) -> Result<()>
 where W: Write + ?Sized {
     let remain = data;
@@ -738,15 +851,24 @@ where W: Write + ?Sized {
     }
     let chunk_size = match data_kind {
         ObjDataKind::Byte2 | ObjDataKind::Short => 2,
-        ObjDataKind::Unknown | ObjDataKind::Byte4 | ObjDataKind::Float | ObjDataKind::Int => 4,
-        ObjDataKind::Byte | ObjDataKind::Byte8 | ObjDataKind::Double => 8,
+        ObjDataKind::Unknown
+        | ObjDataKind::Byte4
+        | ObjDataKind::Float
+        | ObjDataKind::Int
+        | ObjDataKind::JumpTable
+        | ObjDataKind::PointerTable => 4,
+        ObjDataKind::Byte
+        | ObjDataKind::Byte8
+        | ObjDataKind::Double
+        | ObjDataKind::PairedSingle => 8,
+        ObjDataKind::Quadword => 16,
         ObjDataKind::String
         | ObjDataKind::String16
         | ObjDataKind::StringTable
         | ObjDataKind::String16Table => unreachable!(),
     };
     for chunk in remain.chunks(chunk_size) {
-        if data_kind == ObjDataKind::Byte || matches!(chunk.len(), 1 | 3 | 5..=7) {
+        if data_kind == ObjDataKind::Byte || matches!(chunk.len(), 1 | 3 | 5..=7 | 9..=15) {
             let bytes = chunk.iter().map(|c| format!("{:#04X}", c)).collect::<Vec<String>>();
             writeln!(w, "\t.byte {}", bytes.join(", "))?;
         } else {
@@ -760,6 +882,24 @@ where W: Write + ?Sized {
                         writeln!(w, "\t.double {data}")?;
                     }
                 }
+                16 => {
+                    let words = chunk
+                        .chunks(4)
+                        .map(|c| format!("{:#010X}", u32::from_be_bytes(c.try_into().unwrap())))
+                        .collect::<Vec<String>>();
+                    writeln!(w, "\t.4byte {}", words.join(", "))?;
+                }
+                8 if data_kind == ObjDataKind::PairedSingle => {
+                    let ps0 = f32::from_be_bytes(chunk[..4].try_into().unwrap());
+                    let ps1 = f32::from_be_bytes(chunk[4..].try_into().unwrap());
+                    if ps0.is_nan() || ps1.is_nan() {
+                        let int0 = u32::from_be_bytes(chunk[..4].try_into().unwrap());
+                        let int1 = u32::from_be_bytes(chunk[4..].try_into().unwrap());
+                        writeln!(w, "\t.4byte {int0:#010X}, {int1:#010X} # {ps0}, {ps1}")?;
+                    } else {
+                        writeln!(w, "\t.float {ps0}, {ps1}")?;
+                    }
+                }
                 8 => {
                     let data = u64::from_be_bytes(chunk.try_into().unwrap());
                     writeln!(w, "\t.8byte {data:#018X}")?;
@@ -882,6 +1022,51 @@ where
     Ok(())
 }
 
+fn write_verbatim<W>(
+    w: &mut W,
+    symbols: &[ObjSymbol],
+    entries: &BTreeMap<u32, Vec<SymbolEntry>>,
+    section: &ObjSection,
+    path: &str,
+    start: u32,
+    end: u32,
+) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    let mut entry_iter = entries.range(start..end);
+
+    let mut current_address = start;
+    let mut entry = entry_iter.next();
+    let mut begin = true;
+    loop {
+        if current_address == end {
+            break;
+        }
+        if let Some((sym_addr, vec)) = entry {
+            if current_address == *sym_addr {
+                for entry in vec {
+                    if entry.kind == SymbolEntryKind::End && begin {
+                        continue;
+                    }
+                    write_symbol_entry(w, symbols, entry, section)?;
+                }
+                entry = entry_iter.next();
+            }
+        }
+        begin = false;
+
+        let until = entry.map(|(addr, _)| *addr).unwrap_or(end);
+        let size = until - current_address;
+        if size > 0 {
+            let offset = current_address - section.address as u32;
+            writeln!(w, "\t.incbin \"{}\", {:#X}, {:#X}", path, offset, size)?;
+        }
+        current_address = until;
+    }
+    Ok(())
+}
+
 fn write_section_header<W>(
     w: &mut W,
     section: &ObjSection,
@@ -990,3 +1175,122 @@ where W: Write + ?Sized {
 fn is_illegal_instruction(code: u32) -> bool {
     matches!(code, 0x43000000 /* bc 24, lt, 0x0 */ | 0xB8030000 /* lmw r0, 0(r3) */)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section_data, test_symbol, ObjArchitecture, ObjKind, ObjRelocations};
+
+    fn string_comment(data: &[u8], data_kind: ObjDataKind) -> Option<String> {
+        let section = test_section_data(".data", ObjSectionKind::Data, 0, data.to_vec());
+        let symbol = ObjSymbol {
+            name: "str".to_string(),
+            section: Some(0),
+            size: data.len() as u64,
+            size_known: true,
+            kind: ObjSymbolKind::Object,
+            data_kind,
+            ..Default::default()
+        };
+        decode_string_comment(&symbol, &section).unwrap()
+    }
+
+    #[test]
+    fn test_decode_string_comment() {
+        assert_eq!(
+            string_comment(b"Hello, world!\0", ObjDataKind::String).as_deref(),
+            Some("Hello, world!")
+        );
+        // "テスト" in Shift-JIS
+        assert_eq!(
+            string_comment(b"\x83\x65\x83\x58\x83\x67\0", ObjDataKind::String).as_deref(),
+            Some("テスト")
+        );
+        assert_eq!(
+            string_comment(b"a\tb\"c\\\x01\0d\n\0", ObjDataKind::String).as_deref(),
+            Some(r#"a\tb\"c\\\u{1}\0d\n"#)
+        );
+        // Invalid Shift-JIS: lead byte without a trail byte
+        assert_eq!(string_comment(b"ab\x81\0", ObjDataKind::String).as_deref(), Some(r"ab\x81"));
+        assert_eq!(
+            string_comment(b"\0H\0i\0\n\0\0", ObjDataKind::String16).as_deref(),
+            Some(r"Hi\n")
+        );
+        let long = [b'a'; 100];
+        assert_eq!(
+            string_comment(&long, ObjDataKind::String),
+            Some(format!("{}...", "a".repeat(STRING_COMMENT_MAX_CHARS)))
+        );
+        assert_eq!(string_comment(b"abcd", ObjDataKind::Byte4), None);
+    }
+
+    #[test]
+    fn test_write_asm_relocations() {
+        let code: [u32; 6] = [
+            0x3C600000, // lis r3, 0
+            0x38630000, // addi r3, r3, 0
+            0x808D0000, // lwz r4, 0(r13)
+            0x48000001, // bl 0
+            0x4E800020, // blr
+            0x4E800020, // callee: blr
+        ];
+        let text = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        let reloc = |kind, target_symbol| ObjReloc { kind, target_symbol, addend: 0, module: None };
+        let section = |name: &str, kind, data: Vec<u8>, relocations| ObjSection {
+            relocations,
+            ..test_section_data(name, kind, 0, data)
+        };
+        let symbol = |name: &str, address, section, size, kind| {
+            test_symbol(name, Some(section), address, size, kind)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.o".to_string(),
+            vec![
+                symbol("removed", 0x10, 1, 4, ObjSymbolKind::Object),
+                symbol("caller", 0, 0, 0x14, ObjSymbolKind::Function),
+                symbol("callee", 0x14, 0, 4, ObjSymbolKind::Function),
+                symbol("gData", 0, 1, 0x10, ObjSymbolKind::Object),
+                symbol("gCount", 0, 2, 4, ObjSymbolKind::Object),
+            ],
+            vec![
+                section(
+                    ".text",
+                    ObjSectionKind::Code,
+                    text,
+                    ObjRelocations::new(vec![
+                        (0x0, ObjReloc { addend: 8, ..reloc(ObjRelocKind::PpcAddr16Ha, 3) }),
+                        (0x4, ObjReloc { addend: 8, ..reloc(ObjRelocKind::PpcAddr16Lo, 3) }),
+                        (0x8, reloc(ObjRelocKind::PpcEmbSda21, 4)),
+                        (0xC, reloc(ObjRelocKind::PpcRel24, 2)),
+                    ])
+                    .unwrap(),
+                ),
+                section(".data", ObjSectionKind::Data, vec![0; 0x14], Default::default()),
+                section(".sdata", ObjSectionKind::Data, vec![0; 4], Default::default()),
+            ],
+        );
+        // Relocations must still resolve to the right names after removing a symbol
+        obj.symbols.remove(0).unwrap();
+        obj.frame_sizes.insert(SectionAddress::new(0, 0), 0x20);
+
+        let mut out = vec![];
+        write_asm(&mut out, &obj).unwrap();
+        let asm = String::from_utf8(out).unwrap();
+        let instructions = asm
+            .lines()
+            .filter_map(|line| line.split_once("*/\t").map(|(_, ins)| ins))
+            .collect_vec();
+        assert_eq!(instructions, [
+            "lis r3, gData+0x8@ha",
+            "addi r3, r3, gData+0x8@l",
+            "lwz r4, gCount@sda21(r0)",
+            "bl callee",
+            "blr",
+            "blr",
+        ]);
+        assert!(asm.contains("# .text:0x0 | size: 0x14 | frame size: 0x20\n.fn caller"), "{asm}");
+        assert!(asm.contains("# .text:0x14 | size: 0x4\n.fn callee"), "{asm}");
+    }
+}
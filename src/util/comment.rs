@@ -188,6 +188,27 @@ impl MWComment {
     }
 }
 
+/// Whether the `.comment` section data has a CodeWarrior header.
+pub fn is_mw_comment(data: &[u8]) -> bool { data.starts_with(MAGIC) }
+
+/// Parse NUL-separated producer strings (as emitted by GCC) from `.comment` section data.
+pub fn read_producers(data: &[u8]) -> Vec<String> {
+    data.split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+/// Write producer strings as `.comment` section data, each terminated by a NUL.
+pub fn write_producers(producers: &[String]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(producers.iter().map(|s| s.len() + 1).sum());
+    for producer in producers {
+        data.extend_from_slice(producer.as_bytes());
+        data.push(0);
+    }
+    data
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct CommentSym {
     pub align: u32,
@@ -277,10 +298,10 @@ impl CommentSym {
         }
         let mut active_flags = 0;
         if !symbol.flags.is_stripped()
-            && (symbol.flags.is_exported()
+            && (symbol.flags.is_force_active()
                 || (export_all
                     && !symbol.flags.is_no_export()
-                    && matches!(symbol.kind, ObjSymbolKind::Function | ObjSymbolKind::Object)))
+                    && (symbol.is_code() || symbol.is_data())))
         {
             active_flags |= 0x8;
         }
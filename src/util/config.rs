@@ -1,15 +1,24 @@
 use std::{
-    fs,
+    cell::RefCell,
+    ffi::OsStr,
+    fmt, fs,
     io::{BufRead, Write},
     num::ParseIntError,
     str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
-use cwdemangle::{demangle, DemangleOptions};
+use argp::FromArgValue;
+use cwdemangle::DemangleOptions;
 use filetime::FileTime;
+use itertools::Itertools;
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
 use typed_path::Utf8NativePath;
 use xxhash_rust::xxh3::xxh3_64;
@@ -21,6 +30,7 @@ use crate::{
         ObjSymbolFlags, ObjSymbolKind, ObjUnit, SectionIndex,
     },
     util::{
+        demangle::demangle,
         file::{buf_writer, FileReadInfo},
         split::default_section_align,
     },
@@ -52,21 +62,31 @@ pub fn apply_symbols_file(
     Ok(if fs::metadata(path).is_ok_and(|m| m.is_file()) {
         let mut file = open_file(path, true)?;
         let cached = FileReadInfo::new(file.as_mut())?;
-        for result in file.lines() {
-            let line = match result {
-                Ok(line) => line,
-                Err(e) => bail!("Failed to process symbols file: {e:?}"),
-            };
-            if let Some(symbol) = parse_symbol_line(&line, obj)? {
-                obj.add_symbol(symbol, true)?;
-            }
-        }
+        apply_symbols(file.as_mut(), obj)?;
         Some(cached)
     } else {
         None
     })
 }
 
+pub fn apply_symbols<R>(r: &mut R, obj: &mut ObjInfo) -> Result<()>
+where R: BufRead + ?Sized {
+    let mut sort_index = 0;
+    for result in r.lines() {
+        let line = match result {
+            Ok(line) => line,
+            Err(e) => bail!("Failed to process symbols file: {e:?}"),
+        };
+        if let Some(mut symbol) = parse_symbol_line(&line, obj)? {
+            // Record the file order for SymbolSortMode::Stable
+            symbol.sort_index = Some(sort_index);
+            sort_index += 1;
+            obj.add_symbol(symbol, true)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn parse_symbol_line(line: &str, obj: &mut ObjInfo) -> Result<Option<ObjSymbol>> {
     static SYMBOL_LINE: Lazy<Regex> = Lazy::new(|| {
         Regex::new(
@@ -98,10 +118,17 @@ pub fn parse_symbol_line(line: &str, obj: &mut ObjInfo) -> Result<Option<ObjSymb
             ObjSymbol { name, demangled_name, address: addr as u64, section, ..Default::default() };
         // TODO move somewhere common
         if symbol.name.starts_with("..") {
-            symbol.flags.0 |= ObjSymbolFlags::Exported;
+            symbol.flags.0 |= ObjSymbolFlags::ForceActive;
         }
-        let attrs = captures["attrs"].split(' ');
-        for attr in attrs {
+        // Notes are free-form, so they're written last and extend to the end of the line
+        let (attrs, notes) = match captures["attrs"].split_once("note:") {
+            Some((attrs, notes)) if attrs.is_empty() || attrs.ends_with(' ') => {
+                (attrs.trim_end(), Some(notes))
+            }
+            _ => (&captures["attrs"], None),
+        };
+        symbol.notes = notes.map(unescape_notes);
+        for attr in attrs.split(' ').filter(|&s| !s.is_empty()) {
             if let Some((name, value)) = attr.split_once(':') {
                 match name {
                     "type" => {
@@ -140,8 +167,14 @@ pub fn parse_symbol_line(line: &str, obj: &mut ObjInfo) -> Result<Option<ObjSymb
                     "hidden" => {
                         symbol.flags.0 |= ObjSymbolFlags::Hidden;
                     }
+                    "protected" => {
+                        symbol.flags.0 |= ObjSymbolFlags::Protected;
+                    }
+                    "internal" => {
+                        symbol.flags.0 |= ObjSymbolFlags::Internal;
+                    }
                     "force_active" => {
-                        symbol.flags.0 |= ObjSymbolFlags::Exported;
+                        symbol.flags.0 |= ObjSymbolFlags::ForceActive;
                     }
                     "stripped" => {
                         symbol.flags.0 |= ObjSymbolFlags::Stripped;
@@ -164,6 +197,18 @@ pub fn parse_symbol_line(line: &str, obj: &mut ObjInfo) -> Result<Option<ObjSymb
                     "noexport" => {
                         symbol.flags.0 |= ObjSymbolFlags::NoExport;
                     }
+                    "exported" => {
+                        symbol.flags.0 |= ObjSymbolFlags::Exported;
+                    }
+                    "allow_any_reloc" => {
+                        symbol.flags.0 |= ObjSymbolFlags::AllowAnyReloc;
+                    }
+                    "nowrite" => {
+                        symbol.flags.0 |= ObjSymbolFlags::NoWrite;
+                    }
+                    "locked" => {
+                        symbol.flags.0 |= ObjSymbolFlags::Locked;
+                    }
                     _ => bail!("Unknown symbol attribute '{attr}'"),
                 }
             }
@@ -188,17 +233,168 @@ pub fn is_skip_symbol(symbol: &ObjSymbol) -> bool {
     false
 }
 
+/// Hex digit case used for addresses in auto-generated symbol names.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoSymbolAddressFormat {
+    #[default]
+    Upper,
+    Lower,
+}
+
+/// Naming scheme for auto-generated symbols, e.g. `fn_80001234` or `sub_80001234`.
+///
+/// Names in the main module are formatted as `{prefix}{address:08X}`. In REL modules, they're
+/// formatted as `{prefix}{module_id}_{address:X}` for functions and
+/// `{prefix}{module_id}_{section}_{address:X}` otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoSymbolNaming {
+    /// Prefix for functions.
+    pub function_prefix: String,
+    /// Prefix for labels in code sections.
+    pub label_prefix: String,
+    /// Prefix for labels in data sections.
+    pub data_prefix: String,
+    /// Prefix for jump tables.
+    pub jump_table_prefix: String,
+    /// Hex digit case of addresses.
+    pub address_format: AutoSymbolAddressFormat,
+}
+
+impl Default for AutoSymbolNaming {
+    fn default() -> Self {
+        Self {
+            function_prefix: "fn_".to_string(),
+            label_prefix: "lbl_".to_string(),
+            data_prefix: "lbl_".to_string(),
+            jump_table_prefix: "jumptable_".to_string(),
+            address_format: AutoSymbolAddressFormat::Upper,
+        }
+    }
+}
+
+impl AutoSymbolNaming {
+    pub fn function_name(&self, module_id: u32, address: u32) -> String {
+        self.format_name(&self.function_prefix, module_id, None, address)
+    }
+
+    pub fn label_name(&self, module_id: u32, section_name: &str, address: u32) -> String {
+        self.format_name(&self.label_prefix, module_id, Some(section_name), address)
+    }
+
+    pub fn data_name(&self, module_id: u32, section_name: &str, address: u32) -> String {
+        self.format_name(&self.data_prefix, module_id, Some(section_name), address)
+    }
+
+    pub fn jump_table_name(&self, module_id: u32, section_name: &str, address: u32) -> String {
+        self.format_name(&self.jump_table_prefix, module_id, Some(section_name), address)
+    }
+
+    fn format_name(
+        &self,
+        prefix: &str,
+        module_id: u32,
+        section_name: Option<&str>,
+        address: u32,
+    ) -> String {
+        let address = match (self.address_format, module_id) {
+            (AutoSymbolAddressFormat::Upper, 0) => format!("{:08X}", address),
+            (AutoSymbolAddressFormat::Lower, 0) => format!("{:08x}", address),
+            (AutoSymbolAddressFormat::Upper, _) => format!("{:X}", address),
+            (AutoSymbolAddressFormat::Lower, _) => format!("{:x}", address),
+        };
+        match (module_id, section_name) {
+            (0, _) => format!("{}{}", prefix, address),
+            (_, Some(section_name)) => format!(
+                "{}{}_{}_{}",
+                prefix,
+                module_id,
+                section_name.trim_start_matches('.'),
+                address
+            ),
+            (_, None) => format!("{}{}_{}", prefix, module_id, address),
+        }
+    }
+
+    /// Whether `name` has the given prefix, followed by an address (or module ID) in hex.
+    /// Either case is accepted, since symbols files may predate a change of address format.
+    /// Anything after the next `_` is ignored.
+    fn matches(&self, prefix: &str, name: &str) -> bool {
+        let Some(rest) = name.strip_prefix(prefix) else {
+            return false;
+        };
+        let segment = rest.split('_').next().unwrap_or_default();
+        !segment.is_empty() && segment.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    pub fn is_auto_name(&self, name: &str) -> bool {
+        self.matches(&self.function_prefix, name)
+            || self.is_auto_label_name(name)
+            || self.is_auto_jump_table_name(name)
+            // Generated by split, not configurable
+            || name.starts_with("gap_")
+            || name.starts_with("pad_")
+    }
+
+    pub fn is_auto_label_name(&self, name: &str) -> bool {
+        self.matches(&self.label_prefix, name) || self.matches(&self.data_prefix, name)
+    }
+
+    pub fn is_auto_jump_table_name(&self, name: &str) -> bool {
+        self.matches(&self.jump_table_prefix, name)
+    }
+}
+
+static AUTO_SYMBOL_NAMING: Lazy<RwLock<AutoSymbolNaming>> = Lazy::new(Default::default);
+/// Bumped whenever the naming scheme changes, so per-thread copies can be refreshed.
+static AUTO_SYMBOL_NAMING_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static CACHED_AUTO_SYMBOL_NAMING: RefCell<(usize, AutoSymbolNaming)> =
+        RefCell::new((0, AutoSymbolNaming::default()));
+}
+
+/// Calls `f` with this thread's copy of the naming scheme, avoiding the lock on hot paths.
+fn with_auto_symbol_naming<R>(f: impl FnOnce(&AutoSymbolNaming) -> R) -> R {
+    let generation = AUTO_SYMBOL_NAMING_GENERATION.load(Ordering::Acquire);
+    CACHED_AUTO_SYMBOL_NAMING.with(|cached| {
+        let mut cached = cached.borrow_mut();
+        if cached.0 != generation {
+            *cached = (generation, AUTO_SYMBOL_NAMING.read().unwrap().clone());
+        }
+        f(&cached.1)
+    })
+}
+
+/// Sets the naming scheme used to generate and detect auto symbols.
+pub fn set_auto_symbol_naming(naming: AutoSymbolNaming) -> Result<()> {
+    ensure!(
+        !naming.function_prefix.is_empty()
+            && !naming.label_prefix.is_empty()
+            && !naming.data_prefix.is_empty()
+            && !naming.jump_table_prefix.is_empty(),
+        "Auto symbol prefixes must not be empty"
+    );
+    *AUTO_SYMBOL_NAMING.write().unwrap() = naming;
+    AUTO_SYMBOL_NAMING_GENERATION.fetch_add(1, Ordering::Release);
+    Ok(())
+}
+
+/// The naming scheme used to generate and detect auto symbols.
+pub fn auto_symbol_naming() -> AutoSymbolNaming { AUTO_SYMBOL_NAMING.read().unwrap().clone() }
+
 pub fn is_auto_symbol(symbol: &ObjSymbol) -> bool {
-    symbol.name.starts_with("lbl_")
-        || symbol.name.starts_with("fn_")
-        || symbol.name.starts_with("jumptable_")
-        || symbol.name.starts_with("gap_")
-        || symbol.name.starts_with("pad_")
+    with_auto_symbol_naming(|naming| naming.is_auto_name(&symbol.name))
 }
 
-pub fn is_auto_label(symbol: &ObjSymbol) -> bool { symbol.name.starts_with("lbl_") }
+pub fn is_auto_label(symbol: &ObjSymbol) -> bool {
+    with_auto_symbol_naming(|naming| naming.is_auto_label_name(&symbol.name))
+}
 
-pub fn is_auto_jump_table(symbol: &ObjSymbol) -> bool { symbol.name.starts_with("jumptable_") }
+pub fn is_auto_jump_table(symbol: &ObjSymbol) -> bool {
+    with_auto_symbol_naming(|naming| naming.is_auto_jump_table_name(&symbol.name))
+}
 
 fn write_if_unchanged<Cb>(
     path: &Utf8NativePath,
@@ -240,32 +436,153 @@ where
     Ok(())
 }
 
+/// Ordering of symbols written to symbols.txt.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolSortMode {
+    /// Ascending address within each section, in section order. This is the order symbols are
+    /// stored in, and ABS symbols aren't written.
+    #[default]
+    Address,
+    /// Alphabetical by name, including ABS symbols.
+    Name,
+    /// Section order, then ascending address. ABS symbols are written last, by value.
+    Section,
+    /// Order of the symbols file the symbols were loaded from, so that regenerating it only
+    /// changes the lines of added or removed symbols. New symbols are placed after the symbol
+    /// preceding them in [`SymbolSortMode::Section`] order.
+    Stable,
+}
+
+impl FromStr for SymbolSortMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "address" => Ok(Self::Address),
+            "name" => Ok(Self::Name),
+            "section" => Ok(Self::Section),
+            "stable" => Ok(Self::Stable),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for SymbolSortMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Address => "address",
+            Self::Name => "name",
+            Self::Section => "section",
+            Self::Stable => "stable",
+        })
+    }
+}
+
+impl FromArgValue for SymbolSortMode {
+    fn from_arg_value(value: &OsStr) -> Result<Self, String> {
+        String::from_arg_value(value).and_then(|s| {
+            Self::from_str(&s).map_err(|_| {
+                format!("Invalid sort mode '{s}' (expected address, name, section or stable)")
+            })
+        })
+    }
+}
+
 #[inline]
 pub fn write_symbols_file(
     path: &Utf8NativePath,
     obj: &ObjInfo,
+    sort: SymbolSortMode,
     cached_file: Option<FileReadInfo>,
 ) -> Result<()> {
-    write_if_unchanged(path, |w| write_symbols(w, obj), cached_file)
+    write_if_unchanged(path, |w| write_symbols_sorted(w, obj, sort), cached_file)
 }
 
-pub fn write_symbols<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+pub fn write_symbols_sorted<W>(w: &mut W, obj: &ObjInfo, sort: SymbolSortMode) -> Result<()>
 where W: Write + ?Sized {
-    for (_, symbol) in obj.symbols.iter_ordered() {
-        if symbol.kind == ObjSymbolKind::Section || is_skip_symbol(symbol) {
-            continue;
-        }
+    for symbol in sorted_symbols(obj, sort, false) {
+        write_symbol(w, obj, symbol)?;
+    }
+    Ok(())
+}
+
+/// Writes all symbols, including those excluded from the symbols file with
+/// [`ObjSymbolFlags::NoWrite`]. Used for toolkit-internal state like the analysis cache.
+pub fn write_all_symbols<W>(w: &mut W, obj: &ObjInfo) -> Result<()>
+where W: Write + ?Sized {
+    for symbol in sorted_symbols(obj, SymbolSortMode::default(), true) {
         write_symbol(w, obj, symbol)?;
     }
     Ok(())
 }
 
+/// Collects the symbols to be written in the requested order. Sorts are stable and start from
+/// section order, so symbols at the same address keep their relative order in every mode.
+/// Outside of [`SymbolSortMode::Address`], symbols without a section are written (as `ABS:`)
+/// after the others if they're defined: undefined externs and common symbols in relocatable
+/// objects are skipped.
+fn sorted_symbols(obj: &ObjInfo, sort: SymbolSortMode, include_no_write: bool) -> Vec<&ObjSymbol> {
+    let mut abs_symbols = vec![];
+    if sort != SymbolSortMode::Address {
+        abs_symbols = obj
+            .symbols
+            .iter()
+            .map(|(_, s)| s)
+            .filter(|s| s.section.is_none() && is_defined_abs_symbol(obj, s))
+            .collect_vec();
+        // Their address is a value rather than a location, so they're ordered by it separately
+        abs_symbols.sort_by_key(|s| s.address);
+    }
+    let mut symbols = obj
+        .symbols
+        .iter_ordered()
+        .map(|(_, s)| s)
+        .chain(abs_symbols)
+        .filter(|s| s.kind != ObjSymbolKind::Section && (include_no_write || !is_skip_symbol(s)))
+        .collect_vec();
+    match sort {
+        SymbolSortMode::Address | SymbolSortMode::Section => {}
+        SymbolSortMode::Name => symbols.sort_by(|a, b| a.name.cmp(&b.name)),
+        SymbolSortMode::Stable => {
+            // Start from section order, so that new symbols can follow the nearest preceding
+            // symbol from the symbols file
+            let mut anchor = None;
+            let mut keyed = symbols
+                .into_iter()
+                .enumerate()
+                .map(|(position, symbol)| {
+                    let key = match symbol.sort_index {
+                        Some(index) => {
+                            anchor = Some(index);
+                            (anchor, false, position)
+                        }
+                        None => (anchor, true, position),
+                    };
+                    (key, symbol)
+                })
+                .collect_vec();
+            keyed.sort_by_key(|&(key, _)| key);
+            symbols = keyed.into_iter().map(|(_, s)| s).collect();
+        }
+    }
+    symbols
+}
+
+/// Whether a symbol without a section is a defined absolute symbol. Relocatable objects can't
+/// define absolute symbols, so those are undefined externs, and common symbols are allocated
+/// by the linker.
+fn is_defined_abs_symbol(obj: &ObjInfo, symbol: &ObjSymbol) -> bool {
+    obj.kind == ObjKind::Executable && !symbol.flags.is_common()
+}
+
 fn write_symbol<W>(w: &mut W, obj: &ObjInfo, symbol: &ObjSymbol) -> Result<()>
 where W: Write + ?Sized {
     write!(w, "{} = ", symbol.name)?;
-    let section = symbol.section.and_then(|idx| obj.sections.get(idx));
-    if let Some(section) = section {
+    if let Some(section) = symbol.section.and_then(|idx| obj.sections.get(idx)) {
         write!(w, "{}:", section.name)?;
+    } else {
+        write!(w, "ABS:")?;
     }
     write!(w, "{:#010X}; //", symbol.address)?;
     write!(w, " type:{}", symbol_kind_to_str(symbol.kind))?;
@@ -289,9 +606,14 @@ where W: Write + ?Sized {
             write!(w, " dhash:{:#010X}", hash)?;
         }
     }
-    if symbol.flags.is_hidden() {
+    if symbol.flags.is_internal() {
+        write!(w, " internal")?;
+    } else if symbol.flags.is_hidden() {
         write!(w, " hidden")?;
     }
+    if symbol.flags.is_protected() {
+        write!(w, " protected")?;
+    }
     // if symbol.flags.is_force_active() {
     //     write!(w, " force_active")?;
     // }
@@ -304,10 +626,49 @@ where W: Write + ?Sized {
     if symbol.flags.is_no_export() {
         write!(w, " noexport")?;
     }
+    if symbol.flags.is_exported() {
+        write!(w, " exported")?;
+    }
+    if symbol.flags.is_allow_any_reloc() {
+        write!(w, " allow_any_reloc")?;
+    }
+    if symbol.flags.is_no_write() {
+        write!(w, " nowrite")?;
+    }
+    if symbol.flags.is_locked() {
+        write!(w, " locked")?;
+    }
+    if let Some(notes) = &symbol.notes {
+        write!(w, " note:{}", escape_notes(notes))?;
+    }
     writeln!(w)?;
     Ok(())
 }
 
+/// Escapes line breaks, so that notes fit on a single line.
+fn escape_notes(notes: &str) -> String {
+    notes.replace('\\', "\\\\").replace('\r', "\\r").replace('\n', "\\n")
+}
+
+fn unescape_notes(notes: &str) -> String {
+    let mut out = String::with_capacity(notes.len());
+    let mut chars = notes.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('r')) => '\r',
+            ('\\', Some('\\')) => '\\',
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        out.push(escaped);
+        chars.next();
+    }
+    out
+}
+
 #[inline]
 fn symbol_kind_to_str(kind: ObjSymbolKind) -> &'static str {
     match kind {
@@ -334,11 +695,15 @@ fn symbol_data_kind_to_str(kind: ObjDataKind) -> Option<&'static str> {
         ObjDataKind::String16Table => Some("wstring_table"),
         ObjDataKind::Int => Some("int"),
         ObjDataKind::Short => Some("short"),
+        ObjDataKind::JumpTable => Some("jumptable"),
+        ObjDataKind::PointerTable => Some("pointertable"),
+        ObjDataKind::PairedSingle => Some("pairedsingle"),
+        ObjDataKind::Quadword => Some("quadword"),
     }
 }
 
 #[inline]
-fn symbol_kind_from_str(s: &str) -> Option<ObjSymbolKind> {
+pub fn symbol_kind_from_str(s: &str) -> Option<ObjSymbolKind> {
     match s {
         "label" => Some(ObjSymbolKind::Unknown),
         "function" => Some(ObjSymbolKind::Function),
@@ -387,6 +752,10 @@ fn symbol_data_kind_from_str(s: &str) -> Option<ObjDataKind> {
         "wstring_table" => Some(ObjDataKind::String16Table),
         "int" => Some(ObjDataKind::Int),
         "short" => Some(ObjDataKind::Short),
+        "jumptable" => Some(ObjDataKind::JumpTable),
+        "pointertable" => Some(ObjDataKind::PointerTable),
+        "pairedsingle" => Some(ObjDataKind::PairedSingle),
+        "quadword" => Some(ObjDataKind::Quadword),
         _ => None,
     }
 }
@@ -430,6 +799,9 @@ where W: Write + ?Sized {
         if section.align > 0 {
             write!(w, " align:{}", section.align)?;
         }
+        if section.verbatim {
+            write!(w, " verbatim")?;
+        }
         writeln!(w)?;
     }
     for unit in obj.link_order.iter().filter(|unit| all || !unit.autogenerated) {
@@ -495,6 +867,8 @@ pub struct SectionDef {
     pub name: String,
     pub kind: Option<ObjSectionKind>,
     pub align: Option<u32>,
+    /// Excluded from analysis and emitted as a single opaque unit.
+    pub verbatim: bool,
 }
 
 enum SplitLine {
@@ -549,7 +923,8 @@ fn parse_unit_line(captures: Captures) -> Result<SplitLine> {
 fn parse_section_line(captures: Captures, state: &SplitState) -> Result<SplitLine> {
     if matches!(state, SplitState::Sections(_)) {
         let name = &captures["name"];
-        let mut section = SectionDef { name: name.to_string(), kind: None, align: None };
+        let mut section =
+            SectionDef { name: name.to_string(), kind: None, align: None, verbatim: false };
 
         for attr in captures["attrs"].split(' ').filter(|&s| !s.is_empty()) {
             if let Some((attr, value)) = attr.split_once(':') {
@@ -566,7 +941,10 @@ fn parse_section_line(captures: Captures, state: &SplitState) -> Result<SplitLin
                     _ => bail!("Unknown section attribute '{attr}'"),
                 }
             } else {
-                bail!("Unknown section attribute '{attr}'");
+                match attr {
+                    "verbatim" => section.verbatim = true,
+                    _ => bail!("Unknown section attribute '{attr}'"),
+                }
             }
         }
 
@@ -661,7 +1039,10 @@ where R: BufRead + ?Sized {
             (SplitState::None | SplitState::Unit(_), SplitLine::SectionsStart) => {
                 state = SplitState::Sections(0);
             }
-            (SplitState::Sections(index), SplitLine::Section(SectionDef { name, kind, align })) => {
+            (
+                SplitState::Sections(index),
+                SplitLine::Section(SectionDef { name, kind, align, verbatim }),
+            ) => {
                 let Some(obj_section) = obj.sections.get_mut(*index) else {
                     bail!(
                         "Section out of bounds: {} (index {}), object has {} sections",
@@ -680,6 +1061,7 @@ where R: BufRead + ?Sized {
                 if let Some(align) = align {
                     obj_section.align = align as u64;
                 }
+                obj_section.verbatim = verbatim;
                 *index += 1;
             }
             (
@@ -898,3 +1280,416 @@ impl serde::Serialize for SectionAddressRef {
         }
     }
 }
+
+/// Marks matching symbols as `RelocationIgnore` when they're loaded, so that relocations
+/// never resolve to them. Every specified field must match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RelocationIgnoreRule {
+    /// Symbol name pattern, where `*` matches any sequence of characters and `?` any single
+    /// character, e.g. `gap_*`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Section name, e.g. `.sbss`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+}
+
+/// Applies `RelocationIgnore` to the symbols matching any of `rules`.
+/// Returns the number of symbols updated.
+pub fn apply_relocation_ignore_rules(
+    obj: &mut ObjInfo,
+    rules: &[RelocationIgnoreRule],
+) -> Result<usize> {
+    let mut matchers = Vec::with_capacity(rules.len());
+    for rule in rules {
+        ensure!(
+            rule.name.is_some() || rule.section.is_some(),
+            "Relocation ignore rule must specify a name or section"
+        );
+        let pattern = rule
+            .name
+            .as_deref()
+            .map(glob::Pattern::new)
+            .transpose()
+            .with_context(|| format!("Invalid symbol name pattern in {:?}", rule))?;
+        matchers.push((pattern, rule.section.as_deref()));
+    }
+    let mut matched = vec![];
+    for (symbol_index, symbol) in obj.symbols.iter() {
+        if symbol.flags.is_relocation_ignore() {
+            continue;
+        }
+        let section_name = symbol.section.map(|index| obj.sections[index].name.as_str());
+        if matchers.iter().any(|(pattern, section)| {
+            pattern.as_ref().map_or(true, |pattern| pattern.matches(&symbol.name))
+                && section.map_or(true, |section| section_name == Some(section))
+        }) {
+            matched.push(symbol_index);
+        }
+    }
+    for &symbol_index in &matched {
+        obj.symbols.set_relocation_ignore(symbol_index, true);
+    }
+    Ok(matched.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        obj::{test_section, test_symbol, ObjArchitecture, ObjRelocKind, ObjSection, ObjSymbols},
+        util::asm::write_asm,
+    };
+
+    #[test]
+    fn test_auto_symbol_naming() {
+        let default = AutoSymbolNaming::default();
+        assert_eq!(default.function_name(0, 0x80003100), "fn_80003100");
+        assert_eq!(default.function_name(1, 0x100), "fn_1_100");
+        assert_eq!(default.data_name(0, ".data", 0x80400000), "lbl_80400000");
+        assert_eq!(default.data_name(1, ".data", 0x100), "lbl_1_data_100");
+        assert_eq!(default.jump_table_name(2, ".rodata", 0x1C), "jumptable_2_rodata_1C");
+        assert!(default.is_auto_name("lbl_80400000_1"));
+        assert!(default.is_auto_name("lbl_8000abcd"));
+        assert!(!default.is_auto_name("sub_80003100"));
+
+        let naming = AutoSymbolNaming {
+            function_prefix: "sub_".to_string(),
+            label_prefix: "loc_".to_string(),
+            data_prefix: "D_".to_string(),
+            jump_table_prefix: "jtbl_".to_string(),
+            address_format: AutoSymbolAddressFormat::Lower,
+        };
+        let names = [
+            naming.function_name(0, 0x8000ABC0),
+            naming.function_name(1, 0xABC0),
+            naming.label_name(0, ".text", 0x8000ABC4),
+            naming.data_name(1, ".data", 0x1F0),
+            naming.jump_table_name(0, ".rodata", 0x803A0000),
+        ];
+        assert_eq!(names, [
+            "sub_8000abc0",
+            "sub_1_abc0",
+            "loc_8000abc4",
+            "D_1_data_1f0",
+            "jtbl_803a0000"
+        ]);
+        for name in &names {
+            assert!(naming.is_auto_name(name), "{name}");
+        }
+        assert!(naming.is_auto_label_name(&names[2]));
+        assert!(naming.is_auto_label_name(&names[3]));
+        assert!(naming.is_auto_jump_table_name(&names[4]));
+        assert!(!naming.is_auto_jump_table_name(&names[2]));
+        // Named symbols and other schemes aren't auto symbols
+        assert!(!naming.is_auto_name("D_Initialize"));
+        assert!(!naming.is_auto_name("fn_8000abc0"));
+        // Either hex case is accepted
+        assert!(naming.is_auto_name("sub_8000ABC0"));
+    }
+
+    fn sort_test_obj() -> ObjInfo {
+        let section = |name: &str, kind, address| ObjSection {
+            virtual_address: Some(address),
+            ..test_section(name, kind, address, 0x10)
+        };
+        let symbol = |name: &str, section, address| {
+            test_symbol(name, section, address, 0, ObjSymbolKind::Object)
+        };
+        // Section order doesn't match address order
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("_SDA_BASE_", None, 0x80010000),
+                symbol("z_func", Some(1), 0x80003100),
+                symbol("b_data", Some(0), 0x80004000),
+                symbol("a_data", Some(0), 0x80004000),
+                symbol("abs_low", None, 0x10),
+                symbol("m_func", Some(1), 0x80003104),
+            ],
+            vec![
+                section(".data", ObjSectionKind::Data, 0x80004000),
+                section(".text", ObjSectionKind::Code, 0x80003100),
+            ],
+        )
+    }
+
+    fn sorted_names(obj: &ObjInfo, sort: SymbolSortMode) -> Vec<String> {
+        let mut out = Vec::new();
+        write_symbols_sorted(&mut out, obj, sort).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split(" = ").next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_write_symbols_sorted() {
+        let mut obj = sort_test_obj();
+        let expected = [
+            (SymbolSortMode::Address, ["b_data", "a_data", "z_func", "m_func"].as_slice()),
+            (
+                SymbolSortMode::Name,
+                ["_SDA_BASE_", "a_data", "abs_low", "b_data", "m_func", "z_func"].as_slice(),
+            ),
+            (
+                SymbolSortMode::Section,
+                ["b_data", "a_data", "z_func", "m_func", "abs_low", "_SDA_BASE_"].as_slice(),
+            ),
+        ];
+        for (sort, names) in expected {
+            assert_eq!(sorted_names(&obj, sort), names, "{sort}");
+            // Output is stable across runs
+            assert_eq!(sorted_names(&obj, sort), sorted_names(&obj, sort), "{sort}");
+        }
+        assert_eq!(sorted_names(&obj, SymbolSortMode::default()), expected[0].1);
+
+        // ABS symbols round-trip
+        let mut out = Vec::new();
+        write_symbols_sorted(&mut out, &obj, SymbolSortMode::Section).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let line = out.lines().last().unwrap();
+        assert_eq!(line, "_SDA_BASE_ = ABS:0x80010000; // type:object");
+        let symbol = parse_symbol_line(line, &mut obj).unwrap().unwrap();
+        assert_eq!(symbol.section, None);
+        assert_eq!(symbol.address, 0x80010000);
+
+        // Undefined externs and common symbols aren't written as ABS symbols
+        let mut obj = sort_test_obj();
+        obj.kind = ObjKind::Relocatable;
+        obj.symbols = ObjSymbols::new(ObjKind::Relocatable, vec![
+            ObjSymbol { name: "extern_func".to_string(), ..Default::default() },
+            ObjSymbol {
+                name: "common_data".to_string(),
+                size: 4,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::Common),
+                ..Default::default()
+            },
+            ObjSymbol {
+                name: "defined".to_string(),
+                address: 0x4,
+                section: Some(0),
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(sorted_names(&obj, SymbolSortMode::Section), ["defined"]);
+
+        assert_eq!("section".parse(), Ok(SymbolSortMode::Section));
+        assert!("size".parse::<SymbolSortMode>().is_err());
+    }
+
+    #[test]
+    fn test_write_symbols_default_order() {
+        let section = |name: &str, kind, address| ObjSection {
+            virtual_address: Some(address),
+            ..test_section(name, kind, address, 0x10)
+        };
+        let symbol = |name: &str, section, address| {
+            test_symbol(name, section, address, 4, ObjSymbolKind::Object)
+        };
+        // Overlapping and zero-address sections, out of address order
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("_SDA_BASE_", None, 0x80010000),
+                symbol("text_b", Some(0), 0x80003108),
+                symbol("text_a", Some(0), 0x80003100),
+                symbol("data", Some(1), 0x80003104),
+                symbol("zero", Some(2), 0),
+                ObjSymbol {
+                    flags: ObjSymbolFlagSet(ObjSymbolFlags::NoWrite.into()),
+                    ..symbol("hidden", Some(1), 0x80003100)
+                },
+                ObjSymbol { kind: ObjSymbolKind::Section, ..symbol(".text", Some(0), 0x80003100) },
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003100),
+                section(".data", ObjSectionKind::Data, 0x80003100),
+                section(".comment", ObjSectionKind::ReadOnlyData, 0),
+            ],
+        );
+        // The writer before sort modes were added
+        let mut baseline = Vec::new();
+        for (_, symbol) in obj.symbols.iter_ordered() {
+            if symbol.kind == ObjSymbolKind::Section || is_skip_symbol(symbol) {
+                continue;
+            }
+            write_symbol(&mut baseline, &obj, symbol).unwrap();
+        }
+        let mut out = Vec::new();
+        write_symbols_sorted(&mut out, &obj, SymbolSortMode::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), String::from_utf8(baseline).unwrap());
+        assert_eq!(sorted_names(&obj, SymbolSortMode::Address), [
+            "text_a", "text_b", "data", "zero"
+        ]);
+    }
+
+    #[test]
+    fn test_write_symbols_stable() {
+        // Hand-ordered, not in address order
+        let existing = "\
+            m_func = .text:0x80003104; // type:object\n\
+            b_data = .data:0x80004000; // type:object\n\
+            z_func = .text:0x80003100; // type:object\n\
+            _SDA_BASE_ = ABS:0x80010000; // type:object\n";
+        let mut obj = sort_test_obj();
+        obj.symbols = ObjSymbols::new(obj.kind, vec![]);
+        apply_symbols(&mut existing.as_bytes(), &mut obj).unwrap();
+        let write = |obj: &ObjInfo| {
+            let mut out = Vec::new();
+            write_symbols_sorted(&mut out, obj, SymbolSortMode::Stable).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(write(&obj), existing);
+
+        // Adding a symbol changes exactly one line, after the symbol preceding it by address
+        obj.add_symbol(
+            ObjSymbol {
+                name: "n_data".to_string(),
+                address: 0x80004008,
+                section: Some(0),
+                kind: ObjSymbolKind::Object,
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap();
+        let out = write(&obj);
+        let mut lines = out.lines().collect_vec();
+        assert_eq!(lines.remove(2), "n_data = .data:0x80004008; // type:object");
+        assert_eq!(lines, existing.lines().collect_vec());
+
+        assert_eq!("stable".parse(), Ok(SymbolSortMode::Stable));
+    }
+
+    #[test]
+    fn test_relocation_ignore_rules() {
+        let mut obj = sort_test_obj();
+        let gap = obj
+            .symbols
+            .add_direct(test_symbol(
+                "gap_00_80004008_data",
+                Some(0),
+                0x80004008,
+                8,
+                ObjSymbolKind::Object,
+            ))
+            .unwrap();
+        let target = SectionAddress::new(0, 0x8000400C);
+        let (index, _) =
+            obj.symbols.for_relocation(target, ObjRelocKind::Absolute, false).unwrap().unwrap();
+        assert_eq!(index, gap);
+
+        let rules = [
+            RelocationIgnoreRule { name: Some("gap_*".to_string()), section: None },
+            // Doesn't match the section
+            RelocationIgnoreRule {
+                name: Some("*_func".to_string()),
+                section: Some(".data".to_string()),
+            },
+        ];
+        assert_eq!(apply_relocation_ignore_rules(&mut obj, &rules).unwrap(), 1);
+        assert!(obj.symbols[gap].flags.is_relocation_ignore());
+        let result = obj.symbols.for_relocation(target, ObjRelocKind::Absolute, false).unwrap();
+        assert!(result.is_none());
+
+        let result = apply_relocation_ignore_rules(&mut obj, &[RelocationIgnoreRule::default()]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_no_write_symbol() {
+        let mut obj = sort_test_obj();
+        let mut flags = ObjSymbolFlagSet::default();
+        flags.set_no_write(true);
+        let anchor = obj
+            .symbols
+            .add_direct(ObjSymbol {
+                name: "internal_anchor".to_string(),
+                address: 0x80004008,
+                section: Some(0),
+                size: 8,
+                size_known: true,
+                kind: ObjSymbolKind::Object,
+                flags,
+                ..Default::default()
+            })
+            .unwrap();
+
+        // Participates in relocation resolution
+        let result = obj
+            .symbols
+            .resolve_relocation(SectionAddress::new(0, 0x8000400C), ObjRelocKind::Absolute, false)
+            .unwrap();
+        assert_eq!(result, Some((anchor, 4)));
+
+        // Absent from the symbols file
+        let mut out = Vec::new();
+        write_symbols_sorted(&mut out, &obj, SymbolSortMode::Address).unwrap();
+        assert!(!String::from_utf8(out).unwrap().contains("internal_anchor"));
+
+        // Kept in internal state, and the flag round-trips
+        let mut out = Vec::new();
+        write_all_symbols(&mut out, &obj).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let line = out.lines().find(|line| line.starts_with("internal_anchor")).unwrap();
+        let symbol = parse_symbol_line(line, &mut obj).unwrap().unwrap();
+        assert!(symbol.flags.is_no_write());
+    }
+
+    #[test]
+    fn test_exported_symbol() {
+        let mut obj = sort_test_obj();
+        obj.symbols.flags(2).set_exported(true);
+        let mut out = Vec::new();
+        write_symbol(&mut out, &obj, &obj.symbols[2]).unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert!(line.contains(" exported"));
+        let symbol = parse_symbol_line(line.trim_end(), &mut obj).unwrap().unwrap();
+        assert!(symbol.flags.is_exported());
+    }
+
+    #[test]
+    fn test_symbol_notes() {
+        let notes = "matched but not equivalent\nverify stack size";
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![ObjSymbol {
+                name: "sCount".to_string(),
+                address: 0x80004000,
+                section: Some(0),
+                size: 4,
+                size_known: true,
+                kind: ObjSymbolKind::Object,
+                notes: Some(notes.to_string()),
+                ..Default::default()
+            }],
+            vec![test_section(".data", ObjSectionKind::Data, 0x80004000, 4)],
+        );
+
+        let mut out = Vec::new();
+        write_symbol(&mut out, &obj, &obj.symbols[0]).unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert_eq!(
+            line.trim_end(),
+            "sCount = .data:0x80004000; // type:object size:0x4 \
+             note:matched but not equivalent\\nverify stack size"
+        );
+        let symbol = parse_symbol_line(line.trim_end(), &mut obj).unwrap().unwrap();
+        assert_eq!(symbol.notes.as_deref(), Some(notes));
+        assert_eq!(symbol.size, 4);
+
+        let mut asm = Vec::new();
+        write_asm(&mut asm, &obj).unwrap();
+        let asm = String::from_utf8(asm).unwrap();
+        assert!(asm.contains("# Note: matched but not equivalent\n# Note: verify stack size\n"));
+    }
+}
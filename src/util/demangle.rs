@@ -0,0 +1,422 @@
+use std::ops::Range;
+
+use cwdemangle::DemangleOptions;
+
+/// Demangles a CodeWarrior C++ symbol.
+///
+/// Names that [`cwdemangle::demangle`] can't handle, such as EDG-style template parameter lists
+/// (`__tm__`), fall back to [`demangle_partial`].
+pub fn demangle(name: &str, options: &DemangleOptions) -> Option<String> {
+    if !name.contains("__tm__") {
+        if let Some(demangled) = cwdemangle::demangle(name, options) {
+            return Some(demangled);
+        }
+    }
+    demangle_partial(name, options)
+}
+
+/// Best-effort demangling of a CodeWarrior C++ symbol.
+///
+/// Parts of the name that can't be decoded are kept as-is, and parameter lists that can't be
+/// decoded are written as `(?)`. Returns `None` if the name doesn't look mangled.
+pub fn demangle_partial(name: &str, options: &DemangleOptions) -> Option<String> {
+    let Some((base, rest)) = split_base(name) else {
+        // Unqualified template instantiation, e.g. `Foo__tm__2_i`
+        return name.contains("__tm__").then(|| expand_name(name, options));
+    };
+    let mut parser = Parser { s: rest, options };
+    let class_parts = match parser.peek() {
+        Some('Q' | '0'..='9') => parser.qualified_name_parts(),
+        _ => None,
+    };
+    let class = class_parts.as_ref().map(|parts| parts.join("::"));
+    let class_name = class_parts
+        .as_ref()
+        .and_then(|parts| parts.last())
+        .map(|last| last.split('<').next().unwrap_or(last));
+
+    let mut out = match class.as_deref() {
+        Some(class) => format!("{}::", class),
+        None if parser.s.len() < rest.len() => return None,
+        None => String::new(),
+    };
+    match base {
+        "__ct" => out.push_str(class_name?),
+        "__dt" => {
+            out.push('~');
+            out.push_str(class_name?);
+        }
+        _ => {
+            if let Some(op) = operator_name(base) {
+                out.push_str("operator");
+                out.push_str(op);
+            } else if let Some(ty) = base.strip_prefix("__op") {
+                let mut ty_parser = Parser { s: ty, options };
+                out.push_str("operator ");
+                match ty_parser.ty() {
+                    Some(ty) if ty_parser.s.is_empty() => out.push_str(&ty),
+                    _ => out.push_str(ty),
+                }
+            } else {
+                out.push_str(&expand_name(base, options));
+            }
+        }
+    }
+
+    if parser.s.is_empty() {
+        return class.is_some().then_some(out);
+    }
+    let is_const = parser.s.starts_with("CF");
+    if is_const {
+        parser.s = &parser.s[1..];
+    }
+    if parser.eat('F') {
+        match parser.params() {
+            Some(params) if parser.s.is_empty() => {
+                out.push('(');
+                out.push_str(&params);
+                out.push(')');
+            }
+            _ => out.push_str("(?)"),
+        }
+        if is_const {
+            out.push_str(" const");
+        }
+        Some(out)
+    } else if class.is_some() {
+        // Trailing data we don't understand
+        Some(out)
+    } else {
+        None
+    }
+}
+
+/// Splits a mangled name into its base name and the remaining qualifiers and parameters,
+/// skipping over template parameter lists.
+fn split_base(name: &str) -> Option<(&str, &str)> {
+    let bytes = name.as_bytes();
+    // Operators and special functions start with `__`
+    let mut i = if name.starts_with("__") { 2 } else { 1 };
+    let mut depth = 0usize;
+    while i + 1 < bytes.len() {
+        match bytes[i] {
+            b'<' => depth += 1,
+            b'>' => depth = depth.saturating_sub(1),
+            b'_' if depth == 0 && bytes[i + 1] == b'_' => {
+                if name[i..].starts_with("__tm__") {
+                    i = template_range(name, i)?.end;
+                    continue;
+                }
+                let rest = &name[i + 2..];
+                if matches!(rest.as_bytes().first(), Some(b'Q' | b'F' | b'0'..=b'9'))
+                    || rest.starts_with("CF")
+                {
+                    return Some((&name[..i], rest));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Given the position of a `__tm__` marker, returns the range of its parameter list.
+/// The encoded length includes the `_` separator preceding the parameters.
+fn template_range(name: &str, pos: usize) -> Option<Range<usize>> {
+    let start = pos + 6;
+    let digits = name[start..].bytes().take_while(u8::is_ascii_digit).count();
+    let len: usize = name[start..start + digits].parse().ok()?;
+    let sep = start + digits;
+    (len > 0 && name.as_bytes().get(sep) == Some(&b'_') && sep + len <= name.len())
+        .then_some(sep + 1..sep + len)
+}
+
+/// Expands template parameter lists in an identifier, both EDG-style `__tm__` lists and
+/// literal `<...>` lists containing mangled types.
+fn expand_name(name: &str, options: &DemangleOptions) -> String {
+    if let Some(pos) = name.find("__tm__") {
+        let Some(range) = template_range(name, pos) else {
+            return name.to_string();
+        };
+        let end = range.end;
+        let mut parser = Parser { s: &name[range], options };
+        let mut list = vec![];
+        while !parser.s.is_empty() {
+            match parser.ty() {
+                Some(ty) => list.push(ty),
+                None => {
+                    list.push(parser.s.to_string());
+                    break;
+                }
+            }
+        }
+        return format!(
+            "{}<{}>{}",
+            expand_name(&name[..pos], options),
+            list.join(", "),
+            expand_name(&name[end..], options)
+        );
+    }
+
+    let (Some(open), Some(close)) = (name.find('<'), name.rfind('>')) else {
+        return name.to_string();
+    };
+    if close < open {
+        return name.to_string();
+    }
+    let args = split_template_args(&name[open + 1..close])
+        .into_iter()
+        .map(|arg| {
+            let mut parser = Parser { s: arg, options };
+            match parser.ty() {
+                Some(ty) if parser.s.is_empty() => ty,
+                _ => arg.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("{}<{}>{}", &name[..open], args.join(", "), &name[close + 1..])
+}
+
+/// Splits a template argument list on top-level commas.
+fn split_template_args(args: &str) -> Vec<&str> {
+    let mut result = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                result.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(&args[start..]);
+    result
+}
+
+fn operator_name(base: &str) -> Option<&'static str> {
+    Some(match base.strip_prefix("__")? {
+        "nw" => " new",
+        "nwa" => " new[]",
+        "dl" => " delete",
+        "dla" => " delete[]",
+        "pl" => "+",
+        "mi" => "-",
+        "ml" => "*",
+        "dv" => "/",
+        "md" => "%",
+        "er" => "^",
+        "ad" => "&",
+        "or" => "|",
+        "co" => "~",
+        "nt" => "!",
+        "as" => "=",
+        "lt" => "<",
+        "gt" => ">",
+        "apl" => "+=",
+        "ami" => "-=",
+        "amu" => "*=",
+        "adv" => "/=",
+        "amd" => "%=",
+        "aer" => "^=",
+        "aad" => "&=",
+        "aor" => "|=",
+        "ls" => "<<",
+        "rs" => ">>",
+        "als" => "<<=",
+        "ars" => ">>=",
+        "eq" => "==",
+        "ne" => "!=",
+        "le" => "<=",
+        "ge" => ">=",
+        "aa" => "&&",
+        "oo" => "||",
+        "pp" => "++",
+        "mm" => "--",
+        "cm" => ",",
+        "rm" => "->*",
+        "rf" => "->",
+        "cl" => "()",
+        "vc" => "[]",
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    options: &'a DemangleOptions,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> { self.s.chars().next() }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.s = &self.s[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let digits = self.s.bytes().take_while(u8::is_ascii_digit).count();
+        let value = self.s[..digits].parse().ok()?;
+        self.s = &self.s[digits..];
+        Some(value)
+    }
+
+    fn name(&mut self) -> Option<String> {
+        let len = self.number()?;
+        let name = self.s.get(..len)?;
+        self.s = &self.s[len..];
+        Some(expand_name(name, self.options))
+    }
+
+    fn qualified_name_parts(&mut self) -> Option<Vec<String>> {
+        if self.eat('Q') {
+            let count = self.peek()?.to_digit(10)? as usize;
+            self.s = &self.s[1..];
+            (0..count).map(|_| self.name()).collect()
+        } else {
+            Some(vec![self.name()?])
+        }
+    }
+
+    fn qualified_name(&mut self) -> Option<String> { Some(self.qualified_name_parts()?.join("::")) }
+
+    /// Parses a parameter list, up to the end of input or a return type separator.
+    fn params(&mut self) -> Option<String> {
+        let mut params = vec![];
+        while !self.s.is_empty() && !self.s.starts_with('_') {
+            params.push(self.ty()?);
+        }
+        if self.options.omit_empty_parameters && params.len() == 1 && params[0] == "void" {
+            params.clear();
+        }
+        Some(params.join(", "))
+    }
+
+    /// Parses a function type following `F`, returning the return type and parameters.
+    fn function(&mut self) -> Option<(String, String)> {
+        let params = self.params()?;
+        let ret = if self.eat('_') { self.ty()? } else { "void".to_string() };
+        Some((ret, params))
+    }
+
+    fn ty(&mut self) -> Option<String> {
+        let mut cv = vec![];
+        let mut sign = None;
+        loop {
+            match self.peek()? {
+                'C' => cv.push("const"),
+                'V' => cv.push("volatile"),
+                'U' => sign = Some("unsigned "),
+                'S' => sign = Some("signed "),
+                _ => break,
+            }
+            self.s = &self.s[1..];
+        }
+        let c = self.peek()?;
+        let (ty, indirect) = match c {
+            'P' | 'R' => {
+                self.s = &self.s[1..];
+                let ptr = if c == 'P' { "*" } else { "&" };
+                if self.eat('F') {
+                    let (ret, params) = self.function()?;
+                    (format!("{} ({})({})", ret, ptr, params), true)
+                } else {
+                    (format!("{}{}", self.ty()?, ptr), true)
+                }
+            }
+            'A' => {
+                self.s = &self.s[1..];
+                let len = self.number()?;
+                if !self.eat('_') {
+                    return None;
+                }
+                (format!("{}[{}]", self.ty()?, len), false)
+            }
+            'M' => {
+                self.s = &self.s[1..];
+                let class = self.qualified_name()?;
+                (format!("{} {}::*", self.ty()?, class), true)
+            }
+            'F' => {
+                self.s = &self.s[1..];
+                let (ret, params) = self.function()?;
+                (format!("{} ({})", ret, params), false)
+            }
+            'Q' | '0'..='9' => (self.qualified_name()?, false),
+            _ => {
+                let basic = match c {
+                    'v' => "void",
+                    'b' => "bool",
+                    'c' => "char",
+                    's' => "short",
+                    'i' => "int",
+                    'l' => "long",
+                    'x' => "long long",
+                    'f' => "float",
+                    'd' => "double",
+                    'r' => "long double",
+                    'w' => "wchar_t",
+                    'e' => "...",
+                    _ => return None,
+                };
+                self.s = &self.s[1..];
+                (format!("{}{}", sign.unwrap_or_default(), basic), false)
+            }
+        };
+        Some(match (cv.is_empty(), indirect) {
+            (true, _) => ty,
+            (false, true) => format!("{} {}", ty, cv.join(" ")),
+            (false, false) => format!("{} {}", cv.join(" "), ty),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demangle_partial() {
+        let options = DemangleOptions { omit_empty_parameters: true, mw_extensions: false };
+        for (mangled, expected) in [
+            ("__pl__FRC3FooRC3Foo", "operator+(const Foo&, const Foo&)"),
+            (
+                "__as__Q27JGadget5TNodeFRCQ27JGadget5TNode",
+                "JGadget::TNode::operator=(const JGadget::TNode&)",
+            ),
+            ("__vc__11TArray<i,8>CFUl", "TArray<int, 8>::operator[](unsigned long) const"),
+            ("__cl__9JSUFilterFPCci", "JSUFilter::operator()(const char*, int)"),
+            ("__nwa__FUlPv", "operator new[](unsigned long, void*)"),
+            ("__dla__FPv", "operator delete[](void*)"),
+            ("__amu__7Vector3Ff", "Vector3::operator*=(float)"),
+            ("__rf__15TSmartPtr<3Foo>CFv", "TSmartPtr<Foo>::operator->() const"),
+            ("__opb__Q23foo3BarCFv", "foo::Bar::operator bool() const"),
+            ("__ct__29TList<Q23std14vector<3Foo,i>>Fv", "TList<std::vector<Foo, int>>::TList()"),
+            ("__dt__14TBox<PC5Actor>Fv", "TBox<const Actor*>::~TBox()"),
+            ("get__tm__2_i__5StackFv", "Stack::get<int>()"),
+            (
+                "push__tm__7_PC3Foo__11Stack<Ul,8>FRCPC3Foo",
+                "Stack<unsigned long, 8>::push<const Foo*>(const Foo* const&)",
+            ),
+            ("Vec__tm__5_3Foo", "Vec<Foo>"),
+            ("sInstance__7Manager", "Manager::sInstance"),
+            ("registerCallback__FPFi_vPv", "registerCallback(void (*)(int), void*)"),
+            // Unparseable parameters are replaced with a placeholder
+            ("__dt__3FooFQ23Baz", "Foo::~Foo(?)"),
+        ] {
+            assert_eq!(demangle_partial(mangled, &options).as_deref(), Some(expected), "{mangled}");
+        }
+        for name in ["__start", "my__var", "lbl_80001234", "foo__"] {
+            assert_eq!(demangle_partial(name, &options), None, "{name}");
+        }
+    }
+}
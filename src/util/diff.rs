@@ -260,7 +260,7 @@ fn print_line(ins_diff: &ObjInsDiff, base_addr: u64) -> Vec<Span> {
 fn to_objdiff_reloc(obj: &ObjInfo, address: u32, reloc: &ObjReloc) -> objdiff_core::obj::ObjReloc {
     let target_symbol = &obj.symbols[reloc.target_symbol];
     let target_section = target_symbol.section.map(|i| &obj.sections[i]);
-    let (r_offset, r_type) = reloc.to_elf(address);
+    let (r_offset, r_type) = reloc.to_elf(address, obj.endian);
     objdiff_core::obj::ObjReloc {
         flags: RelocationFlags::Elf { r_type },
         address: r_offset,
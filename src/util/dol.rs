@@ -1,17 +1,20 @@
 use std::{
     collections::BTreeMap,
     io,
-    io::{Cursor, Read, Seek},
+    io::{Cursor, Read, Seek, SeekFrom},
 };
 
 use anyhow::{anyhow, bail, ensure, Result};
 
 use crate::{
-    analysis::cfa::{locate_bss_memsets, locate_sda_bases, SectionAddress},
+    analysis::{
+        cfa::{locate_bss_memsets, locate_sda_bases, SectionAddress},
+        extab::create_extab_symbols,
+    },
     array_ref,
     obj::{
-        ObjArchitecture, ObjInfo, ObjKind, ObjSection, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet,
-        ObjSymbolFlags, ObjSymbolKind, SectionIndex,
+        check_section_overlap, ObjArchitecture, ObjInfo, ObjKind, ObjSection, ObjSectionKind,
+        ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, SectionIndex,
     },
     util::{
         alf::{AlfFile, AlfSymbol, ALF_MAGIC},
@@ -131,6 +134,74 @@ impl FromReader for DolFile {
     }
 }
 
+/// Reads a DOL header up front and section data on demand, so that the whole file never
+/// has to be loaded into memory.
+pub struct DolReader<R> {
+    reader: R,
+    pub file: DolFile,
+}
+
+impl<R> DolReader<R>
+where R: Read + Seek
+{
+    pub fn new(mut reader: R) -> Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let file = DolFile::from_reader(&mut reader, Endian::Big)?;
+        // Text and data sections may share file ranges, which is fine since each section is
+        // read independently. BSS has no file backing.
+        for section in file.sections.iter().filter(|s| s.kind != DolSectionKind::Bss) {
+            let end = section.file_offset as u64 + section.data_size as u64;
+            ensure!(
+                end <= len,
+                "DOL section {} @ {:#010X} (file {:#X}-{:#X}) extends past end of file ({:#X})",
+                section.index,
+                section.address,
+                section.file_offset,
+                end,
+                len
+            );
+        }
+        Ok(Self { reader, file })
+    }
+
+    /// Reads the file-backed data of a section. BSS sections have none.
+    pub fn section_data(&mut self, index: SectionIndex) -> Result<Vec<u8>> {
+        let section = self
+            .file
+            .sections
+            .get(index as usize)
+            .ok_or_else(|| anyhow!("DOL section {} out of bounds", index))?;
+        let mut data = vec![0; section.data_size as usize];
+        if !data.is_empty() {
+            self.reader.seek(SeekFrom::Start(section.file_offset as u64))?;
+            self.reader.read_exact(&mut data)?;
+        }
+        Ok(data)
+    }
+
+    /// Reads `size` bytes at virtual address `addr`, which must lie within a single section.
+    pub fn virtual_data_at(&mut self, addr: u32, size: u32) -> Result<Vec<u8>> {
+        let section = self
+            .file
+            .section_by_address(addr)
+            .ok_or_else(|| anyhow!("Failed to locate section for address {:#010X}", addr))?;
+        let offset = addr - section.address;
+        ensure!(
+            offset + size <= section.data_size,
+            "Invalid virtual data range {:#010X}-{:#010X} (section data: {:#010X}-{:#010X})",
+            addr,
+            addr + size,
+            section.address,
+            section.address + section.data_size
+        );
+        let mut data = vec![0; size as usize];
+        self.reader.seek(SeekFrom::Start((section.file_offset + offset) as u64))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DolHeader {
     pub text_offs: [u32; MAX_TEXT_SECTIONS],
@@ -184,7 +255,7 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
     let dol: Box<dyn DolLike> = if buf.len() > 4 && *array_ref!(buf, 0, 4) == ALF_MAGIC {
         Box::new(AlfFile::from_reader(&mut reader, Endian::Little)?)
     } else {
-        Box::new(DolFile::from_reader(&mut reader, Endian::Big)?)
+        Box::new(DolReader::new(reader)?.file)
     };
 
     // Locate _rom_copy_info
@@ -284,7 +355,6 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
     // Locate _eti_init_info
     let num_text_sections =
         dol.sections().iter().filter(|section| section.kind == DolSectionKind::Text).count();
-    let mut eti_entries: Vec<EtiEntry> = Vec::new();
     let mut eti_init_info_range: Option<(u32, u32)> = None;
     let mut extab_section: Option<SectionIndex> = None;
     let mut extabindex_section: Option<SectionIndex> = None;
@@ -343,14 +413,10 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
                     bail!("Invalid _eti_init_info entry: {:#010X?}", eti_init_info);
                 }
                 for addr in (eti_init_info.eti_start..eti_init_info.eti_end).step_by(12) {
-                    let eti_entry = read_eti_entry(buf, dol.as_ref(), addr)?;
-                    let entry_section =
-                        dol.section_by_address(eti_entry.extab_addr).ok_or_else(|| {
-                            anyhow!(
-                                "Failed to locate section for extab address {:#010X}",
-                                eti_entry.extab_addr
-                            )
-                        })?;
+                    let extab_addr = read_u32(buf, dol.as_ref(), addr + 8)?;
+                    let entry_section = dol.section_by_address(extab_addr).ok_or_else(|| {
+                        anyhow!("Failed to locate section for extab address {:#010X}", extab_addr)
+                    })?;
                     if let Some(extab_section) = extab_section {
                         ensure!(
                             entry_section.index == extab_section,
@@ -361,7 +427,6 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
                     } else {
                         extab_section = Some(entry_section.index);
                     }
-                    eti_entries.push(eti_entry);
                 }
             }
             log::debug!("Found _eti_init_info end @ {addr:#010X}");
@@ -434,6 +499,8 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
             virtual_address: Some(dol_section.address as u64),
             file_offset: dol_section.file_offset as u64,
             section_known: known,
+            thread_local: false,
+            verbatim: false,
             splits: Default::default(),
         });
     }
@@ -464,6 +531,8 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
                 virtual_address: Some(addr as u64),
                 file_offset: 0,
                 section_known: false,
+                thread_local: false,
+                verbatim: false,
                 splits: Default::default(),
             });
         }
@@ -484,6 +553,8 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
                 virtual_address: Some(bss_section.address as u64),
                 file_offset: 0,
                 section_known: false,
+                thread_local: false,
+                verbatim: false,
                 splits: Default::default(),
             });
             let mut obj = ObjInfo::new(
@@ -511,6 +582,8 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
                         virtual_address: Some(bss_sections[0].0 as u64),
                         file_offset: 0,
                         section_known: false,
+                        thread_local: false,
+                        verbatim: false,
                         splits: Default::default(),
                     });
                     sections.push(ObjSection {
@@ -525,6 +598,8 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
                         virtual_address: Some(bss_sections[1].0 as u64),
                         file_offset: 0,
                         section_known: false,
+                        thread_local: false,
+                        verbatim: false,
                         splits: Default::default(),
                     });
                 }
@@ -557,6 +632,8 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
         section.elf_index = idx + 1;
     }
 
+    check_section_overlap(&sections)?;
+
     // Guess section alignment
     let mut last_section_end = sections.first().map_or(0, |s| s.address as u32);
     for section in &mut sections {
@@ -643,75 +720,8 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
     }
 
     // Generate symbols for extab & extabindex entries
-    if let (Some(extabindex_section_index), Some(extab_section_index)) =
-        (extabindex_section, extab_section)
-    {
-        let extab_section = &obj.sections[extab_section_index];
-        let extab_section_address = extab_section.address;
-        let extab_section_size = extab_section.size;
-
-        for entry in &eti_entries {
-            // Add functions from extabindex entries as known function bounds
-            let (section_index, _) = obj.sections.at_address(entry.function).map_err(|_| {
-                anyhow!(
-                    "Failed to locate section for function {:#010X} (referenced from extabindex entry {:#010X})",
-                    entry.function,
-                    entry.address,
-                )
-            })?;
-            let addr = SectionAddress::new(section_index, entry.function);
-            if let Some(Some(old_value)) =
-                obj.known_functions.insert(addr, Some(entry.function_size))
-            {
-                if old_value != entry.function_size {
-                    log::warn!(
-                        "Conflicting sizes for {:#010X}: {:#X} != {:#X}",
-                        entry.function,
-                        entry.function_size,
-                        old_value
-                    );
-                }
-            }
-            obj.add_symbol(
-                ObjSymbol {
-                    name: format!("@eti_{:08X}", entry.address),
-                    address: entry.address as u64,
-                    section: Some(extabindex_section_index),
-                    size: 12,
-                    size_known: true,
-                    flags: ObjSymbolFlagSet(ObjSymbolFlags::Local | ObjSymbolFlags::Hidden),
-                    kind: ObjSymbolKind::Object,
-                    ..Default::default()
-                },
-                false,
-            )?;
-        }
-
-        let mut entry_iter = eti_entries.iter().peekable();
-        loop {
-            let (addr, size) = match (entry_iter.next(), entry_iter.peek()) {
-                (Some(a), Some(&b)) => (a.extab_addr, b.extab_addr - a.extab_addr),
-                (Some(a), None) => (
-                    a.extab_addr,
-                    (extab_section_address + extab_section_size) as u32 - a.extab_addr,
-                ),
-                _ => break,
-            };
-            obj.add_symbol(
-                ObjSymbol {
-                    name: format!("@etb_{:08X}", addr),
-                    address: addr as u64,
-                    section: Some(extab_section_index),
-                    size: size as u64,
-                    size_known: true,
-                    flags: ObjSymbolFlagSet(ObjSymbolFlags::Local | ObjSymbolFlags::Hidden),
-                    kind: ObjSymbolKind::Object,
-                    ..Default::default()
-                },
-                false,
-            )?;
-        }
-    }
+    let count = create_extab_symbols(&mut obj)?;
+    log::debug!("Created symbols for {} extabindex entries", count);
 
     // Add .ctors and .dtors functions to known functions if they exist
     for (_, section) in obj.sections.iter() {
@@ -776,6 +786,13 @@ pub fn process_dol(buf: &[u8], name: &str) -> Result<ObjInfo> {
             log::warn!("Failed to locate SDA bases: {:?}", e);
         }
     }
+    if obj.sda_base.is_none() || obj.sda2_base.is_none() {
+        // Fall back to the small data section layout
+        let count = obj.synthesize_sda_bases()?;
+        if count > 0 {
+            log::info!("Synthesized {} SDA base symbols from section layout", count);
+        }
+    }
 
     // Apply ALF symbols
     for symbol in dol.symbols() {
@@ -800,12 +817,27 @@ impl EtiInitInfo {
     }
 }
 
-#[derive(Debug, Clone)]
-struct EtiEntry {
-    address: u32,
-    function: u32,
-    function_size: u32,
-    extab_addr: u32,
+/// Ensure that the object's sections fit in a DOL header.
+pub fn check_dol_section_counts(obj: &ObjInfo) -> Result<()> {
+    let text_count = obj.sections.iter().filter(|(_, s)| s.kind == ObjSectionKind::Code).count();
+    ensure!(
+        text_count <= MAX_TEXT_SECTIONS,
+        "Too many text sections: {} (max {})",
+        text_count,
+        MAX_TEXT_SECTIONS
+    );
+    let data_count = obj
+        .sections
+        .iter()
+        .filter(|(_, s)| matches!(s.kind, ObjSectionKind::Data | ObjSectionKind::ReadOnlyData))
+        .count();
+    ensure!(
+        data_count <= MAX_DATA_SECTIONS,
+        "Too many data sections: {} (max {})",
+        data_count,
+        MAX_DATA_SECTIONS
+    );
+    Ok(())
 }
 
 fn read_eti_init_info(buf: &[u8], dol: &dyn DolLike, addr: u32) -> Result<EtiInitInfo> {
@@ -816,13 +848,6 @@ fn read_eti_init_info(buf: &[u8], dol: &dyn DolLike, addr: u32) -> Result<EtiIni
     Ok(EtiInitInfo { eti_start, eti_end, code_start, code_size })
 }
 
-fn read_eti_entry(buf: &[u8], dol: &dyn DolLike, address: u32) -> Result<EtiEntry> {
-    let function = read_u32(buf, dol, address)?;
-    let function_size = read_u32(buf, dol, address + 4)?;
-    let extab_addr = read_u32(buf, dol, address + 8)?;
-    Ok(EtiEntry { address, function, function_size, extab_addr })
-}
-
 fn validate_eti_init_info(
     dol: &dyn DolLike,
     eti_init_info: &EtiInitInfo,
@@ -847,3 +872,112 @@ fn validate_eti_init_info(
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::test_section;
+
+    /// Counts the bytes read through it.
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.bytes_read += n;
+            Ok(n)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> { self.inner.seek(pos) }
+    }
+
+    fn dol_header(header: &mut [u8], field: usize, index: usize, value: u32) {
+        // Fields in header order: text_offs, data_offs, text_addrs, data_addrs, text_sizes,
+        // data_sizes, followed by bss_addr, bss_size and entry_point
+        const FIELD_OFFSETS: [usize; 6] = [0x0, 0x1C, 0x48, 0x64, 0x90, 0xAC];
+        let offset = FIELD_OFFSETS[field] + index * 4;
+        header[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    #[test]
+    fn test_dol_reader() {
+        // .init @ 0x100, .text @ 0x120, and a data section sharing .text's file range
+        let mut data = vec![0u8; 0x100];
+        dol_header(&mut data, 0, 0, 0x100);
+        dol_header(&mut data, 2, 0, 0x80003100);
+        dol_header(&mut data, 4, 0, 0x20);
+        dol_header(&mut data, 0, 1, 0x120);
+        dol_header(&mut data, 2, 1, 0x80003120);
+        dol_header(&mut data, 4, 1, 0x40);
+        dol_header(&mut data, 1, 0, 0x120);
+        dol_header(&mut data, 3, 0, 0x80005000);
+        dol_header(&mut data, 5, 0, 0x10);
+        data[0xD8..0xDC].copy_from_slice(&0x80006000u32.to_be_bytes()); // bss_addr
+        data[0xDC..0xE0].copy_from_slice(&0x1000u32.to_be_bytes()); // bss_size
+        data[0xE0..0xE4].copy_from_slice(&0x80003100u32.to_be_bytes()); // entry_point
+        data.extend((0..0x60).map(|i| i as u8));
+
+        let mut reader =
+            DolReader::new(CountingReader { inner: Cursor::new(&data), bytes_read: 0 }).unwrap();
+        assert_eq!(reader.file.sections.len(), 4);
+        let header_read = reader.reader.bytes_read;
+        assert!(header_read <= 0x100);
+
+        // Only the requested section is read
+        let text = reader.section_data(1).unwrap();
+        assert_eq!(text, (0x20..0x60).collect::<Vec<u8>>());
+        assert_eq!(reader.reader.bytes_read, header_read + 0x40);
+        let shared = reader.section_data(2).unwrap();
+        assert_eq!(shared, text[..0x10]);
+        assert_eq!(reader.virtual_data_at(0x80003124, 4).unwrap(), [0x24u8, 0x25, 0x26, 0x27]);
+
+        // BSS isn't backed by the file
+        assert_eq!(reader.file.sections[3].kind, DolSectionKind::Bss);
+        assert!(reader.section_data(3).unwrap().is_empty());
+        assert!(reader.virtual_data_at(0x80006000, 4).is_err());
+
+        // Sections past the end of the file are rejected up front
+        data.truncate(0x140);
+        assert!(DolReader::new(Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn test_process_dol_overlap() {
+        // .init @ 0x80003100-0x80003300 overlaps .text @ 0x80003200-0x80003300
+        let mut data = vec![0u8; 0x400];
+        dol_header(&mut data, 0, 0, 0x100);
+        dol_header(&mut data, 2, 0, 0x80003100);
+        dol_header(&mut data, 4, 0, 0x200);
+        dol_header(&mut data, 0, 1, 0x300);
+        dol_header(&mut data, 2, 1, 0x80003200);
+        dol_header(&mut data, 4, 1, 0x100);
+        data[0xD8..0xDC].copy_from_slice(&0x80006000u32.to_be_bytes()); // bss_addr
+        data[0xDC..0xE0].copy_from_slice(&0x100u32.to_be_bytes()); // bss_size
+        data[0xE0..0xE4].copy_from_slice(&0x80003100u32.to_be_bytes()); // entry_point
+        data[0x100..0x104].copy_from_slice(&0x4E800020u32.to_be_bytes()); // blr
+
+        let err = process_dol(&data, "test").unwrap_err();
+        assert!(err.to_string().contains("overlaps section '.text'"), "{err}");
+    }
+
+    #[test]
+    fn test_check_dol_section_counts() {
+        let text_sections = |count: u64| {
+            (0..count)
+                .map(|i| {
+                    test_section(&format!(".text{i}"), ObjSectionKind::Code, 0x80003100 + i * 4, 4)
+                })
+                .collect::<Vec<_>>()
+        };
+        let obj = |sections| {
+            ObjInfo::new(ObjKind::Executable, ObjArchitecture::PowerPc, "".into(), vec![], sections)
+        };
+        check_dol_section_counts(&obj(text_sections(7))).unwrap();
+        assert!(check_dol_section_counts(&obj(text_sections(8))).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     cmp::max,
-    collections::BTreeMap,
+    collections::{btree_map, BTreeMap, BTreeSet},
     fmt::{Display, Formatter, Write},
     io::{BufRead, Cursor, Seek, SeekFrom},
     num::NonZeroU32,
@@ -1810,6 +1810,84 @@ pub fn process_address(block: &[u8], e: Endian) -> Result<u32> {
     }
 }
 
+/// Source location of a function or variable.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceLocation {
+    /// Name of the containing compile unit. DWARF 1.1 has no `DW_AT_decl_file`, so this is the
+    /// closest available source file.
+    pub file: String,
+    /// Line of the function's first `.line` entry. Not available for variables.
+    pub line: Option<u32>,
+}
+
+/// Reads a `.line` section: for each compile unit, a header (size, base address) followed by
+/// entries of (line, position in line, address offset). Returns the first line at each address.
+pub fn read_line_section(data: &[u8], e: Endian) -> Result<BTreeMap<u32, u32>> {
+    let mut reader = Cursor::new(data);
+    let mut lines = BTreeMap::new();
+    while reader.position() < data.len() as u64 {
+        let position = reader.position();
+        let size = u32::from_reader(&mut reader, e)?;
+        ensure!(size >= 8, "Invalid .line size {:#X} at {:#X}", size, position);
+        let base_address = u32::from_reader(&mut reader, e)?;
+        while reader.position() + 10 <= position + size as u64 {
+            let line = u32::from_reader(&mut reader, e)?;
+            let _position_in_line = u16::from_reader(&mut reader, e)?;
+            let offset = u32::from_reader(&mut reader, e)?;
+            if line != 0 {
+                lines.entry(base_address.wrapping_add(offset)).or_insert(line);
+            }
+        }
+        reader.set_position(position + size as u64);
+    }
+    Ok(lines)
+}
+
+/// Collects the source locations of functions and variables, keyed by address.
+/// Inline functions and erased tags are skipped.
+pub fn source_locations(
+    info: &DwarfInfo,
+    lines: &BTreeMap<u32, u32>,
+) -> Result<BTreeMap<u32, SourceLocation>> {
+    let mut locations = BTreeMap::new();
+    for tag in info.tags.values() {
+        if tag.kind != TagKind::CompileUnit || tag.is_erased {
+            continue;
+        }
+        let unit = process_compile_unit(tag)?;
+        for child in tag.children(&info.tags) {
+            if child.is_erased {
+                continue;
+            }
+            let (address, line) = match child.kind {
+                TagKind::GlobalSubroutine | TagKind::Subroutine => {
+                    if child.attribute(AttributeKind::Inline).is_some() {
+                        continue;
+                    }
+                    let Some(address) = child.address_attribute(AttributeKind::LowPc) else {
+                        continue;
+                    };
+                    (address, lines.get(&address).cloned())
+                }
+                TagKind::GlobalVariable | TagKind::LocalVariable => {
+                    let Some(address) = child
+                        .block_attribute(AttributeKind::Location)
+                        .and_then(|block| process_address(block, info.e).ok())
+                    else {
+                        continue;
+                    };
+                    (address, None)
+                }
+                _ => continue,
+            };
+            locations
+                .entry(address)
+                .or_insert_with(|| SourceLocation { file: unit.name.clone(), line });
+        }
+    }
+    Ok(locations)
+}
+
 pub const REGISTER_NAMES: [&str; 109] = [
     "r0", "r1", "r2", "r3", "r4", "r5", "r6", "r7", // 0-7
     "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15", // 8-15
@@ -2797,6 +2875,291 @@ fn variable_string(
     Ok(out)
 }
 
+#[inline]
+fn is_anonymous_name(name: &Option<String>) -> bool {
+    name.as_ref().map_or(true, |n| n.starts_with('@'))
+}
+
+/// Collects the header nodes that must be emitted before a declaration using `t`.
+/// Named structures and unions are only required when used by value; pointers and
+/// references to them are satisfied by a forward declaration.
+fn collect_type_deps(
+    info: &DwarfInfo,
+    typedefs: &TypedefMap,
+    t: &Type,
+    by_value: bool,
+    out: &mut BTreeSet<u32>,
+) -> Result<()> {
+    let by_value = by_value
+        && !t.modifiers.iter().any(|m| {
+            matches!(m, Modifier::MwPointerTo | Modifier::PointerTo | Modifier::ReferenceTo)
+        });
+    let TypeKind::UserDefined(key) = t.kind else {
+        return Ok(());
+    };
+    if let Some(&td_key) = typedefs.get(&key).and_then(|v| v.first()) {
+        out.insert(td_key);
+        return Ok(());
+    }
+    let tag =
+        info.tags.get(&key).ok_or_else(|| anyhow!("Failed to locate user defined type {}", key))?;
+    match ud_type(info, tag)? {
+        UserDefinedType::Structure(s) => {
+            if is_anonymous_name(&s.name) {
+                // Anonymous definitions are emitted inline, so their members must be complete
+                for base in &s.bases {
+                    collect_type_deps(info, typedefs, &base.base_type, true, out)?;
+                }
+                for member in &s.members {
+                    collect_type_deps(info, typedefs, &member.kind, true, out)?;
+                }
+            } else if by_value {
+                out.insert(key);
+            }
+        }
+        UserDefinedType::Union(u) => {
+            if is_anonymous_name(&u.name) {
+                for member in &u.members {
+                    collect_type_deps(info, typedefs, &member.kind, true, out)?;
+                }
+            } else if by_value {
+                out.insert(key);
+            }
+        }
+        UserDefinedType::Enumeration(e) => {
+            // Enumerations can't be forward declared
+            if !is_anonymous_name(&e.name) {
+                out.insert(key);
+            }
+        }
+        UserDefinedType::Array(a) => {
+            collect_type_deps(info, typedefs, &a.element_type, by_value, out)?;
+        }
+        UserDefinedType::Subroutine(s) => {
+            collect_type_deps(info, typedefs, &s.return_type, false, out)?;
+            for parameter in &s.parameters {
+                collect_type_deps(info, typedefs, &parameter.kind, false, out)?;
+            }
+        }
+        UserDefinedType::PtrToMember(p) => {
+            collect_type_deps(info, typedefs, &p.kind, false, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Orders header nodes so that every node follows its dependencies.
+/// Ties are broken by key, which preserves the original DWARF order.
+fn order_header_types(deps: &BTreeMap<u32, BTreeSet<u32>>) -> Vec<u32> {
+    fn visit(
+        key: u32,
+        deps: &BTreeMap<u32, BTreeSet<u32>>,
+        visiting: &mut BTreeSet<u32>,
+        visited: &mut BTreeSet<u32>,
+        out: &mut Vec<u32>,
+    ) {
+        if visited.contains(&key) {
+            return;
+        }
+        if !visiting.insert(key) {
+            log::warn!("Circular type dependency at tag {:X}", key);
+            return;
+        }
+        if let Some(node_deps) = deps.get(&key) {
+            for &dep in node_deps {
+                if dep != key && deps.contains_key(&dep) {
+                    visit(dep, deps, visiting, visited, out);
+                }
+            }
+        }
+        visiting.remove(&key);
+        visited.insert(key);
+        out.push(key);
+    }
+
+    let mut visiting = BTreeSet::new();
+    let mut visited = BTreeSet::new();
+    let mut out = Vec::with_capacity(deps.len());
+    for &key in deps.keys() {
+        visit(key, deps, &mut visiting, &mut visited, &mut out);
+    }
+    out
+}
+
+/// Emits compilable C/C++ declarations for every named structure, union, enumeration
+/// and typedef in the DWARF info. Structures and unions are forward declared, then all
+/// definitions are emitted in dependency order. Types defined in multiple compile units
+/// are emitted once.
+pub fn header_string(info: &DwarfInfo) -> Result<String> {
+    let mut roots = Vec::<&Tag>::new();
+    if let Some((_, mut tag)) = info.tags.first_key_value() {
+        loop {
+            if tag.kind == TagKind::CompileUnit {
+                roots.extend(tag.children(&info.tags));
+            }
+            match tag.next_sibling(&info.tags) {
+                Some(next) => tag = next,
+                None => break,
+            }
+        }
+    }
+
+    // Only typedefs of anonymous types are referenced by name; named types are always
+    // referenced with their keyword, so that typedefs don't need to precede their uses.
+    let mut typedefs = TypedefMap::new();
+    for &tag in &roots {
+        if tag.kind != TagKind::Typedef {
+            continue;
+        }
+        let Some(ud_key) = tag.reference_attribute(AttributeKind::UserDefType) else {
+            continue;
+        };
+        let Some(ud_tag) = info.tags.get(&ud_key) else {
+            continue;
+        };
+        if ud_type(info, ud_tag).is_ok_and(|t| is_anonymous_name(&t.name())) {
+            typedefs.entry(ud_key).or_default().push(tag.key);
+        }
+    }
+
+    enum HeaderType {
+        Structure(StructureType),
+        Union(UnionType),
+        Enumeration(EnumerationType),
+        Typedef(TypedefTag),
+    }
+
+    let mut types = BTreeMap::<u32, HeaderType>::new();
+    let mut aliases = BTreeMap::<u32, u32>::new();
+    let mut names = BTreeMap::<(&str, String), u32>::new();
+    for &tag in &roots {
+        let (kind, name, header_type) = match tag.kind {
+            TagKind::StructureType
+            | TagKind::ClassType
+            | TagKind::UnionType
+            | TagKind::EnumerationType => match ud_type(info, tag) {
+                Ok(UserDefinedType::Structure(t)) if !is_anonymous_name(&t.name) => {
+                    ("struct", t.name.clone().unwrap(), HeaderType::Structure(t))
+                }
+                Ok(UserDefinedType::Union(t)) if !is_anonymous_name(&t.name) => {
+                    ("union", t.name.clone().unwrap(), HeaderType::Union(t))
+                }
+                Ok(UserDefinedType::Enumeration(t)) if !is_anonymous_name(&t.name) => {
+                    ("enum", t.name.clone().unwrap(), HeaderType::Enumeration(t))
+                }
+                Ok(_) => continue,
+                Err(e) => {
+                    log::error!("Failed to process tag {:X}: {}", tag.key, e);
+                    continue;
+                }
+            },
+            TagKind::Typedef => match process_typedef_tag(info, tag) {
+                Ok(t) => ("typedef", t.name.clone(), HeaderType::Typedef(t)),
+                Err(e) => {
+                    log::error!("Failed to process tag {:X}: {}", tag.key, e);
+                    continue;
+                }
+            },
+            _ => continue,
+        };
+        match names.entry((kind, name)) {
+            btree_map::Entry::Vacant(e) => {
+                e.insert(tag.key);
+                types.insert(tag.key, header_type);
+            }
+            btree_map::Entry::Occupied(e) => {
+                aliases.insert(tag.key, *e.get());
+            }
+        }
+    }
+
+    // A typedef of an anonymous type must not refer to itself by name
+    let own_typedefs = |key: u32, t: &TypedefTag| -> Option<TypedefMap> {
+        match t.kind.kind {
+            TypeKind::UserDefined(ud_key)
+                if typedefs.get(&ud_key).and_then(|v| v.first()) == Some(&key) =>
+            {
+                let mut map = typedefs.clone();
+                map.remove(&ud_key);
+                Some(map)
+            }
+            _ => None,
+        }
+    };
+
+    let mut deps = BTreeMap::<u32, BTreeSet<u32>>::new();
+    for (&key, header_type) in &types {
+        let mut node_deps = BTreeSet::new();
+        let result = match header_type {
+            HeaderType::Structure(t) => t
+                .bases
+                .iter()
+                .map(|base| &base.base_type)
+                .chain(t.members.iter().map(|member| &member.kind))
+                .try_for_each(|kind| {
+                    collect_type_deps(info, &typedefs, kind, true, &mut node_deps)
+                }),
+            HeaderType::Union(t) => t.members.iter().try_for_each(|member| {
+                collect_type_deps(info, &typedefs, &member.kind, true, &mut node_deps)
+            }),
+            HeaderType::Enumeration(_) => Ok(()),
+            HeaderType::Typedef(t) => {
+                let own = own_typedefs(key, t);
+                let typedefs = own.as_ref().unwrap_or(&typedefs);
+                collect_type_deps(info, typedefs, &t.kind, true, &mut node_deps)
+            }
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to resolve dependencies for tag {:X}: {}", key, e);
+        }
+        deps.insert(
+            key,
+            node_deps.into_iter().map(|k| aliases.get(&k).copied().unwrap_or(k)).collect(),
+        );
+    }
+
+    let mut out = String::new();
+    let mut wrote_forward = false;
+    for header_type in types.values() {
+        let decl = match header_type {
+            HeaderType::Structure(t) => match t.kind {
+                StructureKind::Struct => format!("struct {};", t.name.as_deref().unwrap_or("")),
+                StructureKind::Class => format!("class {};", t.name.as_deref().unwrap_or("")),
+            },
+            HeaderType::Union(t) => format!("union {};", t.name.as_deref().unwrap_or("")),
+            _ => continue,
+        };
+        if !wrote_forward {
+            out.push_str("// Forward declarations\n");
+            wrote_forward = true;
+        }
+        writeln!(out, "{}", decl)?;
+    }
+
+    for key in order_header_types(&deps) {
+        let result = match &types[&key] {
+            HeaderType::Structure(t) => struct_def_string(info, &typedefs, t).map(|s| s + ";"),
+            HeaderType::Union(t) => union_def_string(info, &typedefs, t).map(|s| s + ";"),
+            HeaderType::Enumeration(t) => enum_def_string(t).map(|s| s + ";"),
+            HeaderType::Typedef(t) => {
+                let own = own_typedefs(key, t);
+                typedef_string(info, own.as_ref().unwrap_or(&typedefs), t)
+            }
+        };
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        match result {
+            Ok(s) => writeln!(out, "{}", s)?,
+            Err(e) => {
+                log::error!("Failed to emit tag {:X}: {}", key, e);
+                writeln!(out, "// ERROR: Failed to emit tag {:X}", key)?;
+            }
+        }
+    }
+    Ok(out)
+}
+
 fn process_typedef_tag(info: &DwarfInfo, tag: &Tag) -> Result<TypedefTag> {
     ensure!(tag.kind == TagKind::Typedef, "{:?} is not a typedef tag", tag.kind);
 
@@ -2871,3 +3234,25 @@ fn process_variable_tag(info: &DwarfInfo, tag: &Tag) -> Result<VariableTag> {
     let local = tag.kind == TagKind::LocalVariable;
     Ok(VariableTag { name, mangled_name, kind, address, local })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order_header_types() {
+        let mut deps = BTreeMap::<u32, BTreeSet<u32>>::new();
+        // 1 embeds 3, 3 embeds 2, 4 depends on 1 and an unknown tag
+        deps.insert(1, BTreeSet::from([3]));
+        deps.insert(2, BTreeSet::new());
+        deps.insert(3, BTreeSet::from([2]));
+        deps.insert(4, BTreeSet::from([1, 99]));
+        assert_eq!(order_header_types(&deps), vec![2, 3, 1, 4]);
+
+        // Cycles are broken rather than looping forever
+        deps.insert(2, BTreeSet::from([1]));
+        let order = order_header_types(&deps);
+        assert_eq!(order.len(), 4);
+        assert_eq!(order.last(), Some(&4));
+    }
+}
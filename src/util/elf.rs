@@ -1,36 +1,40 @@
 use std::{
-    collections::{hash_map, HashMap},
+    collections::{hash_map, BTreeMap, HashMap},
     io::Cursor,
     num::NonZeroU64,
     path::Path,
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
-use cwdemangle::demangle;
 use flagset::Flags;
 use indexmap::IndexMap;
 use objdiff_core::obj::split_meta::{SplitMeta, SHT_SPLITMETA, SPLITMETA_SECTION};
 use object::{
     elf,
-    elf::{SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_LOUSER, SHT_NOBITS, SHT_PROGBITS},
+    elf::{SHF_ALLOC, SHF_EXECINSTR, SHF_TLS, SHF_WRITE, SHT_LOUSER, SHT_NOBITS, SHT_PROGBITS},
     write::{
         elf::{ProgramHeader, Rel, SectionHeader, SectionIndex, SymbolIndex, Writer},
         StringId,
     },
     Architecture, Endianness, Object, ObjectKind, ObjectSection, ObjectSymbol, Relocation,
-    RelocationFlags, RelocationTarget, SectionKind, Symbol, SymbolKind, SymbolScope, SymbolSection,
+    RelocationFlags, RelocationTarget, SectionKind, Symbol, SymbolFlags, SymbolKind, SymbolScope,
+    SymbolSection,
 };
 use typed_path::Utf8NativePath;
 
 use crate::{
     array_ref,
     obj::{
-        ObjArchitecture, ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind,
-        ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, ObjUnit,
-        SectionIndex as ObjSectionIndex, SymbolIndex as ObjSymbolIndex,
+        check_section_overlap, default_section_aliases, is_thread_local_name, ObjArchitecture,
+        ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSectionKind, ObjSplit, ObjSymbol,
+        ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind, ObjUnit, SectionIndex as ObjSectionIndex,
+        SymbolIndex as ObjSymbolIndex,
     },
     util::{
-        comment::{CommentSym, MWComment},
+        comment::{is_mw_comment, read_producers, write_producers, CommentSym, MWComment},
+        demangle::demangle,
+        dwarf::{read_debug_section, read_line_section, source_locations},
         reader::{Endian, FromReader, ToWriter},
     },
     vfs::open_file,
@@ -47,14 +51,36 @@ enum BoundaryState {
     FilesEnded,
 }
 
+static ALLOW_LITTLE_ENDIAN: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether little-endian ELF files are accepted as input. GameCube and Wii objects
+/// are always big endian, so they're rejected by default.
+pub fn set_allow_little_endian(value: bool) { ALLOW_LITTLE_ENDIAN.store(value, Ordering::Relaxed); }
+
+/// Whether little-endian ELF files are accepted as input.
+pub fn allow_little_endian() -> bool { ALLOW_LITTLE_ENDIAN.load(Ordering::Relaxed) }
+
+/// Loads an ELF from `path`, normalizing section names with the default section aliases.
 pub fn process_elf(path: &Utf8NativePath) -> Result<ObjInfo> {
     let mut file = open_file(path, true)?;
-    let obj_file = object::read::File::parse(file.map()?)?;
+    let mut obj = parse_elf(file.map()?)?;
+    obj.apply_section_aliases(&default_section_aliases())?;
+    Ok(obj)
+}
+
+pub fn parse_elf(data: &[u8]) -> Result<ObjInfo> { parse_elf_endian(data, allow_little_endian()) }
+
+fn parse_elf_endian(data: &[u8], allow_little_endian: bool) -> Result<ObjInfo> {
+    let obj_file = object::read::File::parse(data)?;
     let architecture = match obj_file.architecture() {
         Architecture::PowerPc => ObjArchitecture::PowerPc,
         arch => bail!("Unexpected architecture: {arch:?}"),
     };
-    ensure!(obj_file.endianness() == Endianness::Big, "Expected big endian");
+    ensure!(
+        obj_file.endianness() == Endianness::Big || allow_little_endian,
+        "Expected big endian (use --allow-little-endian to accept little-endian input)"
+    );
+    let endian = Endian::from(obj_file.endianness());
     let kind = match obj_file.kind() {
         ObjectKind::Executable => ObjKind::Executable,
         ObjectKind::Relocatable => ObjKind::Relocatable,
@@ -73,22 +99,36 @@ pub fn process_elf(path: &Utf8NativePath) -> Result<ObjInfo> {
     let mut sections: Vec<ObjSection> = vec![];
     let mut section_indexes: Vec<Option<usize>> = vec![None /* ELF null section */];
     for section in obj_file.sections() {
-        if section.size() == 0 {
+        // Relocatable objects can reference zero-length sections (e.g. an empty `.sbss`)
+        // through section symbols, so only drop them from executables
+        if section.size() == 0 && kind == ObjKind::Executable {
             section_indexes.push(None);
             continue;
         }
         let section_name = section.name()?;
-        let section_kind = match section.kind() {
-            SectionKind::Text => ObjSectionKind::Code,
-            SectionKind::Data => ObjSectionKind::Data,
-            SectionKind::ReadOnlyData => ObjSectionKind::ReadOnlyData,
-            SectionKind::UninitializedData => ObjSectionKind::Bss,
+        let (section_kind, thread_local) = match section.kind() {
+            SectionKind::Text => (ObjSectionKind::Code, false),
+            SectionKind::Data => (ObjSectionKind::Data, is_thread_local_name(section_name)),
+            SectionKind::ReadOnlyData => (ObjSectionKind::ReadOnlyData, false),
+            SectionKind::UninitializedData => {
+                (ObjSectionKind::Bss, is_thread_local_name(section_name))
+            }
+            // SHF_TLS
+            SectionKind::Tls => (ObjSectionKind::Data, true),
+            SectionKind::UninitializedTls => (ObjSectionKind::Bss, true),
             // SectionKind::Other if section_name == ".comment" => ObjSectionKind::Comment,
             _ => {
                 section_indexes.push(None);
                 continue;
             }
         };
+        if thread_local {
+            log::warn!(
+                "Section {} is thread-local (TLS). TLS is not fully supported, \
+                references to it may be incorrect",
+                section_name
+            );
+        }
         section_indexes.push(Some(sections.len()));
         sections.push(ObjSection {
             name: section_name.to_string(),
@@ -102,23 +142,34 @@ pub fn process_elf(path: &Utf8NativePath) -> Result<ObjInfo> {
             virtual_address: None, // Loaded from section symbol
             file_offset: section.file_range().map(|(v, _)| v).unwrap_or_default(),
             section_known: true,
+            thread_local,
+            verbatim: false,
             splits: Default::default(),
         });
     }
+    if kind == ObjKind::Executable {
+        check_section_overlap(&sections)?;
+    }
 
+    let mut producers = vec![];
     let mw_comment = if let Some(comment_section) = obj_file.section_by_name(".comment") {
         let data = comment_section.uncompressed_data()?;
         if data.is_empty() {
             None
+        } else if !is_mw_comment(&data) {
+            // GCC and others emit NUL-separated producer strings
+            producers = read_producers(&data);
+            log::debug!("Loaded .comment producers {:?}", producers);
+            None
         } else {
             let mut reader = Cursor::new(&*data);
-            let header = MWComment::from_reader(&mut reader, Endian::Big)
+            let header = MWComment::from_reader(&mut reader, endian)
                 .context("While reading .comment section")?;
             log::debug!("Loaded .comment section header {:?}", header);
             let mut comment_syms = Vec::with_capacity(obj_file.symbols().count());
-            comment_syms.push(CommentSym::from_reader(&mut reader, Endian::Big)?); // ELF null symbol
+            comment_syms.push(CommentSym::from_reader(&mut reader, endian)?); // ELF null symbol
             for symbol in obj_file.symbols() {
-                let comment_sym = CommentSym::from_reader(&mut reader, Endian::Big)?;
+                let comment_sym = CommentSym::from_reader(&mut reader, endian)?;
                 log::debug!("Symbol {:?} -> Comment {:?}", symbol, comment_sym);
                 comment_syms.push(comment_sym);
             }
@@ -358,10 +409,28 @@ pub fn process_elf(path: &Utf8NativePath) -> Result<ObjInfo> {
                 Some(s) => s,
                 None => continue,
             };
-        // Generate relocations
-        for (address, reloc) in section.relocations() {
-            let Some(reloc) =
-                to_obj_reloc(&obj_file, &symbol_indexes, &out_section.data, address, reloc)?
+        // Generate relocations. If both REL and RELA sections target this section,
+        // only use RELA, since the explicit addends are authoritative.
+        let relocations = section.relocations().collect::<Vec<_>>();
+        let has_rela = relocations.iter().any(|(_, r)| !r.has_implicit_addend());
+        if has_rela && relocations.iter().any(|(_, r)| r.has_implicit_addend()) {
+            log::warn!(
+                "Section {} has both REL and RELA relocations, ignoring REL",
+                out_section.name
+            );
+        }
+        for (address, reloc) in relocations {
+            if has_rela && reloc.has_implicit_addend() {
+                continue;
+            }
+            let Some(reloc) = to_obj_reloc(
+                &obj_file,
+                &symbol_indexes,
+                &out_section.data,
+                address,
+                reloc,
+                endian,
+            )?
             else {
                 continue;
             };
@@ -370,8 +439,10 @@ pub fn process_elf(path: &Utf8NativePath) -> Result<ObjInfo> {
     }
 
     let mut obj = ObjInfo::new(kind, architecture, obj_name, symbols, sections);
+    obj.endian = endian;
     obj.entry = NonZeroU64::new(obj_file.entry()).map(|n| n.get());
     obj.mw_comment = mw_comment.map(|(header, _)| header);
+    obj.producers = producers;
     obj.split_meta = split_meta;
     obj.sda2_base = sda2_base;
     obj.sda_base = sda_base;
@@ -384,9 +455,57 @@ pub fn process_elf(path: &Utf8NativePath) -> Result<ObjInfo> {
     Ok(obj)
 }
 
+/// Sets the source file and line of function and object symbols from the DWARF 1.1 debug
+/// info of an ELF file, if present. Returns the number of symbols updated.
+pub fn apply_elf_source_info(obj: &mut ObjInfo, data: &[u8]) -> Result<usize> {
+    let obj_file = object::read::File::parse(data)?;
+    let Some(debug_section) = obj_file.section_by_name(".debug") else {
+        return Ok(0);
+    };
+    let line_data = match obj_file.section_by_name(".line") {
+        Some(section) => Some(section.data()?),
+        None => None,
+    };
+    apply_dwarf_source_info(obj, debug_section.data()?, line_data)
+}
+
+/// Sets the source file and line of function and object symbols from DWARF 1.1 `.debug`
+/// and `.line` section data. Returns the number of symbols updated.
+fn apply_dwarf_source_info(
+    obj: &mut ObjInfo,
+    debug_data: &[u8],
+    line_data: Option<&[u8]>,
+) -> Result<usize> {
+    let info = read_debug_section(&mut Cursor::new(debug_data), obj.endian, false)?;
+    let lines = match line_data {
+        Some(data) => read_line_section(data, obj.endian)?,
+        None => BTreeMap::new(),
+    };
+    let locations = source_locations(&info, &lines)?;
+    let mut updates = vec![];
+    for (symbol_index, symbol) in obj.symbols.iter() {
+        if symbol.section.is_none() || !(symbol.is_code() || symbol.is_data()) {
+            continue;
+        }
+        let Some(location) = locations.get(&(symbol.address as u32)) else {
+            continue;
+        };
+        updates.push((symbol_index, ObjSymbol {
+            source_file: Some(location.file.clone()),
+            source_line: location.line,
+            ..symbol.clone()
+        }));
+    }
+    let count = updates.len();
+    for (symbol_index, symbol) in updates {
+        obj.symbols.replace(symbol_index, symbol)?;
+    }
+    Ok(count)
+}
+
 pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
     let mut out_data = Vec::new();
-    let mut writer = Writer::new(Endianness::Big, false, &mut out_data);
+    let mut writer = Writer::new(obj.endian.into(), false, &mut out_data);
 
     struct OutSection {
         index: SectionIndex,
@@ -452,16 +571,37 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         });
 
         // Generate .comment data
-        let mut comment_data = Vec::<u8>::with_capacity(0x2C + obj.symbols.count() as usize * 8);
-        mw_comment.to_writer_static(&mut comment_data, Endian::Big)?;
+        let mut comment_data =
+            Vec::<u8>::with_capacity(0x2C + obj.symbols.slot_count() as usize * 8);
+        mw_comment.to_writer_static(&mut comment_data, obj.endian)?;
         // Null symbol
         CommentSym { align: 0, vis_flags: 0, active_flags: 0 }
-            .to_writer_static(&mut comment_data, Endian::Big)?;
+            .to_writer_static(&mut comment_data, obj.endian)?;
         Some((comment_data, out_section_idx))
     } else {
         None
     };
 
+    // Generate .comment section with producer strings
+    let producer_data = if obj.mw_comment.is_none() && !obj.producers.is_empty() {
+        // Reserve section
+        let name = writer.add_section_name(".comment".as_bytes());
+        let index = writer.reserve_section_index();
+        let out_section_idx = out_sections.len();
+        out_sections.push(OutSection {
+            index,
+            rela_index: None,
+            offset: 0,
+            rela_offset: 0,
+            name,
+            rela_name: None,
+            virtual_address: None,
+        });
+        Some((write_producers(&obj.producers), out_section_idx))
+    } else {
+        None
+    };
+
     // Generate .note.split section
     let mut split_meta = if let (Some(metadata), Some(_)) = (&obj.split_meta, &obj.mw_comment) {
         // Reserve section
@@ -489,7 +629,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
     };
 
     let mut out_symbols: Vec<OutSymbol> = Vec::with_capacity(obj.symbols.count() as usize);
-    let mut symbol_map = vec![None; obj.symbols.count() as usize];
+    let mut symbol_map = vec![None; obj.symbols.slot_count() as usize];
     let mut section_symbol_offset = 0;
     let mut num_local = 0;
 
@@ -522,7 +662,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         });
         if let Some((comment_data, _)) = &mut comment_data {
             CommentSym { align: 1, vis_flags: 0, active_flags: 0 }
-                .to_writer_static(comment_data, Endian::Big)?;
+                .to_writer_static(comment_data, obj.endian)?;
         }
         if let Some(virtual_addresses) =
             split_meta.as_mut().and_then(|(m, _)| m.virtual_addresses.as_mut())
@@ -550,7 +690,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
             out_symbols.push(OutSymbol { index, sym });
             if let Some((comment_data, _)) = &mut comment_data {
                 CommentSym { align: section.align as u32, vis_flags: 0, active_flags: 0 }
-                    .to_writer_static(comment_data, Endian::Big)?;
+                    .to_writer_static(comment_data, obj.endian)?;
             }
             if let Some(virtual_addresses) =
                 split_meta.as_mut().and_then(|(m, _)| m.virtual_addresses.as_mut())
@@ -561,11 +701,8 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
     }
 
     // Add symbols, starting with local symbols
-    for (symbol_index, symbol) in obj
-        .symbols
-        .iter()
-        .filter(|&(_, s)| s.flags.is_local())
-        .chain(obj.symbols.iter().filter(|&(_, s)| !s.flags.is_local()))
+    for (symbol_index, symbol) in
+        obj.symbols.iter_locals().chain(obj.symbols.iter().filter(|&(_, s)| !s.flags.is_local()))
     {
         if obj.kind == ObjKind::Relocatable && symbol.kind == ObjSymbolKind::Section {
             // We wrote section symbols above, so skip them here
@@ -576,6 +713,21 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
             continue;
         }
 
+        if obj.kind == ObjKind::Relocatable {
+            // Common symbols are allocated by the linker, everything else is defined in
+            // a section or is an undefined reference
+            ensure!(
+                !(symbol.flags.is_common() && symbol.section.is_some()),
+                "Common symbol {} in relocatable object has a section",
+                symbol.name
+            );
+            ensure!(
+                symbol.section.is_some() || symbol.flags.is_common() || symbol.address == 0,
+                "ABS symbol in relocatable object: {}",
+                symbol.name
+            );
+        }
+
         let section = symbol.section.and_then(|idx| out_sections.get(idx as usize));
         let section_index = section.map(|s| s.index);
         let index = writer.reserve_symbol_index(section_index);
@@ -588,9 +740,13 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
             name: name_index,
             section: section_index,
             st_info: {
+                let thread_local = symbol
+                    .section
+                    .is_some_and(|idx| obj.sections.get(idx).is_some_and(|s| s.thread_local));
                 let st_type = match symbol.kind {
                     ObjSymbolKind::Unknown => elf::STT_NOTYPE,
                     ObjSymbolKind::Function => elf::STT_FUNC,
+                    ObjSymbolKind::Object if thread_local => elf::STT_TLS,
                     ObjSymbolKind::Object => elf::STT_OBJECT,
                     ObjSymbolKind::Section => elf::STT_SECTION,
                 };
@@ -603,7 +759,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
                 };
                 (st_bind << 4) + st_type
             },
-            st_other: if symbol.flags.is_hidden() { elf::STV_HIDDEN } else { elf::STV_DEFAULT },
+            st_other: symbol_visibility(symbol.flags),
             st_shndx: if section_index.is_some() {
                 0
             } else if symbol.flags.is_common() {
@@ -622,7 +778,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         out_symbols.push(OutSymbol { index, sym });
         symbol_map[symbol_index as usize] = Some(index.0);
         if let Some((comment_data, _)) = &mut comment_data {
-            CommentSym::from(symbol, export_all).to_writer_static(comment_data, Endian::Big)?;
+            CommentSym::from(symbol, export_all).to_writer_static(comment_data, obj.endian)?;
         }
         if let Some(virtual_addresses) =
             split_meta.as_mut().and_then(|(m, _)| m.virtual_addresses.as_mut())
@@ -673,6 +829,12 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         out_section.offset = writer.reserve(comment_data.len(), 32);
     }
 
+    // Reserve producer .comment section
+    if let Some((producer_data, idx)) = &producer_data {
+        let out_section = &mut out_sections[*idx];
+        out_section.offset = writer.reserve(producer_data.len(), 1);
+    }
+
     // Reserve .note.split section
     if let Some((metadata, idx)) = &split_meta {
         let out_section = &mut out_sections[*idx];
@@ -723,7 +885,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         writer.write_align(32);
         ensure!(writer.len() == out_section.offset);
         if obj.kind == ObjKind::Relocatable {
-            write_relocatable_section_data(&mut writer, section)?;
+            write_relocatable_section_data(&mut writer, section, obj.endian)?;
         } else {
             writer.write(&section.data);
         }
@@ -736,7 +898,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         writer.write_align_relocation();
         ensure!(writer.len() == out_section.rela_offset);
         for (addr, reloc) in section.relocations.iter() {
-            let (r_offset, r_type) = reloc.to_elf(addr);
+            let (r_offset, r_type) = reloc.to_elf(addr, obj.endian);
             let r_sym = symbol_map[reloc.target_symbol as usize]
                 .ok_or_else(|| anyhow!("Relocation against stripped symbol"))?;
             writer.write_relocation(true, &Rel { r_offset, r_sym, r_type, r_addend: reloc.addend });
@@ -759,6 +921,13 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         writer.write(comment_data);
     }
 
+    // Write producer .comment section
+    if let Some((producer_data, idx)) = &producer_data {
+        let out_section = &out_sections[*idx];
+        ensure!(writer.len() == out_section.offset);
+        writer.write(producer_data);
+    }
+
     // Write .note.split section
     if let Some((metadata, idx)) = &split_meta {
         let out_section = &out_sections[*idx];
@@ -766,7 +935,7 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         ensure!(writer.len() == out_section.offset);
         // object::write::elf::Writer doesn't implement std::io::Write...
         let mut data = Vec::with_capacity(metadata.write_size(false));
-        metadata.to_writer(&mut data, object::BigEndian, false)?;
+        metadata.to_writer(&mut data, Endianness::from(obj.endian), false)?;
         writer.write(&data);
     }
 
@@ -780,11 +949,11 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
                 }
                 ObjSectionKind::Bss => SHT_NOBITS,
             },
-            sh_flags: match section.kind {
+            sh_flags: (match section.kind {
                 ObjSectionKind::Code => SHF_ALLOC | SHF_EXECINSTR,
                 ObjSectionKind::Data | ObjSectionKind::Bss => SHF_ALLOC | SHF_WRITE,
                 ObjSectionKind::ReadOnlyData => SHF_ALLOC,
-            } as u64,
+            } | if section.thread_local { SHF_TLS } else { 0 }) as u64,
             sh_addr: section.address,
             sh_offset: out_section.offset as u64,
             sh_size: section.size,
@@ -829,6 +998,23 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
         });
     }
 
+    // Write producer .comment section header
+    if let Some((producer_data, idx)) = &producer_data {
+        let out_section = &out_sections[*idx];
+        writer.write_section_header(&SectionHeader {
+            name: Some(out_section.name),
+            sh_type: SHT_PROGBITS,
+            sh_flags: (elf::SHF_MERGE | elf::SHF_STRINGS) as u64,
+            sh_addr: 0,
+            sh_offset: out_section.offset as u64,
+            sh_size: producer_data.len() as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 1,
+            sh_entsize: 1,
+        });
+    }
+
     // Write .note.split section header
     if let Some((metadata, idx)) = &split_meta {
         let out_section = &out_sections[*idx];
@@ -857,6 +1043,69 @@ pub fn write_elf(obj: &ObjInfo, export_all: bool) -> Result<Vec<u8>> {
     Ok(out_data)
 }
 
+/// Re-reads an ELF written by [`write_elf`] and checks its section sizes, symbol count and
+/// relocation counts against `obj`, failing on the first mismatch. Empty sections of
+/// executables aren't read back, so they and their symbols are skipped.
+pub fn verify_elf(obj: &ObjInfo, data: &[u8]) -> Result<()> {
+    let read = parse_elf_endian(data, obj.endian == Endian::Little)
+        .context("Failed to re-read written ELF")?;
+    // Zero-length sections are only read back for relocatable objects
+    let is_read =
+        |obj: &ObjInfo, section: &ObjSection| section.size > 0 || obj.kind == ObjKind::Relocatable;
+    let sections = obj.sections.iter().filter(|(_, s)| is_read(obj, s)).collect::<Vec<_>>();
+    ensure!(
+        sections.len() == read.sections.len() as usize,
+        "Section count mismatch: expected {}, found {}",
+        sections.len(),
+        read.sections.len()
+    );
+    for (&(_, expected), (_, found)) in sections.iter().zip(read.sections.iter()) {
+        ensure!(
+            expected.name == found.name,
+            "Section name mismatch: expected {}, found {}",
+            expected.name,
+            found.name
+        );
+        ensure!(
+            expected.size == found.size,
+            "Section {} size mismatch: expected {:#X}, found {:#X}",
+            expected.name,
+            expected.size,
+            found.size
+        );
+        ensure!(
+            expected.relocations.len() == found.relocations.len(),
+            "Section {} relocation count mismatch: expected {}, found {}",
+            expected.name,
+            expected.relocations.len(),
+            found.relocations.len()
+        );
+    }
+    let symbol_count = |obj: &ObjInfo| {
+        obj.symbols
+            .iter()
+            .filter(|(_, s)| s.kind != ObjSymbolKind::Section)
+            .filter(|(_, s)| s.section.map_or(true, |idx| is_read(obj, &obj.sections[idx])))
+            .count()
+    };
+    let (expected, found) = (symbol_count(obj), symbol_count(&read));
+    ensure!(expected == found, "Symbol count mismatch: expected {}, found {}", expected, found);
+    Ok(())
+}
+
+/// ELF symbol visibility (`st_other`) for the given symbol flags.
+fn symbol_visibility(flags: ObjSymbolFlagSet) -> u8 {
+    if flags.is_internal() {
+        elf::STV_INTERNAL
+    } else if flags.is_hidden() {
+        elf::STV_HIDDEN
+    } else if flags.is_protected() {
+        elf::STV_PROTECTED
+    } else {
+        elf::STV_DEFAULT
+    }
+}
+
 fn to_obj_symbol(
     obj_file: &object::File<'_>,
     symbol: &Symbol<'_, '_>,
@@ -888,8 +1137,20 @@ fn to_obj_symbol(
     if symbol.is_weak() {
         flags = ObjSymbolFlagSet(flags.0 | ObjSymbolFlags::Weak);
     }
-    if symbol.scope() == SymbolScope::Linkage {
-        flags = ObjSymbolFlagSet(flags.0 | ObjSymbolFlags::Hidden);
+    // Visibility only applies to defined global symbols
+    let visibility = match symbol.flags() {
+        SymbolFlags::Elf { st_other, .. } => st_other & 0x3,
+        _ => elf::STV_DEFAULT,
+    };
+    match symbol.scope() {
+        SymbolScope::Linkage if visibility == elf::STV_INTERNAL => {
+            flags = ObjSymbolFlagSet(flags.0 | ObjSymbolFlags::Internal);
+        }
+        SymbolScope::Linkage => flags = ObjSymbolFlagSet(flags.0 | ObjSymbolFlags::Hidden),
+        SymbolScope::Dynamic if visibility == elf::STV_PROTECTED => {
+            flags = ObjSymbolFlagSet(flags.0 | ObjSymbolFlags::Protected);
+        }
+        _ => {}
     }
     let section_idx = section.as_ref().and_then(|section| section_indexes[section.index().0]);
     Ok(ObjSymbol {
@@ -902,7 +1163,7 @@ fn to_obj_symbol(
         flags,
         kind: match symbol.kind() {
             SymbolKind::Text => ObjSymbolKind::Function,
-            SymbolKind::Data => ObjSymbolKind::Object,
+            SymbolKind::Data | SymbolKind::Tls => ObjSymbolKind::Object,
             SymbolKind::Unknown | SymbolKind::Label => ObjSymbolKind::Unknown,
             SymbolKind::Section => ObjSymbolKind::Section,
             _ => bail!("Unsupported symbol kind: {:?}", symbol),
@@ -919,9 +1180,12 @@ pub fn to_obj_reloc_kind(flags: RelocationFlags) -> Result<ObjRelocKind> {
             elf::R_PPC_ADDR16_LO => ObjRelocKind::PpcAddr16Lo,
             elf::R_PPC_ADDR16_HI => ObjRelocKind::PpcAddr16Hi,
             elf::R_PPC_ADDR16_HA => ObjRelocKind::PpcAddr16Ha,
-            elf::R_PPC_REL24 => ObjRelocKind::PpcRel24,
+            // GCC branches through the PLT or to local labels resolve to direct branches
+            // in a static link
+            elf::R_PPC_REL24 | elf::R_PPC_PLTREL24 | elf::R_PPC_LOCAL24PC => ObjRelocKind::PpcRel24,
             elf::R_PPC_REL14 => ObjRelocKind::PpcRel14,
             elf::R_PPC_EMB_SDA21 => ObjRelocKind::PpcEmbSda21,
+            elf::R_PPC_REL32 => bail!("Unsupported ELF relocation type R_PPC_REL32 ({r_type})"),
             kind => bail!("Unhandled ELF relocation type: {kind}"),
         },
         flags => bail!("Unhandled relocation type: {:?}", flags),
@@ -934,6 +1198,7 @@ fn to_obj_reloc(
     section_data: &[u8],
     address: u64,
     reloc: Relocation,
+    endian: Endian,
 ) -> Result<Option<ObjReloc>> {
     let reloc_kind = to_obj_reloc_kind(reloc.flags())?;
     let symbol = match reloc.target() {
@@ -951,14 +1216,18 @@ fn to_obj_reloc(
     let target_symbol = symbol_indexes[symbol.index().0]
         .ok_or_else(|| anyhow!("Relocation against stripped symbol: {symbol:?}"))?;
     let addend = match symbol.kind() {
-        SymbolKind::Text | SymbolKind::Data | SymbolKind::Unknown | SymbolKind::Label => {
-            Ok(reloc.addend())
-        }
+        SymbolKind::Text
+        | SymbolKind::Data
+        | SymbolKind::Tls
+        | SymbolKind::Unknown
+        | SymbolKind::Label => Ok(reloc.addend()),
         SymbolKind::Section => {
             let addend = if reloc.has_implicit_addend() {
-                let addend = u32::from_be_bytes(
-                    section_data[address as usize..address as usize + 4].try_into()?,
-                ) as i64;
+                let bytes = section_data[address as usize..address as usize + 4].try_into()?;
+                let addend = match endian {
+                    Endian::Big => u32::from_be_bytes(bytes),
+                    Endian::Little => u32::from_le_bytes(bytes),
+                } as i64;
                 match reloc_kind {
                     ObjRelocKind::Absolute => addend,
                     _ => bail!("Unsupported implicit relocation type {reloc_kind:?}"),
@@ -975,12 +1244,20 @@ fn to_obj_reloc(
 }
 
 /// Writes section data while zeroing out relocations.
-fn write_relocatable_section_data(w: &mut Writer, section: &ObjSection) -> Result<()> {
+fn write_relocatable_section_data(
+    w: &mut Writer,
+    section: &ObjSection,
+    endian: Endian,
+) -> Result<()> {
     ensure!(section.address == 0);
     let mut current_address = 0;
     for (addr, reloc) in section.relocations.iter() {
         w.write(&section.data[current_address..addr as usize]);
-        let mut ins = u32::from_be_bytes(*array_ref!(section.data, addr as usize, 4));
+        let bytes = *array_ref!(section.data, addr as usize, 4);
+        let mut ins = match endian {
+            Endian::Big => u32::from_be_bytes(bytes),
+            Endian::Little => u32::from_le_bytes(bytes),
+        };
         match reloc.kind {
             ObjRelocKind::Absolute => {
                 ins = 0;
@@ -998,10 +1275,632 @@ fn write_relocatable_section_data(w: &mut Writer, section: &ObjSection) -> Resul
                 ins &= !0x1FFFFF;
             }
         }
-        w.write(&ins.to_be_bytes());
+        match endian {
+            Endian::Big => w.write(&ins.to_be_bytes()),
+            Endian::Little => w.write(&ins.to_le_bytes()),
+        }
         current_address = addr as usize + 4;
     }
     // Write remaining data
     w.write(&section.data[current_address..]);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use flagset::FlagSet;
+
+    use super::*;
+    use crate::{
+        obj::{test_section, test_section_data, test_symbol, ObjRelocations},
+        util::dwarf::{AttributeKind, TagKind},
+    };
+
+    #[test]
+    fn test_producers_round_trip() {
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            vec![],
+            vec![ObjSection {
+                elf_index: 1,
+                ..test_section_data(".text", ObjSectionKind::Code, 0, vec![0x4E, 0x80, 0x00, 0x20])
+            }],
+        );
+        obj.producers =
+            vec!["GCC: (GNU) 4.3.2".to_string(), "GCC: (devkitPPC release 45) 13.2.0".to_string()];
+        let data = write_elf(&obj, false).unwrap();
+
+        let obj_file = object::read::File::parse(data.as_slice()).unwrap();
+        let comment = obj_file.section_by_name(".comment").unwrap();
+        assert_eq!(
+            comment.data().unwrap(),
+            b"GCC: (GNU) 4.3.2\0GCC: (devkitPPC release 45) 13.2.0\0".as_slice()
+        );
+
+        let read = parse_elf(&data).unwrap();
+        assert!(read.mw_comment.is_none());
+        assert_eq!(read.producers, obj.producers);
+
+        // Survives a second write/read cycle
+        let read = parse_elf(&write_elf(&read, false).unwrap()).unwrap();
+        assert_eq!(read.producers, obj.producers);
+    }
+
+    #[test]
+    fn test_bss_and_common_symbols() {
+        let symbol = |name: &str, address, section, flags| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | flags),
+            ..test_symbol(name, section, address, 8, ObjSymbolKind::Object)
+        };
+        let new_obj = |symbols| {
+            ObjInfo::new(
+                ObjKind::Relocatable,
+                ObjArchitecture::PowerPc,
+                "test.c".to_string(),
+                symbols,
+                vec![ObjSection {
+                    align: 8,
+                    elf_index: 1,
+                    ..test_section(".bss", ObjSectionKind::Bss, 0, 0x10)
+                }],
+            )
+        };
+        let obj = new_obj(vec![
+            symbol("sDefined", 8, Some(0), ObjSymbolFlags::Global),
+            symbol("sCommon", 8, None, ObjSymbolFlags::Common),
+        ]);
+        let data = write_elf(&obj, false).unwrap();
+
+        let obj_file = object::read::File::parse(data.as_slice()).unwrap();
+        let bss = obj_file.section_by_name(".bss").unwrap();
+        let defined = obj_file.symbol_by_name("sDefined").unwrap();
+        assert_eq!(defined.section(), SymbolSection::Section(bss.index()));
+        assert_eq!(defined.address(), 8);
+        assert!(!defined.is_common());
+        let common = obj_file.symbol_by_name("sCommon").unwrap();
+        assert_eq!(common.section(), SymbolSection::Common);
+        assert!(common.is_common());
+
+        let read = parse_elf(&data).unwrap();
+        let (_, defined) = read.symbols.by_name("sDefined").unwrap().unwrap();
+        assert!(defined.section.is_some() && !defined.flags.is_common());
+        let (_, common) = read.symbols.by_name("sCommon").unwrap().unwrap();
+        assert!(common.section.is_none() && common.flags.is_common());
+
+        // Undefined references are allowed, absolute symbols are not
+        let obj = new_obj(vec![symbol("extern", 0, None, ObjSymbolFlags::Global)]);
+        assert!(write_elf(&obj, false).is_ok());
+        let obj = new_obj(vec![symbol("abs", 0x80003000, None, ObjSymbolFlags::Global)]);
+        let err = write_elf(&obj, false).unwrap_err();
+        assert_eq!(err.to_string(), "ABS symbol in relocatable object: abs");
+        let obj = new_obj(vec![symbol("sCommon", 8, Some(0), ObjSymbolFlags::Common)]);
+        let err = write_elf(&obj, false).unwrap_err();
+        assert_eq!(err.to_string(), "Common symbol sCommon in relocatable object has a section");
+    }
+
+    #[test]
+    fn test_symbol_visibility_round_trip() {
+        let visibilities = [
+            ("sDefault", FlagSet::default(), elf::STV_DEFAULT),
+            ("sHidden", ObjSymbolFlags::Hidden.into(), elf::STV_HIDDEN),
+            ("sProtected", ObjSymbolFlags::Protected.into(), elf::STV_PROTECTED),
+            ("sInternal", ObjSymbolFlags::Internal.into(), elf::STV_INTERNAL),
+        ];
+        let symbols = visibilities
+            .iter()
+            .enumerate()
+            .map(|(i, &(name, flags, _))| ObjSymbol {
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | flags),
+                ..test_symbol(name, Some(0), i as u64 * 4, 4, ObjSymbolKind::Object)
+            })
+            .collect();
+        let obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            symbols,
+            vec![ObjSection {
+                elf_index: 1,
+                ..test_section(".data", ObjSectionKind::Data, 0, 0x10)
+            }],
+        );
+        let data = write_elf(&obj, false).unwrap();
+
+        let obj_file = object::read::File::parse(data.as_slice()).unwrap();
+        for &(name, _, visibility) in &visibilities {
+            let symbol = obj_file.symbol_by_name(name).unwrap();
+            let SymbolFlags::Elf { st_other, .. } = symbol.flags() else {
+                panic!("Expected ELF symbol flags");
+            };
+            assert_eq!(st_other & 0x3, visibility, "{name}");
+        }
+
+        // Survives a second write/read cycle, and protected isn't conflated with hidden
+        let read = parse_elf(&write_elf(&parse_elf(&data).unwrap(), false).unwrap()).unwrap();
+        let mask = ObjSymbolFlags::Hidden | ObjSymbolFlags::Protected | ObjSymbolFlags::Internal;
+        for &(name, flags, _) in &visibilities {
+            let (_, symbol) = read.symbols.by_name(name).unwrap().unwrap();
+            assert_eq!(symbol.flags.0 & mask, flags, "{name}");
+        }
+        let (_, internal) = read.symbols.by_name("sInternal").unwrap().unwrap();
+        assert!(internal.flags.is_hidden());
+    }
+
+    #[test]
+    fn test_section_aliases() {
+        let section = |name: &str, kind| test_section(name, kind, 0, 4);
+        let obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            vec![ObjSymbol {
+                name: "sTable".to_string(),
+                section: Some(1),
+                size: 4,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                kind: ObjSymbolKind::Object,
+                ..Default::default()
+            }],
+            vec![
+                section(".text", ObjSectionKind::Code),
+                section(".rodata1", ObjSectionKind::ReadOnlyData),
+            ],
+        );
+        let mut obj = parse_elf(&write_elf(&obj, false).unwrap()).unwrap();
+        let (section_index, _) = obj.sections.by_name(".rodata1").unwrap().unwrap();
+
+        let aliases = BTreeMap::from([(".rodata1".to_string(), ".rodata".to_string())]);
+        assert_eq!(obj.apply_section_aliases(&aliases).unwrap(), 1);
+        assert!(obj.sections.by_name(".rodata1").unwrap().is_none());
+        let (index, section) = obj.sections.by_name(".rodata").unwrap().unwrap();
+        assert_eq!((index, section.kind), (section_index, ObjSectionKind::ReadOnlyData));
+        let (_, symbol) = obj.symbols.by_name("sTable").unwrap().unwrap();
+        assert_eq!(symbol.section, Some(section_index));
+        assert!(obj
+            .symbols
+            .for_section(section_index)
+            .all(|(_, s)| s.kind != ObjSymbolKind::Section || s.name == ".rodata"));
+
+        // Renaming onto an existing section is an error
+        let aliases = BTreeMap::from([(".text".to_string(), ".rodata".to_string())]);
+        assert!(obj.apply_section_aliases(&aliases).is_err());
+    }
+
+    /// Builds an object with a `.data` section containing a single relocation of type `r_type`
+    /// against `target`, optionally with a conflicting REL section.
+    fn build_reloc_elf(with_rel: bool, r_type: u32) -> Vec<u8> {
+        let mut out_data = Vec::new();
+        let mut w = Writer::new(Endianness::Big, false, &mut out_data);
+        w.reserve_null_section_index();
+        let text_name = w.add_section_name(b".text");
+        let text_index = w.reserve_section_index();
+        let data_name = w.add_section_name(b".data");
+        let data_index = w.reserve_section_index();
+        let rel_name = with_rel.then(|| w.add_section_name(b".rel.data"));
+        if with_rel {
+            w.reserve_section_index();
+        }
+        let rela_name = w.add_section_name(b".rela.data");
+        w.reserve_section_index();
+        let symtab = w.reserve_symtab_section_index();
+        w.reserve_strtab_section_index();
+        w.reserve_shstrtab_section_index();
+
+        w.reserve_null_symbol_index();
+        let file_name = w.add_string(b"test.c");
+        w.reserve_symbol_index(None);
+        let target_name = w.add_string(b"target");
+        let target_index = w.reserve_symbol_index(Some(text_index));
+
+        w.reserve_file_header();
+        let text_offset = w.reserve(8, 4);
+        let data_offset = w.reserve(4, 4);
+        let rel_offset = if with_rel { w.reserve_relocations(1, false) } else { 0 };
+        let rela_offset = w.reserve_relocations(1, true);
+        w.reserve_symtab();
+        w.reserve_strtab();
+        w.reserve_shstrtab();
+        w.reserve_section_headers();
+
+        w.write_file_header(&object::write::elf::FileHeader {
+            os_abi: elf::ELFOSABI_SYSV,
+            abi_version: 0,
+            e_type: elf::ET_REL,
+            e_machine: elf::EM_PPC,
+            e_entry: 0,
+            e_flags: elf::EF_PPC_EMB,
+        })
+        .unwrap();
+        w.write_align(4);
+        w.write(&[0x60, 0x00, 0x00, 0x00, 0x4E, 0x80, 0x00, 0x20]); // nop; blr
+        w.write_align(4);
+        w.write(&[0; 4]);
+        let reloc = |r_addend| Rel { r_offset: 0, r_sym: target_index.0, r_type, r_addend };
+        if with_rel {
+            w.write_align_relocation();
+            w.write_relocation(false, &reloc(0));
+        }
+        w.write_align_relocation();
+        w.write_relocation(true, &reloc(4));
+
+        w.write_null_symbol();
+        w.write_symbol(&object::write::elf::Sym {
+            name: Some(file_name),
+            section: None,
+            st_info: (elf::STB_LOCAL << 4) + elf::STT_FILE,
+            st_other: elf::STV_DEFAULT,
+            st_shndx: elf::SHN_ABS,
+            st_value: 0,
+            st_size: 0,
+        });
+        w.write_symbol(&object::write::elf::Sym {
+            name: Some(target_name),
+            section: Some(text_index),
+            st_info: (elf::STB_GLOBAL << 4) + elf::STT_FUNC,
+            st_other: elf::STV_DEFAULT,
+            st_shndx: 0,
+            st_value: 4,
+            st_size: 4,
+        });
+        w.write_strtab();
+        w.write_shstrtab();
+
+        w.write_null_section_header();
+        for (name, offset, size, flags) in [
+            (text_name, text_offset, 8, SHF_ALLOC | SHF_EXECINSTR),
+            (data_name, data_offset, 4, SHF_ALLOC | SHF_WRITE),
+        ] {
+            w.write_section_header(&SectionHeader {
+                name: Some(name),
+                sh_type: SHT_PROGBITS,
+                sh_flags: flags as u64,
+                sh_addr: 0,
+                sh_offset: offset as u64,
+                sh_size: size,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: 4,
+                sh_entsize: 0,
+            });
+        }
+        if let Some(rel_name) = rel_name {
+            w.write_relocation_section_header(rel_name, data_index, symtab, rel_offset, 1, false);
+        }
+        w.write_relocation_section_header(rela_name, data_index, symtab, rela_offset, 1, true);
+        // Null and file symbols are local
+        w.write_symtab_section_header(2);
+        w.write_strtab_section_header();
+        w.write_shstrtab_section_header();
+        assert_eq!(w.reserved_len(), w.len());
+        out_data
+    }
+
+    #[test]
+    fn test_rela_addend() {
+        for with_rel in [false, true] {
+            let obj = parse_elf(&build_reloc_elf(with_rel, elf::R_PPC_ADDR32)).unwrap();
+            let (_, data_section) = obj.sections.by_name(".data").unwrap().unwrap();
+            let relocations = data_section.relocations.iter().collect::<Vec<_>>();
+            assert_eq!(relocations.len(), 1);
+            let (address, reloc) = relocations[0];
+            assert_eq!(address, 0);
+            assert_eq!(reloc.kind, ObjRelocKind::Absolute);
+            assert_eq!(reloc.addend, 4);
+            let target = &obj.symbols[reloc.target_symbol];
+            assert_eq!(target.name, "target");
+            assert_eq!(target.address, 4);
+        }
+    }
+
+    #[test]
+    fn test_gcc_reloc_types() {
+        for r_type in [elf::R_PPC_PLTREL24, elf::R_PPC_LOCAL24PC] {
+            let obj = parse_elf(&build_reloc_elf(false, r_type)).unwrap();
+            let (_, data_section) = obj.sections.by_name(".data").unwrap().unwrap();
+            let (_, reloc) = data_section.relocations.iter().next().unwrap();
+            assert_eq!(reloc.kind, ObjRelocKind::PpcRel24, "{r_type}");
+            assert_eq!(reloc.addend, 4, "{r_type}");
+            assert_eq!(obj.symbols[reloc.target_symbol].name, "target");
+        }
+
+        let err = parse_elf(&build_reloc_elf(false, elf::R_PPC_REL32)).unwrap_err();
+        assert!(format!("{err:#}").contains("R_PPC_REL32 (26)"), "{err:#}");
+    }
+
+    #[test]
+    fn test_little_endian_round_trip() {
+        let section = |name: &str, kind, data: Vec<u8>| test_section_data(name, kind, 0, data);
+        let global = |name: &str, section_index, size, kind| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, Some(section_index), 0, size, kind)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            vec![
+                global("func", 0, 8, ObjSymbolKind::Function),
+                global("gData", 1, 4, ObjSymbolKind::Object),
+            ],
+            vec![
+                // lis r3, gData@ha; addi r3, r3, gData@l
+                section(".text", ObjSectionKind::Code, vec![
+                    0x00, 0x00, 0x60, 0x3C, 0x00, 0x00, 0x63, 0x38,
+                ]),
+                section(".data", ObjSectionKind::Data, vec![0; 4]),
+            ],
+        );
+        obj.endian = Endian::Little;
+        let reloc =
+            |kind, target_symbol, addend| ObjReloc { kind, target_symbol, addend, module: None };
+        obj.sections[0].relocations.insert(0, reloc(ObjRelocKind::PpcAddr16Ha, 1, 0)).unwrap();
+        obj.sections[0].relocations.insert(4, reloc(ObjRelocKind::PpcAddr16Lo, 1, 0)).unwrap();
+        obj.sections[1].relocations.insert(0, reloc(ObjRelocKind::Absolute, 0, 4)).unwrap();
+
+        let data = write_elf(&obj, false).unwrap();
+        assert_eq!(data[elf::EI_DATA], elf::ELFDATA2LSB);
+
+        assert!(parse_elf_endian(&data, false).is_err());
+        let read = parse_elf_endian(&data, true).unwrap();
+        assert_eq!(read.endian, Endian::Little);
+        assert_eq!(read.name, "test.c");
+        for ((_, section), (_, read_section)) in obj.sections.iter().zip(read.sections.iter()) {
+            assert_eq!(read_section.name, section.name);
+            assert_eq!(read_section.data, section.data);
+            let relocations = |obj: &ObjInfo, section: &ObjSection| {
+                section
+                    .relocations
+                    .iter()
+                    .map(|(addr, r)| {
+                        (addr, r.kind, obj.symbols[r.target_symbol].name.clone(), r.addend)
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(relocations(&read, read_section), relocations(&obj, section));
+        }
+        for (_, symbol) in obj.symbols.iter() {
+            let (_, read_symbol) = read.symbols.by_name(&symbol.name).unwrap().unwrap();
+            assert_eq!(read_symbol.address, symbol.address);
+            assert_eq!(read_symbol.size, symbol.size);
+            assert_eq!(read_symbol.kind, symbol.kind);
+        }
+    }
+
+    #[test]
+    fn test_thread_local_round_trip() {
+        let section = |name: &str, kind, thread_local| ObjSection {
+            thread_local,
+            ..test_section(name, kind, 0, 8)
+        };
+        let obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            vec![ObjSymbol {
+                name: "tlsCounter".to_string(),
+                section: Some(1),
+                size: 4,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                kind: ObjSymbolKind::Object,
+                ..Default::default()
+            }],
+            vec![
+                section(".data", ObjSectionKind::Data, false),
+                // Not a conventional TLS name, so only SHF_TLS identifies it
+                section(".tls_vars", ObjSectionKind::Data, true),
+            ],
+        );
+        let data = write_elf(&obj, false).unwrap();
+
+        let file = object::read::File::parse(&*data).unwrap();
+        let section_flags = |name: &str| match file.section_by_name(name).unwrap().flags() {
+            object::SectionFlags::Elf { sh_flags } => sh_flags,
+            flags => panic!("Unexpected flags {flags:?}"),
+        };
+        assert_eq!(section_flags(".data") & SHF_TLS as u64, 0);
+        assert_ne!(section_flags(".tls_vars") & SHF_TLS as u64, 0);
+        let symbol = file.symbol_by_name("tlsCounter").unwrap();
+        assert_eq!(symbol.kind(), SymbolKind::Tls);
+
+        let read = parse_elf(&data).unwrap();
+        let (_, data_section) = read.sections.by_name(".data").unwrap().unwrap();
+        assert!(!data_section.thread_local);
+        let (_, tls_section) = read.sections.by_name(".tls_vars").unwrap().unwrap();
+        assert!(tls_section.thread_local);
+        assert_eq!(tls_section.kind, ObjSectionKind::Data);
+        let (_, symbol) = read.symbols.by_name("tlsCounter").unwrap().unwrap();
+        assert_eq!(symbol.kind, ObjSymbolKind::Object);
+
+        assert!(is_thread_local_name(".tbss"));
+        assert!(is_thread_local_name(".tdata.foo"));
+        assert!(!is_thread_local_name(".tdatas"));
+    }
+
+    #[test]
+    fn test_dwarf_source_info() {
+        fn attr(kind: AttributeKind, value: &[u8]) -> Vec<u8> {
+            [&(kind as u16).to_be_bytes()[..], value].concat()
+        }
+        fn tag(kind: TagKind, attrs: &[Vec<u8>]) -> Vec<u8> {
+            let body = attrs.concat();
+            [
+                &((6 + body.len()) as u32).to_be_bytes()[..],
+                &(kind as u16).to_be_bytes()[..],
+                &body[..],
+            ]
+            .concat()
+        }
+        let debug = [
+            tag(TagKind::CompileUnit, &[
+                // Past the end of the section, so every following tag is a child
+                attr(AttributeKind::Sibling, &0x1000u32.to_be_bytes()),
+                attr(AttributeKind::Name, b"main.c\0"),
+                attr(AttributeKind::StmtList, &0u32.to_be_bytes()),
+            ]),
+            tag(TagKind::GlobalSubroutine, &[
+                attr(AttributeKind::Name, b"func\0"),
+                attr(AttributeKind::LowPc, &0x80003000u32.to_be_bytes()),
+                attr(AttributeKind::HighPc, &0x80003010u32.to_be_bytes()),
+            ]),
+            tag(TagKind::GlobalSubroutine, &[
+                attr(AttributeKind::Name, b"inlineFunc\0"),
+                attr(AttributeKind::Inline, b"\0"),
+                attr(AttributeKind::LowPc, &0x80003010u32.to_be_bytes()),
+                attr(AttributeKind::HighPc, &0x80003020u32.to_be_bytes()),
+            ]),
+            tag(TagKind::GlobalVariable, &[
+                attr(AttributeKind::Name, b"gValue\0"),
+                attr(AttributeKind::Location, &[0, 5, 0x03, 0x80, 0x00, 0x40, 0x00]),
+            ]),
+        ]
+        .concat();
+        // Header (size, base address) followed by (line, position, address offset) entries
+        let line = [
+            &(8u32 + 30).to_be_bytes()[..],
+            &0x80003000u32.to_be_bytes()[..],
+            &[0, 0, 0, 12, 0xFF, 0xFF, 0, 0, 0, 0][..],
+            &[0, 0, 0, 13, 0xFF, 0xFF, 0, 0, 0, 4][..],
+            &[0, 0, 0, 20, 0xFF, 0xFF, 0, 0, 0, 0x10][..],
+        ]
+        .concat();
+
+        let symbol = |name: &str, address, section, kind| {
+            test_symbol(name, Some(section), address, 0x10, kind)
+        };
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x40);
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("func", 0x80003000, 0, ObjSymbolKind::Function),
+                symbol("inlineFunc", 0x80003010, 0, ObjSymbolKind::Function),
+                symbol("noDebug", 0x80003020, 0, ObjSymbolKind::Function),
+                symbol("gValue", 0x80004000, 1, ObjSymbolKind::Object),
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000),
+                section(".data", ObjSectionKind::Data, 0x80004000),
+            ],
+        );
+        assert_eq!(apply_dwarf_source_info(&mut obj, &debug, Some(line.as_slice())).unwrap(), 2);
+
+        let source = |name: &str| {
+            let (_, symbol) = obj.symbols.by_name(name).unwrap().unwrap();
+            (symbol.source_file.clone(), symbol.source_line)
+        };
+        assert_eq!(source("func"), (Some("main.c".to_string()), Some(12)));
+        assert_eq!(source("gValue"), (Some("main.c".to_string()), None));
+        assert_eq!(source("inlineFunc"), (None, None));
+        assert_eq!(source("noDebug"), (None, None));
+    }
+
+    #[test]
+    fn test_verify_elf() {
+        let symbol = |name: &str, section, flags| ObjSymbol {
+            flags: ObjSymbolFlagSet(flags),
+            ..test_symbol(name, section, 0, 8, ObjSymbolKind::Object)
+        };
+        let obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            vec![
+                symbol("sDefined", Some(0), ObjSymbolFlags::Global.into()),
+                symbol("sCommon", None, ObjSymbolFlags::Global | ObjSymbolFlags::Common),
+            ],
+            vec![ObjSection {
+                align: 8,
+                elf_index: 1,
+                ..test_section(".bss", ObjSectionKind::Bss, 0, 0x10)
+            }],
+        );
+        let mut data = write_elf(&obj, false).unwrap();
+        verify_elf(&obj, &data).unwrap();
+
+        // Shrink .symtab by one entry, dropping the last symbol
+        let symtab_index = object::read::File::parse(data.as_slice())
+            .unwrap()
+            .section_by_name(".symtab")
+            .unwrap()
+            .index()
+            .0;
+        let shoff = u32::from_be_bytes(*array_ref!(data, 0x20, 4)) as usize;
+        let size_offset = shoff + symtab_index * 0x28 + 0x14;
+        let size = u32::from_be_bytes(*array_ref!(data, size_offset, 4));
+        data[size_offset..size_offset + 4].copy_from_slice(&(size - 0x10).to_be_bytes());
+        let err = verify_elf(&obj, &data).unwrap_err().to_string();
+        assert_eq!(err, "Symbol count mismatch: expected 2, found 1");
+    }
+
+    #[test]
+    fn test_empty_section() {
+        let section = |name: &str, kind, data: Vec<u8>, relocations| ObjSection {
+            relocations,
+            ..test_section_data(name, kind, 0, data)
+        };
+        let reloc =
+            ObjReloc { kind: ObjRelocKind::Absolute, target_symbol: 0, addend: 0, module: None };
+        let obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.c".to_string(),
+            vec![
+                ObjSymbol {
+                    name: ".sbss".to_string(),
+                    section: Some(1),
+                    kind: ObjSymbolKind::Section,
+                    ..Default::default()
+                },
+                ObjSymbol {
+                    name: "sEmpty".to_string(),
+                    section: Some(1),
+                    size_known: true,
+                    flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                    kind: ObjSymbolKind::Object,
+                    ..Default::default()
+                },
+            ],
+            vec![
+                section(
+                    ".data",
+                    ObjSectionKind::Data,
+                    vec![0; 4],
+                    ObjRelocations::new(vec![(0, reloc)]).unwrap(),
+                ),
+                section(".sbss", ObjSectionKind::Bss, vec![], Default::default()),
+            ],
+        );
+        assert_eq!(obj.symbols.section_count(), 2);
+        let data = write_elf(&obj, false).unwrap();
+        verify_elf(&obj, &data).unwrap();
+
+        let obj_file = object::read::File::parse(data.as_slice()).unwrap();
+        let sbss = obj_file.section_by_name(".sbss").unwrap();
+        assert_eq!((sbss.kind(), sbss.size()), (SectionKind::UninitializedData, 0));
+
+        // The empty section, its symbol and the relocation against it are kept
+        let read = parse_elf(&data).unwrap();
+        let (sbss_index, sbss) = read.sections.by_name(".sbss").unwrap().unwrap();
+        assert_eq!((sbss.kind, sbss.size), (ObjSectionKind::Bss, 0));
+        assert!(read.sections.is_valid_section(sbss_index));
+        assert!(!read.sections.is_valid_section(read.sections.len()));
+        let names = read
+            .symbols
+            .for_section(sbss_index)
+            .filter(|(_, s)| s.kind != ObjSymbolKind::Section)
+            .map(|(_, s)| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["sEmpty"]);
+        let (_, data_section) = read.sections.by_name(".data").unwrap().unwrap();
+        let (_, reloc) = data_section.relocations.iter().next().unwrap();
+        assert_eq!(read.symbols[reloc.target_symbol].section, Some(sbss_index));
+
+        // And written again
+        verify_elf(&read, &write_elf(&read, false).unwrap()).unwrap();
+    }
+}
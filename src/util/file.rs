@@ -5,7 +5,7 @@ use std::{
     io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write},
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use filetime::{set_file_mtime, FileTime};
 use sha1::{Digest, Sha1};
 use typed_path::{Utf8NativePath, Utf8NativePathBuf, Utf8UnixPathBuf};
@@ -133,6 +133,51 @@ impl Iterator for FileIterator {
     fn next(&mut self) -> Option<Self::Item> { self.next_path() }
 }
 
+/// Outcome of processing a batch of files with [`for_each_file`].
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub succeeded: usize,
+    pub failures: Vec<(Utf8NativePathBuf, anyhow::Error)>,
+}
+
+impl BatchReport {
+    pub fn total(&self) -> usize { self.succeeded + self.failures.len() }
+
+    /// Logs each failure and an "N of M succeeded" summary.
+    /// Fails only if every file failed.
+    pub fn finish(self) -> Result<()> {
+        for (path, e) in &self.failures {
+            log::error!("{}: {:?}", path, e);
+        }
+        let total = self.total();
+        log::info!("{} of {} succeeded", self.succeeded, total);
+        ensure!(self.succeeded > 0 || total == 0, "All {} files failed", total);
+        Ok(())
+    }
+}
+
+/// Runs `f` on each file from [`FileIterator`]. With `keep_going`, errors (including failures
+/// to open a file) are collected per file instead of aborting the batch.
+pub fn for_each_file<F>(
+    paths: &[Utf8NativePathBuf],
+    keep_going: bool,
+    mut f: F,
+) -> Result<BatchReport>
+where
+    F: FnMut(&Utf8NativePath, &mut dyn VfsFile) -> Result<()>,
+{
+    let mut report = BatchReport::default();
+    for path in process_rsp(paths)? {
+        let result = open_file(&path, true).and_then(|mut file| f(&path, file.as_mut()));
+        match result {
+            Ok(()) => report.succeeded += 1,
+            Err(e) if keep_going => report.failures.push((path, e)),
+            Err(e) => return Err(e.context(format!("Failed to process '{}'", path))),
+        }
+    }
+    Ok(report)
+}
+
 pub fn touch(path: &Utf8NativePath) -> io::Result<()> {
     if fs::exists(path)? {
         set_file_mtime(path, FileTime::now())
@@ -216,3 +261,38 @@ where
     }
     Ok(hasher.finalize().into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_each_file() {
+        let dir = std::env::temp_dir().join(format!("dtk-batch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| Utf8NativePathBuf::from(dir.join(name).to_string_lossy().as_ref());
+        fs::write(path("good.bin"), b"good").unwrap();
+        fs::write(path("bad.bin"), b"bad").unwrap();
+        let check = |_: &Utf8NativePath, file: &mut dyn VfsFile| -> Result<()> {
+            ensure!(file.map()? == b"good", "Malformed file");
+            Ok(())
+        };
+
+        let paths = vec![path("bad.bin"), path("good.bin"), path("missing.bin")];
+        let report = for_each_file(&paths, true, check).unwrap();
+        assert_eq!((report.succeeded, report.total()), (1, 3));
+        let failed = report.failures.iter().map(|(p, _)| p.clone()).collect::<Vec<_>>();
+        assert_eq!(failed, vec![path("bad.bin"), path("missing.bin")]);
+        report.finish().unwrap();
+
+        // Fails if every file failed
+        let paths = vec![path("bad.bin"), path("missing.bin")];
+        assert!(for_each_file(&paths, true, check).unwrap().finish().is_err());
+
+        // Without keep_going, the first error aborts, naming the file
+        let err = for_each_file(&paths, false, check).unwrap_err();
+        assert!(err.to_string().contains("bad.bin"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
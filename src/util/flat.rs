@@ -0,0 +1,311 @@
+//! Links an object into a flat binary image, like a ROM image, for checksumming against the
+//! original.
+
+use anyhow::{anyhow, bail, ensure, Result};
+use itertools::Itertools;
+
+use crate::{
+    array_ref_mut,
+    obj::{ObjInfo, ObjKind, ObjRelocKind, ObjSectionKind},
+    util::{align_up, reader::Endian},
+};
+
+/// Lays out all sections starting at `base`, applies every relocation using the resolved
+/// symbol values and returns the contiguous image. BSS and gaps between sections are zeroed.
+///
+/// Executable sections are placed at their addresses, and `base` defaults to the lowest
+/// section address. Relocatable sections are placed one after another from `base`, which
+/// defaults to 0.
+pub fn link_flat(obj: &ObjInfo, base: Option<u32>) -> Result<Vec<u8>> {
+    let mut section_bases = Vec::with_capacity(obj.sections.len());
+    let base = match obj.kind {
+        ObjKind::Executable => {
+            let base = base.unwrap_or_else(|| {
+                obj.sections.iter().map(|(_, s)| s.address as u32).min().unwrap_or(0)
+            });
+            for (_, section) in obj.sections.iter() {
+                ensure!(
+                    section.address >= base as u64,
+                    "Section {} at {:#010X} is below the base address {:#010X}",
+                    section.name,
+                    section.address,
+                    base
+                );
+                section_bases.push(section.address as u32);
+            }
+            base
+        }
+        ObjKind::Relocatable => {
+            let base = base.unwrap_or(0);
+            let mut address = base;
+            for (_, section) in obj.sections.iter() {
+                address = align_up(address, section.align.max(1) as u32);
+                section_bases.push(address);
+                address += section.size as u32;
+            }
+            base
+        }
+    };
+
+    let ranges = obj
+        .sections
+        .iter()
+        .map(|(index, section)| {
+            let start = section_bases[index as usize];
+            (start, start + section.size as u32, section.name.as_str())
+        })
+        .sorted()
+        .collect_vec();
+    for ((_, prev_end, prev_name), &(start, end, name)) in ranges.iter().tuple_windows() {
+        ensure!(
+            *prev_end <= start,
+            "Sections {} and {} overlap at {:#010X}-{:#010X}",
+            prev_name,
+            name,
+            start,
+            (*prev_end).min(end)
+        );
+    }
+
+    // Relocatable objects have no linker-defined small data bases, so place them 0x8000 past
+    // the laid out .sdata and .sdata2, as the linker does
+    let laid_out_base = |name: &str| -> Result<Option<u32>> {
+        Ok(obj.sections.by_name(name)?.map(|(index, _)| section_bases[index as usize] + 0x8000))
+    };
+    let (sda_base, sda2_base) = match obj.kind {
+        ObjKind::Executable => (obj.sda_base, obj.sda2_base),
+        ObjKind::Relocatable => (laid_out_base(".sdata")?, laid_out_base(".sdata2")?),
+    };
+
+    let end = ranges.iter().map(|&(_, end, _)| end).max().unwrap_or(base);
+    let mut image = vec![0u8; (end - base) as usize];
+    for (section_index, section) in obj.sections.iter() {
+        if section.kind == ObjSectionKind::Bss {
+            continue;
+        }
+        let section_base = section_bases[section_index as usize];
+        let offset = (section_base - base) as usize;
+        image[offset..offset + section.data.len()].copy_from_slice(&section.data);
+        for (address, reloc) in section.relocations.iter() {
+            ensure!(
+                !reloc.module.is_some_and(|id| id != obj.module_id),
+                "Relocation at {}:{:#010X} targets module {}",
+                section.name,
+                address,
+                reloc.module.unwrap_or_default()
+            );
+            let target_symbol = &obj.symbols[reloc.target_symbol];
+            let (target_section, symbol_value) = match target_symbol.section {
+                Some(target_section) => {
+                    let target = &obj.sections[target_section];
+                    let value = section_bases[target_section as usize] as u64
+                        + (target_symbol.address - target.address);
+                    (Some(target), value)
+                }
+                None if obj.kind == ObjKind::Executable => (None, target_symbol.address),
+                None => bail!("Unresolved external symbol {}", target_symbol.name),
+            };
+            let target = (symbol_value as i64 + reloc.addend) as u32;
+            let source = section_base + (address as u64 - section.address) as u32;
+            let sda_base = match target_section.map(|s| s.name.as_str()) {
+                Some(".sdata" | ".sbss") => Some((13, sda_base)),
+                Some(".sdata2" | ".sbss2") => Some((2, sda2_base)),
+                Some(".PPC.EMB.sdata0" | ".PPC.EMB.sbss0") => Some((0, Some(0))),
+                Some(_) => None,
+                None => Some((0, Some(0))),
+            };
+            let ins_offset = (source - base) as usize;
+            let ins_ref = array_ref_mut!(image, ins_offset, 4);
+            let ins = match obj.endian {
+                Endian::Big => u32::from_be_bytes(*ins_ref),
+                Endian::Little => u32::from_le_bytes(*ins_ref),
+            };
+            let ins = relocate(ins, reloc.kind, source, target, sda_base).map_err(|e| {
+                anyhow!(
+                    "Relocation at {}:{:#010X} to {}: {}",
+                    section.name,
+                    address,
+                    target_symbol.name,
+                    e
+                )
+            })?;
+            *ins_ref = match obj.endian {
+                Endian::Big => ins.to_be_bytes(),
+                Endian::Little => ins.to_le_bytes(),
+            };
+        }
+    }
+    Ok(image)
+}
+
+/// Applies a relocation to the word at `source`. `sda_base` is the base register and its
+/// value for `@sda21` relocations, if the target is addressable through one.
+fn relocate(
+    ins: u32,
+    kind: ObjRelocKind,
+    source: u32,
+    target: u32,
+    sda_base: Option<(u32, Option<u32>)>,
+) -> Result<u32> {
+    let value = match kind {
+        ObjRelocKind::Absolute => target,
+        ObjRelocKind::PpcAddr16Hi => target >> 16,
+        // The low half is sign extended by addi/lwz, so carry into the high half
+        ObjRelocKind::PpcAddr16Ha => target.wrapping_add(0x8000) >> 16,
+        ObjRelocKind::PpcAddr16Lo => target & 0xFFFF,
+        ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14 => {
+            let diff = target.wrapping_sub(source) as i32;
+            let bits = if kind == ObjRelocKind::PpcRel24 { 26 } else { 16 };
+            ensure!(
+                diff >> (bits - 1) == 0 || diff >> (bits - 1) == -1,
+                "branch displacement {:#X} out of range",
+                diff
+            );
+            ensure!(diff & 3 == 0, "misaligned branch target {:#010X}", target);
+            diff as u32
+        }
+        ObjRelocKind::PpcEmbSda21 => {
+            let Some((reg, Some(sda_base))) = sda_base else {
+                bail!("target isn't addressable through a small data base");
+            };
+            let offset = target.wrapping_sub(sda_base) as i32;
+            ensure!(
+                (-0x8000..0x8000).contains(&offset),
+                "small data offset {:#X} out of range",
+                offset
+            );
+            (reg << 16) | (offset as u32 & 0xFFFF)
+        }
+    };
+    let mask = kind.mask();
+    Ok((ins & !mask) | (value & mask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{
+        test_section_data, test_symbol, ObjArchitecture, ObjReloc, ObjRelocations, ObjSection,
+        ObjSymbolKind,
+    };
+
+    #[test]
+    fn test_link_flat() {
+        let code: [u32; 4] = [
+            0x3C600000, // lis r3, value@ha
+            0x38630000, // addi r3, r3, value@l
+            0x48000001, // bl func
+            0x4E800020, // blr
+        ];
+        let text = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        let reloc = |kind, target_symbol| ObjReloc { kind, target_symbol, addend: 0, module: None };
+        let section = |name: &str, kind, data: Vec<u8>, size, relocations| ObjSection {
+            size,
+            align: 0x10,
+            relocations,
+            ..test_section_data(name, kind, 0, data)
+        };
+        let symbol =
+            |name: &str, address, section, kind| test_symbol(name, Some(section), address, 0, kind);
+        let obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.o".to_string(),
+            vec![
+                symbol("func", 0xC, 0, ObjSymbolKind::Function),
+                // Low half >= 0x8000, so @ha carries into the high half
+                symbol("value", 0x8000, 2, ObjSymbolKind::Object),
+            ],
+            vec![
+                section(
+                    ".text",
+                    ObjSectionKind::Code,
+                    text,
+                    0x10,
+                    ObjRelocations::new(vec![
+                        (0x0, reloc(ObjRelocKind::PpcAddr16Ha, 1)),
+                        (0x4, reloc(ObjRelocKind::PpcAddr16Lo, 1)),
+                        (0x8, reloc(ObjRelocKind::PpcRel24, 0)),
+                    ])
+                    .unwrap(),
+                ),
+                section(
+                    ".data",
+                    ObjSectionKind::Data,
+                    vec![0; 4],
+                    4,
+                    ObjRelocations::new(vec![(0x0, reloc(ObjRelocKind::Absolute, 0))]).unwrap(),
+                ),
+                section(".bss", ObjSectionKind::Bss, vec![], 0x8004, Default::default()),
+            ],
+        );
+
+        // .text at 0x80003000, .data at 0x80003010, .bss at 0x80003020
+        let image = link_flat(&obj, Some(0x80003000)).unwrap();
+        assert_eq!(image.len(), 0x8024);
+        assert_eq!(&image[0..0x10], [
+            0x3C, 0x60, 0x80, 0x01, // lis r3, 0x8001
+            0x38, 0x63, 0xB0, 0x20, // addi r3, r3, -0x4FE0
+            0x48, 0x00, 0x00, 0x05, // bl 0x8000300C
+            0x4E, 0x80, 0x00, 0x20, // blr
+        ]);
+        assert_eq!(&image[0x10..0x14], [0x80, 0x00, 0x30, 0x0C]);
+        assert!(image[0x14..].iter().all(|&b| b == 0));
+
+        // Overlapping sections
+        let mut obj = obj;
+        obj.kind = ObjKind::Executable;
+        obj.sections[0].address = 0x80003000;
+        obj.sections[1].address = 0x80003008;
+        obj.sections[2].address = 0x80004000;
+        let err = link_flat(&obj, None).unwrap_err().to_string();
+        assert!(err.contains("Sections .text and .data overlap"), "{err}");
+    }
+
+    #[test]
+    fn test_link_flat_small_data() {
+        let code: [u32; 3] = [
+            0x806D0000, // lwz r3, sValue@sda21(r13)
+            0x80820000, // lwz r4, sConst@sda21(r2)
+            0x80A00000, // lwz r5, sZero@sda21(r0)
+        ];
+        let text = code.iter().flat_map(|ins| ins.to_be_bytes()).collect::<Vec<u8>>();
+        let reloc = |target_symbol| ObjReloc {
+            kind: ObjRelocKind::PpcEmbSda21,
+            target_symbol,
+            addend: 0,
+            module: None,
+        };
+        let symbol = |name: &str, address, section| {
+            test_symbol(name, Some(section), address, 4, ObjSymbolKind::Object)
+        };
+        let obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test.o".to_string(),
+            vec![symbol("sValue", 4, 1), symbol("sConst", 0, 2), symbol("sZero", 0, 3)],
+            vec![
+                ObjSection {
+                    relocations: ObjRelocations::new(vec![
+                        (0x0, reloc(0)),
+                        (0x4, reloc(1)),
+                        (0x8, reloc(2)),
+                    ])
+                    .unwrap(),
+                    ..test_section_data(".text", ObjSectionKind::Code, 0, text)
+                },
+                test_section_data(".sdata", ObjSectionKind::Data, 0, vec![0; 8]),
+                test_section_data(".sdata2", ObjSectionKind::ReadOnlyData, 0, vec![0; 4]),
+                test_section_data(".PPC.EMB.sdata0", ObjSectionKind::Data, 0, vec![0; 4]),
+            ],
+        );
+
+        // .text at 0, .sdata at 0xC, .sdata2 at 0x14, .PPC.EMB.sdata0 at 0x18
+        let image = link_flat(&obj, None).unwrap();
+        assert_eq!(&image[0..0xC], [
+            0x80, 0x6D, 0x80, 0x04, // lwz r3, -0x7FFC(r13)
+            0x80, 0x82, 0x80, 0x00, // lwz r4, -0x8000(r2)
+            0x80, 0xA0, 0x00, 0x18, // lwz r5, 0x18(r0)
+        ]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Symbol export for applying names to a program loaded in Ghidra, in the format read by
+//! Ghidra's stock `ImportSymbolsScript.py`.
+//!
+//! Each line holds the name, runtime address and kind (`f` for functions, `l` for labels) of a
+//! symbol, separated by spaces. Relocatable modules are loaded at runtime addresses, so section
+//! addresses can be overridden.
+
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Result;
+
+use crate::obj::{ObjInfo, ObjKind, ObjSymbolKind};
+
+/// Writes all named symbols for `ImportSymbolsScript.py`. Names are kept mangled, since Ghidra
+/// doesn't accept the spaces and parentheses of demangled names in labels. `section_addresses`
+/// maps section names (or indices) to the runtime address the section is loaded at; other
+/// sections keep their address.
+pub fn write_ghidra_symbols<W>(
+    w: &mut W,
+    obj: &ObjInfo,
+    section_addresses: &BTreeMap<String, u32>,
+) -> Result<()>
+where
+    W: Write + ?Sized,
+{
+    let mut section_bases = obj.sections.iter().map(|(_, s)| s.address).collect::<Vec<_>>();
+    for (section, &address) in section_addresses {
+        section_bases[obj.sections.by_name_or_index(section)? as usize] = address as u64;
+    }
+
+    let abs =
+        (obj.kind == ObjKind::Executable).then(|| obj.symbols.iter_abs()).into_iter().flatten();
+    for (_, symbol) in obj.symbols.iter_ordered().chain(abs) {
+        if symbol.kind == ObjSymbolKind::Section || symbol.name.is_empty() {
+            continue;
+        }
+        let address = match symbol.section {
+            Some(section_index) => {
+                let section = &obj.sections[section_index];
+                section_bases[section_index as usize] + (symbol.address - section.address)
+            }
+            None => symbol.address,
+        };
+        let kind = match symbol.kind {
+            ObjSymbolKind::Function => "f",
+            _ => "l",
+        };
+        writeln!(w, "{} {:08X} {}", symbol.name, address, kind)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{
+        test_section, test_symbol, ObjArchitecture, ObjSectionKind, ObjSymbol, ObjSymbolFlagSet,
+        ObjSymbolFlags,
+    };
+
+    #[test]
+    fn test_write_ghidra_symbols() {
+        let symbol = |name: &str, address, section, size, kind| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, section, address, size, kind)
+        };
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x20);
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                ObjSymbol {
+                    demangled_name: Some("Game::Set(int, int)".to_string()),
+                    ..symbol("Set__4GameFii", 0x80003000, Some(0), 0x10, ObjSymbolKind::Function)
+                },
+                symbol("lbl_80003010", 0x80003010, Some(0), 0, ObjSymbolKind::Unknown),
+                symbol("sCount", 0x80004004, Some(1), 4, ObjSymbolKind::Object),
+                symbol(".data", 0x80004000, Some(1), 0, ObjSymbolKind::Section),
+                symbol("_SDA_BASE_", 0x80408000, None, 0, ObjSymbolKind::Unknown),
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000),
+                section(".data", ObjSectionKind::Data, 0x80004000),
+            ],
+        );
+
+        let write = |section_addresses: &[(&str, u32)]| {
+            let section_addresses =
+                section_addresses.iter().map(|&(name, addr)| (name.to_string(), addr)).collect();
+            let mut out = vec![];
+            write_ghidra_symbols(&mut out, &obj, &section_addresses).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            write(&[]),
+            "Set__4GameFii 80003000 f\n\
+             lbl_80003010 80003010 l\n\
+             sCount 80004004 l\n\
+             _SDA_BASE_ 80408000 l\n"
+        );
+
+        // Rebased sections by name or index, ABS symbols stay in place
+        assert_eq!(
+            write(&[(".text", 0x80500000), ("1", 0x80600000)]),
+            "Set__4GameFii 80500000 f\n\
+             lbl_80003010 80500010 l\n\
+             sCount 80600004 l\n\
+             _SDA_BASE_ 80408000 l\n"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use itertools::Itertools;
-use typed_path::{Utf8NativePathBuf, Utf8UnixPath};
+use typed_path::{Utf8NativePathBuf, Utf8UnixPath, Utf8UnixPathBuf};
 
 use crate::obj::{ObjInfo, ObjKind};
 
@@ -36,7 +36,8 @@ pub fn generate_ldscript(
 
     let mut force_active = force_active.to_vec();
     for (_, symbol) in obj.symbols.iter() {
-        if symbol.flags.is_exported() && symbol.flags.is_global() && !symbol.flags.is_no_write() {
+        if symbol.flags.is_force_active() && symbol.flags.is_global() && !symbol.flags.is_no_write()
+        {
             force_active.push(symbol.name.clone());
         }
     }
@@ -85,7 +86,8 @@ pub fn generate_ldscript_partial(
 
     let mut force_active = force_active.to_vec();
     for (_, symbol) in obj.symbols.iter() {
-        if symbol.flags.is_exported() && symbol.flags.is_global() && !symbol.flags.is_no_write() {
+        if symbol.flags.is_force_active() && symbol.flags.is_global() && !symbol.flags.is_no_write()
+        {
             force_active.push(symbol.name.clone());
         }
     }
@@ -104,3 +106,8 @@ pub fn obj_path_for_unit(unit: &str) -> Utf8NativePathBuf {
 pub fn asm_path_for_unit(unit: &str) -> Utf8NativePathBuf {
     Utf8UnixPath::new(unit).with_encoding().with_extension("s")
 }
+
+/// Path of the raw section contents included by a verbatim section, relative to the asm directory.
+pub fn bin_path_for_unit(unit: &str, section: &str) -> Utf8UnixPathBuf {
+    Utf8UnixPath::new(unit).with_extension(format!("{}.bin", section.trim_start_matches('.')))
+}
@@ -8,22 +8,23 @@ use std::{
 };
 
 use anyhow::{anyhow, bail, Error, Result};
-use cwdemangle::{demangle, DemangleOptions};
+use cwdemangle::DemangleOptions;
 use flagset::FlagSet;
 use indexmap::IndexMap;
 use itertools::Itertools;
 use multimap::MultiMap;
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
+use serde::Serialize;
 use typed_path::Utf8NativePath;
 
 use crate::{
     obj::{
-        section_kind_for_section, ObjArchitecture, ObjInfo, ObjKind, ObjSection, ObjSectionKind,
-        ObjSections, ObjSplit, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
-        ObjSymbols, ObjUnit, SectionIndex,
+        is_thread_local_name, section_kind_for_section, ObjArchitecture, ObjInfo, ObjKind,
+        ObjSection, ObjSectionKind, ObjSections, ObjSplit, ObjSymbol, ObjSymbolFlagSet,
+        ObjSymbolFlags, ObjSymbolKind, ObjSymbols, ObjUnit, SectionIndex,
     },
-    util::nested::NestedVec,
+    util::{demangle::demangle, nested::NestedVec, reader::Endian},
     vfs::open_file,
 };
 
@@ -719,24 +720,27 @@ pub fn apply_map_file(
     obj: &mut ObjInfo,
     common_bss_start: Option<u32>,
     mw_comment_version: Option<u8>,
+    section_aliases: &BTreeMap<String, String>,
 ) -> Result<()> {
     let mut file = open_file(path, true)?;
     let info = process_map(file.as_mut(), common_bss_start, mw_comment_version)?;
-    apply_map(info, obj)
+    apply_map(info, obj, section_aliases)
 }
 
 const DEFAULT_REL_SECTIONS: &[&str] =
     &[".init", ".text", ".ctors", ".dtors", ".rodata", ".data", ".bss"];
 
-fn normalize_section_name(name: &str) -> &str {
-    match name {
-        ".extabindex" => "extabindex",
-        ".extab" => "extab",
-        _ => name,
-    }
+fn normalize_section_name<'a>(name: &'a str, aliases: &'a BTreeMap<String, String>) -> &'a str {
+    aliases.get(name).map_or(name, String::as_str)
 }
 
-pub fn apply_map(mut result: MapInfo, obj: &mut ObjInfo) -> Result<()> {
+/// Applies a parsed map to `obj`. Section names in the map are normalized using
+/// `section_aliases` (alias to canonical name).
+pub fn apply_map(
+    mut result: MapInfo,
+    obj: &mut ObjInfo,
+    section_aliases: &BTreeMap<String, String>,
+) -> Result<()> {
     if result.sections.is_empty() && obj.kind == ObjKind::Executable {
         log::warn!("Memory map section missing, attempting to recreate");
         for (section_name, symbol_map) in &result.section_symbols {
@@ -752,7 +756,7 @@ pub fn apply_map(mut result: MapInfo, obj: &mut ObjInfo) -> Result<()> {
             }
             log::info!("Recreated section {} @ {:#010X} ({:#X})", section_name, address, size);
             result.sections.push(SectionInfo {
-                name: normalize_section_name(section_name).to_string(),
+                name: normalize_section_name(section_name, section_aliases).to_string(),
                 address,
                 size,
                 file_offset: 0,
@@ -771,8 +775,9 @@ pub fn apply_map(mut result: MapInfo, obj: &mut ObjInfo) -> Result<()> {
             result.sections.iter().filter(|s| s.size > 0).nth(section_index as usize)
         };
         if let Some(info) = opt {
-            if section.section_known && section.name != info.name {
-                log::warn!("Section mismatch: was {}, map says {}", section.name, info.name);
+            let name = normalize_section_name(&info.name, section_aliases);
+            if section.section_known && section.name != name {
+                log::warn!("Section mismatch: was {}, map says {}", section.name, name);
             }
             if section.address != info.address as u64 {
                 log::warn!(
@@ -788,7 +793,7 @@ pub fn apply_map(mut result: MapInfo, obj: &mut ObjInfo) -> Result<()> {
                     info.size
                 );
             }
-            section.rename(info.name.clone())?;
+            section.rename(name.to_string())?;
         } else {
             log::warn!("Section {} @ {:#010X} not found in map", section.name, section.address);
             if obj.kind == ObjKind::Relocatable {
@@ -839,7 +844,7 @@ pub fn apply_map(mut result: MapInfo, obj: &mut ObjInfo) -> Result<()> {
         if section_name == ".dead" {
             continue;
         }
-        let section_name = normalize_section_name(section_name);
+        let section_name = normalize_section_name(section_name, section_aliases);
         let (section_index, _) = obj
             .sections
             .by_name(section_name)?
@@ -849,18 +854,27 @@ pub fn apply_map(mut result: MapInfo, obj: &mut ObjInfo) -> Result<()> {
         }
     }
 
-    // Add absolute symbols
-    // TODO
-    // for symbol_entry in result.link_map_symbols.values().filter(|s| s.unit.is_none()) {
-    //     add_symbol(obj, symbol_entry, None)?;
-    // }
+    // Fill in SDA bases, stack and arena addresses from linker generated symbols
+    for symbol_entry in linker_symbols(&result) {
+        let address = Some(symbol_entry.address);
+        match symbol_entry.name.as_str() {
+            "_SDA_BASE_" if obj.sda_base.is_none() => obj.sda_base = address,
+            "_SDA2_BASE_" if obj.sda2_base.is_none() => obj.sda2_base = address,
+            "_stack_addr" if obj.stack_address.is_none() => obj.stack_address = address,
+            "_stack_end" if obj.stack_end.is_none() => obj.stack_end = address,
+            "_db_stack_addr" if obj.db_stack_addr.is_none() => obj.db_stack_addr = address,
+            "__ArenaLo" if obj.arena_lo.is_none() => obj.arena_lo = address,
+            "__ArenaHi" if obj.arena_hi.is_none() => obj.arena_hi = address,
+            _ => {}
+        }
+    }
 
     // Add splits
     for (section_name, unit_order) in &result.section_units {
         if section_name == ".dead" {
             continue;
         }
-        let section_name = normalize_section_name(section_name);
+        let section_name = normalize_section_name(section_name, section_aliases);
         let (_, section) = obj
             .sections
             .iter_mut()
@@ -910,6 +924,7 @@ pub fn create_obj(result: &MapInfo) -> Result<ObjInfo> {
             let size = s.size as u64;
             let file_offset = s.file_offset as u64;
             let kind = section_kind_for_section(&name).unwrap_or(ObjSectionKind::ReadOnlyData);
+            let thread_local = is_thread_local_name(&name);
             ObjSection {
                 name,
                 kind,
@@ -922,6 +937,8 @@ pub fn create_obj(result: &MapInfo) -> Result<ObjInfo> {
                 virtual_address: None,
                 file_offset,
                 section_known: true,
+                thread_local,
+                verbatim: false,
                 splits: Default::default(),
             }
         })
@@ -929,11 +946,18 @@ pub fn create_obj(result: &MapInfo) -> Result<ObjInfo> {
     let mut obj = ObjInfo {
         kind: ObjKind::Executable,
         architecture: ObjArchitecture::PowerPc,
+        endian: Endian::Big,
         name: "".to_string(),
         symbols: ObjSymbols::new(ObjKind::Executable, vec![]),
         sections: ObjSections::new(ObjKind::Executable, sections),
-        entry: None, // TODO result.entry_point
+        entry: result
+            .section_symbols
+            .values()
+            .flat_map(|m| m.values().flatten())
+            .find(|e| e.name == result.entry_point)
+            .map(|e| e.address as u64),
         mw_comment: None,
+        producers: vec![],
         split_meta: None,
         sda2_base: None,
         sda_base: None,
@@ -946,6 +970,7 @@ pub fn create_obj(result: &MapInfo) -> Result<ObjInfo> {
         blocked_relocation_sources: Default::default(),
         blocked_relocation_targets: Default::default(),
         known_functions: Default::default(),
+        frame_sizes: Default::default(),
         module_id: 0,
         unresolved_relocations: vec![],
     };
@@ -968,6 +993,11 @@ pub fn create_obj(result: &MapInfo) -> Result<ObjInfo> {
         }
     }
 
+    // Add absolute symbols
+    for symbol_entry in linker_symbols(result) {
+        add_symbol(&mut obj, symbol_entry, None, true)?;
+    }
+
     // Add splits
     for (section_name, unit_order) in &result.section_units {
         let (_, section) = obj
@@ -1009,6 +1039,17 @@ pub fn create_obj(result: &MapInfo) -> Result<ObjInfo> {
     Ok(obj)
 }
 
+/// Linker generated symbols with a known address, sorted by name.
+fn linker_symbols(result: &MapInfo) -> Vec<&SymbolEntry> {
+    let mut symbols = result
+        .link_map_symbols
+        .values()
+        .filter(|s| s.unit.is_none() && s.address != 0)
+        .collect_vec();
+    symbols.sort_by(|a, b| a.name.cmp(&b.name));
+    symbols
+}
+
 fn add_symbol(
     obj: &mut ObjInfo,
     symbol_entry: &SymbolEntry,
@@ -1024,7 +1065,7 @@ fn add_symbol(
     };
     // TODO move somewhere common
     if symbol_entry.name.starts_with("..") {
-        flags |= ObjSymbolFlags::Exported;
+        flags |= ObjSymbolFlags::ForceActive;
     }
     if symbol_entry.unused {
         flags |= ObjSymbolFlags::Stripped;
@@ -1051,3 +1092,379 @@ fn add_symbol(
     )?;
     Ok(())
 }
+
+/// How a symbol changed between two maps.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapDiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapSymbolDiff {
+    pub kind: MapDiffKind,
+    pub section: String,
+    pub name: String,
+    /// Set if the symbol was matched by position under a different name
+    pub new_name: Option<String>,
+    pub unit: Option<String>,
+    pub old_address: Option<u32>,
+    pub new_address: Option<u32>,
+    pub old_size: Option<u32>,
+    pub new_size: Option<u32>,
+}
+
+/// Total symbol size of a section in both maps.
+#[derive(Debug, Clone, Serialize)]
+pub struct MapSectionDiff {
+    pub name: String,
+    pub old_size: u32,
+    pub new_size: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MapDiff {
+    pub sections: Vec<MapSectionDiff>,
+    pub symbols: Vec<MapSymbolDiff>,
+}
+
+type MapDiffEntry<'a> = (&'a str, &'a SymbolEntry);
+
+fn map_diff_entries(map: &MapInfo) -> Vec<MapDiffEntry> {
+    map.section_symbols
+        .iter()
+        .flat_map(|(section, symbols)| {
+            symbols.values().flatten().map(move |symbol| (section.as_str(), symbol))
+        })
+        .filter(|(_, symbol)| !symbol.unused)
+        .collect()
+}
+
+/// The start address of each unit within each section.
+fn map_diff_unit_starts<'a>(
+    entries: &[MapDiffEntry<'a>],
+) -> HashMap<(&'a str, Option<&'a str>), u32> {
+    let mut starts = HashMap::new();
+    for &(section, symbol) in entries {
+        starts
+            .entry((section, symbol.unit.as_deref()))
+            .and_modify(|start: &mut u32| *start = (*start).min(symbol.address))
+            .or_insert(symbol.address);
+    }
+    starts
+}
+
+/// Compares the symbols of two maps.
+///
+/// Symbols are matched by name and unit first, then by unique name alone (for symbols
+/// that moved between units or sections). Remaining compiler-generated symbols (such as
+/// `@123`) are matched by their offset and size within the same unit and section, since
+/// their names change whenever the numbering shifts. Results are sorted by section, name
+/// and unit.
+pub fn diff_maps(old: &MapInfo, new: &MapInfo) -> MapDiff {
+    let old_entries = map_diff_entries(old);
+    let new_entries = map_diff_entries(new);
+    let mut old_matches: Vec<Option<usize>> = vec![None; old_entries.len()];
+    let mut new_matched = vec![false; new_entries.len()];
+
+    // Match by name and unit, preferring the same section
+    let mut by_name_unit = HashMap::<(&str, Option<&str>), Vec<usize>>::new();
+    for (idx, (_, symbol)) in new_entries.iter().enumerate() {
+        by_name_unit.nested_push((symbol.name.as_str(), symbol.unit.as_deref()), idx);
+    }
+    for (old_idx, &(section, symbol)) in old_entries.iter().enumerate() {
+        let Some(candidates) = by_name_unit.get(&(symbol.name.as_str(), symbol.unit.as_deref()))
+        else {
+            continue;
+        };
+        let found = candidates
+            .iter()
+            .copied()
+            .find(|&idx| !new_matched[idx] && new_entries[idx].0 == section)
+            .or_else(|| candidates.iter().copied().find(|&idx| !new_matched[idx]));
+        if let Some(new_idx) = found {
+            old_matches[old_idx] = Some(new_idx);
+            new_matched[new_idx] = true;
+        }
+    }
+
+    // Match remaining symbols by unique name
+    let mut old_by_name = HashMap::<&str, Vec<usize>>::new();
+    for (idx, (_, symbol)) in old_entries.iter().enumerate() {
+        if old_matches[idx].is_none() {
+            old_by_name.nested_push(symbol.name.as_str(), idx);
+        }
+    }
+    let mut new_by_name = HashMap::<&str, Vec<usize>>::new();
+    for (idx, (_, symbol)) in new_entries.iter().enumerate() {
+        if !new_matched[idx] {
+            new_by_name.nested_push(symbol.name.as_str(), idx);
+        }
+    }
+    for (name, old_idxs) in &old_by_name {
+        if let ([old_idx], Some([new_idx])) =
+            (old_idxs.as_slice(), new_by_name.get(name).map(Vec::as_slice))
+        {
+            old_matches[*old_idx] = Some(*new_idx);
+            new_matched[*new_idx] = true;
+        }
+    }
+
+    // Match remaining compiler-generated symbols by offset and size within their unit
+    let old_unit_starts = map_diff_unit_starts(&old_entries);
+    let new_unit_starts = map_diff_unit_starts(&new_entries);
+    let mut by_offset = HashMap::<(&str, Option<&str>, u32, u32), Vec<usize>>::new();
+    for (idx, &(section, symbol)) in new_entries.iter().enumerate() {
+        if new_matched[idx] || !symbol.name.starts_with('@') {
+            continue;
+        }
+        let unit = symbol.unit.as_deref();
+        let offset = symbol.address - new_unit_starts[&(section, unit)];
+        by_offset.nested_push((section, unit, offset, symbol.size), idx);
+    }
+    for (old_idx, &(section, symbol)) in old_entries.iter().enumerate() {
+        if old_matches[old_idx].is_some() || !symbol.name.starts_with('@') {
+            continue;
+        }
+        let unit = symbol.unit.as_deref();
+        let offset = symbol.address - old_unit_starts[&(section, unit)];
+        let Some(candidates) = by_offset.get(&(section, unit, offset, symbol.size)) else {
+            continue;
+        };
+        if let Some(new_idx) = candidates
+            .iter()
+            .copied()
+            .find(|&idx| !new_matched[idx] && new_entries[idx].1.kind == symbol.kind)
+        {
+            old_matches[old_idx] = Some(new_idx);
+            new_matched[new_idx] = true;
+        }
+    }
+
+    let mut symbols = vec![];
+    for (&(section, old_symbol), new_idx) in old_entries.iter().zip(&old_matches) {
+        let Some(new_idx) = *new_idx else {
+            symbols.push(MapSymbolDiff {
+                kind: MapDiffKind::Removed,
+                section: section.to_string(),
+                name: old_symbol.name.clone(),
+                new_name: None,
+                unit: old_symbol.unit.clone(),
+                old_address: Some(old_symbol.address),
+                new_address: None,
+                old_size: Some(old_symbol.size),
+                new_size: None,
+            });
+            continue;
+        };
+        let (new_section, new_symbol) = new_entries[new_idx];
+        if old_symbol.address == new_symbol.address
+            && old_symbol.size == new_symbol.size
+            && old_symbol.name == new_symbol.name
+            && section == new_section
+        {
+            continue;
+        }
+        symbols.push(MapSymbolDiff {
+            kind: MapDiffKind::Changed,
+            section: new_section.to_string(),
+            name: old_symbol.name.clone(),
+            new_name: (old_symbol.name != new_symbol.name).then(|| new_symbol.name.clone()),
+            unit: new_symbol.unit.clone(),
+            old_address: Some(old_symbol.address),
+            new_address: Some(new_symbol.address),
+            old_size: Some(old_symbol.size),
+            new_size: Some(new_symbol.size),
+        });
+    }
+    for (&(section, new_symbol), _) in
+        new_entries.iter().zip(&new_matched).filter(|(_, matched)| !**matched)
+    {
+        symbols.push(MapSymbolDiff {
+            kind: MapDiffKind::Added,
+            section: section.to_string(),
+            name: new_symbol.name.clone(),
+            new_name: None,
+            unit: new_symbol.unit.clone(),
+            old_address: None,
+            new_address: Some(new_symbol.address),
+            old_size: None,
+            new_size: Some(new_symbol.size),
+        });
+    }
+    symbols.sort_by(|a, b| {
+        (&a.section, &a.name, &a.unit, a.old_address.or(a.new_address)).cmp(&(
+            &b.section,
+            &b.name,
+            &b.unit,
+            b.old_address.or(b.new_address),
+        ))
+    });
+
+    let mut section_sizes = BTreeMap::<&str, (u32, u32)>::new();
+    for &(section, symbol) in &old_entries {
+        section_sizes.entry(section).or_default().0 += symbol.size;
+    }
+    for &(section, symbol) in &new_entries {
+        section_sizes.entry(section).or_default().1 += symbol.size;
+    }
+    let sections = section_sizes
+        .into_iter()
+        .map(|(name, (old_size, new_size))| MapSectionDiff {
+            name: name.to_string(),
+            old_size,
+            new_size,
+        })
+        .collect();
+    MapDiff { sections, symbols }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, unit: &str, address: u32, size: u32) -> SymbolEntry {
+        SymbolEntry {
+            name: name.to_string(),
+            demangled: None,
+            kind: SymbolKind::Object,
+            visibility: SymbolVisibility::Global,
+            unit: Some(unit.to_string()),
+            address,
+            size,
+            align: None,
+            unused: false,
+        }
+    }
+
+    fn map(symbols: Vec<SymbolEntry>) -> MapInfo {
+        let mut section_symbols = BTreeMap::<u32, Vec<SymbolEntry>>::new();
+        for symbol in symbols {
+            section_symbols.nested_push(symbol.address, symbol);
+        }
+        let mut info = MapInfo::default();
+        info.section_symbols.insert(".data".to_string(), section_symbols);
+        info
+    }
+
+    /// Trimmed CodeWarrior map with common BSS and linker generated symbols.
+    const COMMON_BSS_MAP: &str = "\
+Link map of __start
+ 1] __start (func,global) found in init.o
+  2] main (func,global) found in main.o
+   3] gCommonA (object,global) found in main.o
+   3] gCommonB (object,global) found in main.o
+  2] _SDA_BASE_ found as linker generated symbol
+
+.init section layout
+  Starting        Virtual  File
+  address  Size   address  offset
+  ---------------------------------
+  00000000 000010 80003100 00000100  4 .init \tinit.o
+  00000000 000010 80003100 00000100  4 __start \tinit.o
+
+.text section layout
+  Starting        Virtual  File
+  address  Size   address  offset
+  ---------------------------------
+  00000000 000010 80003110 00000110  4 .text \tmain.o
+  00000000 000010 80003110 00000110  4 main \tmain.o
+
+.bss section layout
+  Starting        Virtual  File
+  address  Size   address  offset
+  ---------------------------------
+  00000000 000008 80400000 00000000  8 .bss \tmain.o
+  00000000 000008 80400000 00000000  8 gLocal \tmain.o
+  00000008 000010 80400008 00000000  4 .bss \tutil.o
+  00000008 000010 80400008 00000000  4 gUtil \tutil.o
+  00000018 000028 80400018 00000000  4 gCommonA \tmain.o
+  00000030 000010 80400030 00000000 16 gCommonB \tmain.o
+
+Memory map:
+                   Starting Size     File
+                   address           Offset
+      .init        80003100 00000010 00000100
+      .text        80003110 00000010 00000110
+       .bss        80400000 00000040 00000000
+
+Linker generated symbols:
+            _SDA_BASE_ 80408000
+           _stack_addr 80410000
+";
+
+    #[test]
+    fn test_common_bss_map() {
+        let info = process_map(&mut std::io::Cursor::new(COMMON_BSS_MAP), None, Some(10)).unwrap();
+        assert_eq!(info.entry_point, "__start");
+        assert_eq!(info.common_bss_start, Some(0x80400018));
+        let bss = info
+            .section_symbols
+            .get(".bss")
+            .unwrap()
+            .values()
+            .flatten()
+            .map(|e| (e.name.as_str(), e.address, e.size, e.align))
+            .collect::<Vec<_>>();
+        assert_eq!(bss, vec![
+            ("gLocal", 0x80400000, 0x8, Some(8)),
+            ("gUtil", 0x80400008, 0x10, Some(4)),
+            // Inflated size corrected by subtracting the rest of the TU
+            ("gCommonA", 0x80400018, 0x18, Some(4)),
+            ("gCommonB", 0x80400030, 0x10, Some(16)),
+        ]);
+
+        let obj = create_obj(&info).unwrap();
+        assert_eq!(obj.entry, Some(0x80003100));
+        assert_eq!(obj.sda_base, Some(0x80408000));
+        assert_eq!(obj.stack_address, Some(0x80410000));
+        let (_, sda_base) = obj.symbols.by_name("_SDA_BASE_").unwrap().unwrap();
+        assert_eq!(sda_base.section, None);
+        let (_, bss) = obj.sections.by_name(".bss").unwrap().unwrap();
+        let splits = bss
+            .splits
+            .iter()
+            .map(|(addr, split)| (addr, split.unit.as_str(), split.end, split.common))
+            .collect::<Vec<_>>();
+        assert_eq!(splits, vec![
+            (0x80400000, "main.o", 0x80400008, false),
+            (0x80400008, "util.o", 0x80400018, false),
+            (0x80400018, "main.o", 0x80400040, true),
+        ]);
+    }
+
+    #[test]
+    fn test_diff_maps() {
+        let old = map(vec![
+            entry("same", "a.c", 0x80000000, 0x10),
+            entry("grows", "a.c", 0x80000010, 0x10),
+            entry("@100", "b.c", 0x80000020, 0x8),
+            entry("gone", "b.c", 0x80000028, 0x4),
+        ]);
+        let new = map(vec![
+            entry("same", "a.c", 0x80000000, 0x10),
+            entry("grows", "a.c", 0x80000010, 0x18),
+            entry("@102", "b.c", 0x80000028, 0x8),
+            entry("appeared", "b.c", 0x80000030, 0x4),
+        ]);
+        let diff = diff_maps(&old, &new);
+        let summary = diff
+            .symbols
+            .iter()
+            .map(|s| (s.kind, s.name.as_str(), s.new_name.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            (MapDiffKind::Changed, "@100", Some("@102")),
+            (MapDiffKind::Added, "appeared", None),
+            (MapDiffKind::Removed, "gone", None),
+            (MapDiffKind::Changed, "grows", None),
+        ]);
+        assert_eq!(diff.symbols[0].old_address, Some(0x80000020));
+        assert_eq!(diff.symbols[0].new_address, Some(0x80000028));
+        assert_eq!(diff.symbols[3].new_size, Some(0x18));
+        assert_eq!(diff.sections.len(), 1);
+        assert_eq!((diff.sections[0].old_size, diff.sections[0].new_size), (0x2C, 0x34));
+    }
+}
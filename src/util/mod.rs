@@ -5,17 +5,21 @@ pub mod asm;
 pub mod bin2c;
 pub mod comment;
 pub mod config;
+pub mod demangle;
 pub mod dep;
 pub mod diff;
 pub mod dol;
 pub mod dwarf;
 pub mod elf;
 pub mod file;
+pub mod flat;
+pub mod ghidra;
 pub mod lcf;
 pub mod map;
 pub mod ncompress;
 pub mod nested;
 pub mod nlzss;
+pub mod objdiff;
 pub mod path;
 pub mod rarc;
 pub mod read;
@@ -24,6 +28,8 @@ pub mod rel;
 pub mod rso;
 pub mod signatures;
 pub mod split;
+pub mod symbol_dump;
+pub mod symbol_list;
 pub mod take_seek;
 pub mod u8_arc;
 pub mod wad;
@@ -1,9 +1,44 @@
-use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{anyhow, ensure, Result};
 use orthrus_ncompress::{yay0::Yay0, yaz0::Yaz0};
 
+use crate::vfs::CompressionKind;
+
 pub const YAZ0_MAGIC: [u8; 4] = *b"Yaz0";
 pub const YAY0_MAGIC: [u8; 4] = *b"Yay0";
 
+/// Default limit for the decompressed size of Yaz0/Yay0 data (512 MiB).
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 512 * 1024 * 1024;
+
+static MAX_DECOMPRESSED_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_DECOMPRESSED_SIZE);
+
+/// Sets the limit for the decompressed size of Yaz0/Yay0 data, to guard against
+/// decompression bombs.
+pub fn set_max_decompressed_size(size: usize) {
+    MAX_DECOMPRESSED_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// The limit for the decompressed size of Yaz0/Yay0 data.
+pub fn max_decompressed_size() -> usize { MAX_DECOMPRESSED_SIZE.load(Ordering::Relaxed) }
+
+/// Reads the decompressed size from a Yaz0/Yay0 header and checks it against the limit.
+fn check_decompressed_size(input: &[u8], magic: [u8; 4]) -> Result<usize> {
+    ensure!(
+        input.len() >= 16 && input[0..4] == magic,
+        "Invalid {} header",
+        String::from_utf8_lossy(&magic)
+    );
+    let size = u32::from_be_bytes(input[4..8].try_into().unwrap()) as usize;
+    ensure!(
+        size <= max_decompressed_size(),
+        "Decompressed size {:#X} exceeds limit {:#X}",
+        size,
+        max_decompressed_size()
+    );
+    Ok(size)
+}
+
 /// Compresses the data into a new allocated buffer using Yaz0 compression.
 pub fn compress_yaz0(input: &[u8]) -> Box<[u8]> {
     let mut output = vec![0u8; Yaz0::worst_possible_size(input.len())];
@@ -15,7 +50,10 @@ pub fn compress_yaz0(input: &[u8]) -> Box<[u8]> {
 /// Decompresses the data into a new allocated buffer. Assumes a Yaz0 header followed by
 /// compressed data.
 pub fn decompress_yaz0(input: &[u8]) -> Result<Box<[u8]>> {
-    Yaz0::decompress_from(input).map_err(|e| anyhow!(e))
+    let size = check_decompressed_size(input, YAZ0_MAGIC)?;
+    let output = Yaz0::decompress_from(input).map_err(|e| anyhow!(e))?;
+    ensure!(output.len() == size, "Yaz0 size mismatch: {:#X} != {:#X}", output.len(), size);
+    Ok(output)
 }
 
 /// Compresses the data into a new allocated buffer using Yay0 compression.
@@ -29,5 +67,43 @@ pub fn compress_yay0(input: &[u8]) -> Box<[u8]> {
 /// Decompresses the data into a new allocated buffer. Assumes a Yay0 header followed by
 /// compressed data.
 pub fn decompress_yay0(input: &[u8]) -> Result<Box<[u8]>> {
-    Yay0::decompress_from(input).map_err(|e| anyhow!(e))
+    let size = check_decompressed_size(input, YAY0_MAGIC)?;
+    let output = Yay0::decompress_from(input).map_err(|e| anyhow!(e))?;
+    ensure!(output.len() == size, "Yay0 size mismatch: {:#X} != {:#X}", output.len(), size);
+    Ok(output)
+}
+
+/// Compresses the data using the given compression kind.
+pub fn compress(kind: CompressionKind, input: &[u8]) -> Result<Box<[u8]>> {
+    match kind {
+        CompressionKind::Yaz0 => Ok(compress_yaz0(input)),
+        CompressionKind::Yay0 => Ok(compress_yay0(input)),
+        CompressionKind::Nlzss => Err(anyhow!("NLZSS compression is not supported")),
+    }
+}
+
+/// Parses a compression kind for writing from a command line argument.
+pub fn parse_compression(value: &str) -> Result<CompressionKind, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "yaz0" => Ok(CompressionKind::Yaz0),
+        "yay0" => Ok(CompressionKind::Yay0),
+        _ => Err(format!("Invalid compression '{value}', expected yaz0 or yay0")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompressed_size_limit() {
+        let data = (0..0x400u32).flat_map(|i| (i % 7).to_be_bytes()).collect::<Vec<_>>();
+        let compressed = compress_yaz0(&data);
+        assert_eq!(&*decompress_yaz0(&compressed).unwrap(), data.as_slice());
+
+        // Header claims a larger size than the limit
+        let mut bomb = compressed.to_vec();
+        bomb[4..8].copy_from_slice(&(DEFAULT_MAX_DECOMPRESSED_SIZE as u32 + 1).to_be_bytes());
+        assert!(decompress_yaz0(&bomb).is_err());
+    }
 }
@@ -52,6 +52,9 @@ where
     fn nested_remove(&mut self, v1: &T1, v2: &T2) {
         if let Some(vec) = self.get_mut(v1) {
             vec.retain(|n| n != v2);
+            if vec.is_empty() {
+                self.remove(v1);
+            }
         }
     }
 }
@@ -66,6 +69,9 @@ where
     fn nested_remove(&mut self, v1: &T1, v2: &T2) {
         if let Some(vec) = self.get_mut(v1) {
             vec.retain(|n| n != v2);
+            if vec.is_empty() {
+                self.remove(v1);
+            }
         }
     }
 }
@@ -0,0 +1,234 @@
+//! Reads symbol names from an objdiff progress report (`objdiff-cli report generate`).
+//!
+//! Only the subset of the report needed to recover function names is parsed. 64-bit
+//! integers are accepted both as JSON numbers and as strings, since the protobuf JSON
+//! encoding used by newer objdiff versions emits them as strings.
+
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use typed_path::Utf8NativePath;
+
+use crate::{
+    obj::{ObjInfo, ObjSectionKind, ObjSymbol, ObjSymbolKind},
+    util::config::is_auto_symbol,
+    vfs::open_file,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Report {
+    #[serde(default)]
+    pub units: Vec<ReportUnit>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReportUnit {
+    pub name: String,
+    #[serde(default)]
+    pub functions: Vec<ReportItem>,
+    #[serde(default)]
+    pub metadata: Option<ReportUnitMetadata>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReportUnitMetadata {
+    /// Omitted for the main module (ID 0).
+    #[serde(default)]
+    pub module_id: u32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReportItem {
+    pub name: String,
+    #[serde(default, deserialize_with = "deserialize_u64")]
+    pub size: u64,
+    #[serde(default)]
+    pub metadata: Option<ReportItemMetadata>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReportItemMetadata {
+    #[serde(default)]
+    pub demangled_name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_opt_u64")]
+    pub virtual_address: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum U64Value {
+    Number(u64),
+    String(String),
+}
+
+impl U64Value {
+    fn parse<E: serde::de::Error>(self) -> Result<u64, E> {
+        match self {
+            U64Value::Number(n) => Ok(n),
+            U64Value::String(s) => s.parse().map_err(E::custom),
+        }
+    }
+}
+
+fn deserialize_u64<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where D: Deserializer<'de> {
+    U64Value::deserialize(deserializer)?.parse()
+}
+
+fn deserialize_opt_u64<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where D: Deserializer<'de> {
+    Option::<U64Value>::deserialize(deserializer)?.map(U64Value::parse).transpose()
+}
+
+pub fn read_report<R>(r: &mut R) -> Result<Report>
+where R: Read + ?Sized {
+    Ok(serde_json::from_reader(r)?)
+}
+
+pub fn apply_report_file(path: &Utf8NativePath, obj: &mut ObjInfo) -> Result<usize> {
+    let mut file = open_file(path, true)?;
+    let report = read_report(file.as_mut())
+        .with_context(|| format!("Failed to parse objdiff report '{}'", path))?;
+    apply_report(&report, obj)
+}
+
+/// Adds the functions named in an objdiff report to `obj`. Units for other modules are
+/// skipped. Existing auto-generated symbols are renamed, but known names are kept.
+/// Returns the number of names applied.
+pub fn apply_report(report: &Report, obj: &mut ObjInfo) -> Result<usize> {
+    let mut applied = 0;
+    for unit in &report.units {
+        let module_id = unit.metadata.as_ref().map_or(0, |m| m.module_id);
+        if module_id != obj.module_id {
+            continue;
+        }
+        for function in &unit.functions {
+            let Some(address) = function.metadata.as_ref().and_then(|m| m.virtual_address) else {
+                continue;
+            };
+            let Ok((section_index, section)) = obj.sections.at_address(address as u32) else {
+                log::warn!(
+                    "Function {} ({}) @ {:#010X} is outside of any section",
+                    function.name,
+                    unit.name,
+                    address
+                );
+                continue;
+            };
+            if section.kind != ObjSectionKind::Code {
+                continue;
+            }
+            let existing = obj
+                .symbols
+                .at_section_address(section_index, address as u32)
+                .find(|(_, s)| matches!(s.kind, ObjSymbolKind::Function | ObjSymbolKind::Unknown))
+                .map(|(_, s)| is_auto_symbol(s));
+            if existing == Some(false) {
+                // Keep names that are already known
+                continue;
+            }
+            obj.add_symbol(
+                ObjSymbol {
+                    name: function.name.clone(),
+                    demangled_name: function
+                        .metadata
+                        .as_ref()
+                        .and_then(|m| m.demangled_name.clone()),
+                    address,
+                    section: Some(section_index),
+                    size: function.size,
+                    size_known: function.size != 0,
+                    kind: ObjSymbolKind::Function,
+                    ..Default::default()
+                },
+                existing.is_some(),
+            )?;
+            applied += 1;
+        }
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section, test_symbol, ObjArchitecture, ObjKind};
+
+    const REPORT: &str = r#"{
+        "measures": { "fuzzy_match_percent": 50.0, "total_code": "48" },
+        "units": [
+            {
+                "name": "main/game",
+                "measures": { "total_code": "48" },
+                "functions": [
+                    {
+                        "name": "update__4GameFv",
+                        "size": "16",
+                        "fuzzy_match_percent": 100.0,
+                        "metadata": {
+                            "demangled_name": "Game::update()",
+                            "virtual_address": "2147496192"
+                        }
+                    },
+                    {
+                        "name": "draw__4GameFv",
+                        "size": 16,
+                        "fuzzy_match_percent": 42.5,
+                        "metadata": { "virtual_address": 2147496208 }
+                    },
+                    {
+                        "name": "init__4GameFv",
+                        "size": "16",
+                        "metadata": { "virtual_address": "2147496224" }
+                    },
+                    { "name": "unplaced__4GameFv", "size": "4" }
+                ]
+            },
+            {
+                "name": "rel/stage",
+                "functions": [
+                    {
+                        "name": "stageInit",
+                        "size": "16",
+                        "metadata": { "virtual_address": "2147496192" }
+                    }
+                ],
+                "metadata": { "module_id": 1 }
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_apply_report() {
+        let function = |name: &str, address| {
+            test_symbol(name, Some(0), address, 0x10, ObjSymbolKind::Function)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![function("fn_80003000", 0x80003000), function("GameInit", 0x80003020)],
+            vec![test_section(".text", ObjSectionKind::Code, 0x80003000, 0x40)],
+        );
+
+        let report = read_report(&mut REPORT.as_bytes()).unwrap();
+        assert_eq!(apply_report(&report, &mut obj).unwrap(), 2);
+
+        // Auto symbol renamed
+        assert!(obj.symbols.by_name("fn_80003000").unwrap().is_none());
+        let (_, update) = obj.symbols.by_name("update__4GameFv").unwrap().unwrap();
+        assert_eq!(update.address, 0x80003000);
+        assert_eq!(update.demangled_name.as_deref(), Some("Game::update()"));
+        // New symbol added
+        let (_, draw) = obj.symbols.by_name("draw__4GameFv").unwrap().unwrap();
+        assert_eq!(
+            (draw.address, draw.size, draw.kind),
+            (0x80003010, 0x10, ObjSymbolKind::Function)
+        );
+        // Known names are kept, other modules are skipped
+        assert!(obj.symbols.by_name("GameInit").unwrap().is_some());
+        assert!(obj.symbols.by_name("init__4GameFv").unwrap().is_none());
+        assert!(obj.symbols.by_name("stageInit").unwrap().is_none());
+    }
+}
@@ -20,6 +20,15 @@ impl From<object::Endianness> for Endian {
     }
 }
 
+impl From<Endian> for object::Endianness {
+    fn from(value: Endian) -> Self {
+        match value {
+            Endian::Big => object::Endianness::Big,
+            Endian::Little => object::Endianness::Little,
+        }
+    }
+}
+
 pub const DYNAMIC_SIZE: usize = 0;
 
 pub const fn struct_size<const N: usize>(fields: [usize; N]) -> usize {
@@ -1,7 +1,7 @@
 use std::{
     cmp::Ordering,
     io,
-    io::{Read, Seek, SeekFrom, Write},
+    io::{Cursor, Read, Seek, SeekFrom, Write},
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
@@ -423,6 +423,8 @@ where R: Read + Seek + ?Sized {
             virtual_address: None, // TODO option to set?
             file_offset: offset as u64,
             section_known,
+            thread_local: false,
+            verbatim: false,
             splits: Default::default(),
         });
     }
@@ -435,7 +437,7 @@ where R: Read + Seek + ?Sized {
 
     let mut symbols = Vec::new();
     let mut add_symbol =
-        |rel_section_idx: u8, offset: u32, name: &str, force_active: bool| -> Result<()> {
+        |rel_section_idx: u8, offset: u32, name: &str, exported: bool| -> Result<()> {
             if rel_section_idx > 0 {
                 let (section_index, _) = sections
                     .iter()
@@ -444,8 +446,9 @@ where R: Read + Seek + ?Sized {
                     .ok_or_else(|| anyhow!("Failed to locate {name} section {rel_section_idx}"))?;
                 log::debug!("Adding {name} section {rel_section_idx} offset {offset:#X}");
                 let mut flags = ObjSymbolFlagSet(ObjSymbolFlags::Global.into());
-                if force_active {
+                if exported {
                     flags.set_force_active(true);
+                    flags.set_exported(true);
                 }
                 symbols.push(ObjSymbol {
                     name: name.to_string(),
@@ -634,32 +637,65 @@ fn skip_reloc(module_id: u32, rel_reloc: &RelReloc) -> bool {
         && matches!(rel_reloc.kind, ObjRelocKind::PpcRel24 | ObjRelocKind::PpcRel14)
 }
 
+/// Checks that a relative branch from `source` to `target` (both section offsets) fits in
+/// the displacement field of `kind`, returning the displacement.
+fn branch_displacement(kind: ObjRelocKind, section: u8, source: u32, target: u32) -> Result<i32> {
+    let (name, bits) = match kind {
+        ObjRelocKind::PpcRel24 => ("R_PPC_REL24", 26),
+        ObjRelocKind::PpcRel14 => ("R_PPC_REL14", 16),
+        kind => bail!("Unsupported relocation kind {:?}", kind),
+    };
+    let diff = target as i64 - source as i64;
+    let min = -(1i64 << (bits - 1));
+    let max = (1i64 << (bits - 1)) - 4;
+    if diff < min || diff > max {
+        let overflow = if diff < min { min - diff } else { diff - max };
+        bail!(
+            "{} relocation out of range: branch at section {} offset {:#X} to offset {:#X} \
+            has displacement {}{:#X}, exceeding the maximum of {:#X} by {:#X} bytes; \
+            move the target closer or split the branch through a trampoline",
+            name,
+            section,
+            source,
+            target,
+            if diff < 0 { "-" } else { "" },
+            diff.unsigned_abs(),
+            max,
+            overflow
+        );
+    }
+    ensure!(
+        diff & 3 == 0,
+        "{} relocation at section {} offset {:#X} has misaligned target offset {:#X}",
+        name,
+        section,
+        source,
+        target
+    );
+    Ok(diff as i32)
+}
+
 fn apply_relocation(
     data: &mut [u8],
     module_id: u32,
     rel_reloc: &RelReloc,
     header: &RelHeader,
 ) -> Result<()> {
-    let diff = if rel_reloc.module_id == module_id && rel_reloc.section == rel_reloc.target_section
-    {
-        rel_reloc.addend as i32 - rel_reloc.address as i32
-    } else if header.unresolved_section == rel_reloc.section {
-        header.unresolved_offset as i32 - rel_reloc.address as i32
-    } else {
-        return Ok(());
-    };
+    let target =
+        if rel_reloc.module_id == module_id && rel_reloc.section == rel_reloc.target_section {
+            rel_reloc.addend
+        } else if header.unresolved_section == rel_reloc.section {
+            header.unresolved_offset
+        } else {
+            return Ok(());
+        };
+    let diff = branch_displacement(rel_reloc.kind, rel_reloc.section, rel_reloc.address, target)?;
     let ins_ref = array_ref_mut!(data, rel_reloc.address as usize, 4);
     let mut ins = u32::from_be_bytes(*ins_ref);
     match rel_reloc.kind {
-        ObjRelocKind::PpcRel24 => {
-            ensure!((-0x2000000..0x2000000).contains(&diff), "R_PPC_REL24 relocation out of range");
-            ins = (ins & !0x3fffffc) | (diff as u32 & 0x3fffffc);
-        }
-        ObjRelocKind::PpcRel14 => {
-            ensure!((-0x2000..0x2000).contains(&diff), "R_PPC_REL14 relocation out of range");
-            ins = (ins & !0xfffc) | (diff as u32 & 0xfffc);
-        }
-        kind => bail!("Unsupported relocation kind {:?}", kind),
+        ObjRelocKind::PpcRel24 => ins = (ins & !0x3fffffc) | (diff as u32 & 0x3fffffc),
+        ObjRelocKind::PpcRel14 => ins = (ins & !0xfffc) | (diff as u32 & 0xfffc),
+        _ => unreachable!(),
     }
     *ins_ref = ins.to_be_bytes();
     Ok(())
@@ -705,15 +741,48 @@ pub fn should_write_section(section: &object::Section) -> bool {
     section.kind() != object::SectionKind::UninitializedData
 }
 
+/// Sorts relocations by source offset and collapses duplicate entries (same source offset
+/// and kind), which would otherwise be applied twice. Duplicates with conflicting targets
+/// are an error.
+pub fn normalize_relocations(relocations: &mut Vec<RelReloc>) -> Result<()> {
+    relocations.sort_by(|a, b| a.section.cmp(&b.section).then(a.address.cmp(&b.address)));
+    let mut result = Vec::<RelReloc>::with_capacity(relocations.len());
+    for reloc in relocations.drain(..) {
+        if let Some(existing) = result
+            .iter()
+            .rev()
+            .take_while(|r| r.section == reloc.section && r.address == reloc.address)
+            .find(|r| r.kind == reloc.kind)
+        {
+            ensure!(
+                existing.module_id == reloc.module_id
+                    && existing.target_section == reloc.target_section
+                    && existing.addend == reloc.addend,
+                "Conflicting duplicate {:?} relocations at section {} offset {:#X}",
+                reloc.kind,
+                reloc.section,
+                reloc.address
+            );
+            continue;
+        }
+        result.push(reloc);
+    }
+    *relocations = result;
+    Ok(())
+}
+
+/// Writes a REL from a linked ELF. Returns the number of relocations written, excluding ones
+/// applied directly to the section data.
 pub fn write_rel<W>(
     w: &mut W,
     info: &RelWriteInfo,
     file: &object::File,
     mut relocations: Vec<RelReloc>,
-) -> Result<()>
+) -> Result<usize>
 where
     W: Write + Seek + ?Sized,
 {
+    normalize_relocations(&mut relocations)?;
     if info.version >= 3 {
         // Version 3 RELs put module ID 0 and self-relocations last,
         // so that the space can be reclaimed via OSLinkFixed. (See fix_size)
@@ -1075,6 +1144,47 @@ where
         }
     }
     ensure!(w.stream_position()? as u32 == offset);
+    Ok(relocations.len())
+}
+
+/// Re-reads a REL written by [`write_rel`] and checks its section sizes, `_prolog`, `_epilog`
+/// and `_unresolved` symbols and relocation count against the source ELF, failing on the first
+/// mismatch. `relocation_count` is the value returned by [`write_rel`].
+pub fn verify_rel(data: &[u8], file: &object::File, relocation_count: usize) -> Result<()> {
+    let mut reader = Cursor::new(data);
+    let (header, obj) = process_rel(&mut reader, "").context("Failed to re-read written REL")?;
+    for (index, section) in process_rel_sections(&mut reader, &header)?.iter().enumerate() {
+        let expected = file
+            .section_by_index(object::SectionIndex(index))
+            .ok()
+            .filter(is_permitted_section)
+            .map_or(0, |s| s.size());
+        ensure!(
+            section.size() as u64 == expected,
+            "Section {} size mismatch: expected {:#X}, found {:#X}",
+            index,
+            expected,
+            section.size()
+        );
+    }
+    let expected_symbols = file
+        .symbols()
+        .filter(|s| s.is_definition() && s.section_index().is_some())
+        .filter(|s| matches!(s.name(), Ok("_prolog" | "_epilog" | "_unresolved")))
+        .count();
+    let found_symbols = obj.symbols.iter().count();
+    ensure!(
+        found_symbols == expected_symbols,
+        "Symbol count mismatch: expected {}, found {}",
+        expected_symbols,
+        found_symbols
+    );
+    ensure!(
+        obj.unresolved_relocations.len() == relocation_count,
+        "Relocation count mismatch: expected {}, found {}",
+        relocation_count,
+        obj.unresolved_relocations.len()
+    );
     Ok(())
 }
 
@@ -1120,3 +1230,91 @@ pub fn update_rel_section_alignment(obj: &mut ObjInfo, header: &RelHeader) -> Re
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn branch(address: u32, target: u32) -> RelReloc {
+        RelReloc {
+            kind: ObjRelocKind::PpcRel24,
+            section: 1,
+            address,
+            module_id: 1,
+            target_section: 1,
+            addend: target,
+            original_section: 1,
+            original_target_section: 1,
+        }
+    }
+
+    #[test]
+    fn test_apply_relocation_range() {
+        let header = RelHeader {
+            module_id: 1,
+            num_sections: 2,
+            section_info_offset: 0,
+            name_offset: 0,
+            name_size: 0,
+            version: 3,
+            bss_size: 0,
+            rel_offset: 0,
+            imp_offset: 0,
+            imp_size: 0,
+            prolog_section: 1,
+            epilog_section: 1,
+            unresolved_section: 1,
+            prolog_offset: 0,
+            epilog_offset: 0,
+            unresolved_offset: 0,
+            align: None,
+            bss_align: None,
+            fix_size: None,
+        };
+        let mut data = vec![0u8; 0x10];
+        data[4..8].copy_from_slice(&0x48000001u32.to_be_bytes()); // bl
+
+        // Backwards branch in range
+        apply_relocation(&mut data, 1, &branch(4, 0), &header).unwrap();
+        assert_eq!(u32::from_be_bytes(data[4..8].try_into().unwrap()), 0x4BFFFFFD);
+
+        // Largest forward displacement
+        apply_relocation(&mut data, 1, &branch(4, 0x2000000), &header).unwrap();
+        assert_eq!(u32::from_be_bytes(data[4..8].try_into().unwrap()), 0x49FFFFFD);
+
+        // 4 bytes too far
+        let err = apply_relocation(&mut data, 1, &branch(4, 0x2000004), &header).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("R_PPC_REL24 relocation out of range"), "{message}");
+        assert!(message.contains("offset 0x4 to offset 0x2000004"), "{message}");
+        assert!(message.contains("by 0x4 bytes"), "{message}");
+        // Instruction left untouched
+        assert_eq!(u32::from_be_bytes(data[4..8].try_into().unwrap()), 0x49FFFFFD);
+
+        // REL14 covers a signed 16-bit displacement
+        let reloc = RelReloc { kind: ObjRelocKind::PpcRel14, ..branch(4, 0x8000) };
+        apply_relocation(&mut data, 1, &reloc, &header).unwrap();
+        let reloc = RelReloc { kind: ObjRelocKind::PpcRel14, ..branch(4, 0x8004) };
+        let err = apply_relocation(&mut data, 1, &reloc, &header).unwrap_err();
+        assert!(err.to_string().contains("by 0x4 bytes"), "{err}");
+    }
+
+    #[test]
+    fn test_normalize_relocations() {
+        // Identical duplicates collapse, output is sorted by offset
+        let mut relocations = vec![branch(8, 0x20), branch(4, 0x10), branch(8, 0x20)];
+        normalize_relocations(&mut relocations).unwrap();
+        assert_eq!(relocations.iter().map(|r| r.address).collect::<Vec<_>>(), [4, 8]);
+
+        // Different kinds at the same offset are kept
+        let lo = RelReloc { kind: ObjRelocKind::PpcAddr16Lo, ..branch(4, 0x10) };
+        let mut relocations = vec![branch(4, 0x10), lo];
+        normalize_relocations(&mut relocations).unwrap();
+        assert_eq!(relocations.len(), 2);
+
+        // Conflicting targets are an error
+        let mut relocations = vec![branch(0xC, 0x10), branch(0xC, 0x14)];
+        let err = normalize_relocations(&mut relocations).unwrap_err();
+        assert!(err.to_string().contains("offset 0xC"), "{err}");
+    }
+}
@@ -4,7 +4,7 @@ use std::{
 };
 
 use anyhow::{anyhow, ensure, Result};
-use cwdemangle::{demangle, DemangleOptions};
+use cwdemangle::DemangleOptions;
 
 use crate::{
     obj::{
@@ -12,6 +12,7 @@ use crate::{
         ObjSymbolFlags, ObjSymbolKind, SectionIndex,
     },
     util::{
+        demangle::demangle,
         file::{read_c_string, read_string},
         reader::{struct_size, Endian, FromReader, ToWriter, DYNAMIC_SIZE},
     },
@@ -457,6 +458,8 @@ where R: Read + Seek + ?Sized {
             virtual_address: None, // TODO option to set?
             file_offset: offset as u64,
             section_known: false,
+            thread_local: false,
+            verbatim: false,
             splits: Default::default(),
         });
         if offset == 0 {
@@ -1,8 +1,11 @@
-use std::collections::{btree_map, BTreeMap};
+use std::{
+    collections::{btree_map, BTreeMap},
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 use anyhow::{anyhow, bail, ensure, Result};
 use base64::{engine::general_purpose::STANDARD, Engine};
-use cwdemangle::{demangle, DemangleOptions};
+use cwdemangle::DemangleOptions;
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 use typed_path::Utf8NativePath;
@@ -15,10 +18,10 @@ use crate::{
     },
     array_ref,
     obj::{
-        ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSection, ObjSymbol, ObjSymbolFlagSet,
+        ObjDataKind, ObjInfo, ObjKind, ObjReloc, ObjRelocKind, ObjSymbol, ObjSymbolFlagSet,
         ObjSymbolKind, SectionIndex, SymbolIndex,
     },
-    util::elf::process_elf,
+    util::{config::is_auto_symbol, demangle::demangle, elf::process_elf},
 };
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
@@ -28,8 +31,12 @@ pub struct OutSymbol {
     pub size: u32,
     pub flags: ObjSymbolFlagSet,
     pub section: Option<String>,
+    #[serde(default, skip_serializing_if = "is_unknown_data_kind")]
+    pub data_kind: ObjDataKind,
 }
 
+fn is_unknown_data_kind(kind: &ObjDataKind) -> bool { *kind == ObjDataKind::Unknown }
+
 #[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct OutReloc {
     pub offset: u32,
@@ -47,26 +54,108 @@ pub struct FunctionSignature {
     pub relocations: Vec<OutReloc>,
 }
 
-pub fn check_signature(mut data: &[u8], sig: &FunctionSignature) -> Result<bool> {
+/// Default minimum confidence for signature matches. Only exact matches are accepted.
+pub const DEFAULT_MIN_CONFIDENCE: f32 = 1.0;
+
+static MIN_CONFIDENCE: AtomicU32 = AtomicU32::new(DEFAULT_MIN_CONFIDENCE.to_bits());
+
+/// Sets the minimum confidence (0.0-1.0) required to accept a signature match.
+pub fn set_min_confidence(confidence: f32) {
+    MIN_CONFIDENCE.store(confidence.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+}
+
+/// The minimum confidence required to accept a signature match.
+pub fn min_confidence() -> f32 { f32::from_bits(MIN_CONFIDENCE.load(Ordering::Relaxed)) }
+
+pub fn parse_confidence(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(confidence) if (0.0..=1.0).contains(&confidence) => Ok(confidence),
+        _ => Err(format!("Invalid confidence '{}', expected a value between 0.0 and 1.0", value)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SignatureMatch {
+    pub signature: FunctionSignature,
+    /// Fraction of instructions matched after masking relocations.
+    pub confidence: f32,
+}
+
+/// Counts the instructions of a signature that match the start of `data` after masking
+/// relocations, returning `(matched, total)`. Instructions past the end of `data` count
+/// as mismatches.
+fn instruction_matches(data: &[u8], sig: &FunctionSignature) -> Result<(usize, usize)> {
     let sig_data = STANDARD.decode(&sig.signature)?;
-    // println!(
-    //     "\nChecking signature {} {} (size {})",
-    //     sig.symbols[sig.symbol].name, sig.hash, sig.symbols[sig.symbol].size
-    // );
-    // for chunk in sig_data.chunks_exact(8) {
-    //     let ins = u32::from_be_bytes(*array_ref!(chunk, 0, 4));
-    //     let i = Ins::new(ins, 0);
-    //     println!("=> {}", i.simplified());
-    // }
-    for chunk in sig_data.chunks_exact(8) {
-        let ins = u32::from_be_bytes(*array_ref!(chunk, 0, 4));
-        let pat = u32::from_be_bytes(*array_ref!(chunk, 4, 4));
-        if (u32::from_be_bytes(*array_ref!(data, 0, 4)) & pat) != ins {
-            return Ok(false);
+    let matched = sig_data
+        .chunks_exact(8)
+        .zip(data.chunks_exact(4))
+        .filter(|(chunk, word)| {
+            let ins = u32::from_be_bytes(*array_ref!(chunk, 0, 4));
+            let pat = u32::from_be_bytes(*array_ref!(chunk, 4, 4));
+            u32::from_be_bytes(*array_ref!(word, 0, 4)) & pat == ins
+        })
+        .count();
+    Ok((matched, sig_data.len() / 8))
+}
+
+/// Counts the signature's relocations whose target name agrees with an existing relocation
+/// at the same offset, returning `(agreed, compared)`. Relocations against auto-generated
+/// symbols aren't compared, since their names carry no information.
+fn relocation_name_matches(
+    obj: &ObjInfo,
+    addr: SectionAddress,
+    sig: &FunctionSignature,
+) -> (usize, usize) {
+    let relocations = &obj.sections[addr.section].relocations;
+    let mut agreed = 0;
+    let mut compared = 0;
+    for sig_reloc in &sig.relocations {
+        let Some(target) = relocations
+            .at(addr.address + sig_reloc.offset)
+            .and_then(|reloc| obj.symbols.get(reloc.target_symbol))
+        else {
+            continue;
+        };
+        if is_auto_symbol(target) {
+            continue;
+        }
+        compared += 1;
+        if target.name == sig.symbols[sig_reloc.symbol as usize].name {
+            agreed += 1;
         }
-        data = &data[4..];
     }
-    Ok(true)
+    (agreed, compared)
+}
+
+/// Scores a signature against the data at the start of `data`, returning the fraction
+/// of instructions that match after masking relocations. Instructions past the end
+/// of `data` count as mismatches.
+pub fn signature_confidence(data: &[u8], sig: &FunctionSignature) -> Result<f32> {
+    let (matched, total) = instruction_matches(data, sig)?;
+    if total == 0 {
+        return Ok(0.0);
+    }
+    Ok(matched as f32 / total as f32)
+}
+
+/// Scores a signature at `addr`, like [`signature_confidence`], but also counts existing
+/// relocations whose target names agree (or disagree) with the signature's.
+pub fn score_signature(
+    obj: &ObjInfo,
+    addr: SectionAddress,
+    sig: &FunctionSignature,
+) -> Result<f32> {
+    let data = obj.sections[addr.section].data_range(addr.address, 0)?;
+    let (matched, total) = instruction_matches(data, sig)?;
+    if total == 0 {
+        return Ok(0.0);
+    }
+    let (agreed, compared) = relocation_name_matches(obj, addr, sig);
+    Ok((matched + agreed) as f32 / (total + compared) as f32)
+}
+
+pub fn check_signature(data: &[u8], sig: &FunctionSignature) -> Result<bool> {
+    Ok(signature_confidence(data, sig)? >= 1.0)
 }
 
 pub fn parse_signatures(sig_str: &str) -> Result<Vec<FunctionSignature>> {
@@ -74,38 +163,75 @@ pub fn parse_signatures(sig_str: &str) -> Result<Vec<FunctionSignature>> {
 }
 
 pub fn check_signatures_str(
-    section: &ObjSection,
-    addr: u32,
+    obj: &ObjInfo,
+    addr: SectionAddress,
     sig_str: &str,
 ) -> Result<Option<FunctionSignature>> {
-    check_signatures(section, addr, &parse_signatures(sig_str)?)
+    check_signatures(obj, addr, &parse_signatures(sig_str)?)
 }
 
 pub fn check_signatures(
-    section: &ObjSection,
-    addr: u32,
+    obj: &ObjInfo,
+    addr: SectionAddress,
     signatures: &Vec<FunctionSignature>,
 ) -> Result<Option<FunctionSignature>> {
-    let data = section.data_range(addr, 0)?;
-    let mut name = None;
+    Ok(match_signatures(obj, addr, signatures, min_confidence())?.map(|m| m.signature))
+}
+
+/// Finds the highest scoring signature at `addr` with at least `min_confidence`.
+/// On a tie, the earliest signature wins, so the first exact match is returned immediately.
+pub fn match_signatures(
+    obj: &ObjInfo,
+    addr: SectionAddress,
+    signatures: &[FunctionSignature],
+    min_confidence: f32,
+) -> Result<Option<SignatureMatch>> {
+    let mut best: Option<(&FunctionSignature, f32)> = None;
+    let mut runner_up: Option<(&FunctionSignature, f32)> = None;
     for signature in signatures {
-        if name.is_none() {
-            name = Some(signature.symbols[signature.symbol as usize].name.clone());
+        let confidence = score_signature(obj, addr, signature)?;
+        if confidence < min_confidence {
+            continue;
         }
-        if check_signature(data, signature)? {
-            log::debug!(
-                "Found {} @ {:#010X} (hash {})",
-                signature.symbols[signature.symbol as usize].name,
-                addr,
-                signature.hash
-            );
-            return Ok(Some(signature.clone()));
+        match best {
+            Some((_, best_confidence)) if confidence <= best_confidence => {
+                if runner_up.map_or(true, |(_, c)| confidence > c) {
+                    runner_up = Some((signature, confidence));
+                }
+            }
+            _ => {
+                runner_up = best;
+                best = Some((signature, confidence));
+                if confidence >= 1.0 {
+                    break;
+                }
+            }
         }
     }
-    // if let Some(name) = name {
-    //     log::debug!("Didn't find {} @ {:#010X}", name, addr);
-    // }
-    Ok(None)
+    let Some((signature, confidence)) = best else {
+        return Ok(None);
+    };
+    log::debug!(
+        "Found {} @ {:#010X} (hash {}, confidence {:.3})",
+        signature.symbols[signature.symbol as usize].name,
+        addr,
+        signature.hash,
+        confidence
+    );
+    if let Some((other, other_confidence)) = runner_up {
+        // Exact duplicates are common across signature variants, so only surface fuzzy matches
+        let level = if confidence < 1.0 { log::Level::Info } else { log::Level::Debug };
+        log::log!(
+            level,
+            "Multiple signatures matched @ {:#010X}: kept {} ({:.3}), runner-up {} ({:.3})",
+            addr,
+            signature.symbols[signature.symbol as usize].name,
+            confidence,
+            other.symbols[other.symbol as usize].name,
+            other_confidence
+        );
+    }
+    Ok(Some(SignatureMatch { signature: signature.clone(), confidence }))
 }
 
 pub fn apply_symbol(
@@ -140,6 +266,7 @@ pub fn apply_symbol(
             size_known: sig_symbol.size > 0 || sig_symbol.kind == ObjSymbolKind::Unknown,
             flags: sig_symbol.flags,
             kind: sig_symbol.kind,
+            data_kind: sig_symbol.data_kind,
             ..Default::default()
         },
         false,
@@ -294,6 +421,7 @@ pub fn generate_signature(
             size: symbol.size as u32,
             flags: symbol.flags,
             section: Some(section.name.clone()),
+            data_kind: symbol.data_kind,
         });
         // println!(
         //     "Building signature for {} ({:#010X}-{:#010X})",
@@ -317,45 +445,20 @@ pub fn generate_signature(
                         out_symbols.push(OutSymbol {
                             kind: target.kind,
                             name: target.name.clone(),
-                            size: if target.kind == ObjSymbolKind::Function {
-                                0
-                            } else {
-                                target.size as u32
-                            },
+                            size: if target.is_code() { 0 } else { target.size as u32 },
                             flags: target.flags,
                             section: target
                                 .section
                                 .and_then(|idx| obj.sections.get(idx))
                                 .map(|section| section.name.clone()),
+                            data_kind: target.data_kind,
                         });
                         symbol_idx
                     }
                     btree_map::Entry::Occupied(e) => *e.get(),
                 };
-                match reloc.kind {
-                    ObjRelocKind::Absolute => {
-                        *ins = 0;
-                        *pat = 0;
-                    }
-                    ObjRelocKind::PpcAddr16Hi
-                    | ObjRelocKind::PpcAddr16Ha
-                    | ObjRelocKind::PpcAddr16Lo => {
-                        *ins &= !0xFFFF;
-                        *pat = !0xFFFF;
-                    }
-                    ObjRelocKind::PpcRel24 => {
-                        *ins &= !0x3FFFFFC;
-                        *pat = !0x3FFFFFC;
-                    }
-                    ObjRelocKind::PpcRel14 => {
-                        *ins &= !0xFFFC;
-                        *pat = !0xFFFC;
-                    }
-                    ObjRelocKind::PpcEmbSda21 => {
-                        *ins &= !0x1FFFFF;
-                        *pat = !0x1FFFFF;
-                    }
-                }
+                *ins &= !reloc.kind.mask();
+                *pat = !reloc.kind.mask();
                 out_relocs.push(OutReloc {
                     offset: addr - (symbol.address as u32),
                     kind: reloc.kind,
@@ -388,3 +491,122 @@ pub fn generate_signature(
     }
     Ok(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{
+        test_section_data, ObjArchitecture, ObjRelocations, ObjSection, ObjSectionKind,
+    };
+
+    fn signature(name: &str, words: &[u32]) -> FunctionSignature {
+        let mut data = Vec::with_capacity(words.len() * 8);
+        for &word in words {
+            data.extend_from_slice(&word.to_be_bytes());
+            data.extend_from_slice(&(!0u32).to_be_bytes());
+        }
+        FunctionSignature {
+            symbol: 0,
+            hash: String::new(),
+            signature: STANDARD.encode(&data),
+            symbols: vec![OutSymbol {
+                kind: ObjSymbolKind::Function,
+                name: name.to_string(),
+                size: words.len() as u32 * 4,
+                flags: Default::default(),
+                section: Some(".text".to_string()),
+                data_kind: ObjDataKind::Unknown,
+            }],
+            relocations: vec![],
+        }
+    }
+
+    fn code_obj(words: &[u32], symbols: Vec<ObjSymbol>) -> ObjInfo {
+        let data = words.iter().flat_map(|w| w.to_be_bytes()).collect::<Vec<u8>>();
+        let sections = vec![ObjSection {
+            virtual_address: Some(0x80003100),
+            ..test_section_data(".text", ObjSectionKind::Code, 0x80003100, data)
+        }];
+        ObjInfo::new(ObjKind::Executable, ObjArchitecture::PowerPc, "".into(), symbols, sections)
+    }
+
+    #[test]
+    fn test_signature_confidence() {
+        let words = (0..20).map(|i| 0x38600000 | i).collect::<Vec<u32>>();
+        let mut near_miss = words.clone();
+        near_miss[3] = 0x60000000;
+        near_miss[17] = 0x60000000;
+        let obj = code_obj(&near_miss, vec![]);
+        let addr = SectionAddress::new(0, 0x80003100);
+        let signatures = vec![signature("near_miss", &words)];
+
+        let m = match_signatures(&obj, addr, &signatures, 0.8).unwrap().unwrap();
+        assert_eq!(m.confidence, 0.9);
+        assert!(match_signatures(&obj, addr, &signatures, 0.95).unwrap().is_none());
+        assert!(!check_signature(&obj.sections[0].data, &signatures[0]).unwrap());
+    }
+
+    #[test]
+    fn test_match_signatures_best() {
+        let words = (0..10).map(|i| 0x38600000 | i).collect::<Vec<u32>>();
+        let mut other = words.clone();
+        other[0] = 0x60000000;
+        let obj = code_obj(&words, vec![]);
+        let addr = SectionAddress::new(0, 0x80003100);
+        let signatures = vec![signature("other", &other), signature("exact", &words)];
+
+        let m = match_signatures(&obj, addr, &signatures, 0.5).unwrap().unwrap();
+        assert_eq!(m.signature.symbols[0].name, "exact");
+        assert_eq!(m.confidence, 1.0);
+
+        // Signatures longer than the remaining data count the missing tail as mismatches
+        let long = signature("long", &[words.clone(), words.clone()].concat());
+        assert_eq!(signature_confidence(&obj.sections[0].data, &long).unwrap(), 0.5);
+    }
+
+    #[test]
+    fn test_match_signatures_reloc_names() {
+        let words = (0..9).map(|i| 0x38600000 | i).collect::<Vec<u32>>();
+        let obj = |target: &str| {
+            let symbol = ObjSymbol {
+                name: target.to_string(),
+                address: 0x80003100,
+                section: Some(0),
+                kind: ObjSymbolKind::Function,
+                ..Default::default()
+            };
+            let mut obj = code_obj(&words, vec![symbol]);
+            let reloc = ObjReloc {
+                kind: ObjRelocKind::PpcRel24,
+                target_symbol: 0,
+                addend: 0,
+                module: None,
+            };
+            obj.sections[0].relocations = ObjRelocations::new(vec![(0x80003104, reloc)]).unwrap();
+            obj
+        };
+        let with_reloc = |name: &str| {
+            let mut sig = signature(name, &words);
+            sig.symbols.push(OutSymbol { name: "OSReport".to_string(), ..sig.symbols[0].clone() });
+            sig.relocations.push(OutReloc {
+                offset: 4,
+                kind: ObjRelocKind::PpcRel24,
+                symbol: 1,
+                addend: 0,
+            });
+            sig
+        };
+        let addr = SectionAddress::new(0, 0x80003100);
+        let signatures = vec![with_reloc("first"), with_reloc("second")];
+
+        // The relocation target agrees, so the first signature matches exactly
+        let m = match_signatures(&obj("OSReport"), addr, &signatures, 0.5).unwrap().unwrap();
+        assert_eq!((m.signature.symbols[0].name.as_str(), m.confidence), ("first", 1.0));
+
+        // A conflicting target name lowers the score
+        assert_eq!(score_signature(&obj("OSPanic"), addr, &signatures[0]).unwrap(), 0.9);
+
+        // Auto-generated target names aren't compared
+        assert_eq!(score_signature(&obj("fn_80003100"), addr, &signatures[0]).unwrap(), 1.0);
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     cmp::{max, min, Ordering},
     collections::{btree_map, BTreeMap, HashMap, HashSet},
+    ops::Range,
 };
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
@@ -8,6 +9,7 @@ use itertools::Itertools;
 use objdiff_core::obj::split_meta::SplitMeta;
 use petgraph::{graph::NodeIndex, Graph};
 use sanitise_file_name::sanitize_with_options;
+use serde::{Deserialize, Serialize};
 use tracing_attributes::instrument;
 
 use crate::{
@@ -20,6 +22,16 @@ use crate::{
     util::{align_up, comment::MWComment},
 };
 
+/// C++ runtime functions and their .ctors/.dtors entries, which must never be stripped.
+const CPP_RUNTIME_SYMBOLS: [&str; 6] = [
+    "__init_cpp_exceptions",
+    "__fini_cpp_exceptions",
+    "__destroy_global_chain",
+    "__init_cpp_exceptions_reference",
+    "__fini_cpp_exceptions_reference",
+    "__destroy_global_chain_reference",
+];
+
 /// Create splits for function pointers in the given section.
 fn split_ctors_dtors(obj: &mut ObjInfo, start: SectionAddress, end: SectionAddress) -> Result<()> {
     let ctors_section = &obj.sections[start.section];
@@ -442,6 +454,50 @@ fn create_gap_splits(obj: &mut ObjInfo) -> Result<()> {
     Ok(())
 }
 
+/// Ensures that each verbatim section is covered by a single split, creating an
+/// autogenerated unit for it if necessary.
+fn update_verbatim_splits(obj: &mut ObjInfo) -> Result<()> {
+    for section_index in 0..obj.sections.len() {
+        let section = &obj.sections[section_index];
+        if !section.verbatim || section.size == 0 {
+            continue;
+        }
+        let start = section.address as u32;
+        let end = (section.address + section.size) as u32;
+        let splits = section.splits.iter().map(|(addr, split)| (addr, split.clone())).collect_vec();
+        let split = match splits.as_slice() {
+            [] => ObjSplit {
+                unit: format!(
+                    "auto_{:02}_{:08X}_{}",
+                    section_index,
+                    start,
+                    section.name.trim_start_matches('.')
+                ),
+                end,
+                align: None,
+                common: false,
+                autogenerated: true,
+                skip: false,
+                rename: None,
+            },
+            [(addr, split)] => {
+                if *addr == start && split.end == end {
+                    continue;
+                }
+                obj.sections[section_index].splits.remove(*addr);
+                ObjSplit { end, ..split.clone() }
+            }
+            _ => bail!(
+                "Verbatim section {} can't be split across units: {}",
+                section.name,
+                splits.iter().map(|(_, split)| split.unit.as_str()).unique().join(", ")
+            ),
+        };
+        obj.sections[section_index].splits.push(start, split);
+    }
+    Ok(())
+}
+
 /// Ensures that all .bss splits following a common split are also marked as common.
 fn update_common_splits(obj: &mut ObjInfo, common_start: Option<u32>) -> Result<()> {
     let Some(common_bss_start) = (match common_start {
@@ -528,8 +584,46 @@ fn validate_splits(obj: &ObjInfo) -> Result<()> {
     Ok(())
 }
 
+/// How the original compiler filled the space before an aligned symbol.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaddingStyle {
+    /// Detect from the `.comment` section: GCC if any producer string names GCC,
+    /// otherwise MWCC.
+    #[default]
+    Auto,
+    /// Metrowerks: symbol starts are rounded up to their alignment and zero-filled.
+    Mwcc,
+    /// GCC: like MWCC, but code sections are filled with `nop`.
+    Gcc,
+}
+
+impl PaddingStyle {
+    /// Resolves [`PaddingStyle::Auto`] for the given object.
+    pub fn resolve(self, obj: &ObjInfo) -> Self {
+        match self {
+            PaddingStyle::Auto if obj.producers.iter().any(|p| p.contains("GCC")) => {
+                PaddingStyle::Gcc
+            }
+            PaddingStyle::Auto => PaddingStyle::Mwcc,
+            style => style,
+        }
+    }
+
+    /// Whether `data`, located at `address` in a section of `kind`, matches the fill
+    /// the compiler would have emitted.
+    fn is_fill(self, kind: ObjSectionKind, address: u32, data: &[u8]) -> bool {
+        let fill = match (self, kind) {
+            (PaddingStyle::Gcc, ObjSectionKind::Code) => 0x60000000u32.to_be_bytes(), // nop
+            _ => [0; 4],
+        };
+        data.iter().enumerate().all(|(i, &b)| b == fill[(address as usize + i) % 4])
+    }
+}
+
 /// Add padding symbols to fill in gaps between splits and symbols.
-fn add_padding_symbols(obj: &mut ObjInfo) -> Result<()> {
+fn add_padding_symbols(obj: &mut ObjInfo, style: PaddingStyle) -> Result<()> {
+    let style = style.resolve(obj);
     for (section_index, section, addr, _split) in obj.sections.all_splits() {
         if section.name == ".ctors" || section.name == ".dtors" {
             continue;
@@ -565,7 +659,7 @@ fn add_padding_symbols(obj: &mut ObjInfo) -> Result<()> {
                 size: next_symbol_address - addr as u64,
                 size_known: true,
                 flags: ObjSymbolFlagSet(
-                    ObjSymbolFlags::Local | ObjSymbolFlags::Exported | ObjSymbolFlags::NoWrite,
+                    ObjSymbolFlags::Local | ObjSymbolFlags::ForceActive | ObjSymbolFlags::NoWrite,
                 ),
                 kind: match section.kind {
                     ObjSectionKind::Code => ObjSymbolKind::Function,
@@ -581,6 +675,7 @@ fn add_padding_symbols(obj: &mut ObjInfo) -> Result<()> {
     // Add padding symbols for gaps between symbols
     let common_bss = obj.sections.common_bss_start();
     for (section_index, section) in obj.sections.iter() {
+        // .ctors and .dtors are packed pointer arrays, already split per entry
         if section.name == ".ctors" || section.name == ".dtors" {
             continue;
         }
@@ -614,7 +709,16 @@ fn add_padding_symbols(obj: &mut ObjInfo) -> Result<()> {
                         1,
                     )
                 };
-            let aligned_end = align_up((symbol.address + symbol.size) as u32, next_align);
+            let end = (symbol.address + symbol.size) as u32;
+            let mut aligned_end = align_up(end, next_align);
+            if aligned_end > end
+                && aligned_end <= next_address as u32
+                && section.kind != ObjSectionKind::Bss
+                && !style.is_fill(section.kind, end, section.data_range(end, aligned_end)?)
+            {
+                // Alignment padding wouldn't reproduce these bytes, so cover them with the gap
+                aligned_end = end;
+            }
             match aligned_end.cmp(&(next_address as u32)) {
                 Ordering::Less => {
                     let symbol_name = format!(
@@ -633,7 +737,7 @@ fn add_padding_symbols(obj: &mut ObjInfo) -> Result<()> {
                         flags: ObjSymbolFlagSet(
                             ObjSymbolFlags::Global
                                 | ObjSymbolFlags::Hidden
-                                | ObjSymbolFlags::Exported
+                                | ObjSymbolFlags::ForceActive
                                 | ObjSymbolFlags::NoWrite,
                         ),
                         kind: match section.kind {
@@ -755,10 +859,15 @@ fn trim_linker_generated_symbols(obj: &mut ObjInfo) -> Result<()> {
 /// - Creating splits for gaps between existing splits
 /// - Resolving a new object link order
 #[instrument(level = "debug", skip(obj))]
-pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bool) -> Result<()> {
+pub fn update_splits(
+    obj: &mut ObjInfo,
+    common_start: Option<u32>,
+    fill_gaps: bool,
+    padding: PaddingStyle,
+) -> Result<()> {
     // Create splits for extab and extabindex entries
     if let Some((section_index, section)) = obj.sections.by_name("extabindex")? {
-        if !section.data.is_empty() {
+        if !section.data.is_empty() && !section.verbatim {
             let start = SectionAddress::new(section_index, section.address as u32);
             split_extabindex(obj, start)?;
         }
@@ -766,7 +875,7 @@ pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bo
 
     // Create splits for .ctors entries
     if let Some((section_index, section)) = obj.sections.by_name(".ctors")? {
-        if !section.data.is_empty() {
+        if !section.data.is_empty() && !section.verbatim {
             let start = SectionAddress::new(section_index, section.address as u32);
             let end = start + (section.size as u32 - 4);
             split_ctors_dtors(obj, start, end)?;
@@ -775,7 +884,7 @@ pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bo
 
     // Create splits for .dtors entries
     if let Some((section_index, section)) = obj.sections.by_name(".dtors")? {
-        if !section.data.is_empty() {
+        if !section.data.is_empty() && !section.verbatim {
             let mut start = SectionAddress::new(section_index, section.address as u32);
             let end = start + (section.size as u32 - 4);
             if obj.kind == ObjKind::Executable {
@@ -786,6 +895,16 @@ pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bo
         }
     }
 
+    // Keep the C++ runtime init/fini functions and their table entries
+    for name in CPP_RUNTIME_SYMBOLS {
+        if let Some((symbol_idx, _)) = obj.symbols.by_name(name)? {
+            obj.symbols.flags(symbol_idx).set_force_active(true);
+        }
+    }
+
+    // Keep verbatim sections in a single unit
+    update_verbatim_splits(obj)?;
+
     // Remove linker generated symbols from splits
     trim_linker_generated_symbols(obj)?;
 
@@ -804,7 +923,7 @@ pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bo
 
     if fill_gaps {
         // Add symbols to beginning of any split that doesn't start with a symbol
-        add_padding_symbols(obj)?;
+        add_padding_symbols(obj, padding)?;
     }
 
     // Resolve link order
@@ -813,6 +932,413 @@ pub fn update_splits(obj: &mut ObjInfo, common_start: Option<u32>, fill_gaps: bo
     Ok(())
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BlockOwner {
+    /// Index into the per-function units.
+    Function(usize),
+    Shared,
+}
+
+/// A contiguous range of a unit's split: a function, or top-level data and any symbols nested
+/// inside of it.
+#[derive(Debug)]
+struct UnitBlock {
+    section: SectionIndex,
+    start: u32,
+    end: u32,
+    /// Index into the original units.
+    parent: usize,
+    function: Option<usize>,
+    owner: Option<BlockOwner>,
+}
+
+struct FunctionUnit {
+    parent: usize,
+    name: String,
+}
+
+/// Replaces the splits of every unit containing `.init` or `.text` functions with one split per
+/// function, named `<unit>/<function>`. Data referenced only by a single function of the unit
+/// (directly or through other owned data) moves along with it, as do the extab, extabindex,
+/// .ctors and .dtors entries pointing to it. The remaining data is assigned to a neighbouring
+/// function's unit, so that nothing is duplicated and the link order stays consistent.
+#[instrument(level = "debug", skip(obj))]
+pub fn split_by_function(obj: &mut ObjInfo) -> Result<()> {
+    let mut parents = Vec::<String>::new();
+    let mut parent_indices = HashMap::<String, usize>::new();
+    let mut parent_first_function = Vec::<usize>::new();
+    let mut functions = Vec::<FunctionUnit>::new();
+    let mut used_names = obj
+        .sections
+        .all_splits()
+        .map(|(_, _, _, split)| split.unit.to_ascii_lowercase())
+        .collect::<HashSet<_>>();
+    let mut blocks = Vec::<UnitBlock>::new();
+    // Section, split address and the range of blocks within it
+    let mut split_blocks = Vec::<(SectionIndex, u32, Range<usize>)>::new();
+
+    for (section_index, section) in obj.sections.iter() {
+        if section.kind != ObjSectionKind::Code
+            || section.verbatim
+            || !matches!(section.name.as_str(), ".init" | ".text")
+        {
+            continue;
+        }
+        let section_end = end_for_section(obj, section_index)?.address;
+        for (split_addr, split) in section.splits.iter() {
+            if split.skip {
+                continue;
+            }
+            let split_end = if split.end == 0 { section_end } else { split.end };
+            let mut function_symbols = vec![];
+            let mut last_end = split_addr;
+            for (_, symbol) in obj.symbols.for_section_range(section_index, split_addr..split_end) {
+                if symbol.is_code() && symbol.size > 0 && symbol.address as u32 >= last_end {
+                    function_symbols.push(symbol);
+                    last_end = (symbol.address + symbol.size) as u32;
+                }
+            }
+            if function_symbols.is_empty() {
+                continue;
+            }
+
+            let parent = match parent_indices.get(&split.unit) {
+                Some(&parent) => parent,
+                None => {
+                    parent_indices.insert(split.unit.clone(), parents.len());
+                    parents.push(split.unit.clone());
+                    parent_first_function.push(functions.len());
+                    parents.len() - 1
+                }
+            };
+            let first_block = blocks.len();
+            for (i, symbol) in function_symbols.iter().enumerate() {
+                let start = if i == 0 { split_addr } else { symbol.address as u32 };
+                let end = function_symbols.get(i + 1).map_or(split_end, |s| s.address as u32);
+                let name = function_unit_name(&split.unit, &symbol.name, &mut used_names);
+                blocks.push(UnitBlock {
+                    section: section_index,
+                    start,
+                    end,
+                    parent,
+                    function: Some(functions.len()),
+                    owner: Some(BlockOwner::Function(functions.len())),
+                });
+                functions.push(FunctionUnit { parent, name });
+            }
+            split_blocks.push((section_index, split_addr, first_block..blocks.len()));
+        }
+    }
+    if functions.is_empty() {
+        return Ok(());
+    }
+
+    // Divide the remaining splits of these units at top-level symbols
+    for (section_index, section) in obj.sections.iter() {
+        let section_end = end_for_section(obj, section_index)?.address;
+        for (split_addr, split) in section.splits.iter() {
+            let Some(&parent) = parent_indices.get(&split.unit) else {
+                continue;
+            };
+            if split_blocks.iter().any(|&(s, a, _)| s == section_index && a == split_addr) {
+                continue;
+            }
+            let split_end = if split.end == 0 { section_end } else { split.end };
+            let mut starts = vec![split_addr];
+            let mut max_end = split_addr;
+            // Verbatim sections are kept whole
+            let range =
+                if section.verbatim { split_addr..split_addr } else { split_addr..split_end };
+            for (_, symbol) in obj.symbols.for_section_range(section_index, range) {
+                if symbol.kind == ObjSymbolKind::Section {
+                    continue;
+                }
+                let address = symbol.address as u32;
+                if address >= max_end && starts.last() != Some(&address) {
+                    starts.push(address);
+                }
+                max_end = max(max_end, (symbol.address + symbol.size) as u32);
+            }
+            let first_block = blocks.len();
+            for (i, &start) in starts.iter().enumerate() {
+                blocks.push(UnitBlock {
+                    section: section_index,
+                    start,
+                    end: starts.get(i + 1).copied().unwrap_or(split_end),
+                    parent,
+                    function: None,
+                    owner: None,
+                });
+            }
+            split_blocks.push((section_index, split_addr, first_block..blocks.len()));
+        }
+    }
+
+    // Collect references between blocks. `None` is a reference from outside of these units.
+    let mut block_starts = BTreeMap::<SectionAddress, usize>::new();
+    for (index, block) in blocks.iter().enumerate() {
+        block_starts.insert(SectionAddress::new(block.section, block.start), index);
+    }
+    let block_for = |section: SectionIndex, address: u32| {
+        block_starts
+            .range(..=SectionAddress::new(section, address))
+            .next_back()
+            .map(|(_, &index)| index)
+            .filter(|&index| blocks[index].section == section && address < blocks[index].end)
+    };
+    let mut referrers = vec![Vec::<Option<usize>>::new(); blocks.len()];
+    let mut targets = vec![Vec::<usize>::new(); blocks.len()];
+    for (section_index, section) in obj.sections.iter() {
+        for (address, reloc) in section.relocations.iter() {
+            if reloc.module.is_some_and(|module| module != obj.module_id) {
+                continue;
+            }
+            let target = &obj.symbols[reloc.target_symbol];
+            let Some(target_section) = target.section else {
+                continue;
+            };
+            let Some(target_block) = block_for(target_section, target.address as u32) else {
+                continue;
+            };
+            let source_block = block_for(section_index, address);
+            if source_block == Some(target_block) {
+                continue;
+            }
+            referrers[target_block].push(source_block);
+            if let Some(source_block) = source_block {
+                targets[source_block].push(target_block);
+            }
+        }
+    }
+
+    // Resolve data ownership until nothing changes; anything left over is shared
+    loop {
+        let mut changed = false;
+        for index in 0..blocks.len() {
+            if blocks[index].owner.is_some() {
+                continue;
+            }
+            let owner = if referrers[index].is_empty() {
+                // Unreferenced tables of function pointers (extabindex, .ctors, .dtors)
+                // belong to the function they point to
+                let owners = targets[index]
+                    .iter()
+                    .filter_map(|&target| blocks[target].function)
+                    .unique()
+                    .collect_vec();
+                match owners.as_slice() {
+                    &[function] if functions[function].parent == blocks[index].parent => {
+                        Some(BlockOwner::Function(function))
+                    }
+                    _ => Some(BlockOwner::Shared),
+                }
+            } else {
+                combine_block_owners(&blocks, &functions, &referrers[index], blocks[index].parent)
+            };
+            if owner.is_some() {
+                blocks[index].owner = owner;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    // Assign every block a function unit. Within each split, the units must appear in function
+    // order; out of order and shared data stays with the preceding unit instead.
+    let mut new_splits = vec![];
+    for (section_index, split_addr, range) in split_blocks {
+        let mut assigned = Vec::with_capacity(range.len());
+        let mut current = None;
+        for block in &blocks[range.clone()] {
+            if let Some(BlockOwner::Function(function)) = block.owner {
+                if current.map_or(true, |current| function >= current) {
+                    current = Some(function);
+                }
+            }
+            assigned.push(current);
+        }
+        let parent = blocks[range.start].parent;
+        let first =
+            assigned.iter().flatten().next().copied().unwrap_or(parent_first_function[parent]);
+        let original = obj.sections[section_index]
+            .splits
+            .for_range(split_addr..=split_addr)
+            .find(|(_, split)| split.unit == parents[parent])
+            .map(|(_, split)| split.clone())
+            .ok_or_else(|| anyhow!("Split {} @ {:#010X} not found", parents[parent], split_addr))?;
+        for (function, chunk) in
+            &blocks[range].iter().zip(assigned).chunk_by(|(_, function)| function.unwrap_or(first))
+        {
+            let chunk = chunk.collect_vec();
+            let start = chunk[0].0.start;
+            log::debug!(
+                "Assigning {} {:#010X} to {}",
+                obj.sections[section_index].name,
+                start,
+                functions[function].name
+            );
+            new_splits.push((section_index, start, ObjSplit {
+                unit: functions[function].name.clone(),
+                end: chunk[chunk.len() - 1].0.end,
+                align: if start == split_addr { original.align } else { None },
+                common: original.common,
+                autogenerated: original.autogenerated,
+                skip: original.skip,
+                rename: original.rename.clone(),
+            }));
+        }
+    }
+
+    // Replace the original splits
+    for (_, section) in obj.sections.iter_mut() {
+        let addresses = section
+            .splits
+            .iter()
+            .filter(|(_, split)| parent_indices.contains_key(&split.unit))
+            .map(|(addr, _)| addr)
+            .collect_vec();
+        for address in addresses {
+            for split in section.splits.remove(address).unwrap_or_default() {
+                if !parent_indices.contains_key(&split.unit) {
+                    section.splits.push(address, split);
+                }
+            }
+        }
+    }
+    for (section_index, address, split) in new_splits {
+        obj.sections[section_index].splits.push(address, split);
+    }
+
+    // Replace the original units, keeping their settings
+    let mut link_order = vec![];
+    for unit in &obj.link_order {
+        match parent_indices.get(&unit.name) {
+            Some(&parent) => {
+                link_order.extend(functions.iter().filter(|f| f.parent == parent).map(|f| {
+                    ObjUnit {
+                        name: f.name.clone(),
+                        autogenerated: unit.autogenerated,
+                        comment_version: unit.comment_version,
+                        order: None,
+                    }
+                }))
+            }
+            None => link_order.push(unit.clone()),
+        }
+    }
+    obj.link_order = link_order;
+    obj.link_order = resolve_link_order(obj)?;
+    Ok(())
+}
+
+/// Splits every section without splits by the source file of its symbols, as set by
+/// [`apply_elf_source_info`](crate::util::elf::apply_elf_source_info). Symbols without a
+/// source file stay with the preceding unit. Returns the number of splits created.
+pub fn split_by_source_file(obj: &mut ObjInfo) -> Result<usize> {
+    let mut new_splits = vec![];
+    for (section_index, section) in obj.sections.iter() {
+        if section.splits.iter().next().is_some() {
+            continue;
+        }
+        let section_end = (section.address + section.size) as u32;
+        let mut current: Option<(u32, &str)> = None;
+        for (_, symbol) in obj.symbols.for_section(section_index) {
+            let Some(file) = symbol.source_file.as_deref() else {
+                continue;
+            };
+            let address = symbol.address as u32;
+            match current {
+                Some((_, unit)) if unit == file => {}
+                Some((start, unit)) => {
+                    new_splits.push((section_index, start, address, unit.to_string()));
+                    current = Some((address, file));
+                }
+                None => current = Some((address, file)),
+            }
+        }
+        if let Some((start, unit)) = current {
+            new_splits.push((section_index, start, section_end, unit.to_string()));
+        }
+    }
+
+    let count = new_splits.len();
+    for (section_index, start, end, unit) in new_splits {
+        if !obj.link_order.iter().any(|u| u.name == unit) {
+            obj.link_order.push(ObjUnit {
+                name: unit.clone(),
+                autogenerated: true,
+                comment_version: None,
+                order: None,
+            });
+        }
+        let split = ObjSplit {
+            unit,
+            end,
+            align: None,
+            common: false,
+            autogenerated: true,
+            skip: false,
+            rename: None,
+        };
+        obj.sections[section_index].splits.push(start, split);
+    }
+    if count > 0 {
+        obj.link_order = resolve_link_order(obj)?;
+    }
+    Ok(count)
+}
+
+/// Combines the owners of the blocks referencing a block. Returns `None` if the result depends
+/// on referrers that are still unresolved.
+fn combine_block_owners(
+    blocks: &[UnitBlock],
+    functions: &[FunctionUnit],
+    referrers: &[Option<usize>],
+    parent: usize,
+) -> Option<BlockOwner> {
+    let mut result = None;
+    let mut pending = false;
+    for &referrer in referrers {
+        let function = match referrer.map(|referrer| blocks[referrer].owner) {
+            Some(None) => {
+                pending = true;
+                continue;
+            }
+            Some(Some(BlockOwner::Function(function))) if functions[function].parent == parent => {
+                function
+            }
+            // Referenced from outside of the unit, or from shared data
+            _ => return Some(BlockOwner::Shared),
+        };
+        match result {
+            None => result = Some(function),
+            Some(existing) if existing != function => return Some(BlockOwner::Shared),
+            _ => {}
+        }
+    }
+    if pending {
+        None
+    } else {
+        result.map(BlockOwner::Function)
+    }
+}
+
+/// Generates a unique unit name for a function split out of `unit`.
+fn function_unit_name(unit: &str, function: &str, used_names: &mut HashSet<String>) -> String {
+    let name = sanitize_with_options(function, &sanitise_file_name::Options::default())
+        // Also replace $ to avoid issues with build.ninja, and . to keep the extension intact
+        .replace(['$', '.'], "_");
+    let mut unit_name = format!("{}/{}", unit, name);
+    let mut i = 1;
+    while !used_names.insert(unit_name.to_ascii_lowercase()) {
+        unit_name = format!("{}/{}_{}", unit, name, i);
+        i += 1;
+    }
+    unit_name
+}
+
 /// The ordering of TUs inside of each section represents a directed edge in a DAG.
 /// We can use a topological sort to determine a valid global TU order.
 /// There can be ambiguities, but any solution that satisfies the link order
@@ -937,7 +1463,7 @@ pub fn split_obj(obj: &ObjInfo, module_name: Option<&str>) -> Result<Vec<ObjInfo
     let mut name_to_obj: HashMap<String, usize> = HashMap::new();
     for unit in &obj.link_order {
         name_to_obj.insert(unit.name.clone(), objects.len());
-        object_symbols.push(vec![None; obj.symbols.count() as usize]);
+        object_symbols.push(vec![None; obj.symbols.slot_count() as usize]);
         let mut split_obj = ObjInfo::new(
             ObjKind::Relocatable,
             ObjArchitecture::PowerPc,
@@ -952,6 +1478,8 @@ pub fn split_obj(obj: &ObjInfo, module_name: Option<&str>) -> Result<Vec<ObjInfo
         } else {
             split_obj.mw_comment.clone_from(&obj.mw_comment);
         }
+        split_obj.endian = obj.endian;
+        split_obj.producers.clone_from(&obj.producers);
         split_obj.split_meta = Some(SplitMeta {
             generator: Some(format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))),
             module_name: module_name.map(str::to_string),
@@ -1113,8 +1641,21 @@ pub fn split_obj(obj: &ObjInfo, module_name: Option<&str>) -> Result<Vec<ObjInfo
                     data_kind: symbol.data_kind,
                     name_hash: symbol.name_hash,
                     demangled_name_hash: symbol.demangled_name_hash,
+                    source_file: symbol.source_file.clone(),
+                    source_line: symbol.source_line,
+                    notes: symbol.notes.clone(),
+                    sort_index: symbol.sort_index,
                 })?;
                 symbol_idxs[symbol_idx as usize] = Some(new_index);
+                if !split.common {
+                    let address = SectionAddress::new(section_index, symbol.address as u32);
+                    if let Some(&frame_size) = obj.frame_sizes.get(&address) {
+                        let new_address = address.address - current_address.address;
+                        split_obj
+                            .frame_sizes
+                            .insert(SectionAddress::new(out_section_idx, new_address), frame_size);
+                    }
+                }
             }
 
             // For mwldeppc 2.7 and above, a .comment section is required to link without error
@@ -1143,6 +1684,8 @@ pub fn split_obj(obj: &ObjInfo, module_name: Option<&str>) -> Result<Vec<ObjInfo
                     file_offset: section.file_offset
                         + (current_address.address as u64 - section.address),
                     section_known: true,
+                    thread_local: section.thread_local,
+                    verbatim: section.verbatim,
                     splits: Default::default(),
                 });
             }
@@ -1163,7 +1706,7 @@ pub fn split_obj(obj: &ObjInfo, module_name: Option<&str>) -> Result<Vec<ObjInfo
                     }
                     None => {
                         // Extern
-                        let out_sym_idx = out_obj.symbols.count();
+                        let out_sym_idx = out_obj.symbols.slot_count();
                         let target_sym = &obj.symbols[reloc.target_symbol];
 
                         // If the symbol is local, we'll upgrade the scope to global
@@ -1264,6 +1807,11 @@ pub fn split_obj(obj: &ObjInfo, module_name: Option<&str>) -> Result<Vec<ObjInfo
         }
     }
 
+    // Section-relative relocations are written against section symbols
+    for obj in &mut objects {
+        obj.ensure_section_symbols()?;
+    }
+
     Ok(objects)
 }
 
@@ -1445,3 +1993,328 @@ fn unit_exists(
             .any(|(_, split)| split.unit.eq_ignore_ascii_case(unit_name))
         || new_splits.values().any(|split| split.unit.eq_ignore_ascii_case(unit_name))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        obj::{test_section, test_section_data, test_symbol},
+        util::asm::write_asm,
+    };
+
+    fn two_symbol_obj(kind: ObjSectionKind, padding: [u8; 4]) -> ObjInfo {
+        let mut data = vec![0x11; 0x10];
+        data[4..8].copy_from_slice(&padding);
+        let symbol_kind = match kind {
+            ObjSectionKind::Code => ObjSymbolKind::Function,
+            _ => ObjSymbolKind::Object,
+        };
+        let symbol = |name: &str, address, size, align| ObjSymbol {
+            align,
+            ..test_symbol(name, Some(0), address, size, symbol_kind)
+        };
+        let mut section = ObjSection {
+            align: 8,
+            ..test_section_data(
+                if kind == ObjSectionKind::Code { ".text" } else { ".data" },
+                kind,
+                0x80003000,
+                data,
+            )
+        };
+        section.splits.push(0x80003000, ObjSplit {
+            unit: "main.o".to_string(),
+            end: 0x80003010,
+            align: None,
+            common: false,
+            autogenerated: false,
+            skip: false,
+            rename: None,
+        });
+        ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![symbol("first", 0x80003000, 4, None), symbol("second", 0x80003008, 8, Some(8))],
+            vec![section],
+        )
+    }
+
+    fn gap_symbols(obj: &ObjInfo) -> Vec<(u64, u64)> {
+        obj.symbols
+            .iter()
+            .filter(|(_, s)| s.name.starts_with("gap_"))
+            .map(|(_, s)| (s.address, s.size))
+            .collect()
+    }
+
+    #[test]
+    fn test_padding_style_aligned_symbol() {
+        // Zero padding before an 8-byte aligned symbol is reproduced by MWCC
+        let mut obj = two_symbol_obj(ObjSectionKind::Data, [0; 4]);
+        add_padding_symbols(&mut obj, PaddingStyle::Mwcc).unwrap();
+        assert!(gap_symbols(&obj).is_empty());
+
+        // Non-zero padding must be covered by a gap symbol
+        let mut obj = two_symbol_obj(ObjSectionKind::Data, [0xFF; 4]);
+        add_padding_symbols(&mut obj, PaddingStyle::Mwcc).unwrap();
+        assert_eq!(gap_symbols(&obj), [(0x80003004, 4)]);
+
+        // GCC pads code with nop
+        let nop = 0x60000000u32.to_be_bytes();
+        let mut obj = two_symbol_obj(ObjSectionKind::Code, nop);
+        add_padding_symbols(&mut obj, PaddingStyle::Gcc).unwrap();
+        assert!(gap_symbols(&obj).is_empty());
+        let mut obj = two_symbol_obj(ObjSectionKind::Code, nop);
+        add_padding_symbols(&mut obj, PaddingStyle::Mwcc).unwrap();
+        assert_eq!(gap_symbols(&obj), [(0x80003004, 4)]);
+
+        // Auto detects GCC from the .comment producers
+        let mut obj = two_symbol_obj(ObjSectionKind::Code, nop);
+        obj.producers = vec!["GCC: (GNU) 4.4.0".to_string()];
+        add_padding_symbols(&mut obj, PaddingStyle::Auto).unwrap();
+        assert!(gap_symbols(&obj).is_empty());
+    }
+
+    #[test]
+    fn test_split_by_function() {
+        let symbol = |name: &str, address, section, size, kind| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, Some(section), address, size, kind)
+        };
+        let reloc = |kind, target_symbol| ObjReloc { kind, target_symbol, addend: 0, module: None };
+        let section = |name: &str, kind, address, relocations| {
+            let mut section = ObjSection {
+                relocations: ObjRelocations::new(relocations).unwrap(),
+                ..test_section(name, kind, address, 0x10)
+            };
+            section.splits.push(address as u32, ObjSplit {
+                unit: "main.c".to_string(),
+                end: address as u32 + 0x10,
+                align: None,
+                common: false,
+                autogenerated: false,
+                skip: false,
+                rename: None,
+            });
+            section
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("fn_a", 0x80003000, 0, 8, ObjSymbolKind::Function),
+                symbol("fn_b", 0x80003008, 0, 8, ObjSymbolKind::Function),
+                symbol("a_data", 0x80004000, 1, 4, ObjSymbolKind::Object),
+                symbol("shared", 0x80004004, 1, 4, ObjSymbolKind::Object),
+                symbol("b_data", 0x80004008, 1, 8, ObjSymbolKind::Object),
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000, vec![
+                    (0x80003000, reloc(ObjRelocKind::PpcAddr16Ha, 2)),
+                    (0x80003004, reloc(ObjRelocKind::PpcAddr16Lo, 3)),
+                    (0x80003008, reloc(ObjRelocKind::PpcAddr16Ha, 4)),
+                    (0x8000300C, reloc(ObjRelocKind::PpcAddr16Lo, 3)),
+                ]),
+                section(".data", ObjSectionKind::Data, 0x80004000, vec![]),
+            ],
+        );
+        obj.link_order = vec![ObjUnit {
+            name: "main.c".to_string(),
+            autogenerated: false,
+            comment_version: None,
+            order: None,
+        }];
+
+        split_by_function(&mut obj).unwrap();
+        let units = obj.link_order.iter().map(|u| u.name.as_str()).collect_vec();
+        assert_eq!(units, ["main.c/fn_a", "main.c/fn_b"]);
+        // Shared data stays with the preceding function instead of being duplicated
+        let data_splits = obj.sections[1]
+            .splits
+            .iter()
+            .map(|(addr, s)| (addr, s.unit.as_str(), s.end))
+            .collect_vec();
+        assert_eq!(data_splits, [
+            (0x80004000, "main.c/fn_a", 0x80004008),
+            (0x80004008, "main.c/fn_b", 0x80004010)
+        ]);
+
+        let objects = split_obj(&obj, None).unwrap();
+        assert_eq!(objects.len(), 2);
+        let relocs = |obj: &ObjInfo| {
+            obj.sections[0]
+                .relocations
+                .iter()
+                .map(|(addr, r)| (addr, obj.symbols[r.target_symbol].name.clone()))
+                .collect_vec()
+        };
+        assert_eq!(relocs(&objects[0]), [(0, "a_data".to_string()), (4, "shared".to_string())]);
+        assert_eq!(relocs(&objects[1]), [(0, "b_data".to_string()), (4, "shared".to_string())]);
+        let (_, shared) = objects[1].symbols.by_name("shared").unwrap().unwrap();
+        assert_eq!(shared.section, None);
+    }
+
+    #[test]
+    fn test_ctors_order() {
+        let symbol = |name: &str, address, section, size, kind| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, Some(section), address, size, kind)
+        };
+        let split = |unit: &str, end| ObjSplit {
+            unit: unit.to_string(),
+            end,
+            align: None,
+            common: false,
+            autogenerated: false,
+            skip: false,
+            rename: None,
+        };
+        let section = |name: &str, kind, size, splits: &[(u32, ObjSplit)], relocations| {
+            let mut section = ObjSection {
+                relocations: ObjRelocations::new(relocations).unwrap(),
+                ..test_section(name, kind, 0, size)
+            };
+            for (address, split) in splits {
+                section.splits.push(*address, split.clone());
+            }
+            section
+        };
+        let entry = |target_symbol| ObjReloc {
+            kind: ObjRelocKind::Absolute,
+            target_symbol,
+            addend: 0,
+            module: None,
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Relocatable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("__init_cpp_exceptions", 0, 0, 0x10, ObjSymbolKind::Function),
+                symbol("init_c", 0x10, 0, 0x10, ObjSymbolKind::Function),
+                symbol("init_a", 0x20, 0, 0x10, ObjSymbolKind::Function),
+                symbol("init_b", 0x30, 0, 0x10, ObjSymbolKind::Function),
+                symbol("a_data", 0, 1, 4, ObjSymbolKind::Object),
+                symbol("b_data", 4, 1, 4, ObjSymbolKind::Object),
+            ],
+            vec![
+                // Only the runtime function is split, the others are added to their entry's unit
+                section(
+                    ".text",
+                    ObjSectionKind::Code,
+                    0x40,
+                    &[(0, split("__init_cpp_exceptions.cpp", 0x10))],
+                    vec![],
+                ),
+                section(
+                    ".data",
+                    ObjSectionKind::Data,
+                    8,
+                    &[(0, split("a.c", 4)), (4, split("b.c", 8))],
+                    vec![],
+                ),
+                // The runtime entry, three entries and the null terminator
+                section(
+                    ".ctors",
+                    ObjSectionKind::ReadOnlyData,
+                    0x14,
+                    &[
+                        (0, split("__init_cpp_exceptions.cpp", 4)),
+                        (4, split("c.c", 8)),
+                        (8, split("a.c", 0xC)),
+                        (0xC, split("b.c", 0x10)),
+                    ],
+                    vec![(0, entry(0)), (4, entry(1)), (8, entry(2)), (0xC, entry(3))],
+                ),
+            ],
+        );
+
+        update_splits(&mut obj, None, false, PaddingStyle::Auto).unwrap();
+        let position = |unit: &str| obj.link_order.iter().position(|u| u.name == unit).unwrap();
+        assert!(position("c.c") < position("a.c"));
+        assert!(position("a.c") < position("b.c"));
+        let ctors_units = obj.sections[2].splits.iter().map(|(_, s)| s.unit.as_str()).collect_vec();
+        assert_eq!(ctors_units, ["__init_cpp_exceptions.cpp", "c.c", "a.c", "b.c"]);
+        let text_units = obj.sections[0].splits.iter().map(|(_, s)| s.unit.as_str()).collect_vec();
+        assert_eq!(text_units, ["__init_cpp_exceptions.cpp", "c.c", "a.c", "b.c"]);
+        let (_, init) = obj.symbols.by_name("__init_cpp_exceptions").unwrap().unwrap();
+        assert!(init.flags.is_force_active());
+    }
+
+    #[test]
+    fn test_verbatim_section() {
+        let symbol = |name: &str, address| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, Some(0), address, 8, ObjSymbolKind::Function)
+        };
+        // li r3, 0; blr
+        let data = [0x38600000u32, 0x4E800020, 0x38600000, 0x4E800020]
+            .iter()
+            .flat_map(|ins| ins.to_be_bytes())
+            .collect_vec();
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![symbol("fn_a", 0x80003100), symbol("fn_b", 0x80003108)],
+            vec![ObjSection {
+                verbatim: true,
+                ..test_section_data(".init", ObjSectionKind::Code, 0x80003100, data)
+            }],
+        );
+
+        update_splits(&mut obj, None, false, PaddingStyle::Mwcc).unwrap();
+        split_by_function(&mut obj).unwrap();
+        let splits = obj.sections[0]
+            .splits
+            .iter()
+            .map(|(addr, s)| (addr, s.unit.as_str(), s.end))
+            .collect_vec();
+        assert_eq!(splits, [(0x80003100, "auto_00_80003100_init", 0x80003110)]);
+
+        let objects = split_obj(&obj, None).unwrap();
+        assert_eq!(objects.len(), 1);
+        let mut asm = vec![];
+        write_asm(&mut asm, &objects[0]).unwrap();
+        let asm = String::from_utf8(asm).unwrap();
+        assert!(asm.contains("\t.incbin \"auto_00_80003100_init.init.bin\", 0x0, 0x8"));
+        assert!(asm.contains("\t.incbin \"auto_00_80003100_init.init.bin\", 0x8, 0x8"));
+        assert!(asm.contains(".fn fn_b, global"));
+        assert!(!asm.contains("blr"));
+    }
+
+    #[test]
+    fn test_split_by_source_file() {
+        let symbol = |name: &str, section, address, file: Option<&str>| ObjSymbol {
+            source_file: file.map(str::to_string),
+            ..test_symbol(name, Some(section), address, 0x10, ObjSymbolKind::Function)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("fn_a", 0, 0x80003000, Some("a.c")),
+                symbol("fn_a2", 0, 0x80003010, None),
+                symbol("fn_b", 0, 0x80003020, Some("b.c")),
+                symbol("data_a", 1, 0x80004000, Some("a.c")),
+            ],
+            vec![
+                test_section(".text", ObjSectionKind::Code, 0x80003000, 0x30),
+                test_section(".data", ObjSectionKind::Data, 0x80004000, 0x10),
+            ],
+        );
+
+        assert_eq!(split_by_source_file(&mut obj).unwrap(), 3);
+        let splits = obj.sections[0]
+            .splits
+            .iter()
+            .map(|(addr, s)| (addr, s.end, s.unit.as_str()))
+            .collect_vec();
+        assert_eq!(splits, [(0x80003000, 0x80003020, "a.c"), (0x80003020, 0x80003030, "b.c")]);
+        let units = obj.link_order.iter().map(|u| u.name.as_str()).collect_vec();
+        assert_eq!(units, ["a.c", "b.c"]);
+    }
+}
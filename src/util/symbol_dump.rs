@@ -0,0 +1,326 @@
+//! JSON export of the complete symbol table, for external tooling.
+//!
+//! Unlike the `ObjSymbol` serialization, flags are expanded to named booleans, so that
+//! consumers don't depend on the bit layout of `ObjSymbolFlags`.
+
+use std::io::{Read, Write};
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::obj::{
+    ObjDataKind, ObjInfo, ObjKind, ObjSymbol, ObjSymbolFlagSet, ObjSymbolFlags, ObjSymbolKind,
+    SectionIndex, SymbolIndex,
+};
+
+/// Incremented whenever the layout changes incompatibly.
+pub const SYMBOL_DUMP_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolDump {
+    pub version: u32,
+    /// Symbols with a section, ordered by section and address.
+    pub symbols: Vec<SymbolDumpEntry>,
+    /// Symbols without a section.
+    pub abs: Vec<SymbolDumpEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolDumpEntry {
+    pub name: String,
+    pub demangled_name: Option<String>,
+    pub address: u64,
+    pub section: Option<SectionIndex>,
+    /// Informational only, `section` is used when reading.
+    pub section_name: Option<String>,
+    pub size: u64,
+    pub size_known: bool,
+    pub flags: SymbolDumpFlags,
+    pub kind: ObjSymbolKind,
+    pub align: Option<u32>,
+    pub data_kind: ObjDataKind,
+    pub name_hash: Option<u32>,
+    pub demangled_name_hash: Option<u32>,
+    pub source_file: Option<String>,
+    pub source_line: Option<u32>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SymbolDumpFlags {
+    pub global: bool,
+    pub local: bool,
+    pub weak: bool,
+    pub common: bool,
+    pub hidden: bool,
+    pub force_active: bool,
+    pub relocation_ignore: bool,
+    pub no_write: bool,
+    pub stripped: bool,
+    pub no_export: bool,
+    pub no_reloc: bool,
+    pub exported: bool,
+    pub allow_any_reloc: bool,
+    pub locked: bool,
+    pub protected: bool,
+    pub internal: bool,
+}
+
+impl SymbolDumpFlags {
+    fn pairs(&self) -> [(bool, ObjSymbolFlags); 16] {
+        [
+            (self.global, ObjSymbolFlags::Global),
+            (self.local, ObjSymbolFlags::Local),
+            (self.weak, ObjSymbolFlags::Weak),
+            (self.common, ObjSymbolFlags::Common),
+            (self.hidden, ObjSymbolFlags::Hidden),
+            (self.force_active, ObjSymbolFlags::ForceActive),
+            (self.relocation_ignore, ObjSymbolFlags::RelocationIgnore),
+            (self.no_write, ObjSymbolFlags::NoWrite),
+            (self.stripped, ObjSymbolFlags::Stripped),
+            (self.no_export, ObjSymbolFlags::NoExport),
+            (self.no_reloc, ObjSymbolFlags::NoReloc),
+            (self.exported, ObjSymbolFlags::Exported),
+            (self.allow_any_reloc, ObjSymbolFlags::AllowAnyReloc),
+            (self.locked, ObjSymbolFlags::Locked),
+            (self.protected, ObjSymbolFlags::Protected),
+            (self.internal, ObjSymbolFlags::Internal),
+        ]
+    }
+
+    pub fn to_flag_set(&self) -> ObjSymbolFlagSet {
+        let mut flags = ObjSymbolFlagSet::default();
+        for (set, flag) in self.pairs() {
+            if set {
+                flags.0 |= flag;
+            }
+        }
+        flags
+    }
+}
+
+impl From<ObjSymbolFlagSet> for SymbolDumpFlags {
+    fn from(flags: ObjSymbolFlagSet) -> Self {
+        let has = |flag: ObjSymbolFlags| flags.0.contains(flag);
+        Self {
+            global: has(ObjSymbolFlags::Global),
+            local: has(ObjSymbolFlags::Local),
+            weak: has(ObjSymbolFlags::Weak),
+            common: has(ObjSymbolFlags::Common),
+            hidden: has(ObjSymbolFlags::Hidden),
+            force_active: has(ObjSymbolFlags::ForceActive),
+            relocation_ignore: has(ObjSymbolFlags::RelocationIgnore),
+            no_write: has(ObjSymbolFlags::NoWrite),
+            stripped: has(ObjSymbolFlags::Stripped),
+            no_export: has(ObjSymbolFlags::NoExport),
+            no_reloc: has(ObjSymbolFlags::NoReloc),
+            exported: has(ObjSymbolFlags::Exported),
+            allow_any_reloc: has(ObjSymbolFlags::AllowAnyReloc),
+            locked: has(ObjSymbolFlags::Locked),
+            protected: has(ObjSymbolFlags::Protected),
+            internal: has(ObjSymbolFlags::Internal),
+        }
+    }
+}
+
+impl SymbolDumpEntry {
+    pub fn new(obj: &ObjInfo, symbol: &ObjSymbol) -> Self {
+        Self {
+            name: symbol.name.clone(),
+            demangled_name: symbol.demangled_name.clone(),
+            address: symbol.address,
+            section: symbol.section,
+            section_name: symbol.section.map(|index| obj.sections[index].name.clone()),
+            size: symbol.size,
+            size_known: symbol.size_known,
+            flags: symbol.flags.into(),
+            kind: symbol.kind,
+            align: symbol.align,
+            data_kind: symbol.data_kind,
+            name_hash: symbol.name_hash,
+            demangled_name_hash: symbol.demangled_name_hash,
+            source_file: symbol.source_file.clone(),
+            source_line: symbol.source_line,
+            notes: symbol.notes.clone(),
+        }
+    }
+
+    pub fn to_symbol(&self) -> ObjSymbol {
+        ObjSymbol {
+            name: self.name.clone(),
+            demangled_name: self.demangled_name.clone(),
+            address: self.address,
+            section: self.section,
+            size: self.size,
+            size_known: self.size_known,
+            flags: self.flags.to_flag_set(),
+            kind: self.kind,
+            align: self.align,
+            data_kind: self.data_kind,
+            name_hash: self.name_hash,
+            demangled_name_hash: self.demangled_name_hash,
+            source_file: self.source_file.clone(),
+            source_line: self.source_line,
+            notes: self.notes.clone(),
+            sort_index: None,
+        }
+    }
+}
+
+impl SymbolDump {
+    /// Dumps all symbols, or only those in `section` if given. Absolute symbols are only
+    /// included in unfiltered dumps.
+    pub fn new(obj: &ObjInfo, section: Option<SectionIndex>) -> Self {
+        let entry = |(_, s): (SymbolIndex, &ObjSymbol)| SymbolDumpEntry::new(obj, s);
+        let (symbols, abs) = match section {
+            Some(section) => (obj.symbols.for_section(section).map(entry).collect(), vec![]),
+            None => {
+                let abs = if obj.kind == ObjKind::Executable {
+                    obj.symbols.iter_abs().map(entry).collect()
+                } else {
+                    vec![]
+                };
+                (obj.symbols.iter_ordered().map(entry).collect(), abs)
+            }
+        };
+        Self { version: SYMBOL_DUMP_VERSION, symbols, abs }
+    }
+
+    /// All symbols, sectioned symbols first.
+    pub fn to_symbols(&self) -> Vec<ObjSymbol> {
+        self.symbols.iter().chain(&self.abs).map(SymbolDumpEntry::to_symbol).collect()
+    }
+}
+
+pub fn write_symbol_dump<W>(w: &mut W, obj: &ObjInfo, section: Option<SectionIndex>) -> Result<()>
+where W: Write + ?Sized {
+    serde_json::to_writer_pretty(w, &SymbolDump::new(obj, section))?;
+    Ok(())
+}
+
+pub fn read_symbol_dump<R>(r: &mut R) -> Result<SymbolDump>
+where R: Read + ?Sized {
+    let dump: SymbolDump = serde_json::from_reader(r)?;
+    ensure!(
+        dump.version == SYMBOL_DUMP_VERSION,
+        "Unsupported symbol dump version {} (expected {})",
+        dump.version,
+        SYMBOL_DUMP_VERSION
+    );
+    Ok(dump)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section, test_symbol, ObjArchitecture, ObjSectionKind};
+
+    #[test]
+    fn test_symbol_dump_round_trip() {
+        let symbols = vec![
+            ObjSymbol {
+                name: "__ct__4GameFv".to_string(),
+                demangled_name: Some("Game::Game()".to_string()),
+                address: 0x80003000,
+                section: Some(0),
+                size: 0x20,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global | ObjSymbolFlags::ForceActive),
+                kind: ObjSymbolKind::Function,
+                align: Some(16),
+                name_hash: Some(0x12345678),
+                source_file: Some("game.cpp".to_string()),
+                source_line: Some(42),
+                ..Default::default()
+            },
+            ObjSymbol {
+                name: "sCount".to_string(),
+                address: 0x80004000,
+                section: Some(1),
+                size: 4,
+                size_known: true,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Local | ObjSymbolFlags::Locked),
+                kind: ObjSymbolKind::Object,
+                data_kind: ObjDataKind::Int,
+                ..Default::default()
+            },
+            ObjSymbol {
+                name: "_SDA_BASE_".to_string(),
+                address: 0x80408000,
+                flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+                ..Default::default()
+            },
+        ];
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x20);
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            symbols.clone(),
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000),
+                section(".data", ObjSectionKind::Data, 0x80004000),
+            ],
+        );
+
+        let mut json = vec![];
+        write_symbol_dump(&mut json, &obj, None).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(value["version"], SYMBOL_DUMP_VERSION);
+        assert_eq!(value["symbols"][0]["section_name"], ".text");
+        assert_eq!(value["symbols"][0]["flags"]["force_active"], true);
+        assert_eq!(value["symbols"][1]["flags"]["locked"], true);
+        assert_eq!(value["symbols"][1]["data_kind"], "Int");
+        assert_eq!(value["abs"][0]["name"], "_SDA_BASE_");
+
+        let dump = read_symbol_dump(&mut json.as_slice()).unwrap();
+        assert_eq!(dump.to_symbols(), symbols);
+
+        let mut newer = value;
+        newer["version"] = (SYMBOL_DUMP_VERSION + 1).into();
+        let json = serde_json::to_vec(&newer).unwrap();
+        assert!(read_symbol_dump(&mut json.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_symbol_dump_section_filter() {
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x20);
+        let symbol = |name: &str, address, section| ObjSymbol {
+            flags: ObjSymbolFlagSet(ObjSymbolFlags::Global.into()),
+            ..test_symbol(name, section, address, 4, ObjSymbolKind::Unknown)
+        };
+        let obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![
+                symbol("func", 0x80003000, Some(0)),
+                symbol("sData", 0x80004000, Some(1)),
+                symbol("sOther", 0x80004010, Some(1)),
+                symbol("_SDA_BASE_", 0x80408000, None),
+            ],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003000),
+                section(".data", ObjSectionKind::Data, 0x80004000),
+            ],
+        );
+        let names = |section| {
+            let dump = SymbolDump::new(&obj, Some(section));
+            assert!(dump.abs.is_empty());
+            dump.symbols.into_iter().map(|s| s.name).collect::<Vec<_>>()
+        };
+
+        // Name and index forms resolve to the same section
+        let by_name = obj.sections.by_name_or_index(".data").unwrap();
+        let by_index = obj.sections.by_name_or_index("1").unwrap();
+        assert_eq!(by_name, by_index);
+        assert_eq!(names(by_name), ["sData", "sOther"]);
+        assert_eq!(names(obj.sections.by_name_or_index("0").unwrap()), ["func"]);
+
+        let err = obj.sections.by_name_or_index(".bss").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown section '.bss' (available: .text, .data)");
+        let err = obj.sections.by_name_or_index("2").unwrap_err();
+        assert_eq!(err.to_string(), "Section index 2 out of range (object has 2 sections)");
+    }
+}
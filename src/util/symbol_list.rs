@@ -0,0 +1,189 @@
+//! Reads symbol names from a plain text list, as exported by disassemblers or written by hand.
+//!
+//! Each line holds an address and a name, optionally followed by a size and a kind (`function`,
+//! `object` or `label`), separated by whitespace. Everything after a `#` is a comment.
+//!
+//! ```text
+//! # From Ghidra
+//! 0x80003100 main
+//! 0x80400000 gGameState 0x40 object
+//! ```
+
+use std::io::BufRead;
+
+use anyhow::{anyhow, bail, Context, Result};
+use typed_path::Utf8NativePath;
+
+use crate::{
+    analysis::cfa::SectionAddress,
+    obj::{ObjInfo, ObjSectionKind, ObjSymbol, ObjSymbolKind},
+    util::config::{is_auto_symbol, parse_u32, symbol_kind_from_str},
+    vfs::open_file,
+};
+
+/// Result of [`apply_symbol_list`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolListResult {
+    /// The number of names applied.
+    pub applied: usize,
+    /// Line numbers (1-based) and addresses of entries outside of any section.
+    pub unresolved: Vec<(usize, u32)>,
+}
+
+pub fn apply_symbol_list_file(
+    path: &Utf8NativePath,
+    obj: &mut ObjInfo,
+) -> Result<SymbolListResult> {
+    let mut file = open_file(path, true)?;
+    apply_symbol_list(file.as_mut(), obj)
+        .with_context(|| format!("Failed to process symbol list '{}'", path))
+}
+
+/// Adds the symbols in a symbol list to `obj`. Existing auto-generated symbols are renamed, but
+/// known names are kept. Without a kind column, symbols in code sections are functions, or labels
+/// if they fall inside an existing function, and others are objects.
+pub fn apply_symbol_list<R>(r: &mut R, obj: &mut ObjInfo) -> Result<SymbolListResult>
+where R: BufRead + ?Sized {
+    let mut result = SymbolListResult::default();
+    for (line_idx, line) in r.lines().enumerate() {
+        let line = line?;
+        let line_number = line_idx + 1;
+        let content = line.split_once('#').map_or(line.as_str(), |(content, _)| content);
+        let mut columns = content.split_whitespace();
+        let Some(address) = columns.next() else {
+            continue;
+        };
+        let address = parse_u32(address)
+            .map_err(|e| anyhow!("Line {}: invalid address '{}': {}", line_number, address, e))?;
+        let Some(name) = columns.next() else {
+            bail!("Line {}: missing symbol name", line_number);
+        };
+        let size = columns
+            .next()
+            .map(|s| {
+                parse_u32(s)
+                    .map_err(|e| anyhow!("Line {}: invalid size '{}': {}", line_number, s, e))
+            })
+            .transpose()?;
+        let kind = columns
+            .next()
+            .map(|s| {
+                symbol_kind_from_str(s)
+                    .filter(|&kind| kind != ObjSymbolKind::Section)
+                    .ok_or_else(|| anyhow!("Line {}: invalid symbol kind '{}'", line_number, s))
+            })
+            .transpose()?;
+        if let Some(extra) = columns.next() {
+            bail!("Line {}: unexpected column '{}'", line_number, extra);
+        }
+
+        let Ok((section_index, section)) = obj.sections.at_address(address) else {
+            result.unresolved.push((line_number, address));
+            continue;
+        };
+        let addr = SectionAddress::new(section_index, address);
+        let kind = match kind {
+            Some(kind) => kind,
+            None if section.kind != ObjSectionKind::Code => ObjSymbolKind::Object,
+            None => {
+                // Interior labels would otherwise split the function containing them
+                let inside_function = obj
+                    .symbols
+                    .for_section_range_overlapping(section_index, address..address + 1)
+                    .any(|(_, s)| s.is_code() && s.address < address as u64);
+                if inside_function {
+                    ObjSymbolKind::Unknown
+                } else {
+                    ObjSymbolKind::Function
+                }
+            }
+        };
+        let existing = obj
+            .symbols
+            .at_section_address(addr.section, addr.address)
+            .find(|(_, s)| s.kind != ObjSymbolKind::Section)
+            .map(|(_, s)| is_auto_symbol(s));
+        if existing == Some(false) {
+            // Keep names that are already known
+            continue;
+        }
+        obj.add_symbol(
+            ObjSymbol {
+                name: name.to_string(),
+                address: addr.address as u64,
+                section: Some(addr.section),
+                size: size.unwrap_or(0) as u64,
+                size_known: size.is_some(),
+                kind,
+                ..Default::default()
+            },
+            existing.is_some(),
+        )?;
+        result.applied += 1;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::obj::{test_section, test_symbol, ObjArchitecture, ObjKind};
+
+    #[test]
+    fn test_apply_symbol_list() {
+        let section = |name: &str, kind, address| test_section(name, kind, address, 0x100);
+        let function = |name: &str, address| {
+            test_symbol(name, Some(0), address, 0x10, ObjSymbolKind::Function)
+        };
+        let mut obj = ObjInfo::new(
+            ObjKind::Executable,
+            ObjArchitecture::PowerPc,
+            "test".to_string(),
+            vec![function("fn_80003100", 0x80003100), function("GameInit", 0x80003110)],
+            vec![
+                section(".text", ObjSectionKind::Code, 0x80003100),
+                section(".data", ObjSectionKind::Data, 0x80400000),
+            ],
+        );
+
+        let list = "\
+# Exported from a disassembler
+0x80003100 main
+0x80003110 init__4GameFv   # already named
+0x80003120 draw__4GameFv 0x10
+0x80003118 init_loop
+
+0x80400000 gGameState 0x40 object
+0x80400040 gTable 8 label
+0x90000000 outOfRange
+";
+        let result = apply_symbol_list(&mut list.as_bytes(), &mut obj).unwrap();
+        assert_eq!(result, SymbolListResult { applied: 5, unresolved: vec![(9, 0x90000000)] });
+
+        // Auto symbol renamed
+        assert!(obj.symbols.by_name("fn_80003100").unwrap().is_none());
+        let (_, main) = obj.symbols.by_name("main").unwrap().unwrap();
+        assert_eq!(
+            (main.address, main.size, main.kind),
+            (0x80003100, 0x10, ObjSymbolKind::Function)
+        );
+        // Known names are kept
+        assert!(obj.symbols.by_name("GameInit").unwrap().is_some());
+        assert!(obj.symbols.by_name("init__4GameFv").unwrap().is_none());
+        // Size and kind columns
+        let (_, draw) = obj.symbols.by_name("draw__4GameFv").unwrap().unwrap();
+        assert_eq!((draw.size, draw.kind), (0x10, ObjSymbolKind::Function));
+        // Interior labels don't split functions
+        let (_, init_loop) = obj.symbols.by_name("init_loop").unwrap().unwrap();
+        assert_eq!(init_loop.kind, ObjSymbolKind::Unknown);
+        let (_, state) = obj.symbols.by_name("gGameState").unwrap().unwrap();
+        assert_eq!((state.section, state.size, state.kind), (Some(1), 0x40, ObjSymbolKind::Object));
+        let (_, table) = obj.symbols.by_name("gTable").unwrap().unwrap();
+        assert_eq!((table.size, table.kind), (8, ObjSymbolKind::Unknown));
+
+        // Malformed lines are errors
+        let err =
+            apply_symbol_list(&mut "0x80003100 a 4 bogus\n".as_bytes(), &mut obj).unwrap_err();
+        assert!(err.to_string().contains("Line 1: invalid symbol kind 'bogus'"), "{err}");
+    }
+}
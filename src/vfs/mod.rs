@@ -26,7 +26,7 @@ use u8_arc::U8Fs;
 use wad::WadFs;
 
 use crate::util::{
-    ncompress::{YAY0_MAGIC, YAZ0_MAGIC},
+    ncompress::{decompress_yay0, decompress_yaz0, YAY0_MAGIC, YAZ0_MAGIC},
     nlzss,
     rarc::RARC_MAGIC,
     u8_arc::U8_MAGIC,
@@ -350,13 +350,13 @@ pub fn decompress_file(
     match kind {
         CompressionKind::Yay0 => {
             let data = file.map()?;
-            let result = orthrus_ncompress::yay0::Yay0::decompress_from(data)
+            let result = decompress_yay0(data)
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
             Ok(Box::new(StaticFile::new(Arc::from(result), metadata.mtime)))
         }
         CompressionKind::Yaz0 => {
             let data = file.map()?;
-            let result = orthrus_ncompress::yaz0::Yaz0::decompress_from(data)
+            let result = decompress_yaz0(data)
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
             Ok(Box::new(StaticFile::new(Arc::from(result), metadata.mtime)))
         }
@@ -378,3 +378,41 @@ pub fn next_non_empty<'a>(iter: &mut impl Iterator<Item = &'a str>) -> &'a str {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::{
+        dol::DolFile,
+        ncompress::compress,
+        reader::{Endian, FromReader},
+    };
+
+    #[test]
+    fn test_decompress_dol() {
+        // Minimal DOL with a single text section
+        let mut dol = vec![0u8; 0x120];
+        dol[0x00..0x04].copy_from_slice(&0x100u32.to_be_bytes()); // text_offs[0]
+        dol[0x48..0x4C].copy_from_slice(&0x80003100u32.to_be_bytes()); // text_addrs[0]
+        dol[0x90..0x94].copy_from_slice(&0x20u32.to_be_bytes()); // text_sizes[0]
+        dol[0xE0..0xE4].copy_from_slice(&0x80003120u32.to_be_bytes()); // bss_addr
+        dol[0xE4..0xE8].copy_from_slice(&0x40u32.to_be_bytes()); // bss_size
+        dol[0xE8..0xEC].copy_from_slice(&0x80003100u32.to_be_bytes()); // entry_point
+        dol[0x100..0x104].copy_from_slice(&0x4E800020u32.to_be_bytes()); // blr
+
+        let mut file = StaticFile::new(Arc::from(dol.as_slice()), None);
+        assert_eq!(detect(&mut file).unwrap(), FileFormat::Regular);
+        let expected = DolFile::from_reader(&mut file, Endian::Big).unwrap();
+
+        for kind in [CompressionKind::Yaz0, CompressionKind::Yay0] {
+            let compressed = compress(kind, &dol).unwrap();
+            let mut file = StaticFile::new(Arc::from(compressed), None);
+            assert_eq!(detect(&mut file).unwrap(), FileFormat::Compressed(kind));
+            let mut file = decompress_file(&mut file, kind).unwrap();
+            assert_eq!(file.map().unwrap(), dol.as_slice());
+            let result = DolFile::from_reader(file.as_mut(), Endian::Big).unwrap();
+            assert_eq!(result.header.entry_point, expected.header.entry_point);
+            assert_eq!(result.sections.len(), expected.sections.len());
+        }
+    }
+}